@@ -0,0 +1,240 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGl2RenderingContext, WebGlFramebuffer, WebGlRenderbuffer};
+
+// Sample count of "msaa" when none is given
+const DEFAULT_SAMPLES: u32 = 4;
+
+// How the edges of shapes are smoothed
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum Antialiasing {
+    // Hard triangle edges
+    #[default]
+    None,
+    // The scene is drawn into a multisampled renderbuffer resolved at the end of the frame
+    Msaa(u32),
+    // Fills and strokes get a one pixel wide feather whose coverage falls off to the outside
+    Analytic,
+}
+
+impl Antialiasing {
+    pub(crate) fn parse(mode: &str, samples: Option<u32>) -> Result<Antialiasing, JsValue> {
+        Ok(match mode {
+            "none" => Antialiasing::None,
+            "msaa" => {
+                let samples = samples.unwrap_or(DEFAULT_SAMPLES);
+                if !(2..=16).contains(&samples) {
+                    return Err(JsValue::from_str("MSAA needs between 2 and 16 samples"));
+                }
+                Antialiasing::Msaa(samples)
+            }
+            "analytic" => Antialiasing::Analytic,
+            _ => return Err(JsValue::from_str(&format!("Invalid antialiasing mode \"{}\"", mode))),
+        })
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Antialiasing::None => "none",
+            Antialiasing::Msaa(_) => "msaa",
+            Antialiasing::Analytic => "analytic",
+        }
+    }
+}
+
+// Multisampled color and depth-stencil renderbuffers the scene is drawn into, resolved into
+// whatever framebuffer was bound when the frame began. Blits out of a multisampled
+// framebuffer need matching formats, so the samples go through a single-sampled copy with
+// the format of the scene first
+pub(crate) struct MultisampleTarget {
+    framebuffer: WebGlFramebuffer,
+    color: WebGlRenderbuffer,
+    depth_stencil: WebGlRenderbuffer,
+    resolve_framebuffer: WebGlFramebuffer,
+    resolve_color: WebGlRenderbuffer,
+    samples: i32,
+    // RGBA8, or SRGB8_ALPHA8 for a linear-light scene
+    internal_format: u32,
+    width: u32,
+    height: u32,
+    // Framebuffer the frame resolves into
+    scene: Option<WebGlFramebuffer>,
+}
+
+impl MultisampleTarget {
+    pub(crate) fn new(
+        gl: &WebGl2RenderingContext,
+        samples: u32,
+        width: u32,
+        height: u32,
+        srgb: bool,
+    ) -> Result<MultisampleTarget, JsValue> {
+        // Fall back to what the implementation supports
+        let max_samples = gl.get_parameter(WebGl2RenderingContext::MAX_SAMPLES)?.as_f64().unwrap_or(4.0) as u32;
+
+        let framebuffer = gl.create_framebuffer().ok_or("Failed to create multisample framebuffer")?;
+        let resolve_framebuffer = gl.create_framebuffer().ok_or("Failed to create resolve framebuffer")?;
+        let mut target = MultisampleTarget {
+            framebuffer,
+            color: gl.create_renderbuffer().ok_or("Failed to create multisample renderbuffer")?,
+            depth_stencil: gl.create_renderbuffer().ok_or("Failed to create multisample renderbuffer")?,
+            resolve_framebuffer,
+            resolve_color: gl.create_renderbuffer().ok_or("Failed to create resolve renderbuffer")?,
+            samples: samples.min(max_samples).max(1) as i32,
+            internal_format: format(srgb),
+            width,
+            height,
+            scene: None,
+        };
+        target.allocate(gl);
+
+        for (framebuffer, attachments) in [
+            (
+                &target.framebuffer,
+                vec![
+                    (WebGl2RenderingContext::COLOR_ATTACHMENT0, &target.color),
+                    (WebGl2RenderingContext::DEPTH_STENCIL_ATTACHMENT, &target.depth_stencil),
+                ],
+            ),
+            (&target.resolve_framebuffer, vec![(WebGl2RenderingContext::COLOR_ATTACHMENT0, &target.resolve_color)]),
+        ] {
+            gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(framebuffer));
+            for (attachment, renderbuffer) in attachments {
+                gl.framebuffer_renderbuffer(
+                    WebGl2RenderingContext::FRAMEBUFFER,
+                    attachment,
+                    WebGl2RenderingContext::RENDERBUFFER,
+                    Some(renderbuffer),
+                );
+            }
+            let status = gl.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+            if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE {
+                gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+                target.delete(gl);
+                return Err(JsValue::from_str(&format!("Multisample target is incomplete (status 0x{:x})", status)));
+            }
+        }
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+        Ok(target)
+    }
+
+    // Samples per pixel actually allocated
+    pub(crate) fn samples(&self) -> u32 {
+        self.samples as u32
+    }
+
+    // Reallocate the renderbuffers when the size or the encoding of the scene changes
+    pub(crate) fn configure(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32, srgb: bool) {
+        if self.width == width && self.height == height && self.internal_format == format(srgb) {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.internal_format = format(srgb);
+        self.allocate(gl);
+    }
+
+    // Redirect the scene into the multisampled renderbuffers, remembering where it was going
+    pub(crate) fn begin(&mut self, gl: &WebGl2RenderingContext) -> Result<(), JsValue> {
+        self.scene = gl
+            .get_parameter(WebGl2RenderingContext::FRAMEBUFFER_BINDING)?
+            .dyn_into::<WebGlFramebuffer>()
+            .ok();
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.viewport(0, 0, self.width as i32, self.height as i32);
+
+        Ok(())
+    }
+
+    // The multisampled framebuffer and its single-sampled resolve, for copies of the backdrop
+    pub(crate) fn framebuffers(&self) -> (WebGlFramebuffer, WebGlFramebuffer) {
+        (self.framebuffer.clone(), self.resolve_framebuffer.clone())
+    }
+
+    // Average the samples into the framebuffer bound when the frame began and bind it again
+    pub(crate) fn resolve(&mut self, gl: &WebGl2RenderingContext) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+
+        gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(&self.framebuffer));
+        gl.bind_framebuffer(WebGl2RenderingContext::DRAW_FRAMEBUFFER, Some(&self.resolve_framebuffer));
+        blit(gl, [0, 0, width, height]);
+
+        gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(&self.resolve_framebuffer));
+        gl.bind_framebuffer(WebGl2RenderingContext::DRAW_FRAMEBUFFER, self.scene.as_ref());
+        blit(gl, [0, 0, width, height]);
+
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, self.scene.take().as_ref());
+        gl.viewport(0, 0, width, height);
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_framebuffer(Some(&self.resolve_framebuffer));
+        gl.delete_renderbuffer(Some(&self.color));
+        gl.delete_renderbuffer(Some(&self.depth_stencil));
+        gl.delete_renderbuffer(Some(&self.resolve_color));
+    }
+
+    fn allocate(&mut self, gl: &WebGl2RenderingContext) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        for (renderbuffer, samples, internal_format) in [
+            (&self.color, self.samples, self.internal_format),
+            (&self.depth_stencil, self.samples, WebGl2RenderingContext::DEPTH24_STENCIL8),
+            (&self.resolve_color, 0, self.internal_format),
+        ] {
+            gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(renderbuffer));
+            gl.renderbuffer_storage_multisample(
+                WebGl2RenderingContext::RENDERBUFFER,
+                samples,
+                internal_format,
+                width,
+                height,
+            );
+        }
+        gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+    }
+}
+
+// Copy a device pixel box [x0, y0, x1, y1] of the color buffer from the read to the draw
+// framebuffer
+pub(crate) fn blit(gl: &WebGl2RenderingContext, area: [i32; 4]) {
+    let [x0, y0, x1, y1] = area;
+    gl.blit_framebuffer(
+        x0,
+        y0,
+        x1,
+        y1,
+        x0,
+        y0,
+        x1,
+        y1,
+        WebGl2RenderingContext::COLOR_BUFFER_BIT,
+        WebGl2RenderingContext::NEAREST,
+    );
+}
+
+fn format(srgb: bool) -> u32 {
+    if srgb {
+        WebGl2RenderingContext::SRGB8_ALPHA8
+    } else {
+        WebGl2RenderingContext::RGBA8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip_through_their_names() {
+        assert_eq!(Antialiasing::parse("none", None).unwrap(), Antialiasing::None);
+        assert_eq!(Antialiasing::parse("msaa", None).unwrap(), Antialiasing::Msaa(DEFAULT_SAMPLES));
+        assert_eq!(Antialiasing::parse("msaa", Some(8)).unwrap(), Antialiasing::Msaa(8));
+        for mode in ["none", "msaa", "analytic"] {
+            assert_eq!(Antialiasing::parse(mode, None).unwrap().name(), mode);
+        }
+    }
+}
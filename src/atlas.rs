@@ -0,0 +1,336 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlTexture};
+
+// Side of a newly created atlas page in pixels, pages double up to the maximum size
+const INITIAL_ATLAS_SIZE: u32 = 512;
+
+// Upper bound on the atlas size even on GPUs supporting larger textures
+pub(crate) const MAX_ATLAS_SIZE: u32 = 4096;
+
+// Empty texels kept around every rectangle so linear filtering never reads a neighbor
+const ATLAS_PADDING: u32 = 1;
+
+// Top edge of a horizontal run of the skyline
+#[derive(Copy, Clone, Debug)]
+struct SkylineNode {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+// Skyline bottom-left rectangle packer: the packed area is described by its top outline
+// and every rectangle is placed where it ends up lowest
+pub(crate) struct SkylinePacker {
+    width: u32,
+    height: u32,
+    nodes: Vec<SkylineNode>,
+}
+
+impl SkylinePacker {
+    pub(crate) fn new(width: u32, height: u32) -> SkylinePacker {
+        SkylinePacker {
+            width,
+            height,
+            nodes: vec![SkylineNode { x: 0, y: 0, width }],
+        }
+    }
+
+    // Find room for a rectangle and return its top-left corner, None when it does not fit
+    pub(crate) fn pack(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 || width > self.width || height > self.height {
+            return None;
+        }
+
+        // Lowest resulting top edge wins, the narrowest node breaks ties to limit waste
+        let mut best: Option<(usize, u32, u32, u32)> = None;
+        for index in 0..self.nodes.len() {
+            if let Some(y) = self.fit(index, width, height) {
+                let node_width = self.nodes[index].width;
+                let better = best.is_none_or(|(_, best_y, best_width, _)| {
+                    y + height < best_y || (y + height == best_y && node_width < best_width)
+                });
+                if better {
+                    best = Some((index, y + height, node_width, y));
+                }
+            }
+        }
+
+        let (index, _, _, y) = best?;
+        let x = self.nodes[index].x;
+        self.add_level(index, x, y, width, height);
+
+        Some((x, y))
+    }
+
+    // Enlarge the packing area, rectangles already placed keep their position
+    pub(crate) fn grow(&mut self, width: u32, height: u32) {
+        if width > self.width {
+            self.nodes.push(SkylineNode {
+                x: self.width,
+                y: 0,
+                width: width - self.width,
+            });
+            self.merge();
+        }
+        self.width = self.width.max(width);
+        self.height = self.height.max(height);
+    }
+
+    // Top of a rectangle placed at the left edge of a node, None if it would leave the area
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.nodes[index].x;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining = width;
+        for node in &self.nodes[index..] {
+            y = y.max(node.y);
+            if y + height > self.height {
+                return None;
+            }
+            if node.width >= remaining {
+                return Some(y);
+            }
+            remaining -= node.width;
+        }
+
+        None
+    }
+
+    // Raise the skyline over a newly placed rectangle
+    fn add_level(&mut self, index: usize, x: u32, y: u32, width: u32, height: u32) {
+        self.nodes.insert(index, SkylineNode { x, y: y + height, width });
+
+        // Trim or drop the nodes now covered by the rectangle
+        let right = x + width;
+        let next = index + 1;
+        while next < self.nodes.len() {
+            let node = self.nodes[next];
+            if node.x >= right {
+                break;
+            }
+
+            let overlap = right - node.x;
+            if node.width <= overlap {
+                self.nodes.remove(next);
+            } else {
+                self.nodes[next].x += overlap;
+                self.nodes[next].width -= overlap;
+                break;
+            }
+        }
+
+        self.merge();
+    }
+
+    // Join neighboring nodes at the same height
+    fn merge(&mut self) {
+        let mut index = 0;
+        while index + 1 < self.nodes.len() {
+            if self.nodes[index].y == self.nodes[index + 1].y {
+                self.nodes[index].width += self.nodes[index + 1].width;
+                self.nodes.remove(index + 1);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+// A square RGBA texture that images are packed into
+pub(crate) struct Atlas {
+    texture: WebGlTexture,
+    packer: SkylinePacker,
+    size: u32,
+}
+
+impl Atlas {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<Atlas, JsValue> {
+        let texture = create_atlas_texture(gl, INITIAL_ATLAS_SIZE)?;
+
+        Ok(Atlas {
+            texture,
+            packer: SkylinePacker::new(INITIAL_ATLAS_SIZE, INITIAL_ATLAS_SIZE),
+            size: INITIAL_ATLAS_SIZE,
+        })
+    }
+
+    pub(crate) fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        self.size
+    }
+
+    // Reserve a padded rectangle and return the top-left corner of its usable area.
+    // The atlas doubles in size up to max_size when it is full; None means it cannot fit
+    pub(crate) fn allocate(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        width: u32,
+        height: u32,
+        max_size: u32,
+    ) -> Result<Option<(u32, u32)>, JsValue> {
+        let padded_width = width + ATLAS_PADDING * 2;
+        let padded_height = height + ATLAS_PADDING * 2;
+        if padded_width > max_size || padded_height > max_size {
+            return Ok(None);
+        }
+
+        loop {
+            if let Some((x, y)) = self.packer.pack(padded_width, padded_height) {
+                return Ok(Some((x + ATLAS_PADDING, y + ATLAS_PADDING)));
+            }
+
+            if self.size * 2 > max_size {
+                return Ok(None);
+            }
+            self.grow(gl, self.size * 2)?;
+        }
+    }
+
+    // Forget every rectangle and clear the texels so stale images cannot bleed into new ones
+    pub(crate) fn reset(&mut self, gl: &WebGl2RenderingContext) -> Result<(), JsValue> {
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+        allocate_atlas_storage(gl, self.size)?;
+        self.packer = SkylinePacker::new(self.size, self.size);
+
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_texture(Some(&self.texture));
+    }
+
+    // Move the content into a larger texture. Rectangles keep their pixel position,
+    // texture coordinates are derived from the atlas size at draw time
+    fn grow(&mut self, gl: &WebGl2RenderingContext, size: u32) -> Result<(), JsValue> {
+        let texture = create_atlas_texture(gl, size)?;
+
+        let framebuffer = gl.create_framebuffer().ok_or("Failed to create atlas framebuffer")?;
+        gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGl2RenderingContext::READ_FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&self.texture),
+            0,
+        );
+        gl.copy_tex_sub_image_2d(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            0,
+            0,
+            0,
+            0,
+            self.size as i32,
+            self.size as i32,
+        );
+        gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, None);
+        gl.delete_framebuffer(Some(&framebuffer));
+
+        gl.delete_texture(Some(&self.texture));
+        self.texture = texture;
+        self.packer.grow(size, size);
+        self.size = size;
+
+        Ok(())
+    }
+}
+
+// Create a zero-filled atlas texture and leave it bound
+fn create_atlas_texture(gl: &WebGl2RenderingContext, size: u32) -> Result<WebGlTexture, JsValue> {
+    let texture = gl.create_texture().ok_or("Failed to create atlas texture")?;
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+    allocate_atlas_storage(gl, size)?;
+
+    for (parameter, value) in [
+        (WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR),
+        (WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR),
+        (WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE),
+        (WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, parameter, value as i32);
+    }
+
+    Ok(texture)
+}
+
+// (Re)allocate the bound texture, WebGL initializes the texels to transparent black
+fn allocate_atlas_storage(gl: &WebGl2RenderingContext, size: u32) -> Result<(), JsValue> {
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        0,
+        WebGl2RenderingContext::RGBA8 as i32,
+        size as i32,
+        size as i32,
+        0,
+        WebGl2RenderingContext::RGBA,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pack pseudo-random rectangles until one does not fit, returning what was placed
+    fn fill(packer: &mut SkylinePacker, seed: &mut u32) -> Vec<(u32, u32, u32, u32)> {
+        let mut placed = Vec::new();
+        loop {
+            *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let (width, height) = (8 + (*seed >> 8) % 40, 8 + (*seed >> 20) % 40);
+            match packer.pack(width, height) {
+                Some((x, y)) => placed.push((x, y, width, height)),
+                None => return placed,
+            }
+        }
+    }
+
+    fn assert_disjoint_within(placed: &[(u32, u32, u32, u32)], width: u32, height: u32) {
+        for (i, a) in placed.iter().enumerate() {
+            assert!(a.0 + a.2 <= width && a.1 + a.3 <= height, "{:?} leaves the area", a);
+            for b in &placed[i + 1..] {
+                let apart = a.0 + a.2 <= b.0 || b.0 + b.2 <= a.0 || a.1 + a.3 <= b.1 || b.1 + b.3 <= a.1;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn packed_rectangles_do_not_overlap() {
+        let mut packer = SkylinePacker::new(256, 256);
+        let mut seed = 7;
+        let placed = fill(&mut packer, &mut seed);
+        assert!(placed.len() > 20);
+        assert_disjoint_within(&placed, 256, 256);
+
+        // Most of the area is used before the first failure
+        let used: u32 = placed.iter().map(|rect| rect.2 * rect.3).sum();
+        assert!(used * 10 > 256 * 256 * 6, "only {} texels used", used);
+    }
+
+    #[test]
+    fn growing_keeps_placed_rectangles() {
+        let mut packer = SkylinePacker::new(128, 128);
+        let mut seed = 42;
+        let mut placed = fill(&mut packer, &mut seed);
+        packer.grow(256, 256);
+        placed.extend(fill(&mut packer, &mut seed));
+        assert_disjoint_within(&placed, 256, 256);
+        assert!(placed.iter().any(|rect| rect.0 + rect.2 > 128 || rect.1 + rect.3 > 128));
+    }
+
+    #[test]
+    fn oversized_and_empty_rectangles_are_refused() {
+        let mut packer = SkylinePacker::new(64, 64);
+        assert_eq!(packer.pack(65, 1), None);
+        assert_eq!(packer.pack(0, 10), None);
+        assert_eq!(packer.pack(64, 64), Some((0, 0)));
+        assert_eq!(packer.pack(1, 1), None);
+    }
+}
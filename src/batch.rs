@@ -0,0 +1,1275 @@
+use std::rc::Rc;
+
+use js_sys::{Float32Array, Uint32Array};
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture, WebGlUniformLocation,
+    WebGlVertexArrayObject
+};
+
+use crate::clip::{Clip, Clipper};
+use crate::composite::{CompositeOperation, Compositor, BACKDROP_TEXTURE_UNIT};
+use crate::filter::{Filter, FilterRenderer};
+use crate::gradient::{Gradient, GradientKind, SpreadMode, MAX_GRADIENT_STOPS};
+use crate::path::{self, Contour, FillRule};
+use crate::pipeline::{expand_shader, ColorPipeline};
+use crate::shadow::{Shadow, ShadowRenderer};
+use crate::texture::{Pattern, Texture};
+use crate::{compile_shader, link_program};
+
+// Vertex layout: position (2), paint coordinates (2), color (4), glyph (3), coverage (1)
+const FLOATS_PER_VERTEX: usize = 12;
+
+// Glyph attribute of vertices that are not text: coverage is always 1
+const NO_GLYPH: [f32; 3] = [0.0, 0.0, -1.0];
+
+// Miter joins are clamped to this ratio of miter length to half line width
+pub(crate) const MITER_LIMIT: f32 = 10.0;
+
+// Longest extrusion of a feathered corner, in feather widths
+pub(crate) const FEATHER_MITER_LIMIT: f32 = 4.0;
+
+// Maximum distance in device pixels between a curve and its tessellation
+pub(crate) const CURVE_TOLERANCE: f32 = 0.25;
+
+// Shape vertex shader: positions are in world space (CSS pixels), paint
+// coordinates are the user-space coordinates gradients and patterns are
+// evaluated in, or texture coordinates for images
+const SHAPE_VERTEX_SHADER_SRC: &str = r#"#version 300 es
+in vec2 aPosition;
+in vec2 aPaintCoord;
+in vec4 aColor;
+in vec3 aGlyph;
+in float aCoverage;
+
+uniform mat4 uMatrix;
+
+out vec2 vPaintCoord;
+out vec4 vColor;
+out vec3 vGlyph;
+out float vCoverage;
+
+void main() {
+    vPaintCoord = aPaintCoord;
+    vColor = aColor;
+    vGlyph = aGlyph;
+    vCoverage = aCoverage;
+    gl_Position = uMatrix * vec4(aPosition, 0.0, 1.0);
+}
+"#;
+
+// Shape fragment shader: flat vertex colors, per-fragment gradients, images and patterns
+const SHAPE_FRAGMENT_SHADER_SRC: &str = r#"#version 300 es
+precision highp float;
+
+#define MAX_STOPS 16
+#define PI 3.14159265359
+
+in vec2 vPaintCoord;
+in vec4 vColor;
+// Glyph atlas texel, and the mode: < 0 not text, 0 filled text, > 0 stroke half width in atlas pixels
+in vec3 vGlyph;
+// Edge coverage, falling off to 0 across the feather of antialiased edges
+in float vCoverage;
+
+uniform sampler2D uGlyphAtlas;
+uniform vec2 uGlyphAtlasSize;
+// Distance in atlas pixels encoded on each side of the glyph outlines
+uniform float uGlyphSpread;
+
+// 0 = vertex color, 1 = gradient, 2 = image, 3 = pattern
+uniform int uPaintMode;
+
+uniform sampler2D uTexture;
+uniform bool uTexturePremultiplied;
+// pattern: image size in pixels and 1.0 on the axes it tiles along
+uniform vec2 uTextureSize;
+uniform vec2 uRepeat;
+
+// 0 = linear, 1 = radial, 2 = conic
+uniform int uGradientKind;
+// linear: start, end / radial: start center, end center / conic: center, unused
+uniform vec4 uGradientPoints;
+// radial: start radius, end radius / conic: start angle, unused
+uniform vec2 uGradientParams;
+// 0 = pad, 1 = repeat, 2 = reflect
+uniform int uSpreadMode;
+uniform int uStopCount;
+uniform float uStopOffsets[MAX_STOPS];
+uniform vec4 uStopColors[MAX_STOPS];
+
+#pragma pipeline
+#pragma composite
+
+out vec4 outColor;
+
+// Gradient parameter at this fragment, returns false where the gradient is not defined
+bool gradientT(vec2 p, out float t) {
+    if (uGradientKind == 0) {
+        vec2 start = uGradientPoints.xy;
+        vec2 dir = uGradientPoints.zw - start;
+        float len2 = dot(dir, dir);
+        if (len2 == 0.0) {
+            return false;
+        }
+        t = dot(p - start, dir) / len2;
+        return true;
+    }
+
+    if (uGradientKind == 1) {
+        // Two-point conical gradient as specified by CanvasRenderingContext2D:
+        // largest w such that p lies on the circle (c0 + w * dc, r0 + w * dr) with a positive radius
+        vec2 c0 = uGradientPoints.xy;
+        vec2 dc = uGradientPoints.zw - c0;
+        float r0 = uGradientParams.x;
+        float dr = uGradientParams.y - r0;
+        vec2 pd = p - c0;
+        float a = dot(dc, dc) - dr * dr;
+        float b = dot(pd, dc) + r0 * dr;
+        float c = dot(pd, pd) - r0 * r0;
+
+        if (abs(a) < 1e-6) {
+            if (b == 0.0) {
+                return false;
+            }
+            t = c / (2.0 * b);
+            return r0 + t * dr >= 0.0;
+        }
+
+        float disc = b * b - a * c;
+        if (disc < 0.0) {
+            return false;
+        }
+
+        float root = sqrt(disc);
+        float w1 = (b + root) / a;
+        float w2 = (b - root) / a;
+        float hi = max(w1, w2);
+        float lo = min(w1, w2);
+        if (r0 + hi * dr >= 0.0) {
+            t = hi;
+            return true;
+        }
+        if (r0 + lo * dr >= 0.0) {
+            t = lo;
+            return true;
+        }
+        return false;
+    }
+
+    // Conic: angle around the center, clockwise in y-down space, starting at the start angle
+    vec2 d = p - uGradientPoints.xy;
+    float angle = atan(d.y, d.x) - uGradientParams.x;
+    t = fract(angle / (2.0 * PI));
+    return true;
+}
+
+vec4 gradientColor(vec2 p) {
+    float t;
+    if (uStopCount == 0 || !gradientT(p, t)) {
+        return vec4(0.0);
+    }
+
+    if (uSpreadMode == 1) {
+        t = fract(t);
+    } else if (uSpreadMode == 2) {
+        t = 1.0 - abs(mod(t, 2.0) - 1.0);
+    } else {
+        t = clamp(t, 0.0, 1.0);
+    }
+
+    // Walk the sorted stops, the last segment starting before t wins
+    vec4 color = uStopColors[0];
+    for (int i = 1; i < MAX_STOPS; i++) {
+        if (i >= uStopCount) {
+            break;
+        }
+        float o0 = uStopOffsets[i - 1];
+        float o1 = uStopOffsets[i];
+        if (t >= o0) {
+            color = o1 > o0
+                ? mix(uStopColors[i - 1], uStopColors[i], clamp((t - o0) / (o1 - o0), 0.0, 1.0))
+                : uStopColors[i];
+        }
+    }
+    return color;
+}
+
+vec4 patternColor(vec2 p) {
+    vec2 uv = p / uTextureSize;
+    bool outsideX = uRepeat.x == 0.0 && (uv.x < 0.0 || uv.x > 1.0);
+    bool outsideY = uRepeat.y == 0.0 && (uv.y < 0.0 || uv.y > 1.0);
+    if (outsideX || outsideY) {
+        return vec4(0.0);
+    }
+    return textureColor(texture(uTexture, uv), uTexturePremultiplied);
+}
+
+// Antialiased coverage of signed-distance-field text, 1 for other shapes
+float glyphCoverage() {
+    // Atlas pixels per screen pixel, keeps edges one pixel wide at any scale
+    float w = max(length(fwidth(vGlyph.xy)) * 0.70710678, 1e-4);
+    if (vGlyph.z < 0.0) {
+        return 1.0;
+    }
+
+    // Signed distance to the outline in atlas pixels, positive inside
+    float d = (texture(uGlyphAtlas, vGlyph.xy / uGlyphAtlasSize).r - 0.5) * 2.0 * uGlyphSpread;
+    if (vGlyph.z == 0.0) {
+        return clamp(d / w + 0.5, 0.0, 1.0);
+    }
+    return clamp((vGlyph.z - abs(d)) / w + 0.5, 0.0, 1.0);
+}
+
+void main() {
+    vec4 paint = vec4(1.0);
+    if (uPaintMode == 1) {
+        paint = gradientColor(vPaintCoord);
+    } else if (uPaintMode == 2) {
+        paint = textureColor(texture(uTexture, vPaintCoord), uTexturePremultiplied);
+    } else if (uPaintMode == 3) {
+        paint = patternColor(vPaintCoord);
+    }
+    outColor = composite(premultiply(paint * vColor, glyphCoverage() * clamp(vCoverage, 0.0, 1.0)));
+}
+"#;
+
+// Fill or stroke style of a shape
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Paint {
+    Color([f32; 4]),
+    Gradient(Gradient),
+    Pattern(Pattern),
+    // Texture coordinates come from the vertices, used by draw_image
+    Image(Rc<Texture>),
+}
+
+impl Paint {
+    // Shapes can share a draw call when their paints only differ by vertex color
+    fn batches_with(&self, other: &Paint) -> bool {
+        match (self, other) {
+            (Paint::Color(_), Paint::Color(_)) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    fn vertex_color(&self) -> [f32; 4] {
+        match self {
+            Paint::Color(color) => *color,
+            _ => [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+// A run of indices drawn with the same paint, clip and composite operation
+struct DrawCommand {
+    paint: Paint,
+    first_index: usize,
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    // World-space bounding box [x0, y0, x1, y1] of the vertices, for the backdrop copy
+    bounds: [f32; 4],
+    // Stencil-then-cover path fill: indices before fringe_index are the winding fans, then
+    // the feathered edges drawn outside the path and the bounding box drawn with the paint
+    fill: Option<PathFill>,
+    // Drawn under the shapes of the command when visible
+    shadow: Option<Shadow>,
+    // The shapes are drawn into a layer the filter runs over, unless it is "none"
+    filter: Option<Rc<Filter>>,
+}
+
+struct PathFill {
+    rule: FillRule,
+    fringe_index: usize,
+    cover_index: usize,
+}
+
+// Immediate-mode shape renderer: shapes are tessellated on the CPU into a
+// single vertex/index stream and drawn with one call per paint change
+pub(crate) struct ShapeBatch {
+    program: WebGlProgram,
+    vao: WebGlVertexArrayObject,
+    vertex_buffer: WebGlBuffer,
+    index_buffer: WebGlBuffer,
+    matrix_location: Option<WebGlUniformLocation>,
+    paint_mode_location: Option<WebGlUniformLocation>,
+    gradient_kind_location: Option<WebGlUniformLocation>,
+    gradient_points_location: Option<WebGlUniformLocation>,
+    gradient_params_location: Option<WebGlUniformLocation>,
+    spread_mode_location: Option<WebGlUniformLocation>,
+    stop_count_location: Option<WebGlUniformLocation>,
+    stop_offsets_location: Option<WebGlUniformLocation>,
+    stop_colors_location: Option<WebGlUniformLocation>,
+    texture_location: Option<WebGlUniformLocation>,
+    texture_size_location: Option<WebGlUniformLocation>,
+    repeat_location: Option<WebGlUniformLocation>,
+    texture_premultiplied_location: Option<WebGlUniformLocation>,
+    glyph_atlas_location: Option<WebGlUniformLocation>,
+    glyph_atlas_size_location: Option<WebGlUniformLocation>,
+    glyph_spread_location: Option<WebGlUniformLocation>,
+    blend_mode_location: Option<WebGlUniformLocation>,
+    backdrop_location: Option<WebGlUniformLocation>,
+    premultiplied_location: Option<WebGlUniformLocation>,
+    linear_location: Option<WebGlUniformLocation>,
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    commands: Vec<DrawCommand>,
+    // Current vertex color while emitting a shape
+    color: [f32; 4],
+    // Clip, composite operation, shadow and filter of the shapes queued from now on
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    shadow: Shadow,
+    filter: Rc<Filter>,
+    // Encoding of vertex colors and gradient stops
+    pipeline: ColorPipeline,
+    // Width in world units of the analytic antialiasing feather (one device pixel), 0 when off
+    feather: f32,
+}
+
+impl ShapeBatch {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<ShapeBatch, JsValue> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, SHAPE_VERTEX_SHADER_SRC)?;
+        let fragment_src = expand_shader(SHAPE_FRAGMENT_SHADER_SRC);
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, &fragment_src)?;
+        let program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        // Create the VAO describing the interleaved vertex layout
+        let vao = gl.create_vertex_array().ok_or("Failed to create shape vertex array")?;
+        gl.bind_vertex_array(Some(&vao));
+
+        let vertex_buffer = gl.create_buffer().ok_or("Failed to create shape vertex buffer")?;
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
+
+        let stride = (FLOATS_PER_VERTEX * std::mem::size_of::<f32>()) as i32;
+        let attributes = [("aPosition", 2, 0), ("aPaintCoord", 2, 2), ("aColor", 4, 4), ("aGlyph", 3, 8), ("aCoverage", 1, 11)];
+        for (name, size, offset) in attributes {
+            let location = gl.get_attrib_location(&program, name);
+            if location < 0 {
+                continue;
+            }
+            gl.vertex_attrib_pointer_with_i32(
+                location as u32,
+                size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                stride,
+                offset * std::mem::size_of::<f32>() as i32,
+            );
+            gl.enable_vertex_attrib_array(location as u32);
+        }
+
+        let index_buffer = gl.create_buffer().ok_or("Failed to create shape index buffer")?;
+        gl.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
+
+        gl.bind_vertex_array(None);
+
+        Ok(ShapeBatch {
+            matrix_location: gl.get_uniform_location(&program, "uMatrix"),
+            paint_mode_location: gl.get_uniform_location(&program, "uPaintMode"),
+            gradient_kind_location: gl.get_uniform_location(&program, "uGradientKind"),
+            gradient_points_location: gl.get_uniform_location(&program, "uGradientPoints"),
+            gradient_params_location: gl.get_uniform_location(&program, "uGradientParams"),
+            spread_mode_location: gl.get_uniform_location(&program, "uSpreadMode"),
+            stop_count_location: gl.get_uniform_location(&program, "uStopCount"),
+            stop_offsets_location: gl.get_uniform_location(&program, "uStopOffsets"),
+            stop_colors_location: gl.get_uniform_location(&program, "uStopColors"),
+            texture_location: gl.get_uniform_location(&program, "uTexture"),
+            texture_size_location: gl.get_uniform_location(&program, "uTextureSize"),
+            repeat_location: gl.get_uniform_location(&program, "uRepeat"),
+            texture_premultiplied_location: gl.get_uniform_location(&program, "uTexturePremultiplied"),
+            glyph_atlas_location: gl.get_uniform_location(&program, "uGlyphAtlas"),
+            glyph_atlas_size_location: gl.get_uniform_location(&program, "uGlyphAtlasSize"),
+            glyph_spread_location: gl.get_uniform_location(&program, "uGlyphSpread"),
+            blend_mode_location: gl.get_uniform_location(&program, "uBlendMode"),
+            backdrop_location: gl.get_uniform_location(&program, "uBackdrop"),
+            premultiplied_location: gl.get_uniform_location(&program, "uPremultiplied"),
+            linear_location: gl.get_uniform_location(&program, "uLinear"),
+            program,
+            vao,
+            vertex_buffer,
+            index_buffer,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            commands: Vec::new(),
+            color: [1.0, 1.0, 1.0, 1.0],
+            clip: Rc::new(Clip::default()),
+            composite: CompositeOperation::SourceOver,
+            shadow: Shadow::default(),
+            filter: Rc::new(Filter::default()),
+            pipeline: ColorPipeline::default(),
+            feather: 0.0,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    // Clip the shapes queued after this call
+    pub(crate) fn set_clip(&mut self, clip: Rc<Clip>) {
+        self.clip = clip;
+    }
+
+    // Composite operation of the shapes queued after this call
+    pub(crate) fn set_composite(&mut self, composite: CompositeOperation) {
+        self.composite = composite;
+    }
+
+    // Shadow of the shapes queued after this call
+    pub(crate) fn set_shadow(&mut self, shadow: Shadow) {
+        self.shadow = shadow;
+    }
+
+    // Filter of the shapes queued after this call
+    pub(crate) fn set_filter(&mut self, filter: Rc<Filter>) {
+        self.filter = filter;
+    }
+
+    // Feather the edges of the fills and strokes queued after this call over `width` world
+    // units, one device pixel at the current zoom. 0 turns analytic antialiasing off
+    pub(crate) fn set_feather(&mut self, width: f32) {
+        self.feather = width.max(0.0);
+    }
+
+    // Color pipeline of the shapes queued after this call
+    pub(crate) fn set_pipeline(&mut self, pipeline: ColorPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    // Start a shape with the given paint, reusing the current draw call when possible.
+    // Shapes blended against a backdrop copy each get their own call so they see each other,
+    // and so do shapes with a shadow, which falls over the shapes drawn before, and filtered
+    // shapes, which are each filtered on their own
+    fn begin(&mut self, paint: &Paint) {
+        let reuse = self.commands.last().is_some_and(|command| {
+            command.fill.is_none()
+                && command.paint.batches_with(paint)
+                && Rc::ptr_eq(&command.clip, &self.clip)
+                && command.composite == self.composite
+                && !self.composite.reads_backdrop()
+                && command.shadow.is_none()
+                && !self.shadow.is_visible()
+                && command.filter.is_none()
+                && self.filter.is_none()
+        });
+        if !reuse {
+            self.push_command(paint);
+        }
+        self.color = self.pipeline.encode(paint.vertex_color());
+    }
+
+    fn push_command(&mut self, paint: &Paint) {
+        self.commands.push(DrawCommand {
+            paint: paint.clone(),
+            first_index: self.indices.len(),
+            clip: self.clip.clone(),
+            composite: self.composite,
+            bounds: [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY],
+            fill: None,
+            shadow: Some(self.shadow).filter(Shadow::is_visible),
+            filter: Some(self.filter.clone()).filter(|filter| !filter.is_none()),
+        });
+    }
+
+    // Append a vertex and return its index
+    fn vertex(&mut self, x: f32, y: f32) -> u32 {
+        self.vertex_with_paint_coord(x, y, x, y)
+    }
+
+    fn vertex_with_paint_coord(&mut self, x: f32, y: f32, u: f32, v: f32) -> u32 {
+        self.push_vertex([x, y, u, v], NO_GLYPH, 1.0)
+    }
+
+    // Vertex of a feathered edge
+    fn vertex_with_coverage(&mut self, (x, y): (f32, f32), coverage: f32) -> u32 {
+        self.push_vertex([x, y, x, y], NO_GLYPH, coverage)
+    }
+
+    fn push_vertex(&mut self, position: [f32; 4], glyph: [f32; 3], coverage: f32) -> u32 {
+        let index = (self.vertices.len() / FLOATS_PER_VERTEX) as u32;
+        if let Some(command) = self.commands.last_mut() {
+            let [x, y, ..] = position;
+            let b = command.bounds;
+            command.bounds = [b[0].min(x), b[1].min(y), b[2].max(x), b[3].max(y)];
+        }
+        self.vertices.extend_from_slice(&position);
+        self.vertices.extend_from_slice(&self.color);
+        self.vertices.extend_from_slice(&glyph);
+        self.vertices.push(coverage);
+        index
+    }
+
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    fn quad(&mut self, a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)) {
+        let ia = self.vertex(a.0, a.1);
+        let ib = self.vertex(b.0, b.1);
+        let ic = self.vertex(c.0, c.1);
+        let id = self.vertex(d.0, d.1);
+        self.triangle(ia, ib, ic);
+        self.triangle(ia, ic, id);
+    }
+
+    pub(crate) fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, paint: &Paint) {
+        if self.feather > 0.0 {
+            self.fill_convex(&[(x, y), (x + width, y), (x + width, y + height), (x, y + height)], paint);
+            return;
+        }
+
+        self.begin(paint);
+        self.quad((x, y), (x + width, y), (x + width, y + height), (x, y + height));
+    }
+
+    // Draw the source rectangle of a texture (in texels) into the destination rectangle
+    pub(crate) fn draw_image(&mut self, texture: &Rc<Texture>, source: [f32; 4], destination: [f32; 4]) {
+        self.begin(&Paint::Image(texture.clone()));
+
+        let tw = texture.width() as f32;
+        let th = texture.height() as f32;
+        let (u0, v0) = (source[0] / tw, source[1] / th);
+        let (u1, v1) = ((source[0] + source[2]) / tw, (source[1] + source[3]) / th);
+        let [x, y, w, h] = destination;
+
+        let a = self.vertex_with_paint_coord(x, y, u0, v0);
+        let b = self.vertex_with_paint_coord(x + w, y, u1, v0);
+        let c = self.vertex_with_paint_coord(x + w, y + h, u1, v1);
+        let d = self.vertex_with_paint_coord(x, y + h, u0, v1);
+        self.triangle(a, b, c);
+        self.triangle(a, c, d);
+    }
+
+    // Queue a text glyph: its world rect, its rect in the glyph atlas (texels) and the
+    // coverage mode, 0 to fill or the stroke half width in atlas pixels
+    pub(crate) fn glyph(&mut self, rect: [f32; 4], texels: [f32; 4], mode: f32, paint: &Paint) {
+        self.begin(paint);
+
+        let [x, y, w, h] = rect;
+        let [tx, ty, tw, th] = texels;
+        let a = self.push_vertex([x, y, x, y], [tx, ty, mode], 1.0);
+        let b = self.push_vertex([x + w, y, x + w, y], [tx + tw, ty, mode], 1.0);
+        let c = self.push_vertex([x + w, y + h, x + w, y + h], [tx + tw, ty + th, mode], 1.0);
+        let d = self.push_vertex([x, y + h, x, y + h], [tx, ty + th, mode], 1.0);
+        self.triangle(a, b, c);
+        self.triangle(a, c, d);
+    }
+
+    // Fill a convex polygon as a triangle fan
+    pub(crate) fn fill_convex(&mut self, points: &[(f32, f32)], paint: &Paint) {
+        if points.len() < 3 {
+            return;
+        }
+
+        self.begin(paint);
+        if self.feather > 0.0 {
+            self.fill_convex_feathered(points);
+            return;
+        }
+
+        let first = self.vertex(points[0].0, points[0].1);
+        let mut previous = self.vertex(points[1].0, points[1].1);
+        for point in &points[2..] {
+            let current = self.vertex(point.0, point.1);
+            self.triangle(first, previous, current);
+            previous = current;
+        }
+    }
+
+    // Draw triangles with a straight-alpha sRGB color per vertex, as tessellated SVG documents
+    pub(crate) fn fill_mesh(&mut self, positions: &[(f32, f32)], colors: &[[f32; 4]], indices: &[u32]) {
+        if indices.is_empty() {
+            return;
+        }
+
+        self.begin(&Paint::Color([1.0, 1.0, 1.0, 1.0]));
+        let base = (self.vertices.len() / FLOATS_PER_VERTEX) as u32;
+        for (position, color) in positions.iter().zip(colors) {
+            self.color = self.pipeline.encode(*color);
+            self.vertex(position.0, position.1);
+        }
+        self.indices.extend(indices.iter().map(|index| base + index));
+    }
+
+    // Convex fill whose edges fade out across the feather: an inner ring inset by half the
+    // feather at full coverage, an outer ring outset by half at none
+    fn fill_convex_feathered(&mut self, points: &[(f32, f32)]) {
+        let points = distinct_points(points, true);
+        let count = points.len();
+        if count < 3 {
+            return;
+        }
+
+        // Outward is to the right of the edges of a positive area polygon
+        let area: f32 = (0..count)
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % count]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum();
+        let side = if area > 0.0 { -1.0 } else { 1.0 };
+        let half = self.feather * 0.5;
+
+        let mut ring = Vec::with_capacity(count);
+        for i in 0..count {
+            let point = points[i];
+            let m = miter(normal(points[(i + count - 1) % count], point), normal(point, points[(i + 1) % count]), FEATHER_MITER_LIMIT);
+            let inner = self.vertex_with_coverage((point.0 - m.0 * half * side, point.1 - m.1 * half * side), 1.0);
+            let outer = self.vertex_with_coverage((point.0 + m.0 * half * side, point.1 + m.1 * half * side), 0.0);
+            ring.push((inner, outer));
+        }
+
+        for i in 1..count - 1 {
+            self.triangle(ring[0].0, ring[i].0, ring[i + 1].0);
+        }
+        for i in 0..count {
+            let (inner, outer) = ring[i];
+            let (next_inner, next_outer) = ring[(i + 1) % count];
+            self.triangle(inner, outer, next_outer);
+            self.triangle(inner, next_outer, next_inner);
+        }
+    }
+
+    // Fill arbitrary (concave, self-intersecting, holed) contours with a winding rule through
+    // the stencil buffer. Needs a context created with a stencil buffer
+    pub(crate) fn fill_path(&mut self, contours: &[Contour], rule: FillRule, paint: &Paint) {
+        let Some([x0, y0, x1, y1]) = path::bounds(contours) else {
+            return;
+        };
+        let fans = path::stencil_fans(contours);
+        if fans.is_empty() {
+            return;
+        }
+
+        self.push_command(paint);
+        self.color = self.pipeline.encode(paint.vertex_color());
+
+        for triangle in fans.chunks_exact(6) {
+            let a = self.vertex(triangle[0], triangle[1]);
+            let b = self.vertex(triangle[2], triangle[3]);
+            let c = self.vertex(triangle[4], triangle[5]);
+            self.triangle(a, b, c);
+        }
+
+        // Strips across the contours fading out half a feather on each side. Only the half
+        // outside the path shows, the stencil test leaves out the inside
+        let fringe_index = self.indices.len();
+        if self.feather > 0.0 {
+            let half = self.feather * 0.5;
+            for contour in contours {
+                let points = distinct_points(&contour.points, true);
+                let count = points.len();
+                if count < 3 {
+                    continue;
+                }
+
+                let mut strip = Vec::with_capacity(count);
+                for i in 0..count {
+                    let point = points[i];
+                    let m = miter(normal(points[(i + count - 1) % count], point), normal(point, points[(i + 1) % count]), FEATHER_MITER_LIMIT);
+                    strip.push([
+                        self.vertex_with_coverage((point.0 - m.0 * half, point.1 - m.1 * half), 0.0),
+                        self.vertex_with_coverage(point, 0.5),
+                        self.vertex_with_coverage((point.0 + m.0 * half, point.1 + m.1 * half), 0.0),
+                    ]);
+                }
+                for i in 0..count {
+                    let (row, next) = (strip[i], strip[(i + 1) % count]);
+                    for j in 0..2 {
+                        self.triangle(row[j], row[j + 1], next[j + 1]);
+                        self.triangle(row[j], next[j + 1], next[j]);
+                    }
+                }
+            }
+        }
+
+        let cover_index = self.indices.len();
+        self.quad((x0, y0), (x1, y0), (x1, y1), (x0, y1));
+        if let Some(command) = self.commands.last_mut() {
+            command.fill = Some(PathFill { rule, fringe_index, cover_index });
+        }
+    }
+
+    // Stroke a polyline with miter joins (clamped at the miter limit) and butt caps, as one
+    // strip of two rows along the path so that no pixel is covered twice and translucent
+    // strokes stay even across the joins
+    pub(crate) fn stroke_polyline(&mut self, points: &[(f32, f32)], closed: bool, width: f32, paint: &Paint) {
+        let path = distinct_points(points, closed);
+        if path.len() < 2 || width <= 0.0 {
+            return;
+        }
+
+        self.begin(paint);
+        if self.feather > 0.0 {
+            self.stroke_polyline_feathered(&path, closed, width);
+            return;
+        }
+
+        let half = width * 0.5;
+        let mut columns = stroke_directions(&path, closed);
+        if closed {
+            columns.push(columns[0]);
+        }
+
+        let mut previous: Option<[u32; 2]> = None;
+        for ((x, y), (mx, my)) in columns {
+            let column = [
+                self.vertex(x + mx * half, y + my * half),
+                self.vertex(x - mx * half, y - my * half),
+            ];
+            if let Some(previous) = previous {
+                self.triangle(previous[0], column[0], column[1]);
+                self.triangle(previous[0], column[1], previous[1]);
+            }
+            previous = Some(column);
+        }
+    }
+
+    // Stroke as a strip of four rows along the path: the outer rows half a feather beyond the
+    // edges at no coverage, the inner rows half a feather within at full coverage. Lines thinner
+    // than the feather are widened to it and fade instead. Joins are clamped miters
+    fn stroke_polyline_feathered(&mut self, path: &[(f32, f32)], closed: bool, width: f32) {
+        let feather = self.feather;
+        let (width, coverage) = if width < feather { (feather, width / feather) } else { (width, 1.0) };
+        let half = width * 0.5;
+        let rows = [(half + feather * 0.5, 0.0), (half - feather * 0.5, coverage)];
+        let rows = [rows[0], rows[1], (-rows[1].0, coverage), (-rows[0].0, 0.0)];
+
+        // Columns of the strip: a point, its offset direction and whether it is a cap fringe
+        type Column = ((f32, f32), (f32, f32), bool);
+        let count = path.len();
+        let mut columns: Vec<Column> = stroke_directions(path, closed)
+            .into_iter()
+            .map(|(point, direction)| (point, direction, false))
+            .collect();
+
+        if closed {
+            columns.push(columns[0]);
+        } else {
+            // Butt caps fade across the feather too: pull the ends in by half of it (or half
+            // the segment) and add a transparent column half a feather beyond them. The far
+            // end goes first so inserting the near cap does not shift its index
+            for (end, inner) in [(count - 1, count - 2), (0, 1)] {
+                let (point, direction, _) = columns[end];
+                let (dx, dy) = (path[inner].0 - point.0, path[inner].1 - point.1);
+                let length = (dx * dx + dy * dy).sqrt().max(1e-12);
+                let (tx, ty) = (dx / length, dy / length);
+                let shift = (feather * 0.5).min(length * 0.5);
+                columns[end].0 = (point.0 + tx * shift, point.1 + ty * shift);
+                let cap = ((point.0 - tx * feather * 0.5, point.1 - ty * feather * 0.5), direction, true);
+                if end == 0 {
+                    columns.insert(0, cap);
+                } else {
+                    columns.push(cap);
+                }
+            }
+        }
+
+        let mut previous: Option<[u32; 4]> = None;
+        for ((x, y), (mx, my), cap) in columns {
+            let mut column = [0; 4];
+            for (index, (offset, row_coverage)) in rows.iter().enumerate() {
+                let row_coverage = if cap { 0.0 } else { *row_coverage };
+                column[index] = self.vertex_with_coverage((x + mx * offset, y + my * offset), row_coverage);
+            }
+            if let Some(previous) = previous {
+                for j in 0..3 {
+                    self.triangle(previous[j], column[j], column[j + 1]);
+                    self.triangle(previous[j], column[j + 1], previous[j + 1]);
+                }
+            }
+            previous = Some(column);
+        }
+    }
+
+    // Upload the batched geometry and draw it, then reset the batch
+    // Draw and clear the queued shapes. Text needs the glyph atlas texture with its size and
+    // distance spread in pixels
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn flush(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        matrix: &[f32; 16],
+        glyph_atlas: Option<(&WebGlTexture, f32, f32)>,
+        clipper: &mut Clipper,
+        compositor: &mut Compositor,
+        shadows: &mut ShadowRenderer,
+        filters: &mut FilterRenderer,
+    ) -> Result<(), JsValue> {
+        if self.indices.is_empty() {
+            self.commands.clear();
+            self.vertices.clear();
+            return Ok(());
+        }
+
+        gl.use_program(Some(&self.program));
+        gl.bind_vertex_array(Some(&self.vao));
+
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.vertex_buffer));
+        let vertex_array = Float32Array::from(&self.vertices[..]);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &vertex_array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+
+        gl.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.index_buffer));
+        let index_array = Uint32Array::from(&self.indices[..]);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+            &index_array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+
+        gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, matrix);
+
+        // The glyph atlas lives on texture unit 1, paints use unit 0
+        if let Some((texture, size, spread)) = glyph_atlas {
+            gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+            gl.uniform1i(self.glyph_atlas_location.as_ref(), 1);
+            gl.uniform2f(self.glyph_atlas_size_location.as_ref(), size, size);
+            gl.uniform1f(self.glyph_spread_location.as_ref(), spread);
+        }
+        gl.uniform1i(self.backdrop_location.as_ref(), BACKDROP_TEXTURE_UNIT as i32);
+        self.pipeline.set_uniforms(gl, self.premultiplied_location.as_ref(), self.linear_location.as_ref());
+
+        for (i, command) in self.commands.iter().enumerate() {
+            let end = self.commands.get(i + 1).map_or(self.indices.len(), |next| next.first_index);
+            let count = end - command.first_index;
+            if count == 0 {
+                continue;
+            }
+
+            // Filtered shapes are drawn into a layer with plain source-over blending, the filter
+            // and shadow run over it and the result is composited into the scene
+            if let Some(filter) = &command.filter {
+                filters.begin(gl, filter, command.shadow.as_ref(), command.bounds, matrix)?;
+                compositor.apply(gl, CompositeOperation::SourceOver);
+                gl.uniform1i(self.blend_mode_location.as_ref(), 0);
+                self.apply_paint(gl, &command.paint);
+                draw_command(gl, clipper, command, end);
+                filters.finish(gl, self.pipeline, command.composite, &command.clip, matrix, clipper, compositor)?;
+                gl.use_program(Some(&self.program));
+                gl.bind_vertex_array(Some(&self.vao));
+                continue;
+            }
+
+            // The shadow goes under the shapes: their coverage is drawn offscreen with plain
+            // source-over blending, then blurred and composited into the scene
+            if let Some(shadow) = &command.shadow {
+                shadows.begin(gl, shadow, command.bounds, matrix)?;
+                compositor.apply(gl, CompositeOperation::SourceOver);
+                gl.uniform1i(self.blend_mode_location.as_ref(), 0);
+                self.apply_paint(gl, &command.paint);
+                draw_command(gl, clipper, command, end);
+                shadows.finish(gl, shadow, self.pipeline, &command.clip, matrix, clipper, compositor);
+                gl.use_program(Some(&self.program));
+                gl.bind_vertex_array(Some(&self.vao));
+            }
+
+            // Redrawing the stencil mask switches to the clip program
+            if clipper.apply(gl, &command.clip, matrix) {
+                gl.use_program(Some(&self.program));
+                gl.bind_vertex_array(Some(&self.vao));
+            }
+
+            compositor.apply(gl, command.composite);
+            gl.uniform1i(self.blend_mode_location.as_ref(), command.composite.blend_mode());
+            if command.composite.reads_backdrop() {
+                compositor.copy_backdrop(gl, command.bounds, matrix)?;
+            }
+
+            self.apply_paint(gl, &command.paint);
+            draw_command(gl, clipper, command, end);
+        }
+
+        gl.bind_vertex_array(None);
+
+        self.vertices.clear();
+        self.indices.clear();
+        self.commands.clear();
+
+        Ok(())
+    }
+
+    // Set the paint uniforms for a draw call
+    fn apply_paint(&self, gl: &WebGl2RenderingContext, paint: &Paint) {
+        let gradient = match paint {
+            Paint::Color(_) => {
+                gl.uniform1i(self.paint_mode_location.as_ref(), 0);
+                return;
+            }
+            Paint::Image(texture) => {
+                gl.uniform1i(self.paint_mode_location.as_ref(), 2);
+                self.bind_texture(gl, texture, false, false);
+                return;
+            }
+            Paint::Pattern(pattern) => {
+                let texture = pattern.texture();
+                let repetition = pattern.repetition();
+                gl.uniform1i(self.paint_mode_location.as_ref(), 3);
+                gl.uniform2f(self.texture_size_location.as_ref(), texture.width() as f32, texture.height() as f32);
+                gl.uniform2f(
+                    self.repeat_location.as_ref(),
+                    if repetition.repeats_x() { 1.0 } else { 0.0 },
+                    if repetition.repeats_y() { 1.0 } else { 0.0 },
+                );
+                self.bind_texture(gl, texture, repetition.repeats_x(), repetition.repeats_y());
+                return;
+            }
+            Paint::Gradient(gradient) => gradient,
+        };
+
+        gl.uniform1i(self.paint_mode_location.as_ref(), 1);
+
+        let (kind, points, params) = match gradient.kind() {
+            GradientKind::Linear { x0, y0, x1, y1 } => (0, [x0, y0, x1, y1], [0.0, 0.0]),
+            GradientKind::Radial { x0, y0, r0, x1, y1, r1 } => (1, [x0, y0, x1, y1], [r0, r1]),
+            GradientKind::Conic { start_angle, x, y } => (2, [x, y, 0.0, 0.0], [start_angle, 0.0]),
+        };
+        gl.uniform1i(self.gradient_kind_location.as_ref(), kind);
+        gl.uniform4f(self.gradient_points_location.as_ref(), points[0], points[1], points[2], points[3]);
+        gl.uniform2f(self.gradient_params_location.as_ref(), params[0], params[1]);
+
+        let spread = match gradient.spread() {
+            SpreadMode::Pad => 0,
+            SpreadMode::Repeat => 1,
+            SpreadMode::Reflect => 2,
+        };
+        gl.uniform1i(self.spread_mode_location.as_ref(), spread);
+
+        let mut offsets = [0.0f32; MAX_GRADIENT_STOPS];
+        let mut colors = [0.0f32; MAX_GRADIENT_STOPS * 4];
+        for (i, stop) in gradient.stops().iter().enumerate() {
+            offsets[i] = stop.offset;
+            colors[i * 4..i * 4 + 4].copy_from_slice(&self.pipeline.encode(stop.color));
+        }
+        gl.uniform1i(self.stop_count_location.as_ref(), gradient.stops().len() as i32);
+        gl.uniform1fv_with_f32_array(self.stop_offsets_location.as_ref(), &offsets);
+        gl.uniform4fv_with_f32_array(self.stop_colors_location.as_ref(), &colors);
+    }
+
+    // Bind a texture on unit 0 with the wrap mode of the draw call
+    fn bind_texture(&self, gl: &WebGl2RenderingContext, texture: &Texture, repeat_x: bool, repeat_y: bool) {
+        let wrap = |repeat: bool| {
+            if repeat {
+                WebGl2RenderingContext::REPEAT as i32
+            } else {
+                WebGl2RenderingContext::CLAMP_TO_EDGE as i32
+            }
+        };
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture.texture()));
+        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, wrap(repeat_x));
+        gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, wrap(repeat_y));
+        gl.uniform1i(self.texture_location.as_ref(), 0);
+        gl.uniform1i(self.texture_premultiplied_location.as_ref(), texture.premultiplied() as i32);
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_program(Some(&self.program));
+        gl.delete_vertex_array(Some(&self.vao));
+        gl.delete_buffer(Some(&self.vertex_buffer));
+        gl.delete_buffer(Some(&self.index_buffer));
+    }
+}
+
+// Draw the indices of a command up to `end`, path fills through the stencil buffer
+fn draw_command(gl: &WebGl2RenderingContext, clipper: &Clipper, command: &DrawCommand, end: usize) {
+    match &command.fill {
+        None => draw_range(gl, command.first_index, end),
+        Some(fill) => {
+            clipper.begin_path_fill(gl, fill.rule);
+            draw_range(gl, command.first_index, fill.fringe_index);
+            if fill.cover_index > fill.fringe_index {
+                clipper.begin_path_fringe(gl);
+                draw_range(gl, fill.fringe_index, fill.cover_index);
+            }
+            clipper.begin_path_cover(gl);
+            draw_range(gl, fill.cover_index, end);
+            clipper.end_path_fill(gl);
+        }
+    }
+}
+
+// Draw the triangles of an index range of the bound element buffer
+fn draw_range(gl: &WebGl2RenderingContext, start: usize, end: usize) {
+    if end > start {
+        gl.draw_elements_with_i32(
+            WebGl2RenderingContext::TRIANGLES,
+            (end - start) as i32,
+            WebGl2RenderingContext::UNSIGNED_INT,
+            (start * std::mem::size_of::<u32>()) as i32,
+        );
+    }
+}
+
+// Number of segments needed to keep a circle of the given radius (in device
+// pixels) within the curve tolerance
+pub(crate) fn segments_for_radius(radius: f32) -> usize {
+    if radius <= CURVE_TOLERANCE {
+        return 8;
+    }
+
+    let angle = 2.0 * (1.0 - CURVE_TOLERANCE / radius).acos();
+    ((2.0 * std::f32::consts::PI / angle).ceil() as usize).clamp(8, 512)
+}
+
+// Points of a circle approximated with the given number of segments
+pub(crate) fn circle_points(x: f32, y: f32, radius: f32, segments: usize) -> Vec<(f32, f32)> {
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 * 2.0 * std::f32::consts::PI / segments as f32;
+            (x + radius * angle.cos(), y + radius * angle.sin())
+        })
+        .collect()
+}
+
+// The points without consecutive repeats, which have no direction, nor the closing point of a
+// closed contour
+fn distinct_points(points: &[(f32, f32)], closed: bool) -> Vec<(f32, f32)> {
+    let mut distinct: Vec<(f32, f32)> = Vec::with_capacity(points.len());
+    for point in points {
+        if distinct.last().is_none_or(|last| (last.0 - point.0).abs() > 1e-6 || (last.1 - point.1).abs() > 1e-6) {
+            distinct.push(*point);
+        }
+    }
+    if closed && distinct.len() > 2 && distinct.first() == distinct.last() {
+        distinct.pop();
+    }
+    distinct
+}
+
+// Every point of a stroke with the direction its edges are offset along: the clamped miter
+// at joins and the segment normal at open ends
+fn stroke_directions(path: &[(f32, f32)], closed: bool) -> Vec<((f32, f32), (f32, f32))> {
+    let count = path.len();
+    (0..count)
+        .map(|i| {
+            let point = path[i];
+            let direction = if closed || (i > 0 && i < count - 1) {
+                let n0 = normal(path[(i + count - 1) % count], point);
+                let n1 = normal(point, path[(i + 1) % count]);
+                miter(n0, n1, MITER_LIMIT)
+            } else if i == 0 {
+                normal(point, path[1])
+            } else {
+                normal(path[i - 1], point)
+            };
+            (point, direction)
+        })
+        .collect()
+}
+
+// Offset of a corner between edges with unit normals n0 and n1 that moves both edges by one
+// unit, no longer than `limit`
+pub(crate) fn miter(n0: (f32, f32), n1: (f32, f32), limit: f32) -> (f32, f32) {
+    let scale = 1.0 + n0.0 * n1.0 + n0.1 * n1.1;
+    let m = if scale > 1e-6 { ((n0.0 + n1.0) / scale, (n0.1 + n1.1) / scale) } else { (n1.1 * limit, -n1.0 * limit) };
+    let length = (m.0 * m.0 + m.1 * m.1).sqrt();
+    if length > limit {
+        (m.0 / length * limit, m.1 / length * limit)
+    } else {
+        m
+    }
+}
+
+// Unit normal to the left of the segment a -> b
+fn normal(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let dx = b.0 - a.0;
+    let dy = b.1 - a.1;
+    let length = (dx * dx + dy * dy).sqrt().max(1e-12);
+    (-dy / length, dx / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+
+    // Tessellation only, the GL handles are never used
+    fn batch() -> ShapeBatch {
+        ShapeBatch {
+            program: JsValue::NULL.unchecked_into(),
+            vao: JsValue::NULL.unchecked_into(),
+            vertex_buffer: JsValue::NULL.unchecked_into(),
+            index_buffer: JsValue::NULL.unchecked_into(),
+            matrix_location: None,
+            paint_mode_location: None,
+            gradient_kind_location: None,
+            gradient_points_location: None,
+            gradient_params_location: None,
+            spread_mode_location: None,
+            stop_count_location: None,
+            stop_offsets_location: None,
+            stop_colors_location: None,
+            texture_location: None,
+            texture_size_location: None,
+            repeat_location: None,
+            texture_premultiplied_location: None,
+            glyph_atlas_location: None,
+            glyph_atlas_size_location: None,
+            glyph_spread_location: None,
+            blend_mode_location: None,
+            backdrop_location: None,
+            premultiplied_location: None,
+            linear_location: None,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            commands: Vec::new(),
+            clip: Rc::new(Clip::default()),
+            composite: CompositeOperation::default(),
+            feather: 0.0,
+            shadow: Shadow::default(),
+            filter: Rc::new(Filter::default()),
+            pipeline: ColorPipeline::default(),
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    // Total area of the batched triangles
+    fn covered_area(batch: &ShapeBatch) -> f32 {
+        let point = |index: u32| {
+            let offset = index as usize * FLOATS_PER_VERTEX;
+            (batch.vertices[offset], batch.vertices[offset + 1])
+        };
+        batch
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let (a, b, c) = (point(triangle[0]), point(triangle[1]), point(triangle[2]));
+                ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() * 0.5
+            })
+            .sum()
+    }
+
+    // Area weighted by the coverage of the vertices, which is linear across each triangle
+    fn coverage_area(batch: &ShapeBatch) -> f32 {
+        let vertex = |index: u32| &batch.vertices[index as usize * FLOATS_PER_VERTEX..(index as usize + 1) * FLOATS_PER_VERTEX];
+        batch
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| vertex(triangle[i]));
+                let area = ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() * 0.5;
+                area * (a[FLOATS_PER_VERTEX - 1] + b[FLOATS_PER_VERTEX - 1] + c[FLOATS_PER_VERTEX - 1]) / 3.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn colors_share_a_draw_call_and_gradients_do_not() {
+        let mut batch = batch();
+        batch.fill_rect(0.0, 0.0, 10.0, 10.0, &Paint::Color([1.0, 0.0, 0.0, 1.0]));
+        batch.fill_rect(20.0, 0.0, 10.0, 10.0, &Paint::Color([0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(batch.commands.len(), 1);
+
+        let gradient = Paint::Gradient(Gradient::linear(0.0, 0.0, 10.0, 0.0));
+        batch.fill_rect(40.0, 0.0, 10.0, 10.0, &gradient);
+        batch.fill_rect(60.0, 0.0, 10.0, 10.0, &gradient);
+        batch.fill_rect(80.0, 0.0, 10.0, 10.0, &Paint::Color([0.0, 1.0, 0.0, 1.0]));
+        assert_eq!(batch.commands.len(), 3);
+        assert_eq!(batch.commands[1].first_index, 12);
+
+        // Gradient vertices are white so the paint is not tinted
+        assert_eq!(&batch.vertices[2 * 4 * FLOATS_PER_VERTEX + 4..2 * 4 * FLOATS_PER_VERTEX + 8], &[1.0; 4]);
+    }
+
+    #[test]
+    fn shadowed_and_filtered_shapes_get_their_own_draw_calls() {
+        let mut batch = batch();
+        let paint = Paint::Color([1.0, 0.0, 0.0, 1.0]);
+        batch.set_shadow(Shadow {
+            color: [0.0, 0.0, 0.0, 0.5],
+            blur: 4.0,
+            ..Shadow::default()
+        });
+        batch.fill_rect(0.0, 0.0, 10.0, 10.0, &paint);
+        batch.fill_rect(20.0, 0.0, 10.0, 10.0, &paint);
+        assert_eq!(batch.commands.len(), 2);
+        assert!(batch.commands.iter().all(|command| command.shadow.is_some()));
+
+        // An invisible shadow batches like no shadow at all
+        batch.set_shadow(Shadow::default());
+        batch.fill_rect(40.0, 0.0, 10.0, 10.0, &paint);
+        batch.fill_rect(60.0, 0.0, 10.0, 10.0, &paint);
+        assert_eq!(batch.commands.len(), 3);
+        assert_eq!(batch.commands[2].shadow, None);
+
+        // Filtered shapes are drawn into a layer of their own as well
+        batch.set_filter(Rc::new(Filter::parse("blur(2px)").unwrap()));
+        batch.fill_rect(80.0, 0.0, 10.0, 10.0, &paint);
+        batch.set_filter(Rc::new(Filter::default()));
+        batch.fill_rect(100.0, 0.0, 10.0, 10.0, &paint);
+        assert_eq!(batch.commands.len(), 5);
+        assert!(batch.commands[3].filter.is_some() && batch.commands[4].filter.is_none());
+    }
+
+    #[test]
+    fn feathered_shapes_keep_their_coverage() {
+        let paint = Paint::Color([1.0; 4]);
+        let mut rect = batch();
+        rect.set_feather(1.0);
+        rect.fill_rect(0.0, 0.0, 30.0, 40.0, &paint);
+        // The feather straddles the edges, only the corners add a little
+        assert!((coverage_area(&rect) - 1200.0).abs() < 1.0);
+        assert!(covered_area(&rect) > 1200.0);
+
+        let mut line = batch();
+        line.set_feather(1.0);
+        line.stroke_polyline(&[(0.0, 0.0), (50.0, 0.0)], false, 4.0, &paint);
+        assert!((coverage_area(&line) - 200.0).abs() < 1.0);
+
+        // Hairlines are widened to the feather and fade to the coverage of their width
+        let mut hairline = batch();
+        hairline.set_feather(1.0);
+        hairline.stroke_polyline(&[(0.0, 0.0), (50.0, 0.0)], false, 0.25, &paint);
+        assert!((coverage_area(&hairline) - 12.5).abs() < 0.5);
+    }
+
+    #[test]
+    fn straight_strokes_cover_length_times_width() {
+        let mut line = batch();
+        let paint = Paint::Color([1.0; 4]);
+        line.stroke_polyline(&[(0.0, 0.0), (50.0, 0.0), (50.0, 0.0), (120.0, 0.0)], false, 4.0, &paint);
+        assert!((covered_area(&line) - 480.0).abs() < 1e-3);
+
+        // Degenerate input draws nothing
+        let mut empty = batch();
+        empty.stroke_polyline(&[(5.0, 5.0), (5.0, 5.0)], false, 4.0, &paint);
+        empty.stroke_polyline(&[(0.0, 0.0), (10.0, 0.0)], false, 0.0, &paint);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn joins_do_not_overlap() {
+        // The triangles of a mitered corner tile the stroke exactly once, so their summed area
+        // is the centerline length times the width
+        let paint = Paint::Color([1.0, 0.0, 0.0, 0.5]);
+        let mut corner = batch();
+        corner.stroke_polyline(&[(0.0, 0.0), (50.0, 0.0), (50.0, 50.0)], false, 4.0, &paint);
+        assert!((covered_area(&corner) - 400.0).abs() < 1e-3);
+
+        let mut square = batch();
+        square.stroke_polyline(&[(0.0, 0.0), (50.0, 0.0), (50.0, 50.0), (0.0, 50.0)], true, 4.0, &paint);
+        assert!((covered_area(&square) - (54.0 * 54.0 - 46.0 * 46.0)).abs() < 1e-2);
+        assert_eq!(square.indices.len(), 4 * 6);
+    }
+
+    #[test]
+    fn circle_segments_stay_within_the_tolerance() {
+        for radius in [1.0, 10.0, 100.0, 1000.0] {
+            let segments = segments_for_radius(radius);
+            // Distance from the chord midpoint to the arc
+            let sagitta = radius * (1.0 - (std::f32::consts::PI / segments as f32).cos());
+            assert!(sagitta <= CURVE_TOLERANCE + 1e-4 || segments == 512, "radius {}", radius);
+        }
+        assert_eq!(segments_for_radius(0.1), 8);
+    }
+}
@@ -0,0 +1,236 @@
+use wasm_bindgen::prelude::*;
+
+// Zoom limits to keep the projection well-conditioned
+const MIN_ZOOM: f32 = 0.01;
+const MAX_ZOOM: f32 = 1000.0;
+
+// How world coordinates map onto the viewport at zoom 1
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CoordinateSystem {
+    // CSS pixels with the origin at the top-left and y pointing down, like CanvasRenderingContext2D
+    TopLeft,
+    // Pixels with the origin at the center of the viewport and y pointing up
+    Center,
+}
+
+// 2D camera with a pixel-space orthographic projection.
+// World units are CSS pixels at zoom 1. The camera position is the world point shown at
+// the anchor of the coordinate system: the top-left corner for `TopLeft` (the default)
+// and the center of the viewport for `Center`.
+#[wasm_bindgen]
+#[derive(Copy, Clone)]
+pub struct Camera2D {
+    pub x: f32,
+    pub y: f32,
+    // Private so every change goes through set_zoom's limits
+    zoom: f32,
+    pub rotation: f32,
+    pub coordinate_system: CoordinateSystem,
+    width: f32,
+    height: f32,
+}
+
+#[wasm_bindgen]
+impl Camera2D {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: f32, height: f32) -> Camera2D {
+        Camera2D {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+            rotation: 0.0,
+            coordinate_system: CoordinateSystem::TopLeft,
+            width: width.max(1.0),
+            height: height.max(1.0),
+        }
+    }
+
+    // Viewport size in pixels the projection is derived from
+    #[wasm_bindgen]
+    pub fn set_viewport(&mut self, width: f32, height: f32) {
+        self.width = width.max(1.0);
+        self.height = height.max(1.0);
+    }
+
+    #[wasm_bindgen]
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    // NaN is ignored, clamp would pass it through
+    #[wasm_bindgen]
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if !zoom.is_nan() {
+            self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    // Move the camera so the content follows a drag of (dx, dy) screen pixels
+    #[wasm_bindgen]
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let (x0, y0) = self.screen_to_world_point(0.0, 0.0);
+        let (x1, y1) = self.screen_to_world_point(dx, dy);
+        self.x -= x1 - x0;
+        self.y -= y1 - y0;
+    }
+
+    // Multiply the zoom while keeping the world point under (screen_x, screen_y) in place
+    #[wasm_bindgen]
+    pub fn zoom_at(&mut self, factor: f32, screen_x: f32, screen_y: f32) {
+        let (before_x, before_y) = self.screen_to_world_point(screen_x, screen_y);
+        self.set_zoom(self.zoom * factor);
+        let (after_x, after_y) = self.screen_to_world_point(screen_x, screen_y);
+        self.x += before_x - after_x;
+        self.y += before_y - after_y;
+    }
+
+    // Convert a screen position (pixels, origin top-left, y down) to world coordinates
+    #[wasm_bindgen]
+    pub fn screen_to_world(&self, screen_x: f32, screen_y: f32) -> Vec<f32> {
+        let (x, y) = self.screen_to_world_point(screen_x, screen_y);
+        vec![x, y]
+    }
+
+    // Convert a world position to screen coordinates (pixels, origin top-left, y down)
+    #[wasm_bindgen]
+    pub fn world_to_screen(&self, world_x: f32, world_y: f32) -> Vec<f32> {
+        let (x, y) = self.world_to_screen_point(world_x, world_y);
+        vec![x, y]
+    }
+
+    // Column-major 4x4 matrix mapping world coordinates to clip space
+    #[wasm_bindgen]
+    pub fn view_projection(&self) -> Vec<f32> {
+        self.matrix().to_vec()
+    }
+}
+
+impl Camera2D {
+    // Screen position of the camera position and the direction of the world y axis on screen
+    fn anchor(&self) -> (f32, f32, f32) {
+        match self.coordinate_system {
+            CoordinateSystem::TopLeft => (0.0, 0.0, 1.0),
+            CoordinateSystem::Center => (self.width * 0.5, self.height * 0.5, -1.0),
+        }
+    }
+
+    pub(crate) fn world_to_screen_point(&self, world_x: f32, world_y: f32) -> (f32, f32) {
+        let (anchor_x, anchor_y, y_axis) = self.anchor();
+        let (sin, cos) = self.rotation.sin_cos();
+        let dx = world_x - self.x;
+        let dy = world_y - self.y;
+
+        // Undo the camera rotation, then scale into pixels
+        let rx = (dx * cos + dy * sin) * self.zoom;
+        let ry = (-dx * sin + dy * cos) * self.zoom;
+
+        (anchor_x + rx, anchor_y + y_axis * ry)
+    }
+
+    pub(crate) fn screen_to_world_point(&self, screen_x: f32, screen_y: f32) -> (f32, f32) {
+        let (anchor_x, anchor_y, y_axis) = self.anchor();
+        let (sin, cos) = self.rotation.sin_cos();
+        let rx = (screen_x - anchor_x) / self.zoom;
+        let ry = y_axis * (screen_y - anchor_y) / self.zoom;
+
+        (self.x + rx * cos - ry * sin, self.y + rx * sin + ry * cos)
+    }
+
+    pub(crate) fn matrix(&self) -> [f32; 16] {
+        let (anchor_x, anchor_y, y_axis) = self.anchor();
+        let (sin, cos) = self.rotation.sin_cos();
+        let sx = 2.0 * self.zoom / self.width;
+        let sy = -2.0 * y_axis * self.zoom / self.height;
+
+        // clip = pixels_to_clip * (anchor + scale * rotate(-rotation) * (world - position))
+        let m00 = sx * cos;
+        let m01 = sx * sin;
+        let m10 = -sy * sin;
+        let m11 = sy * cos;
+        let tx = 2.0 * anchor_x / self.width - 1.0;
+        let ty = 1.0 - 2.0 * anchor_y / self.height;
+
+        [
+            m00, m10, 0.0, 0.0,
+            m01, m11, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            tx - (m00 * self.x + m01 * self.y), ty - (m10 * self.x + m11 * self.y), 0.0, 1.0,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f32, f32), b: (f32, f32)) {
+        assert!((a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn camera(coordinate_system: CoordinateSystem) -> Camera2D {
+        let mut camera = Camera2D::new(800.0, 600.0);
+        camera.coordinate_system = coordinate_system;
+        camera.set_position(120.0, -40.0);
+        camera.set_zoom(2.5);
+        camera.rotation = 0.6;
+        camera
+    }
+
+    #[test]
+    fn screen_and_world_points_round_trip() {
+        for (coordinate_system, anchor) in [(CoordinateSystem::TopLeft, (0.0, 0.0)), (CoordinateSystem::Center, (400.0, 300.0))] {
+            let camera = camera(coordinate_system);
+            for &(x, y) in &[(0.0, 0.0), (400.0, 300.0), (799.0, 12.0), (-50.0, 700.0)] {
+                let (world_x, world_y) = camera.screen_to_world_point(x, y);
+                assert_close(camera.world_to_screen_point(world_x, world_y), (x, y));
+            }
+            // The camera position is shown at the anchor of the coordinate system
+            assert_close(camera.world_to_screen_point(120.0, -40.0), anchor);
+        }
+    }
+
+    #[test]
+    fn top_left_y_points_down() {
+        let camera = Camera2D::new(800.0, 600.0);
+        assert_close(camera.world_to_screen_point(10.0, 20.0), (10.0, 20.0));
+        let mut centered = camera;
+        centered.coordinate_system = CoordinateSystem::Center;
+        assert_close(centered.world_to_screen_point(10.0, 20.0), (410.0, 280.0));
+    }
+
+    #[test]
+    fn matrix_agrees_with_world_to_screen() {
+        for coordinate_system in [CoordinateSystem::TopLeft, CoordinateSystem::Center] {
+            let camera = camera(coordinate_system);
+            let m = camera.matrix();
+            let (x, y) = (75.0, 210.0);
+            let clip = (m[0] * x + m[4] * y + m[12], m[1] * x + m[5] * y + m[13]);
+            let (screen_x, screen_y) = camera.world_to_screen_point(x, y);
+            assert_close(clip, (screen_x / 400.0 - 1.0, 1.0 - screen_y / 300.0));
+        }
+    }
+
+    #[test]
+    fn zoom_at_keeps_the_point_under_the_cursor() {
+        let mut camera = camera(CoordinateSystem::TopLeft);
+        let before = camera.screen_to_world_point(650.0, 120.0);
+        camera.zoom_at(1.7, 650.0, 120.0);
+        assert_close(camera.screen_to_world_point(650.0, 120.0), before);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut camera = camera(CoordinateSystem::TopLeft);
+        camera.set_zoom(0.0);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        camera.zoom_at(1.0e9, 0.0, 0.0);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+}
@@ -1,22 +1,64 @@
-use js_sys::{Array, Float32Array, Object, Reflect, Uint16Array};
+use js_sys::{ArrayBuffer, Float32Array, Object, Reflect, Uint16Array};
 use wasm_bindgen::prelude::*;
-use web_sys::{GpuBuffer, GpuDevice};
+
+use crate::antialias::Antialiasing;
+use crate::batch::{miter, segments_for_radius, CURVE_TOLERANCE, FEATHER_MITER_LIMIT};
+use crate::camera::{Camera2D, CoordinateSystem};
+use crate::svg::SvgDocument;
+
+// web-sys only generates its WebGPU types with --cfg=web_sys_unstable_apis, which would move
+// every web-sys API the crate uses onto its unstable bindings. The canvas needs just these
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = GPUDevice)]
+    #[derive(Clone, Debug)]
+    pub type GpuDevice;
+    
+    #[wasm_bindgen(method, getter)]
+    fn queue(this: &GpuDevice) -> GpuQueue;
+    
+    #[wasm_bindgen(method, catch, js_name = createBuffer)]
+    fn create_buffer(this: &GpuDevice, descriptor: &Object) -> Result<GpuBuffer, JsValue>;
+    
+    #[wasm_bindgen(js_name = GPUQueue)]
+    type GpuQueue;
+    
+    // Offset and size are in bytes for an ArrayBuffer source
+    #[wasm_bindgen(method, catch, js_name = writeBuffer)]
+    fn write_buffer(
+        this: &GpuQueue,
+        buffer: &GpuBuffer,
+        offset: u32,
+        data: &ArrayBuffer,
+        data_offset: u32,
+        size: u32,
+    ) -> Result<(), JsValue>;
+    
+    #[wasm_bindgen(js_name = GPUBuffer)]
+    #[derive(Clone, Debug)]
+    pub type GpuBuffer;
+}
 
 // Constants for buffer usage flags
 const VERTEX_BUFFER: u32 = 1 << 3;
 const INDEX_BUFFER: u32 = 1 << 4;
+const UNIFORM_BUFFER: u32 = 1 << 6;
 const COPY_DST: u32 = 1 << 1;
 
 // Maximum vertices and indices
 const MAX_VERTICES: usize = 10000;
 const MAX_INDICES: usize = 15000;
 
+// Indices are u16, so every vertex of a frame must be addressable by one
+const _: () = assert!(MAX_VERTICES <= u16::MAX as usize + 1);
+
 // 2D Canvas-like Drawing Context
 pub struct Canvas2D {
     // WebGPU resources
     device: GpuDevice,
     vertex_buffer: GpuBuffer,
     index_buffer: GpuBuffer,
+    projection_buffer: GpuBuffer,
     
     // Dynamic geometry data
     vertices: Vec<f32>,
@@ -26,26 +68,44 @@ pub struct Canvas2D {
     vertex_count: usize,
     index_count: usize,
     current_color: [f32; 4],
+    
+    // Projection from CSS pixels to clip space
+    camera: Camera2D,
+    pixel_ratio: f32,
+    
+    // Edge antialiasing: MSAA is up to the render pipeline, analytic feathers the geometry
+    antialiasing: Antialiasing,
 }
 
 impl Canvas2D {
-    pub fn new(device: &GpuDevice) -> Result<Self, JsValue> {
+    // Width and height are the drawing size in CSS pixels
+    pub fn new(device: &GpuDevice, width: f32, height: f32) -> Result<Self, JsValue> {
         // Create vertex buffer (position, color)
         let vertex_buffer = Self::create_vertex_buffer(device, MAX_VERTICES * 6)?; // 2 for position, 4 for color
         
         // Create index buffer
         let index_buffer = Self::create_index_buffer(device, MAX_INDICES)?;
         
-        Ok(Self {
+        // Create projection uniform buffer (one 4x4 matrix)
+        let projection_buffer = Self::create_projection_buffer(device)?;
+        
+        let canvas = Self {
             device: device.clone(),
             vertex_buffer,
             index_buffer,
+            projection_buffer,
             vertices: Vec::with_capacity(MAX_VERTICES * 6),
             indices: Vec::with_capacity(MAX_INDICES),
             vertex_count: 0,
             index_count: 0,
             current_color: [1.0, 1.0, 1.0, 1.0], // White by default
-        })
+            camera: Camera2D::new(width, height),
+            pixel_ratio: 1.0,
+            antialiasing: Antialiasing::None,
+        };
+        canvas.write_projection()?;
+        
+        Ok(canvas)
     }
     
     // Create the vertex buffer
@@ -66,8 +126,7 @@ impl Canvas2D {
         // Set buffer label
         Reflect::set(&buffer_desc, &JsValue::from_str("label"), &JsValue::from_str("Canvas2D Vertex Buffer"))?;
         
-        let buffer = device.create_buffer(&buffer_desc);
-        Ok(buffer)
+        device.create_buffer(&buffer_desc)
     }
     
     // Create the index buffer
@@ -88,8 +147,86 @@ impl Canvas2D {
         // Set buffer label
         Reflect::set(&buffer_desc, &JsValue::from_str("label"), &JsValue::from_str("Canvas2D Index Buffer"))?;
         
-        let buffer = device.create_buffer(&buffer_desc);
-        Ok(buffer)
+        device.create_buffer(&buffer_desc)
+    }
+    
+    // Create the uniform buffer holding the projection matrix
+    fn create_projection_buffer(device: &GpuDevice) -> Result<GpuBuffer, JsValue> {
+        let buffer_desc = Object::new();
+        
+        // Set buffer size (16 floats)
+        let byte_size = (16 * std::mem::size_of::<f32>()) as f64;
+        Reflect::set(&buffer_desc, &JsValue::from_str("size"), &JsValue::from_f64(byte_size))?;
+        
+        // Set buffer usage
+        Reflect::set(
+            &buffer_desc, 
+            &JsValue::from_str("usage"), 
+            &JsValue::from_f64((UNIFORM_BUFFER | COPY_DST) as f64)
+        )?;
+        
+        // Set buffer label
+        Reflect::set(&buffer_desc, &JsValue::from_str("label"), &JsValue::from_str("Canvas2D Projection Buffer"))?;
+        
+        device.create_buffer(&buffer_desc)
+    }
+    
+    // Upload the current projection matrix
+    fn write_projection(&self) -> Result<(), JsValue> {
+        let matrix = self.camera.matrix();
+        let array = Float32Array::new_with_length(16);
+        array.copy_from(&matrix);
+        
+        self.device.queue().write_buffer(
+            &self.projection_buffer,
+            0,
+            &array.buffer(),
+            array.byte_offset(),
+            array.byte_length(),
+        )
+    }
+    
+    // Resize the drawing area. Geometry stays in CSS pixels, the pixel ratio only
+    // describes how large the swap chain is in device pixels
+    pub fn resize(&mut self, width: f32, height: f32, pixel_ratio: f32) -> Result<(), JsValue> {
+        self.camera.set_viewport(width, height);
+        self.pixel_ratio = pixel_ratio.max(0.01);
+        self.write_projection()
+    }
+    
+    // Choose between top-left/y-down CSS pixels (the default) and centered/y-up pixels
+    pub fn set_coordinate_system(&mut self, coordinate_system: CoordinateSystem) -> Result<(), JsValue> {
+        self.camera.coordinate_system = coordinate_system;
+        self.write_projection()
+    }
+    
+    // Device pixels per CSS pixel
+    pub fn pixel_ratio(&self) -> f32 {
+        self.pixel_ratio
+    }
+    
+    // Smooth the edges of the following shapes: "none", "msaa" with `samples` per pixel or
+    // "analytic" (a one device pixel feather fading to transparent)
+    pub fn set_antialiasing(&mut self, mode: &str, samples: Option<u32>) -> Result<(), JsValue> {
+        self.antialiasing = Antialiasing::parse(mode, samples)?;
+        Ok(())
+    }
+    
+    // Sample count to create the render pipeline and its color attachment with, 1 unless
+    // antialiasing with MSAA
+    pub fn sample_count(&self) -> u32 {
+        match self.antialiasing {
+            Antialiasing::Msaa(samples) => samples,
+            _ => 1,
+        }
+    }
+    
+    // Width of the analytic antialiasing feather in CSS pixels, 0 when off
+    fn feather(&self) -> f32 {
+        match self.antialiasing {
+            Antialiasing::Analytic => 1.0 / (self.camera.get_zoom() * self.pixel_ratio).max(1e-6),
+            _ => 0.0,
+        }
     }
     
     // Set the current drawing color
@@ -98,90 +235,331 @@ impl Canvas2D {
     }
     
     // Draw a rectangle
-    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
-        // Add vertices for rectangle (4 corners)
-        let base_vertex = self.vertex_count as u16;
-        
-        // Top-left
-        self.add_vertex(x, y);
-        
-        // Top-right
-        self.add_vertex(x + width, y);
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) -> Result<(), JsValue> {
+        self.fill_convex(&[(x, y), (x + width, y), (x + width, y + height), (x, y + height)])
+    }
+    
+    // Draw a circle
+    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32, segments: u32) -> Result<(), JsValue> {
+        let points: Vec<(f32, f32)> = (0..segments)
+            .map(|i| {
+                let angle = (i as f32) * 2.0 * std::f32::consts::PI / (segments as f32);
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .collect();
+        self.fill_convex(&points)
+    }
+    
+    // Draw a line with thickness
+    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32) -> Result<(), JsValue> {
+        // Calculate perpendicular direction
+        let dx = x2 - x1;
+        let dy = y2 - y1;
+        let length = (dx * dx + dy * dy).sqrt();
         
-        // Bottom-right
-        self.add_vertex(x + width, y + height);
+        if length < 0.0001 {
+            return Ok(()); // Too short to draw
+        }
         
-        // Bottom-left
-        self.add_vertex(x, y + height);
+        // Lines thinner than the feather are widened to it and fade instead
+        let feather = self.feather();
+        let alpha = self.current_color[3];
+        if thickness < feather {
+            self.current_color[3] *= thickness / feather;
+        }
+        let half = thickness.max(feather) * 0.5;
+        let (nx, ny) = (dy / length * half, -dx / length * half);
+        let result = self.fill_convex(&[(x1 + nx, y1 + ny), (x2 + nx, y2 + ny), (x2 - nx, y2 - ny), (x1 - nx, y1 - ny)]);
+        self.current_color[3] = alpha;
+        result
+    }
+    
+    // Fill a rectangle with rounded corners. Radii follow CanvasRenderingContext2D.roundRect:
+    // 1 value for all corners, 2 for top-left/bottom-right and top-right/bottom-left, 3 for
+    // top-left, top-right/bottom-left and bottom-right, or 4 clockwise from the top-left.
+    // Radii that do not fit are scaled down together
+    pub fn round_rect(&mut self, x: f32, y: f32, width: f32, height: f32, radii: &[f32]) -> Result<(), JsValue> {
+        let [top_left, top_right, bottom_right, bottom_left] = match radii {
+            [] => [0.0; 4],
+            [all] => [*all; 4],
+            [a, b] => [*a, *b, *a, *b],
+            [a, b, c] => [*a, *b, *c, *b],
+            [a, b, c, d] => [*a, *b, *c, *d],
+            _ => return Err(JsValue::from_str("Rounded rectangles take 1 to 4 corner radii")),
+        };
+        if radii.iter().any(|radius| *radius < 0.0 || !radius.is_finite()) {
+            return Err(JsValue::from_str("Corner radii must be non-negative numbers"));
+        }
         
-        // Add indices for two triangles
-        self.indices.push(base_vertex);
-        self.indices.push(base_vertex + 1);
-        self.indices.push(base_vertex + 2);
+        // Negative sizes draw from the other corner
+        let (x, width) = if width < 0.0 { (x + width, -width) } else { (x, width) };
+        let (y, height) = if height < 0.0 { (y + height, -height) } else { (y, height) };
+        if width == 0.0 || height == 0.0 {
+            return Ok(());
+        }
         
-        self.indices.push(base_vertex);
-        self.indices.push(base_vertex + 2);
-        self.indices.push(base_vertex + 3);
+        let scale = [
+            width / (top_left + top_right),
+            width / (bottom_left + bottom_right),
+            height / (top_left + bottom_left),
+            height / (top_right + bottom_right),
+        ]
+        .into_iter()
+        .filter(|scale| scale.is_finite())
+        .fold(1.0f32, f32::min);
+        
+        // Quarter arcs clockwise from the top-left corner, in y-down drawing coordinates
+        let corners = [
+            (x + top_left * scale, y + top_left * scale, top_left * scale, std::f32::consts::PI),
+            (x + width - top_right * scale, y + top_right * scale, top_right * scale, 1.5 * std::f32::consts::PI),
+            (x + width - bottom_right * scale, y + height - bottom_right * scale, bottom_right * scale, 0.0),
+            (x + bottom_left * scale, y + height - bottom_left * scale, bottom_left * scale, 0.5 * std::f32::consts::PI),
+        ];
+        let mut points: Vec<(f32, f32)> = Vec::new();
+        for (cx, cy, radius, start) in corners {
+            let segments = if radius > 0.0 { self.segments_for(radius, std::f32::consts::FRAC_PI_2) } else { 0 };
+            for i in 0..=segments {
+                let angle = start + std::f32::consts::FRAC_PI_2 * i as f32 / segments.max(1) as f32;
+                let point = (cx + radius * angle.cos(), cy + radius * angle.sin());
+                // Corners meet where the radii take up a whole side
+                if points.last().is_none_or(|last| (last.0 - point.0).abs() > 1e-4 || (last.1 - point.1).abs() > 1e-4) {
+                    points.push(point);
+                }
+            }
+        }
+        if points.len() > 1 && points.first().zip(points.last()).is_some_and(|(first, last)| {
+            (first.0 - last.0).abs() <= 1e-4 && (first.1 - last.1).abs() <= 1e-4
+        }) {
+            points.pop();
+        }
+        self.fill_convex(&points)
+    }
+    
+    // Fill an ellipse with radii (radius_x, radius_y), rotated by `rotation` radians
+    pub fn fill_ellipse(&mut self, x: f32, y: f32, radius_x: f32, radius_y: f32, rotation: f32) -> Result<(), JsValue> {
+        let (radius_x, radius_y) = (radius_x.abs(), radius_y.abs());
+        if radius_x == 0.0 || radius_y == 0.0 {
+            return Ok(());
+        }
         
-        self.index_count += 6;
+        let segments = self.segments_for(radius_x.max(radius_y), std::f32::consts::TAU);
+        let (sin, cos) = rotation.sin_cos();
+        let points: Vec<(f32, f32)> = (0..segments)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / segments as f32;
+                let (ex, ey) = (radius_x * angle.cos(), radius_y * angle.sin());
+                (x + ex * cos - ey * sin, y + ex * sin + ey * cos)
+            })
+            .collect();
+        self.fill_convex(&points)
     }
     
-    // Draw a circle
-    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32, segments: u32) {
-        let base_vertex = self.vertex_count as u16;
-        
-        // Center vertex
-        self.add_vertex(x, y);
-        
-        // Edge vertices
-        for i in 0..segments {
-            let angle = (i as f32) * 2.0 * std::f32::consts::PI / (segments as f32);
-            let vx = x + radius * angle.cos();
-            let vy = y + radius * angle.sin();
-            self.add_vertex(vx, vy);
+    // Stroke a circular arc from `start_angle` to `end_angle` (radians, clockwise on screen
+    // in the default coordinate system) with butt ends
+    pub fn stroke_arc(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        thickness: f32,
+    ) -> Result<(), JsValue> {
+        let half = thickness.abs() * 0.5;
+        let radius = radius.abs();
+        self.fill_band(x, y, (radius - half).max(0.0), radius + half, start_angle, end_angle - start_angle)
+    }
+    
+    // Fill a pie slice of a circle from `start_angle` to `end_angle` (radians)
+    pub fn fill_sector(&mut self, x: f32, y: f32, radius: f32, start_angle: f32, end_angle: f32) -> Result<(), JsValue> {
+        let radius = radius.abs();
+        let sweep = (end_angle - start_angle).clamp(-std::f32::consts::TAU, std::f32::consts::TAU);
+        if radius == 0.0 || sweep == 0.0 {
+            return Ok(());
         }
         
-        // Add indices for triangle fan
-        for i in 0..segments {
-            self.indices.push(base_vertex); // Center
-            self.indices.push(base_vertex + 1 + i as u16); // Current edge vertex
-            self.indices.push(base_vertex + 1 + ((i + 1) % segments) as u16); // Next edge vertex
-            self.index_count += 3;
+        let segments = self.segments_for(radius, sweep);
+        let mut points: Vec<(f32, f32)> = (0..=segments)
+            .map(|i| {
+                let angle = start_angle + sweep * i as f32 / segments as f32;
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .collect();
+        if sweep.abs() >= std::f32::consts::TAU {
+            points.pop();
+        } else {
+            // Slices wider than a half circle are not convex, but every slice can be fanned
+            // from its center
+            points.insert(0, (x, y));
         }
+        self.fill_convex(&points)
     }
     
-    // Draw a line with thickness
-    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32) {
-        let base_vertex = self.vertex_count as u16;
-        
-        // Calculate perpendicular direction
-        let dx = x2 - x1;
-        let dy = y2 - y1;
-        let length = (dx * dx + dy * dy).sqrt();
+    // Fill an annulus between two radii, e.g. a donut chart background
+    pub fn fill_ring(&mut self, x: f32, y: f32, inner_radius: f32, outer_radius: f32) -> Result<(), JsValue> {
+        let (inner, outer) = (inner_radius.abs().min(outer_radius.abs()), inner_radius.abs().max(outer_radius.abs()));
+        self.fill_band(x, y, inner, outer, 0.0, std::f32::consts::TAU)
+    }
+    
+    // Draw an SVG document with its top-left corner at (x, y), scaled uniformly. Its fills
+    // and strokes keep their own colors. Fails without drawing anything when the document does
+    // not fit in the remaining vertex and index space of the frame
+    pub fn draw_svg(&mut self, document: &SvgDocument, x: f32, y: f32, scale: f32) -> Result<(), JsValue> {
+        let tolerance = CURVE_TOLERANCE / (self.camera.get_zoom() * self.pixel_ratio).max(1e-6);
+        let mesh = document.tessellate([scale, 0.0, 0.0, scale, x, y], tolerance);
+        let base_vertex = self.reserve("SVG document", mesh.positions.len(), mesh.indices.len())?;
+        
+        let color = self.current_color;
+        for (position, vertex_color) in mesh.positions.iter().zip(&mesh.colors) {
+            self.current_color = *vertex_color;
+            self.add_vertex(position.0, position.1);
+        }
+        self.current_color = color;
+        for triangle in mesh.indices.chunks_exact(3) {
+            self.triangle(base_vertex + triangle[0] as u16, base_vertex + triangle[1] as u16, base_vertex + triangle[2] as u16);
+        }
         
-        if length < 0.0001 {
-            return; // Too short to draw
+        Ok(())
+    }
+    
+    // Whether a shape of `vertices` vertices and `indices` indices fits in the rest of the frame
+    fn fits(&self, vertices: usize, indices: usize) -> bool {
+        self.vertex_count + vertices <= MAX_VERTICES && self.index_count + indices <= MAX_INDICES
+    }
+    
+    // Base vertex of the next shape, or an error without drawing anything when the shape does
+    // not fit in the remaining vertex and index space of the frame
+    fn reserve(&self, what: &str, vertices: usize, indices: usize) -> Result<u16, JsValue> {
+        if !self.fits(vertices, indices) {
+            return Err(JsValue::from_str(&format!(
+                "{} needs {} vertices and {} indices, the frame has room for {} and {}",
+                what,
+                vertices,
+                indices,
+                MAX_VERTICES - self.vertex_count,
+                MAX_INDICES - self.index_count,
+            )));
+        }
+        Ok(self.vertex_count as u16)
+    }
+    
+    // Segments for an arc of `sweep` radians keeping within the curve tolerance at the
+    // on-screen size of the radius
+    fn segments_for(&self, radius: f32, sweep: f32) -> usize {
+        let full = segments_for_radius(radius * self.camera.get_zoom() * self.pixel_ratio);
+        ((full as f32 * sweep.abs() / std::f32::consts::TAU).ceil() as usize).max(1)
+    }
+    
+    // Fill a polygon fanned from its first point, so convex or star-shaped around that point,
+    // feathered when antialiasing analytically
+    fn fill_convex(&mut self, points: &[(f32, f32)]) -> Result<(), JsValue> {
+        if points.len() < 3 {
+            return Ok(());
         }
         
-        let nx = dy / length * thickness * 0.5;
-        let ny = -dx / length * thickness * 0.5;
+        let feather = self.feather();
+        if feather > 0.0 {
+            return self.fill_feathered(points, feather);
+        }
         
-        // Add vertices for line quad
-        self.add_vertex(x1 + nx, y1 + ny); // Top-left
-        self.add_vertex(x2 + nx, y2 + ny); // Top-right
-        self.add_vertex(x2 - nx, y2 - ny); // Bottom-right
-        self.add_vertex(x1 - nx, y1 - ny); // Bottom-left
+        let base_vertex = self.reserve("Shape", points.len(), 3 * (points.len() - 2))?;
+        for point in points {
+            self.add_vertex(point.0, point.1);
+        }
+        for i in 1..points.len() as u16 - 1 {
+            self.triangle(base_vertex, base_vertex + i, base_vertex + i + 1);
+        }
+        Ok(())
+    }
+    
+    // Fill the band between two radii over an angle range. With analytic antialiasing both
+    // circular edges fade out across the feather, the straight ends stay hard
+    fn fill_band(&mut self, x: f32, y: f32, inner: f32, outer: f32, start_angle: f32, sweep: f32) -> Result<(), JsValue> {
+        let sweep = sweep.clamp(-std::f32::consts::TAU, std::f32::consts::TAU);
+        if outer <= inner || sweep == 0.0 {
+            return Ok(());
+        }
         
-        // Add indices for two triangles
-        self.indices.push(base_vertex);
-        self.indices.push(base_vertex + 1);
-        self.indices.push(base_vertex + 2);
+        let half = self.feather() * 0.5;
+        let rows: Vec<(f32, f32)> = if half > 0.0 {
+            vec![((inner - half).max(0.0), 0.0), (inner + half, 1.0), (outer - half, 1.0), (outer + half, 0.0)]
+        } else {
+            vec![(inner, 1.0), (outer, 1.0)]
+        };
+        
+        let segments = self.segments_for(outer, sweep);
+        let base_vertex = self.reserve("Shape", rows.len() * (segments + 1), 6 * (rows.len() - 1) * segments)?;
+        let color = self.current_color;
+        for i in 0..=segments {
+            let (sin, cos) = (start_angle + sweep * i as f32 / segments as f32).sin_cos();
+            for (radius, alpha) in &rows {
+                self.current_color[3] = color[3] * alpha;
+                self.add_vertex(x + radius * cos, y + radius * sin);
+            }
+        }
+        self.current_color = color;
+        
+        let stride = rows.len() as u16;
+        for i in 0..segments as u16 {
+            let column = base_vertex + i * stride;
+            for row in 0..stride - 1 {
+                let (a, b) = (column + row, column + row + 1);
+                self.triangle(a, b, b + stride);
+                self.triangle(a, b + stride, a + stride);
+            }
+        }
+        Ok(())
+    }
+    
+    // Fill a polygon with its edges fading out across the feather: an inner ring inset by half
+    // the feather in the current color, an outer ring outset by half fully transparent. The
+    // inner ring is fanned from its first point like fill_convex
+    fn fill_feathered(&mut self, points: &[(f32, f32)], feather: f32) -> Result<(), JsValue> {
+        let count = points.len();
+        let area: f32 = (0..count)
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % count]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum();
+        if area.abs() < 1e-9 {
+            return Ok(());
+        }
         
-        self.indices.push(base_vertex);
-        self.indices.push(base_vertex + 2);
-        self.indices.push(base_vertex + 3);
+        // Outward is to the right of the edges of a positive area polygon
+        let side = if area > 0.0 { -1.0 } else { 1.0 };
+        let half = feather * 0.5;
+        let base_vertex = self.reserve("Shape", 2 * count, 3 * (count - 2) + 6 * count)?;
+        let color = self.current_color;
+        for i in 0..count {
+            let point = points[i];
+            let m = miter(
+                normal(points[(i + count - 1) % count], point),
+                normal(point, points[(i + 1) % count]),
+                FEATHER_MITER_LIMIT,
+            );
+            self.add_vertex(point.0 - m.0 * half * side, point.1 - m.1 * half * side);
+            self.current_color[3] = 0.0;
+            self.add_vertex(point.0 + m.0 * half * side, point.1 + m.1 * half * side);
+            self.current_color = color;
+        }
         
-        self.index_count += 6;
+        // Fan over the inner ring, then a quad from each edge to the outer ring
+        for i in 1..count - 1 {
+            self.triangle(base_vertex, base_vertex + 2 * i as u16, base_vertex + 2 * (i as u16 + 1));
+        }
+        for i in 0..count {
+            let inner = base_vertex + 2 * i as u16;
+            let next_inner = base_vertex + 2 * ((i + 1) % count) as u16;
+            self.triangle(inner, inner + 1, next_inner + 1);
+            self.triangle(inner, next_inner + 1, next_inner);
+        }
+        Ok(())
+    }
+    
+    fn triangle(&mut self, a: u16, b: u16, c: u16) {
+        self.indices.extend_from_slice(&[a, b, c]);
+        self.index_count += 3;
     }
     
     // Helper to add a vertex with the current color
@@ -209,28 +587,29 @@ impl Canvas2D {
         
         // Upload to GPU
         let queue = self.device.queue();
-        queue.write_buffer_with_u32_and_buffer_source(
+        queue.write_buffer(
             &self.vertex_buffer,
             0,
             &vertex_array.buffer(),
             vertex_array.byte_offset(),
             vertex_array.byte_length(),
-        );
+        )?;
         
-        // Upload indices
-        let index_array = Uint16Array::new_with_length(self.indices.len() as u32);
+        // Upload indices. Writes must be a multiple of 4 bytes, an odd index count is padded
+        // with an index that is never drawn
+        let index_array = Uint16Array::new_with_length(self.indices.len().next_multiple_of(2) as u32);
         for (i, value) in self.indices.iter().enumerate() {
             index_array.set_index(i as u32, *value);
         }
         
         // Upload to GPU
-        queue.write_buffer_with_u32_and_buffer_source(
+        queue.write_buffer(
             &self.index_buffer,
             0,
             &index_array.buffer(),
             index_array.byte_offset(),
             index_array.byte_length(),
-        );
+        )?;
         
         Ok(())
     }
@@ -253,6 +632,11 @@ impl Canvas2D {
         &self.index_buffer
     }
     
+    // Get projection uniform buffer for rendering
+    pub fn projection_buffer(&self) -> &GpuBuffer {
+        &self.projection_buffer
+    }
+    
     // Get the number of indices to draw
     pub fn index_count(&self) -> usize {
         self.index_count
@@ -262,4 +646,193 @@ impl Canvas2D {
     pub fn vertex_stride() -> u64 {
         6 * std::mem::size_of::<f32>() as u64
     }
-} 
\ No newline at end of file
+}
+
+// Unit normal to the left of the segment a -> b
+fn normal(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let dx = b.0 - a.0;
+    let dy = b.1 - a.1;
+    let length = (dx * dx + dy * dy).sqrt().max(1e-12);
+    (-dy / length, dx / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+
+    // Geometry is built on the CPU, the GPU handles are never touched before upload
+    fn canvas(antialiasing: Antialiasing) -> Canvas2D {
+        Canvas2D {
+            device: JsValue::NULL.unchecked_into(),
+            vertex_buffer: JsValue::NULL.unchecked_into(),
+            index_buffer: JsValue::NULL.unchecked_into(),
+            projection_buffer: JsValue::NULL.unchecked_into(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            vertex_count: 0,
+            index_count: 0,
+            current_color: [1.0, 1.0, 1.0, 1.0],
+            camera: Camera2D::new(800.0, 600.0),
+            pixel_ratio: 1.0,
+            antialiasing,
+        }
+    }
+
+    fn positions(canvas: &Canvas2D) -> Vec<(f32, f32)> {
+        canvas.vertices.chunks_exact(6).map(|vertex| (vertex[0], vertex[1])).collect()
+    }
+
+    fn alphas(canvas: &Canvas2D) -> Vec<f32> {
+        canvas.vertices.chunks_exact(6).map(|vertex| vertex[5]).collect()
+    }
+
+    // Total signed area of triangles, positive for the y-down clockwise winding
+    fn area(canvas: &Canvas2D, indices: &[u16]) -> f32 {
+        let points = positions(canvas);
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| points[triangle[i] as usize]);
+                ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)) * 0.5
+            })
+            .sum()
+    }
+
+    fn covered_area(canvas: &Canvas2D) -> f32 {
+        area(canvas, &canvas.indices)
+    }
+
+    fn assert_indices_in_range(canvas: &Canvas2D) {
+        assert_eq!(canvas.vertices.len(), canvas.vertex_count * 6);
+        assert_eq!(canvas.indices.len(), canvas.index_count);
+        assert!(canvas.indices.iter().all(|&index| (index as usize) < canvas.vertex_count));
+    }
+
+    #[test]
+    fn geometry_stays_in_css_pixels() {
+        let mut canvas = canvas(Antialiasing::None);
+        canvas.pixel_ratio = 2.0;
+        canvas.fill_rect(10.0, 20.0, 30.0, 40.0).unwrap();
+        assert_eq!(positions(&canvas), vec![(10.0, 20.0), (40.0, 20.0), (40.0, 60.0), (10.0, 60.0)]);
+
+        // The projection maps the top-left CSS pixel corner to the top-left of clip space
+        let m = canvas.camera.matrix();
+        let clip = |x: f32, y: f32| (m[0] * x + m[4] * y + m[12], m[1] * x + m[5] * y + m[13]);
+        assert_eq!(clip(0.0, 0.0), (-1.0, 1.0));
+        assert_eq!(clip(800.0, 600.0), (1.0, -1.0));
+    }
+
+    #[test]
+    fn feathered_edges_straddle_the_outline() {
+        let mut canvas = canvas(Antialiasing::Analytic);
+        canvas.pixel_ratio = 2.0;
+        canvas.fill_rect(10.0, 20.0, 30.0, 40.0).unwrap();
+        assert_indices_in_range(&canvas);
+        // Half a device pixel inside in the current color, half outside transparent
+        assert_eq!(
+            positions(&canvas)[..4],
+            [(10.25, 20.25), (9.75, 19.75), (39.75, 20.25), (40.25, 19.75)]
+        );
+        assert_eq!(alphas(&canvas)[..4], [1.0, 0.0, 1.0, 0.0]);
+        // The fan covers the inset rectangle, the ring the band around it
+        let inset = 29.5 * 39.5;
+        assert!((area(&canvas, &canvas.indices[..6]) - inset).abs() < 1e-3);
+        assert!((area(&canvas, &canvas.indices) - 30.5 * 40.5).abs() < 1e-2);
+    }
+
+    #[test]
+    fn hairlines_fade_instead_of_thinning() {
+        let mut line = canvas(Antialiasing::Analytic);
+        line.set_color(1.0, 1.0, 1.0, 0.8);
+        line.draw_line(0.0, 0.0, 100.0, 0.0, 0.25).unwrap();
+        assert_indices_in_range(&line);
+        // Widened to the feather, a quarter as opaque
+        assert_eq!(alphas(&line).iter().copied().fold(0.0, f32::max), 0.2);
+        assert_eq!(line.current_color[3], 0.8);
+        let ys: Vec<f32> = positions(&line).iter().map(|point| point.1.abs()).collect();
+        assert!(ys.iter().all(|&y| y == 0.0 || y == 1.0));
+
+        // Without antialiasing the line keeps its width
+        let mut plain = canvas(Antialiasing::None);
+        plain.draw_line(0.0, 0.0, 100.0, 0.0, 0.25).unwrap();
+        assert!((area(&plain, &plain.indices).abs() - 25.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn round_rect_scales_radii_that_do_not_fit() {
+        let mut canvas = canvas(Antialiasing::None);
+        canvas.round_rect(10.0, 20.0, 100.0, 40.0, &[30.0, 30.0, 30.0, 30.0]).unwrap();
+        assert_indices_in_range(&canvas);
+        for (x, y) in positions(&canvas) {
+            assert!((10.0 - 1e-3..=110.0 + 1e-3).contains(&x) && (20.0 - 1e-3..=60.0 + 1e-3).contains(&y));
+        }
+        // Radii of 20 after scaling, a stadium of 100 x 40
+        let expected = 60.0 * 40.0 + std::f32::consts::PI * 20.0 * 20.0;
+        assert!((covered_area(&canvas) - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn ellipse_covers_its_area() {
+        let mut canvas = canvas(Antialiasing::None);
+        canvas.fill_ellipse(0.0, 0.0, 80.0, 30.0, 0.7).unwrap();
+        assert_indices_in_range(&canvas);
+        let expected = std::f32::consts::PI * 80.0 * 30.0;
+        assert!((covered_area(&canvas) - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn sectors_wider_than_a_half_circle_cover_their_area() {
+        let mut canvas = canvas(Antialiasing::None);
+        let sweep = 1.5 * std::f32::consts::PI;
+        canvas.fill_sector(0.0, 0.0, 50.0, 0.0, sweep).unwrap();
+        assert_indices_in_range(&canvas);
+        let expected = 0.5 * 50.0 * 50.0 * sweep;
+        assert!((covered_area(&canvas) - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn sectors_wider_than_a_half_circle_are_feathered() {
+        let mut canvas = canvas(Antialiasing::Analytic);
+        canvas.fill_sector(0.0, 0.0, 50.0, 0.0, 1.5 * std::f32::consts::PI).unwrap();
+        assert_indices_in_range(&canvas);
+        // Every point has an opaque inset vertex and a transparent outset one
+        let alphas = alphas(&canvas);
+        assert_eq!(alphas.iter().filter(|&&alpha| alpha == 0.0).count(), canvas.vertex_count / 2);
+        assert_eq!(alphas.iter().filter(|&&alpha| alpha == 1.0).count(), canvas.vertex_count / 2);
+        // The fan over the inset vertices covers the slice minus half the feather along its
+        // outline of two radii and the arc
+        let inner = canvas.vertex_count / 2;
+        let sweep = 1.5 * std::f32::consts::PI;
+        let expected = 0.5 * 50.0 * 50.0 * sweep - 0.5 * (100.0 + 50.0 * sweep);
+        assert!((area(&canvas, &canvas.indices[..3 * (inner - 2)]) - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn rings_and_arcs_stay_within_their_radii() {
+        let mut canvas = canvas(Antialiasing::None);
+        canvas.fill_ring(0.0, 0.0, 30.0, 40.0).unwrap();
+        canvas.stroke_arc(0.0, 0.0, 100.0, 0.0, 1.0, 4.0).unwrap();
+        assert_indices_in_range(&canvas);
+        for (x, y) in positions(&canvas) {
+            let distance = (x * x + y * y).sqrt();
+            assert!((30.0 - 1e-3..=40.0 + 1e-3).contains(&distance) || (98.0 - 1e-3..=102.0 + 1e-3).contains(&distance));
+        }
+        let expected = std::f32::consts::PI * (40.0 * 40.0 - 30.0 * 30.0) + 0.5 * (102.0 * 102.0 - 98.0 * 98.0);
+        assert!((covered_area(&canvas) - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn frames_never_exceed_the_buffers() {
+        let mut canvas = canvas(Antialiasing::Analytic);
+        let mut drawn = 0;
+        // A feathered circle of 64 points needs 128 vertices and 570 indices
+        while canvas.fits(128, 570) {
+            canvas.fill_circle(drawn as f32, 0.0, 10.0, 64).unwrap();
+            drawn += 1;
+        }
+        assert!(drawn > 0);
+        assert!(canvas.vertex_count <= MAX_VERTICES && canvas.index_count <= MAX_INDICES);
+        assert_indices_in_range(&canvas);
+    }
+}
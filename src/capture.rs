@@ -0,0 +1,155 @@
+use crate::gif::encode_gif;
+use crate::png::encode_apng;
+
+// Frames are kept uncompressed until export, so long captures of large canvases would
+// exhaust the wasm memory
+pub(crate) const MAX_CAPTURE_BYTES: usize = 512 << 20;
+
+// Frames collected by render() at a fixed time step, for exporting animation loops as
+// GIF or APNG. Frame i is rendered at start_time + i / fps seconds whatever the wall
+// clock says, so the same scene always produces the same frames
+pub(crate) struct FrameCapture {
+    fps: f32,
+    start_time: f64,
+    frame_limit: u32,
+    width: u32,
+    height: u32,
+    // Length of one frame, width * height * 4
+    frame_bytes: usize,
+    // RGBA frames with straight alpha, rows from top to bottom
+    frames: Vec<Vec<u8>>,
+    active: bool,
+}
+
+impl FrameCapture {
+    pub(crate) fn new(frame_limit: u32, fps: f32, start_time: f64, width: u32, height: u32) -> Result<Self, String> {
+        let frame_bytes = (width as usize).checked_mul(height as usize).and_then(|texels| texels.checked_mul(4));
+        let total = frame_bytes.and_then(|bytes| bytes.checked_mul(frame_limit as usize));
+        let Some(frame_bytes) = frame_bytes.filter(|_| total.is_some_and(|total| total <= MAX_CAPTURE_BYTES)) else {
+            return Err(format!(
+                "{} frames of {}x{} pixels exceed the capture limit of {} MiB",
+                frame_limit,
+                width,
+                height,
+                MAX_CAPTURE_BYTES >> 20
+            ));
+        };
+
+        Ok(FrameCapture {
+            fps,
+            start_time,
+            frame_limit,
+            width,
+            height,
+            frame_bytes,
+            frames: Vec::new(),
+            active: frame_limit > 0,
+        })
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Scene time of the next frame and the step to it, in seconds
+    pub(crate) fn next_time(&self) -> (f64, f64) {
+        let step = 1.0 / self.fps as f64;
+        (self.start_time + self.frames.len() as f64 * step, step)
+    }
+
+    // Add a rendered frame of the capture size, capture stops once the requested number of
+    // frames is reached
+    pub(crate) fn push(&mut self, frame: Vec<u8>) -> Result<(), String> {
+        if !self.active || frame.len() != self.frame_bytes {
+            self.active = false;
+            return Err("Captured frame does not match the capture size".to_string());
+        }
+        self.frames.push(frame);
+        if self.frames.len() >= self.frame_limit as usize {
+            self.active = false;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn gif(&self) -> Vec<u8> {
+        encode_gif(self.width, self.height, &self.frames, &self.delays(100))
+    }
+
+    pub(crate) fn apng(&self) -> Vec<u8> {
+        encode_apng(self.width, self.height, &self.frames, &self.delays(1000))
+    }
+
+    // Frame durations in 1 / units_per_second. Each frame ends at its rounded ideal end
+    // time, so rounding errors do not add up over the loop
+    fn delays(&self, units_per_second: u32) -> Vec<u16> {
+        let end = |frame: usize| (frame as f64 * units_per_second as f64 / self.fps as f64).round();
+        (0..self.frames.len())
+            .map(|frame| (end(frame + 1) - end(frame)).clamp(1.0, u16::MAX as f64) as u16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_step_from_the_start_time() {
+        let mut capture = FrameCapture::new(3, 30.0, 2.5, 4, 2).unwrap();
+        assert!(capture.is_active());
+        for frame in 0..3 {
+            let (time, step) = capture.next_time();
+            assert!((time - (2.5 + frame as f64 / 30.0)).abs() < 1e-12);
+            assert!((step - 1.0 / 30.0).abs() < 1e-12);
+            capture.push(vec![0; 4 * 2 * 4]).unwrap();
+        }
+        assert!(!capture.is_active());
+        assert_eq!((capture.len(), capture.size()), (3, (4, 2)));
+        assert!(!FrameCapture::new(0, 30.0, 0.0, 4, 2).unwrap().is_active());
+    }
+
+    #[test]
+    fn delays_do_not_drift() {
+        let mut capture = FrameCapture::new(30, 30.0, 0.0, 1, 1).unwrap();
+        for _ in 0..30 {
+            capture.push(vec![0; 4]).unwrap();
+        }
+        // 3.33 centiseconds per frame, rounded so that the loop lasts exactly one second
+        let delays = capture.delays(100);
+        assert!(delays.iter().all(|&delay| delay == 3 || delay == 4));
+        assert_eq!(delays.iter().map(|&delay| delay as u32).sum::<u32>(), 100);
+        assert_eq!(capture.delays(1000).iter().map(|&delay| delay as u32).sum::<u32>(), 1000);
+
+        // Frames shorter than a unit still last one
+        let mut fast = FrameCapture::new(2, 500.0, 0.0, 1, 1).unwrap();
+        fast.push(vec![0; 4]).unwrap();
+        fast.push(vec![0; 4]).unwrap();
+        assert_eq!(fast.delays(100), [1, 1]);
+    }
+
+    #[test]
+    fn captures_stay_within_the_memory_limit() {
+        // 2048 x 2048 frames are 16 MiB each
+        assert!(FrameCapture::new(32, 30.0, 0.0, 2048, 2048).is_ok());
+        let error = FrameCapture::new(33, 30.0, 0.0, 2048, 2048).err().unwrap();
+        assert_eq!(error, "33 frames of 2048x2048 pixels exceed the capture limit of 512 MiB");
+        assert!(FrameCapture::new(u32::MAX, 30.0, 0.0, u32::MAX, u32::MAX).is_err());
+
+        let mut capture = FrameCapture::new(2, 30.0, 0.0, 2, 2).unwrap();
+        assert!(capture.push(vec![0; 4]).is_err());
+        assert!(!capture.is_active());
+        assert_eq!(capture.len(), 0);
+    }
+}
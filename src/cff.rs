@@ -0,0 +1,630 @@
+use crate::font::{i16_at, u16_at, u32_at, u8_at, Outline, OutlineBuilder};
+
+// Limits from the Type 2 charstring specification
+const MAX_OPERANDS: usize = 48;
+const MAX_SUBR_DEPTH: u32 = 10;
+
+// Byte range of every object in a CFF INDEX
+#[derive(Clone, Debug, Default)]
+struct Index {
+    ranges: Vec<(usize, usize)>,
+}
+
+impl Index {
+    // Parse an INDEX at an absolute offset, returns it with the offset right after it
+    fn parse(data: &[u8], offset: usize) -> Result<(Index, usize), String> {
+        let truncated = || "Truncated CFF index".to_string();
+        let count = u16_at(data, offset).ok_or_else(truncated)? as usize;
+        if count == 0 {
+            return Ok((Index::default(), offset + 2));
+        }
+
+        let offset_size = u8_at(data, offset + 2).ok_or_else(truncated)? as usize;
+        if !(1..=4).contains(&offset_size) {
+            return Err("Invalid CFF index offset size".to_string());
+        }
+
+        let read_offset = |i: usize| -> Option<usize> {
+            let start = offset + 3 + i * offset_size;
+            let bytes = data.get(start..start + offset_size)?;
+            Some(bytes.iter().fold(0usize, |value, byte| (value << 8) | *byte as usize))
+        };
+
+        // Offsets are 1-based, relative to the byte before the object data
+        let data_start = offset + 3 + (count + 1) * offset_size - 1;
+        let mut ranges = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = data_start.checked_add(read_offset(i).ok_or_else(truncated)?);
+            let end = data_start.checked_add(read_offset(i + 1).ok_or_else(truncated)?);
+            match (start, end) {
+                (Some(start), Some(end)) if start <= end && end <= data.len() => ranges.push((start, end)),
+                _ => return Err("Invalid CFF index offsets".to_string()),
+            }
+        }
+
+        let end = ranges.last().map_or(data_start, |range| range.1);
+        Ok((Index { ranges }, end))
+    }
+
+    fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, usize)> {
+        self.ranges.get(index).copied()
+    }
+}
+
+// Operator and operands of a DICT entry
+struct DictEntry {
+    operator: u16,
+    operands: Vec<f32>,
+}
+
+// Decode a Top or Private DICT
+fn parse_dict(data: &[u8], start: usize, end: usize) -> Result<Vec<DictEntry>, String> {
+    let truncated = || "Truncated CFF dict".to_string();
+    let mut entries = Vec::new();
+    let mut operands = Vec::new();
+    let mut offset = start;
+
+    while offset < end {
+        let b0 = u8_at(data, offset).ok_or_else(truncated)?;
+        offset += 1;
+
+        match b0 {
+            0..=21 => {
+                let operator = if b0 == 12 {
+                    let b1 = u8_at(data, offset).ok_or_else(truncated)?;
+                    offset += 1;
+                    1200 + b1 as u16
+                } else {
+                    b0 as u16
+                };
+                entries.push(DictEntry {
+                    operator,
+                    operands: std::mem::take(&mut operands),
+                });
+            }
+            28 => {
+                operands.push(i16_at(data, offset).ok_or_else(truncated)? as f32);
+                offset += 2;
+            }
+            29 => {
+                operands.push(u32_at(data, offset).ok_or_else(truncated)? as i32 as f32);
+                offset += 4;
+            }
+            30 => {
+                // Real number as packed nibbles, only needed to skip it correctly
+                let mut text = String::new();
+                'nibbles: loop {
+                    let byte = u8_at(data, offset).ok_or_else(truncated)?;
+                    offset += 1;
+                    for nibble in [byte >> 4, byte & 0x0F] {
+                        match nibble {
+                            0..=9 => text.push((b'0' + nibble) as char),
+                            0xA => text.push('.'),
+                            0xB => text.push('E'),
+                            0xC => text.push_str("E-"),
+                            0xE => text.push('-'),
+                            0xF => break 'nibbles,
+                            _ => {}
+                        }
+                    }
+                }
+                operands.push(text.parse().unwrap_or(0.0));
+            }
+            32..=246 => operands.push(b0 as f32 - 139.0),
+            247..=250 => {
+                let b1 = u8_at(data, offset).ok_or_else(truncated)?;
+                offset += 1;
+                operands.push((b0 as f32 - 247.0) * 256.0 + b1 as f32 + 108.0);
+            }
+            251..=254 => {
+                let b1 = u8_at(data, offset).ok_or_else(truncated)?;
+                offset += 1;
+                operands.push(-(b0 as f32 - 251.0) * 256.0 - b1 as f32 - 108.0);
+            }
+            _ => return Err(format!("Invalid CFF dict byte {}", b0)),
+        }
+    }
+
+    Ok(entries)
+}
+
+fn dict_operands(entries: &[DictEntry], operator: u16) -> Option<&[f32]> {
+    entries
+        .iter()
+        .find(|entry| entry.operator == operator)
+        .map(|entry| entry.operands.as_slice())
+}
+
+// Local subroutines of a Private DICT, empty when there are none
+fn parse_private_subrs(data: &[u8], cff: usize, private: &[f32]) -> Result<Index, String> {
+    let [size, offset] = private else {
+        return Err("Invalid CFF Private operator".to_string());
+    };
+    let start = cff + *offset as usize;
+    let entries = parse_dict(data, start, start + *size as usize)?;
+
+    match dict_operands(&entries, 19) {
+        Some([subrs]) => Ok(Index::parse(data, start + *subrs as usize)?.0),
+        _ => Ok(Index::default()),
+    }
+}
+
+// Subroutine index bias, depends on the number of subroutines
+fn subr_bias(count: usize) -> i32 {
+    if count < 1240 {
+        107
+    } else if count < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+// How CID-keyed fonts select the local subroutines of a glyph
+enum FdSelect {
+    // One font dict index per glyph
+    Format0(usize),
+    // Ranges of glyphs sharing a font dict
+    Format3(usize),
+}
+
+// Compact Font Format table of an OpenType font with PostScript outlines
+pub(crate) struct Cff {
+    char_strings: Index,
+    global_subrs: Index,
+    // One local subroutine index, or one per font dict for CID-keyed fonts
+    local_subrs: Vec<Index>,
+    fd_select: Option<FdSelect>,
+}
+
+impl Cff {
+    pub(crate) fn parse(data: &[u8], cff: usize) -> Result<Cff, String> {
+        let header_size = u8_at(data, cff + 2).ok_or("Truncated CFF header")? as usize;
+        let (_names, offset) = Index::parse(data, cff + header_size)?;
+        let (top_dicts, offset) = Index::parse(data, offset)?;
+        let (_strings, offset) = Index::parse(data, offset)?;
+        let (global_subrs, _) = Index::parse(data, offset)?;
+
+        let (top_start, top_end) = top_dicts.get(0).ok_or("CFF table has no top dict")?;
+        let top = parse_dict(data, top_start, top_end)?;
+
+        if dict_operands(&top, 1206).is_some_and(|operands| operands != [2.0]) {
+            return Err("Only Type 2 charstrings are supported".to_string());
+        }
+
+        let char_strings = match dict_operands(&top, 17) {
+            Some([offset]) => Index::parse(data, cff + *offset as usize)?.0,
+            _ => return Err("CFF font has no charstrings".to_string()),
+        };
+
+        // CID-keyed fonts have an array of font dicts, each with its own private dict
+        let (local_subrs, fd_select) = if dict_operands(&top, 1230).is_some() {
+            let fd_array = match dict_operands(&top, 1236) {
+                Some([offset]) => Index::parse(data, cff + *offset as usize)?.0,
+                _ => return Err("CID-keyed CFF font has no FDArray".to_string()),
+            };
+            let fd_select_offset = match dict_operands(&top, 1237) {
+                Some([offset]) => cff + *offset as usize,
+                _ => return Err("CID-keyed CFF font has no FDSelect".to_string()),
+            };
+            let fd_select = match u8_at(data, fd_select_offset) {
+                Some(0) => FdSelect::Format0(fd_select_offset + 1),
+                Some(3) => FdSelect::Format3(fd_select_offset + 1),
+                _ => return Err("Unsupported CFF FDSelect format".to_string()),
+            };
+
+            let mut local_subrs = Vec::with_capacity(fd_array.len());
+            for i in 0..fd_array.len() {
+                let (start, end) = fd_array.get(i).ok_or("Invalid CFF FDArray")?;
+                let font_dict = parse_dict(data, start, end)?;
+                local_subrs.push(match dict_operands(&font_dict, 18) {
+                    Some(private) => parse_private_subrs(data, cff, private)?,
+                    None => Index::default(),
+                });
+            }
+            (local_subrs, Some(fd_select))
+        } else {
+            let subrs = match dict_operands(&top, 18) {
+                Some(private) => parse_private_subrs(data, cff, private)?,
+                None => Index::default(),
+            };
+            (vec![subrs], None)
+        };
+
+        Ok(Cff {
+            char_strings,
+            global_subrs,
+            local_subrs,
+            fd_select,
+        })
+    }
+
+    pub(crate) fn outline(&self, data: &[u8], glyph: u16) -> Result<Outline, String> {
+        let (start, end) = self
+            .char_strings
+            .get(glyph as usize)
+            .ok_or_else(|| format!("Glyph {} has no charstring", glyph))?;
+
+        let local_subrs = self
+            .local_subrs
+            .get(self.font_dict(data, glyph))
+            .ok_or("Invalid CFF font dict index")?;
+
+        let mut interpreter = CharStringInterpreter {
+            data,
+            global_subrs: &self.global_subrs,
+            local_subrs,
+            builder: OutlineBuilder::default(),
+            stack: Vec::with_capacity(MAX_OPERANDS),
+            stem_count: 0,
+            width_parsed: false,
+            x: 0.0,
+            y: 0.0,
+        };
+        interpreter.run(start, end, 0)?;
+
+        Ok(interpreter.builder.finish())
+    }
+
+    fn font_dict(&self, data: &[u8], glyph: u16) -> usize {
+        match self.fd_select {
+            None => 0,
+            Some(FdSelect::Format0(table)) => u8_at(data, table + glyph as usize).unwrap_or(0) as usize,
+            Some(FdSelect::Format3(table)) => {
+                let range_count = u16_at(data, table).unwrap_or(0) as usize;
+                let mut fd = 0;
+                for i in 0..range_count {
+                    let range = table + 2 + i * 3;
+                    let first = u16_at(data, range).unwrap_or(u16::MAX);
+                    if first > glyph {
+                        break;
+                    }
+                    fd = u8_at(data, range + 2).unwrap_or(0) as usize;
+                }
+                fd
+            }
+        }
+    }
+}
+
+// Type 2 charstring interpreter producing glyph outlines
+struct CharStringInterpreter<'a> {
+    data: &'a [u8],
+    global_subrs: &'a Index,
+    local_subrs: &'a Index,
+    builder: OutlineBuilder,
+    stack: Vec<f32>,
+    stem_count: usize,
+    width_parsed: bool,
+    x: f32,
+    y: f32,
+}
+
+impl CharStringInterpreter<'_> {
+    // Execute a charstring, returns true when endchar was reached
+    fn run(&mut self, start: usize, end: usize, depth: u32) -> Result<bool, String> {
+        if depth > MAX_SUBR_DEPTH {
+            return Err("CFF subroutine nesting is too deep".to_string());
+        }
+
+        let truncated = || "Truncated charstring".to_string();
+        let mut offset = start;
+        while offset < end {
+            let b0 = u8_at(self.data, offset).ok_or_else(truncated)?;
+            offset += 1;
+
+            match b0 {
+                // hstem, vstem, hstemhm, vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.take_width(self.stack.len() % 2 == 1);
+                    self.stem_count += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                // hintmask, cntrmask: pending operands are implicit vstems
+                19 | 20 => {
+                    self.take_width(self.stack.len() % 2 == 1);
+                    self.stem_count += self.stack.len() / 2;
+                    self.stack.clear();
+                    offset += self.stem_count.div_ceil(8);
+                }
+                // rmoveto
+                21 => {
+                    self.take_width(self.stack.len() > 2);
+                    let [dx, dy] = self.args::<2>()?;
+                    self.move_by(dx, dy);
+                }
+                // hmoveto
+                22 => {
+                    self.take_width(self.stack.len() > 1);
+                    let [dx] = self.args::<1>()?;
+                    self.move_by(dx, 0.0);
+                }
+                // vmoveto
+                4 => {
+                    self.take_width(self.stack.len() > 1);
+                    let [dy] = self.args::<1>()?;
+                    self.move_by(0.0, dy);
+                }
+                // rlineto
+                5 => {
+                    for pair in self.stack.chunks_exact(2) {
+                        self.x += pair[0];
+                        self.y += pair[1];
+                        self.builder.line_to([self.x, self.y]);
+                    }
+                    self.stack.clear();
+                }
+                // hlineto, vlineto: alternating axis-aligned lines
+                6 | 7 => {
+                    let mut horizontal = b0 == 6;
+                    for &delta in &self.stack {
+                        if horizontal {
+                            self.x += delta;
+                        } else {
+                            self.y += delta;
+                        }
+                        self.builder.line_to([self.x, self.y]);
+                        horizontal = !horizontal;
+                    }
+                    self.stack.clear();
+                }
+                // rrcurveto
+                8 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    for args in stack.chunks_exact(6) {
+                        self.curve_by(args[0], args[1], args[2], args[3], args[4], args[5]);
+                    }
+                }
+                // rcurveline: curves followed by a line
+                24 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    if stack.len() < 8 {
+                        return Err("Invalid rcurveline".to_string());
+                    }
+                    let (curves, line) = stack.split_at(stack.len() - 2);
+                    for args in curves.chunks_exact(6) {
+                        self.curve_by(args[0], args[1], args[2], args[3], args[4], args[5]);
+                    }
+                    self.x += line[0];
+                    self.y += line[1];
+                    self.builder.line_to([self.x, self.y]);
+                }
+                // rlinecurve: lines followed by a curve
+                25 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    if stack.len() < 8 {
+                        return Err("Invalid rlinecurve".to_string());
+                    }
+                    let (lines, curve) = stack.split_at(stack.len() - 6);
+                    for pair in lines.chunks_exact(2) {
+                        self.x += pair[0];
+                        self.y += pair[1];
+                        self.builder.line_to([self.x, self.y]);
+                    }
+                    self.curve_by(curve[0], curve[1], curve[2], curve[3], curve[4], curve[5]);
+                }
+                // vvcurveto
+                26 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let (mut dx1, args) = if stack.len() % 2 == 1 { (stack[0], &stack[1..]) } else { (0.0, &stack[..]) };
+                    for a in args.chunks_exact(4) {
+                        self.curve_by(dx1, a[0], a[1], a[2], 0.0, a[3]);
+                        dx1 = 0.0;
+                    }
+                }
+                // hhcurveto
+                27 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let (mut dy1, args) = if stack.len() % 2 == 1 { (stack[0], &stack[1..]) } else { (0.0, &stack[..]) };
+                    for a in args.chunks_exact(4) {
+                        self.curve_by(a[0], dy1, a[1], a[2], a[3], 0.0);
+                        dy1 = 0.0;
+                    }
+                }
+                // vhcurveto, hvcurveto: curves alternating between vertical and horizontal tangents
+                30 | 31 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let mut horizontal = b0 == 31;
+                    let mut i = 0;
+                    while i + 4 <= stack.len() {
+                        let a = &stack[i..];
+                        // The last curve may carry one extra delta for its end point
+                        let last = if stack.len() - i == 5 { a[4] } else { 0.0 };
+                        if horizontal {
+                            self.curve_by(a[0], 0.0, a[1], a[2], last, a[3]);
+                        } else {
+                            self.curve_by(0.0, a[0], a[1], a[2], a[3], last);
+                        }
+                        horizontal = !horizontal;
+                        i += 4;
+                    }
+                }
+                // callsubr, callgsubr
+                10 | 29 => {
+                    let subrs = if b0 == 10 { self.local_subrs } else { self.global_subrs };
+                    let index = self.stack.pop().ok_or("Missing subroutine index")? as i32 + subr_bias(subrs.len());
+                    let (subr_start, subr_end) = usize::try_from(index)
+                        .ok()
+                        .and_then(|index| subrs.get(index))
+                        .ok_or("Invalid subroutine index")?;
+                    if self.run(subr_start, subr_end, depth + 1)? {
+                        return Ok(true);
+                    }
+                }
+                // return
+                11 => return Ok(false),
+                // endchar (accented character composition via seac is not supported)
+                14 => {
+                    self.take_width(self.stack.len() == 1 || self.stack.len() == 5);
+                    self.stack.clear();
+                    self.builder.close();
+                    return Ok(true);
+                }
+                12 => {
+                    let b1 = u8_at(self.data, offset).ok_or_else(truncated)?;
+                    offset += 1;
+                    self.flex(b1)?;
+                }
+                28 => {
+                    let value = i16_at(self.data, offset).ok_or_else(truncated)?;
+                    offset += 2;
+                    self.push(value as f32)?;
+                }
+                32..=246 => self.push(b0 as f32 - 139.0)?,
+                247..=250 => {
+                    let b1 = u8_at(self.data, offset).ok_or_else(truncated)?;
+                    offset += 1;
+                    self.push((b0 as f32 - 247.0) * 256.0 + b1 as f32 + 108.0)?;
+                }
+                251..=254 => {
+                    let b1 = u8_at(self.data, offset).ok_or_else(truncated)?;
+                    offset += 1;
+                    self.push(-(b0 as f32 - 251.0) * 256.0 - b1 as f32 - 108.0)?;
+                }
+                255 => {
+                    // 16.16 fixed point
+                    let value = u32_at(self.data, offset).ok_or_else(truncated)? as i32;
+                    offset += 4;
+                    self.push(value as f32 / 65536.0)?;
+                }
+                _ => return Err(format!("Unsupported charstring operator {}", b0)),
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Flex operators, drawn as plain curves. Other escaped operators are ignored
+    fn flex(&mut self, operator: u8) -> Result<(), String> {
+        let stack = std::mem::take(&mut self.stack);
+        let invalid = || "Invalid flex operands".to_string();
+
+        match operator {
+            // flex
+            35 => {
+                let a = stack.get(..12).ok_or_else(invalid)?;
+                self.curve_by(a[0], a[1], a[2], a[3], a[4], a[5]);
+                self.curve_by(a[6], a[7], a[8], a[9], a[10], a[11]);
+            }
+            // hflex
+            34 => {
+                let a = stack.get(..7).ok_or_else(invalid)?;
+                self.curve_by(a[0], 0.0, a[1], a[2], a[3], 0.0);
+                self.curve_by(a[4], 0.0, a[5], -a[2], a[6], 0.0);
+            }
+            // hflex1
+            36 => {
+                let a = stack.get(..9).ok_or_else(invalid)?;
+                self.curve_by(a[0], a[1], a[2], a[3], a[4], 0.0);
+                self.curve_by(a[5], 0.0, a[6], a[7], a[8], -(a[1] + a[3] + a[7]));
+            }
+            // flex1: the last delta is along the dominant axis of the whole flex
+            37 => {
+                let a = stack.get(..11).ok_or_else(invalid)?;
+                let dx: f32 = a[..10].iter().step_by(2).sum();
+                let dy: f32 = a[1..10].iter().step_by(2).sum();
+                let (dx6, dy6) = if dx.abs() > dy.abs() { (a[10], -dy) } else { (-dx, a[10]) };
+                self.curve_by(a[0], a[1], a[2], a[3], a[4], a[5]);
+                self.curve_by(a[6], a[7], a[8], a[9], dx6, dy6);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, value: f32) -> Result<(), String> {
+        if self.stack.len() >= MAX_OPERANDS {
+            return Err("Charstring operand stack overflow".to_string());
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    // The first stack-clearing operator may carry the advance width as an extra leading operand
+    fn take_width(&mut self, has_extra: bool) {
+        if !self.width_parsed {
+            if has_extra && !self.stack.is_empty() {
+                self.stack.remove(0);
+            }
+            self.width_parsed = true;
+        }
+    }
+
+    // Pop exactly N operands
+    fn args<const N: usize>(&mut self) -> Result<[f32; N], String> {
+        let args: [f32; N] = self
+            .stack
+            .get(..N)
+            .and_then(|values| values.try_into().ok())
+            .ok_or("Missing charstring operands")?;
+        self.stack.clear();
+        Ok(args)
+    }
+
+    fn move_by(&mut self, dx: f32, dy: f32) {
+        self.x += dx;
+        self.y += dy;
+        self.builder.move_to([self.x, self.y]);
+    }
+
+    // Cubic curve with relative control points, each relative to the previous point
+    fn curve_by(&mut self, dx1: f32, dy1: f32, dx2: f32, dy2: f32, dx3: f32, dy3: f32) {
+        let c1 = [self.x + dx1, self.y + dy1];
+        let c2 = [c1[0] + dx2, c1[1] + dy2];
+        let end = [c2[0] + dx3, c2[1] + dy3];
+        self.builder.cubic_to(c1, c2, end);
+        self.x = end[0];
+        self.y = end[1];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_ranges_follow_the_offsets() {
+        // Three objects of 2, 0 and 3 bytes with one byte offsets, then a trailing byte
+        let data = [0xAA, 0, 3, 1, 1, 3, 3, 6, b'a', b'b', b'c', b'd', b'e', 0xFF];
+        let (index, end) = Index::parse(&data, 1).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get(0), Some((8, 10)));
+        assert_eq!(index.get(1), Some((10, 10)));
+        assert_eq!(index.get(2), Some((10, 13)));
+        assert_eq!(end, 13);
+
+        let (empty, end) = Index::parse(&[0, 0], 0).unwrap();
+        assert_eq!((empty.len(), end), (0, 2));
+        assert!(Index::parse(&[0, 1, 5, 1, 2], 0).is_err());
+        assert!(Index::parse(&[0, 1, 1, 1, 9, 0], 0).is_err());
+    }
+
+    #[test]
+    fn dict_operands_in_every_encoding() {
+        // 0, 100 and -100 in one byte, 1000 and -1000 in two, 30000 in three, 100000 in five,
+        // -2.25 as a real, then operators 17 and 12 7
+        let data = [
+            139, 239, 39, 0xFA, 0x7C, 0xFE, 0x7C, 28, 0x75, 0x30, 29, 0x00, 0x01, 0x86, 0xA0, 30, 0xE2, 0xA2, 0x5F, 17, 12, 7,
+        ];
+        let entries = parse_dict(&data, 0, data.len()).unwrap();
+        assert_eq!(
+            dict_operands(&entries, 17),
+            Some(&[0.0, 100.0, -100.0, 1000.0, -1000.0, 30000.0, 100000.0, -2.25][..])
+        );
+        assert_eq!(dict_operands(&entries, 1207), Some(&[][..]));
+        assert_eq!(dict_operands(&entries, 18), None);
+        assert!(parse_dict(&[28, 1], 0, 2).is_err());
+    }
+
+    #[test]
+    fn subroutine_bias_depends_on_the_count() {
+        assert_eq!(subr_bias(0), 107);
+        assert_eq!(subr_bias(1240), 1131);
+        assert_eq!(subr_bias(33900), 32768);
+    }
+}
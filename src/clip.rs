@@ -0,0 +1,386 @@
+use std::rc::Rc;
+
+use js_sys::Float32Array;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlUniformLocation, WebGlVertexArrayObject};
+
+use crate::path::FillRule;
+use crate::{compile_shader, link_program};
+
+// Stencil layout: the top bit marks pixels inside the clip, the low bits count path windings
+pub(crate) const CLIP_BIT: i32 = 0x80;
+const WINDING_BITS: u32 = 0x7F;
+
+const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0,
+];
+
+// Two triangles covering the viewport in clip space
+const FULLSCREEN: [f32; 12] = [-1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0];
+
+// Clip paths only touch the stencil buffer, the fragment output is masked
+const CLIP_VERTEX_SHADER_SRC: &str = r#"#version 300 es
+in vec2 aPosition;
+
+uniform mat4 uMatrix;
+
+void main() {
+    gl_Position = uMatrix * vec4(aPosition, 0.0, 1.0);
+}
+"#;
+
+const CLIP_FRAGMENT_SHADER_SRC: &str = r#"#version 300 es
+precision mediump float;
+
+out vec4 outColor;
+
+void main() {
+    outColor = vec4(0.0);
+}
+"#;
+
+// A clip path flattened in world space, kept as the triangle fans that rebuild its stencil mask
+#[derive(Debug)]
+pub(crate) struct ClipPath {
+    triangles: Vec<f32>,
+    rule: FillRule,
+}
+
+impl ClipPath {
+    pub(crate) fn new(triangles: Vec<f32>, rule: FillRule) -> ClipPath {
+        ClipPath { triangles, rule }
+    }
+}
+
+// Intersection of the clip regions of the drawing state. Rectangles become a scissor box
+// while the camera is axis-aligned, other paths go through the stencil buffer
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Clip {
+    // World rectangle [x, y, width, height] and the same rectangle as a path for rotated cameras
+    rects: Vec<([f32; 4], Rc<ClipPath>)>,
+    paths: Vec<Rc<ClipPath>>,
+}
+
+impl Clip {
+    // This clip intersected with a rectangle
+    pub(crate) fn with_rect(&self, rect: [f32; 4]) -> Clip {
+        let [x, y, w, h] = rect;
+        let triangles = vec![x, y, x + w, y, x + w, y + h, x, y, x + w, y + h, x, y + h];
+
+        let mut clip = self.clone();
+        clip.rects.push((rect, Rc::new(ClipPath::new(triangles, FillRule::NonZero))));
+        clip
+    }
+
+    // This clip intersected with a path
+    pub(crate) fn with_path(&self, path: ClipPath) -> Clip {
+        let mut clip = self.clone();
+        clip.paths.push(Rc::new(path));
+        clip
+    }
+}
+
+// Applies clips to the GL state while queued draws are flushed: sets the scissor box and
+// rebuilds the stencil mask when the clip changes between draw calls
+pub(crate) struct Clipper {
+    program: WebGlProgram,
+    vao: WebGlVertexArrayObject,
+    buffer: WebGlBuffer,
+    matrix_location: Option<WebGlUniformLocation>,
+    // The default framebuffer was created with a stencil buffer
+    has_stencil: bool,
+    // Drawing buffer size in device pixels
+    viewport: (u32, u32),
+    // Clip currently applied and the paths currently in the stencil buffer
+    applied: Option<Rc<Clip>>,
+    stencil_paths: Vec<Rc<ClipPath>>,
+}
+
+impl Clipper {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<Clipper, JsValue> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, CLIP_VERTEX_SHADER_SRC)?;
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, CLIP_FRAGMENT_SHADER_SRC)?;
+        let program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let vao = gl.create_vertex_array().ok_or("Failed to create clip vertex array")?;
+        gl.bind_vertex_array(Some(&vao));
+        let buffer = gl.create_buffer().ok_or("Failed to create clip vertex buffer")?;
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
+        let location = gl.get_attrib_location(&program, "aPosition");
+        if location >= 0 {
+            gl.vertex_attrib_pointer_with_i32(location as u32, 2, WebGl2RenderingContext::FLOAT, false, 0, 0);
+            gl.enable_vertex_attrib_array(location as u32);
+        }
+        gl.bind_vertex_array(None);
+
+        let has_stencil = gl
+            .get_context_attributes()
+            .and_then(|attributes| attributes.get_stencil())
+            .unwrap_or(false);
+
+        Ok(Clipper {
+            matrix_location: gl.get_uniform_location(&program, "uMatrix"),
+            program,
+            vao,
+            buffer,
+            has_stencil,
+            viewport: (1, 1),
+            applied: None,
+            stencil_paths: Vec::new(),
+        })
+    }
+
+    // Path clips and fills need the context to be created with { stencil: true }
+    pub(crate) fn has_stencil(&self) -> bool {
+        self.has_stencil
+    }
+
+    pub(crate) fn set_viewport(&mut self, width: u32, height: u32) {
+        self.viewport = (width, height);
+    }
+
+    // Make `clip` the active clip. Returns true when the stencil mask was redrawn, in which
+    // case the caller has to bind its program and vertex array again
+    pub(crate) fn apply(&mut self, gl: &WebGl2RenderingContext, clip: &Rc<Clip>, matrix: &[f32; 16]) -> bool {
+        if self.applied.as_ref().is_some_and(|applied| Rc::ptr_eq(applied, clip)) {
+            return false;
+        }
+        self.applied = Some(clip.clone());
+
+        // A scissor box only matches the rectangles while they stay axis-aligned on screen
+        let axis_aligned = matrix[1].abs() < 1e-6 && matrix[4].abs() < 1e-6;
+        let mut stencil_paths: Vec<Rc<ClipPath>> = Vec::new();
+        let mut scissor: Option<[i32; 4]> = None;
+        for (rect, path) in &clip.rects {
+            if axis_aligned || !self.has_stencil {
+                let device = self.device_rect(rect, matrix);
+                scissor = Some(match scissor {
+                    Some(current) => intersect(current, device),
+                    None => device,
+                });
+            } else {
+                stencil_paths.push(path.clone());
+            }
+        }
+        stencil_paths.extend(clip.paths.iter().cloned());
+
+        match scissor {
+            Some([x0, y0, x1, y1]) => {
+                gl.enable(WebGl2RenderingContext::SCISSOR_TEST);
+                gl.scissor(x0, y0, (x1 - x0).max(0), (y1 - y0).max(0));
+            }
+            None => gl.disable(WebGl2RenderingContext::SCISSOR_TEST),
+        }
+
+        if !self.has_stencil {
+            return false;
+        }
+
+        let redraw = !same_paths(&stencil_paths, &self.stencil_paths);
+        if redraw {
+            // Only intersect the new paths when the previous mask is a prefix of this one
+            let keep = self.stencil_paths.len();
+            let extends = keep < stencil_paths.len() && same_paths(&stencil_paths[..keep], &self.stencil_paths);
+            let first = if extends { keep } else { 0 };
+            self.draw_stencil(gl, &stencil_paths, first, matrix, scissor);
+            self.stencil_paths = stencil_paths;
+        }
+        self.set_draw_state(gl);
+
+        redraw
+    }
+
+    // Stencil state for ordinary draws: pass inside the clip, never write
+    fn set_draw_state(&self, gl: &WebGl2RenderingContext) {
+        if self.stencil_paths.is_empty() {
+            gl.disable(WebGl2RenderingContext::STENCIL_TEST);
+            return;
+        }
+
+        gl.enable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.stencil_mask(0);
+        gl.stencil_func(WebGl2RenderingContext::EQUAL, CLIP_BIT, CLIP_BIT as u32);
+        gl.stencil_op(WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP);
+    }
+
+    // Draw the masks of paths[first..] into the stencil buffer, clearing it first when
+    // starting from scratch
+    fn draw_stencil(
+        &self,
+        gl: &WebGl2RenderingContext,
+        paths: &[Rc<ClipPath>],
+        first: usize,
+        matrix: &[f32; 16],
+        scissor: Option<[i32; 4]>,
+    ) {
+        // The whole buffer has to stay consistent for later clips with another scissor box
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.enable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.stencil_mask(0xFF);
+        if first == 0 {
+            gl.clear_stencil(CLIP_BIT);
+            gl.clear(WebGl2RenderingContext::STENCIL_BUFFER_BIT);
+        }
+
+        if first < paths.len() {
+            gl.use_program(Some(&self.program));
+            gl.bind_vertex_array(Some(&self.vao));
+            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffer));
+            gl.color_mask(false, false, false, false);
+
+            for path in &paths[first..] {
+                // Winding numbers of the path in the low bits
+                gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, matrix);
+                begin_winding(gl, path.rule);
+                draw_triangles(gl, &path.triangles);
+
+                // Keep the clip bit only where the winding rule says inside, zero the rest
+                gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, &IDENTITY);
+                gl.stencil_mask(0xFF);
+                gl.stencil_func(WebGl2RenderingContext::LESS, CLIP_BIT, 0xFF);
+                gl.stencil_op(WebGl2RenderingContext::ZERO, WebGl2RenderingContext::ZERO, WebGl2RenderingContext::REPLACE);
+                draw_triangles(gl, &FULLSCREEN);
+            }
+
+            gl.color_mask(true, true, true, true);
+            gl.bind_vertex_array(None);
+        }
+
+        if scissor.is_some() {
+            gl.enable(WebGl2RenderingContext::SCISSOR_TEST);
+        }
+    }
+
+    // First pass of a stencil-then-cover path fill: accumulate winding numbers without
+    // touching the color buffer
+    pub(crate) fn begin_path_fill(&self, gl: &WebGl2RenderingContext, rule: FillRule) {
+        gl.enable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.color_mask(false, false, false, false);
+        begin_winding(gl, rule);
+    }
+
+    // Second pass: color the pixels inside the path (and the clip), clearing the winding
+    // numbers as the cover geometry passes over them
+    pub(crate) fn begin_path_cover(&self, gl: &WebGl2RenderingContext) {
+        gl.color_mask(true, true, true, true);
+        gl.stencil_mask(WINDING_BITS);
+        if self.stencil_paths.is_empty() {
+            gl.stencil_func(WebGl2RenderingContext::NOTEQUAL, 0, WINDING_BITS);
+        } else {
+            // Clip bit set and a non-zero winding number
+            gl.stencil_func(WebGl2RenderingContext::LESS, CLIP_BIT, 0xFF);
+        }
+        gl.stencil_op(WebGl2RenderingContext::ZERO, WebGl2RenderingContext::ZERO, WebGl2RenderingContext::ZERO);
+    }
+
+    // Between the passes: color the feathered edges of an antialiased path, only where they
+    // fall outside of it (and inside the clip), leaving the winding numbers alone. Both rules
+    // only leave zero winding bits outside the path
+    pub(crate) fn begin_path_fringe(&self, gl: &WebGl2RenderingContext) {
+        gl.color_mask(true, true, true, true);
+        gl.stencil_mask(0);
+        if self.stencil_paths.is_empty() {
+            gl.stencil_func(WebGl2RenderingContext::EQUAL, 0, WINDING_BITS);
+        } else {
+            gl.stencil_func(WebGl2RenderingContext::EQUAL, CLIP_BIT, 0xFF);
+        }
+        gl.stencil_op(WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP);
+    }
+
+    pub(crate) fn end_path_fill(&self, gl: &WebGl2RenderingContext) {
+        self.set_draw_state(gl);
+    }
+
+    // Forget the applied clip after drawing into another framebuffer, so the next apply sets
+    // the scissor box and stencil state again. The stencil mask itself is still valid
+    pub(crate) fn invalidate(&mut self) {
+        self.applied = None;
+    }
+
+    // Leave the GL state unclipped once the frame is drawn. The stencil buffer is not kept
+    // across frames, so the next clip rebuilds it
+    pub(crate) fn finish(&mut self, gl: &WebGl2RenderingContext) {
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.disable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.stencil_mask(0xFF);
+        self.applied = None;
+        self.stencil_paths.clear();
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_program(Some(&self.program));
+        gl.delete_vertex_array(Some(&self.vao));
+        gl.delete_buffer(Some(&self.buffer));
+    }
+
+    // Device pixel box [x0, y0, x1, y1] (bottom-left origin) covering the pixel centers of a
+    // world rectangle
+    fn device_rect(&self, rect: &[f32; 4], matrix: &[f32; 16]) -> [i32; 4] {
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        let [x, y, w, h] = *rect;
+        let corners = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+
+        let mut bounds = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        for (cx, cy) in corners {
+            let clip_w = matrix[3] * cx + matrix[7] * cy + matrix[15];
+            let nx = (matrix[0] * cx + matrix[4] * cy + matrix[12]) / clip_w;
+            let ny = (matrix[1] * cx + matrix[5] * cy + matrix[13]) / clip_w;
+            let dx = (nx + 1.0) * 0.5 * width;
+            let dy = (ny + 1.0) * 0.5 * height;
+            bounds = [bounds[0].min(dx), bounds[1].min(dy), bounds[2].max(dx), bounds[3].max(dy)];
+        }
+
+        [
+            bounds[0].round().clamp(0.0, width) as i32,
+            bounds[1].round().clamp(0.0, height) as i32,
+            bounds[2].round().clamp(0.0, width) as i32,
+            bounds[3].round().clamp(0.0, height) as i32,
+        ]
+    }
+}
+
+// Stencil state counting windings in the low bits: front faces increment and back faces
+// decrement for nonzero, every face toggles the lowest bit for evenodd
+fn begin_winding(gl: &WebGl2RenderingContext, rule: FillRule) {
+    gl.stencil_func(WebGl2RenderingContext::ALWAYS, 0, 0xFF);
+    match rule {
+        FillRule::NonZero => {
+            gl.stencil_mask(WINDING_BITS);
+            gl.stencil_op_separate(
+                WebGl2RenderingContext::FRONT,
+                WebGl2RenderingContext::KEEP,
+                WebGl2RenderingContext::KEEP,
+                WebGl2RenderingContext::INCR_WRAP,
+            );
+            gl.stencil_op_separate(
+                WebGl2RenderingContext::BACK,
+                WebGl2RenderingContext::KEEP,
+                WebGl2RenderingContext::KEEP,
+                WebGl2RenderingContext::DECR_WRAP,
+            );
+        }
+        FillRule::EvenOdd => {
+            gl.stencil_mask(0x01);
+            gl.stencil_op(WebGl2RenderingContext::KEEP, WebGl2RenderingContext::KEEP, WebGl2RenderingContext::INVERT);
+        }
+    }
+}
+
+fn draw_triangles(gl: &WebGl2RenderingContext, triangles: &[f32]) {
+    let array = Float32Array::from(triangles);
+    gl.buffer_data_with_array_buffer_view(WebGl2RenderingContext::ARRAY_BUFFER, &array, WebGl2RenderingContext::STREAM_DRAW);
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, (triangles.len() / 2) as i32);
+}
+
+fn same_paths(a: &[Rc<ClipPath>], b: &[Rc<ClipPath>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| Rc::ptr_eq(a, b))
+}
+
+fn intersect(a: [i32; 4], b: [i32; 4]) -> [i32; 4] {
+    [a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])]
+}
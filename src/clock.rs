@@ -0,0 +1,216 @@
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::Performance;
+
+// Step of the fixed mode when none is given, in seconds
+const DEFAULT_STEP: f64 = 1.0 / 60.0;
+
+// How scene time advances from one rendered frame to the next
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum ClockMode {
+    // Follows the wall clock
+    RealTime,
+    // Only moves when set_time is called
+    Manual,
+    // Advances by the same step every frame, in seconds
+    FixedStep(f64),
+}
+
+impl ClockMode {
+    pub(crate) fn parse(mode: &str, step: Option<f64>) -> Result<ClockMode, JsValue> {
+        Ok(match mode {
+            "realtime" => ClockMode::RealTime,
+            "manual" => ClockMode::Manual,
+            "fixed" => {
+                let step = step.unwrap_or(DEFAULT_STEP);
+                if !(step.is_finite() && step > 0.0) {
+                    return Err(JsValue::from_str("Fixed time step must be a positive number of seconds"));
+                }
+                ClockMode::FixedStep(step)
+            }
+            _ => return Err(JsValue::from_str(&format!("Invalid clock mode \"{}\"", mode))),
+        })
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ClockMode::RealTime => "realtime",
+            ClockMode::Manual => "manual",
+            ClockMode::FixedStep(_) => "fixed",
+        }
+    }
+}
+
+// Scene time in seconds that render() animates with. Pausing stops time in every mode but
+// set_time, and the time scale speeds up, slows down or reverses the real-time and fixed
+// steps. Only the real-time mode reads the wall clock, so the other modes render the same
+// frames on every run
+pub(crate) struct Clock {
+    mode: ClockMode,
+    paused: bool,
+    scale: f64,
+    // Current scene time, and the scene time of the previous frame
+    time: f64,
+    frame_time: f64,
+    // Wall clock reading of the previous frame in milliseconds, None until the first frame
+    // in real time
+    wall_time: Option<f64>,
+}
+
+impl Clock {
+    // A real-time clock starting at zero with the first frame
+    pub(crate) fn new() -> Self {
+        Clock {
+            mode: ClockMode::RealTime,
+            paused: false,
+            scale: 1.0,
+            time: 0.0,
+            frame_time: 0.0,
+            wall_time: None,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    // Switching to real time continues from the current scene time at the next frame
+    pub(crate) fn set_mode(&mut self, mode: ClockMode) {
+        self.mode = mode;
+        self.wall_time = None;
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub(crate) fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    pub(crate) fn set_scale(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
+    pub(crate) fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub(crate) fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    // Advance to the next frame, returns the scene time and how far it moved since the
+    // previous frame, negative after scrubbing backwards
+    pub(crate) fn tick(&mut self) -> (f64, f64) {
+        let step = match self.mode {
+            ClockMode::RealTime => {
+                // The wall clock is followed while paused too, so resuming does not jump
+                let now = wall_clock();
+                let previous = self.wall_time.replace(now).unwrap_or(now);
+                (now - previous) / 1000.0
+            }
+            ClockMode::Manual => 0.0,
+            ClockMode::FixedStep(step) => step,
+        };
+        if !self.paused {
+            self.time += step * self.scale;
+        }
+
+        let delta = self.time - self.frame_time;
+        self.frame_time = self.time;
+        (self.time, delta)
+    }
+}
+
+// Milliseconds from performance.now() of the global scope, which exists in windows and
+// workers alike, or from Date.now() where there is no performance timer
+fn wall_clock() -> f64 {
+    Reflect::get(&js_sys::global(), &JsValue::from_str("performance"))
+        .ok()
+        .and_then(|performance| performance.dyn_into::<Performance>().ok())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clocks in the modes that never read the wall clock, which needs a JavaScript host
+    fn clock(mode: ClockMode) -> Clock {
+        let mut clock = Clock::new();
+        clock.set_mode(mode);
+        clock
+    }
+
+    fn assert_tick(clock: &mut Clock, time: f64, delta: f64) {
+        let (actual_time, actual_delta) = clock.tick();
+        assert!((actual_time - time).abs() < 1e-12, "time {} for {}", actual_time, time);
+        assert!((actual_delta - delta).abs() < 1e-12, "delta {} for {}", actual_delta, delta);
+    }
+
+    #[test]
+    fn fixed_steps_advance_the_same_every_frame() {
+        let mut clock = clock(ClockMode::FixedStep(0.25));
+        assert_eq!(clock.mode(), ClockMode::FixedStep(0.25));
+        for frame in 1..=4 {
+            assert_tick(&mut clock, frame as f64 * 0.25, 0.25);
+        }
+    }
+
+    #[test]
+    fn manual_time_moves_only_when_set() {
+        let mut clock = clock(ClockMode::Manual);
+        assert_tick(&mut clock, 0.0, 0.0);
+        clock.set_time(2.0);
+        assert_tick(&mut clock, 2.0, 2.0);
+        assert_tick(&mut clock, 2.0, 0.0);
+        // Scrubbing backwards gives a negative delta
+        clock.set_time(0.5);
+        assert_tick(&mut clock, 0.5, -1.5);
+    }
+
+    #[test]
+    fn pausing_stops_everything_but_set_time() {
+        let mut clock = clock(ClockMode::FixedStep(0.5));
+        assert_tick(&mut clock, 0.5, 0.5);
+        clock.set_paused(true);
+        assert!(clock.is_paused());
+        assert_tick(&mut clock, 0.5, 0.0);
+        clock.set_time(3.0);
+        assert_tick(&mut clock, 3.0, 2.5);
+        clock.set_paused(false);
+        assert_tick(&mut clock, 3.5, 0.5);
+    }
+
+    #[test]
+    fn the_scale_speeds_up_and_reverses_steps() {
+        let mut clock = clock(ClockMode::FixedStep(0.5));
+        clock.set_scale(2.0);
+        assert_tick(&mut clock, 1.0, 1.0);
+        clock.set_scale(-0.5);
+        assert_eq!(clock.scale(), -0.5);
+        assert_tick(&mut clock, 0.75, -0.25);
+        assert_tick(&mut clock, 0.5, -0.25);
+        // Switching modes keeps the scene time
+        clock.set_mode(ClockMode::Manual);
+        assert_tick(&mut clock, 0.5, 0.0);
+        assert_eq!(clock.time(), 0.5);
+    }
+
+    #[test]
+    fn modes_parse_from_their_names() {
+        assert_eq!(ClockMode::parse("realtime", None).unwrap(), ClockMode::RealTime);
+        assert_eq!(ClockMode::parse("manual", Some(3.0)).unwrap(), ClockMode::Manual);
+        assert_eq!(ClockMode::parse("fixed", None).unwrap(), ClockMode::FixedStep(DEFAULT_STEP));
+        assert_eq!(ClockMode::parse("fixed", Some(0.1)).unwrap(), ClockMode::FixedStep(0.1));
+        for mode in [ClockMode::RealTime, ClockMode::Manual, ClockMode::FixedStep(0.1)] {
+            assert_eq!(ClockMode::parse(mode.name(), Some(0.1)).unwrap(), mode);
+        }
+    }
+}
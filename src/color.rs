@@ -0,0 +1,759 @@
+use wasm_bindgen::prelude::*;
+
+// CSS named colors (CSS Color Module Level 4), as 0xRRGGBB
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+// CIE Lab reference white (D50, as CSS uses for lab() and lch())
+const D50_WHITE: [f32; 3] = [0.96422, 1.0, 0.82521];
+
+// Linear sRGB to CIE XYZ relative to D50 (Bradford-adapted), and back
+const LINEAR_SRGB_TO_XYZ_D50: [[f32; 3]; 3] = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+const XYZ_D50_TO_LINEAR_SRGB: [[f32; 3]; 3] = [
+    [3.133_856, -1.616_866_7, -0.490_614_6],
+    [-0.978_768_4, 1.916_141_5, 0.033_454_0],
+    [0.071_945_3, -0.228_991_4, 1.405_242_7],
+];
+
+// A straight-alpha sRGB color with channels in [0, 1]. Parses CSS color strings and converts
+// to and from linear sRGB, HSL, HSV, HWB, CIE Lab/LCH and OKLab/OKLCH. Hues are in degrees
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+#[wasm_bindgen]
+impl Color {
+    #[wasm_bindgen(constructor)]
+    pub fn new(r: f32, g: f32, b: f32, a: Option<f32>) -> Color {
+        Color { r, g, b, a: a.unwrap_or(1.0) }
+    }
+
+    // Parse a CSS color: #rgb, #rgba, #rrggbb, #rrggbbaa, rgb(), rgba(), hsl(), hsla(), hwb(),
+    // lab(), lch(), oklab(), oklch(), a named color or "transparent"
+    #[wasm_bindgen]
+    pub fn parse(css: &str) -> Result<Color, JsValue> {
+        parse_color(css).map(Color::from_array)
+    }
+
+    // From linear-light sRGB channels
+    #[wasm_bindgen]
+    pub fn from_linear(r: f32, g: f32, b: f32, a: Option<f32>) -> Color {
+        Color::clipped([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b)], a)
+    }
+
+    // Hue in degrees, saturation and lightness in [0, 1]
+    #[wasm_bindgen]
+    pub fn from_hsl(h: f32, s: f32, l: f32, a: Option<f32>) -> Color {
+        let (r, g, b) = hsl_to_rgb(h.rem_euclid(360.0) / 360.0, s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        Color::clipped([r, g, b], a)
+    }
+
+    // Hue in degrees, saturation and value in [0, 1]
+    #[wasm_bindgen]
+    pub fn from_hsv(h: f32, s: f32, v: f32, a: Option<f32>) -> Color {
+        Color::clipped(hsv_to_rgb(h, s.clamp(0.0, 1.0), v.clamp(0.0, 1.0)), a)
+    }
+
+    // Hue in degrees, whiteness and blackness in [0, 1]
+    #[wasm_bindgen]
+    pub fn from_hwb(h: f32, w: f32, b: f32, a: Option<f32>) -> Color {
+        Color::clipped(hwb_to_rgb(h, w.max(0.0), b.max(0.0)), a)
+    }
+
+    // CIE Lab (D50) with lightness in [0, 100]
+    #[wasm_bindgen]
+    pub fn from_lab(l: f32, a: f32, b: f32, alpha: Option<f32>) -> Color {
+        Color::clipped(lab_to_rgb([l, a, b]), alpha)
+    }
+
+    // CIE LCH (D50) with lightness in [0, 100] and hue in degrees
+    #[wasm_bindgen]
+    pub fn from_lch(l: f32, c: f32, h: f32, alpha: Option<f32>) -> Color {
+        Color::clipped(lab_to_rgb(from_polar([l, c, h])), alpha)
+    }
+
+    // OKLab with lightness in [0, 1]
+    #[wasm_bindgen]
+    pub fn from_oklab(l: f32, a: f32, b: f32, alpha: Option<f32>) -> Color {
+        Color::clipped(oklab_to_rgb([l, a, b]), alpha)
+    }
+
+    // OKLCH with lightness in [0, 1] and hue in degrees
+    #[wasm_bindgen]
+    pub fn from_oklch(l: f32, c: f32, h: f32, alpha: Option<f32>) -> Color {
+        Color::clipped(oklab_to_rgb(from_polar([l, c, h])), alpha)
+    }
+
+    // [r, g, b] in linear light
+    #[wasm_bindgen]
+    pub fn to_linear(&self) -> Vec<f32> {
+        vec![srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b)]
+    }
+
+    // [h, s, l], hue in degrees
+    #[wasm_bindgen]
+    pub fn to_hsl(&self) -> Vec<f32> {
+        let (max, min, h) = self.hue();
+        let l = (max + min) * 0.5;
+        let d = max - min;
+        let s = if d == 0.0 { 0.0 } else { d / (1.0 - (2.0 * l - 1.0).abs()) };
+        vec![h, s, l]
+    }
+
+    // [h, s, v], hue in degrees
+    #[wasm_bindgen]
+    pub fn to_hsv(&self) -> Vec<f32> {
+        let (max, min, h) = self.hue();
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+        vec![h, s, max]
+    }
+
+    // [h, w, b], hue in degrees
+    #[wasm_bindgen]
+    pub fn to_hwb(&self) -> Vec<f32> {
+        let (max, min, h) = self.hue();
+        vec![h, min, 1.0 - max]
+    }
+
+    // [l, a, b] in CIE Lab (D50)
+    #[wasm_bindgen]
+    pub fn to_lab(&self) -> Vec<f32> {
+        rgb_to_lab(self.rgb()).to_vec()
+    }
+
+    // [l, c, h] in CIE LCH (D50), hue in degrees
+    #[wasm_bindgen]
+    pub fn to_lch(&self) -> Vec<f32> {
+        to_polar(rgb_to_lab(self.rgb())).to_vec()
+    }
+
+    // [l, a, b] in OKLab
+    #[wasm_bindgen]
+    pub fn to_oklab(&self) -> Vec<f32> {
+        rgb_to_oklab(self.rgb()).to_vec()
+    }
+
+    // [l, c, h] in OKLCH, hue in degrees
+    #[wasm_bindgen]
+    pub fn to_oklch(&self) -> Vec<f32> {
+        to_polar(rgb_to_oklab(self.rgb())).to_vec()
+    }
+
+    // "#rrggbb", or "#rrggbbaa" when translucent
+    #[wasm_bindgen]
+    pub fn to_hex(&self) -> String {
+        let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let hex = format!("#{:02x}{:02x}{:02x}", byte(self.r), byte(self.g), byte(self.b));
+        if self.a < 1.0 {
+            format!("{}{:02x}", hex, byte(self.a))
+        } else {
+            hex
+        }
+    }
+
+    // "rgb(r g b)" or "rgb(r g b / a)", with channels in [0, 255]
+    #[wasm_bindgen]
+    pub fn to_css(&self) -> String {
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round();
+        let rgb = format!("rgb({} {} {}", channel(self.r), channel(self.g), channel(self.b));
+        if self.a < 1.0 {
+            format!("{} / {})", rgb, (self.a.clamp(0.0, 1.0) * 1000.0).round() / 1000.0)
+        } else {
+            format!("{})", rgb)
+        }
+    }
+}
+
+impl Color {
+    pub(crate) fn from_array(rgba: [f32; 4]) -> Color {
+        let [r, g, b, a] = rgba;
+        Color { r, g, b, a }
+    }
+
+    fn clipped(rgb: [f32; 3], a: Option<f32>) -> Color {
+        let [r, g, b] = rgb.map(|c| c.clamp(0.0, 1.0));
+        Color { r, g, b, a: a.unwrap_or(1.0).clamp(0.0, 1.0) }
+    }
+
+    fn rgb(&self) -> [f32; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    // Largest and smallest channels and the hue in degrees shared by HSL, HSV and HWB
+    fn hue(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let d = max - min;
+        let h = if d == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / d).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / d + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / d + 4.0)
+        };
+        (max, min, h)
+    }
+}
+
+// Parse a CSS color string into straight-alpha sRGB
+pub(crate) fn parse_color(css: &str) -> Result<[f32; 4], JsValue> {
+    let input = css.trim().to_ascii_lowercase();
+    let invalid = || JsValue::from_str(&format!("Invalid CSS color \"{}\"", css));
+
+    if let Some(hex) = input.strip_prefix('#') {
+        return parse_hex(hex).ok_or_else(invalid);
+    }
+
+    if let Some(open) = input.find('(') {
+        let name = input[..open].trim();
+        let body = input[open + 1..].strip_suffix(')').ok_or_else(invalid)?;
+        return parse_function(name, body).ok_or_else(invalid);
+    }
+
+    if input == "transparent" {
+        return Ok([0.0, 0.0, 0.0, 0.0]);
+    }
+
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == input)
+        .map(|&(_, rgb)| {
+            let channel = |shift: u32| ((rgb >> shift) & 0xFF) as f32 / 255.0;
+            [channel(16), channel(8), channel(0), 1.0]
+        })
+        .ok_or_else(invalid)
+}
+
+fn parse_hex(hex: &str) -> Option<[f32; 4]> {
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let channels: Vec<u8> = match hex.len() {
+        3 | 4 => (0..hex.len()).map(|i| digit(i).map(|d| d * 17)).collect::<Option<_>>()?,
+        6 | 8 => (0..hex.len() / 2).map(|i| pair(i * 2)).collect::<Option<_>>()?,
+        _ => return None,
+    };
+
+    let alpha = channels.get(3).map_or(1.0, |&a| a as f32 / 255.0);
+    Some([channels[0] as f32 / 255.0, channels[1] as f32 / 255.0, channels[2] as f32 / 255.0, alpha])
+}
+
+// A component of a color function
+#[derive(Copy, Clone)]
+enum Component {
+    Number(f32),
+    Percent(f32),
+    // Degrees
+    Angle(f32),
+    None,
+}
+
+impl Component {
+    fn parse(token: &str) -> Option<Component> {
+        if token == "none" {
+            return Some(Component::None);
+        }
+        if let Some(value) = token.strip_suffix('%') {
+            return parse_number(value).map(Component::Percent);
+        }
+        for (unit, degrees) in [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / std::f32::consts::PI), ("turn", 360.0)] {
+            if let Some(value) = token.strip_suffix(unit) {
+                return parse_number(value).map(|value| Component::Angle(value * degrees));
+            }
+        }
+        parse_number(token).map(Component::Number)
+    }
+
+    // Numbers as they are, percentages scaled so 100% is `full`
+    fn value(self, full: f32) -> Option<f32> {
+        match self {
+            Component::Number(value) => Some(value),
+            Component::Percent(value) => Some(value / 100.0 * full),
+            Component::Angle(_) => None,
+            Component::None => Some(0.0),
+        }
+    }
+
+    fn hue(self) -> Option<f32> {
+        match self {
+            Component::Number(value) | Component::Angle(value) => Some(value),
+            Component::Percent(_) => None,
+            Component::None => Some(0.0),
+        }
+    }
+
+    // Saturation, lightness, whiteness and blackness: percentages, or numbers out of 100
+    fn fraction(self) -> Option<f32> {
+        self.value(100.0).map(|value| value / 100.0)
+    }
+}
+
+fn parse_number(value: &str) -> Option<f32> {
+    value.parse::<f32>().ok().filter(|value| value.is_finite())
+}
+
+// Split the arguments of a color function into three channels and an optional alpha. Both
+// the legacy comma-separated and the modern space-separated syntax with "/ alpha" are accepted
+fn parse_arguments(body: &str) -> Option<([Component; 3], Option<Component>)> {
+    let tokens: Vec<&str> = if body.contains(',') {
+        // The legacy comma syntax takes alpha as a fourth argument, never after a slash
+        if body.contains('/') {
+            return None;
+        }
+        body.split(',').map(str::trim).collect()
+    } else {
+        let (channels, alpha) = match body.split_once('/') {
+            Some((channels, alpha)) => (channels, Some(alpha.trim())),
+            None => (body, None),
+        };
+        let mut tokens: Vec<&str> = channels.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return None;
+        }
+        tokens.extend(alpha);
+        tokens
+    };
+
+    if !(3..=4).contains(&tokens.len()) {
+        return None;
+    }
+
+    let components = tokens.iter().map(|token| Component::parse(token)).collect::<Option<Vec<_>>>()?;
+    Some(([components[0], components[1], components[2]], components.get(3).copied()))
+}
+
+fn parse_function(name: &str, body: &str) -> Option<[f32; 4]> {
+    let ([c0, c1, c2], alpha) = parse_arguments(body)?;
+    let alpha = match alpha {
+        Some(alpha) => alpha.value(1.0)?.clamp(0.0, 1.0),
+        None => 1.0,
+    };
+
+    let rgb = match name {
+        "rgb" | "rgba" => [c0.value(255.0)?, c1.value(255.0)?, c2.value(255.0)?].map(|c| c / 255.0),
+        "hsl" | "hsla" => {
+            let (r, g, b) = hsl_to_rgb(
+                c0.hue()?.rem_euclid(360.0) / 360.0,
+                c1.fraction()?.clamp(0.0, 1.0),
+                c2.fraction()?.clamp(0.0, 1.0),
+            );
+            [r, g, b]
+        }
+        "hwb" => hwb_to_rgb(c0.hue()?, c1.fraction()?.max(0.0), c2.fraction()?.max(0.0)),
+        "lab" => lab_to_rgb([c0.value(100.0)?.max(0.0), c1.value(125.0)?, c2.value(125.0)?]),
+        "lch" => lab_to_rgb(from_polar([c0.value(100.0)?.max(0.0), c1.value(150.0)?.max(0.0), c2.hue()?])),
+        "oklab" => oklab_to_rgb([c0.value(1.0)?.max(0.0), c1.value(0.4)?, c2.value(0.4)?]),
+        "oklch" => oklab_to_rgb(from_polar([c0.value(1.0)?.max(0.0), c1.value(0.4)?.max(0.0), c2.hue()?])),
+        _ => return None,
+    };
+
+    // Out-of-gamut colors are clipped to sRGB
+    let [r, g, b] = rgb.map(|c| c.clamp(0.0, 1.0));
+    Some([r, g, b, alpha])
+}
+
+// sRGB transfer function, from an encoded channel to linear light
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c.abs() <= 0.04045 {
+        c / 12.92
+    } else {
+        c.signum() * ((c.abs() + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Inverse of srgb_to_linear
+pub(crate) fn linear_to_srgb(c: f32) -> f32 {
+    if c.abs() <= 0.0031308 {
+        c * 12.92
+    } else {
+        c.signum() * (1.055 * c.abs().powf(1.0 / 2.4) - 0.055)
+    }
+}
+
+// Convert HSL color to RGB, all components in [0, 1]
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    if s == 0.0 {
+        // Achromatic (gray)
+        return (l, l, l);
+    }
+
+    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
+    let p = 2.0 * l - q;
+
+    (
+        hue_to_rgb(p, q, h + 1.0 / 3.0),
+        hue_to_rgb(p, q, h),
+        hue_to_rgb(p, q, h - 1.0 / 3.0)
+    )
+}
+
+// Helper function for HSL to RGB conversion
+fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
+    if t < 0.0 { t += 1.0; }
+    if t > 1.0 { t -= 1.0; }
+
+    if t < 1.0 / 6.0 {
+        return p + (q - p) * 6.0 * t;
+    }
+    if t < 1.0 / 2.0 {
+        return q;
+    }
+    if t < 2.0 / 3.0 {
+        return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
+    }
+
+    p
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
+    let channel = |n: f32| {
+        let k = (n + h.rem_euclid(360.0) / 60.0) % 6.0;
+        v - v * s * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    [channel(5.0), channel(3.0), channel(1.0)]
+}
+
+fn hwb_to_rgb(h: f32, w: f32, b: f32) -> [f32; 3] {
+    if w + b >= 1.0 {
+        let gray = w / (w + b);
+        return [gray; 3];
+    }
+    let (r, g, bl) = hsl_to_rgb(h.rem_euclid(360.0) / 360.0, 1.0, 0.5);
+    [r, g, bl].map(|c| c * (1.0 - w - b) + w)
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn rgb_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    const EPSILON: f32 = 216.0 / 24389.0;
+    const KAPPA: f32 = 24389.0 / 27.0;
+
+    let xyz = multiply(&LINEAR_SRGB_TO_XYZ_D50, rgb.map(srgb_to_linear));
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let t = xyz[i] / D50_WHITE[i];
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.0) / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_rgb(lab: [f32; 3]) -> [f32; 3] {
+    const EPSILON: f32 = 216.0 / 24389.0;
+    const KAPPA: f32 = 24389.0 / 27.0;
+
+    let [l, a, b] = lab;
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let inverse = |f: f32| {
+        let cube = f * f * f;
+        if cube > EPSILON {
+            cube
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON { fy * fy * fy } else { l / KAPPA };
+    let xyz = [inverse(fx) * D50_WHITE[0], y * D50_WHITE[1], inverse(fz) * D50_WHITE[2]];
+    multiply(&XYZ_D50_TO_LINEAR_SRGB, xyz).map(linear_to_srgb)
+}
+
+// OKLab by Björn Ottosson, from linear sRGB through an LMS cone space
+pub(crate) fn rgb_to_oklab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(srgb_to_linear);
+    let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+pub(crate) fn oklab_to_rgb(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab;
+    let l_ = l + 0.396_337_78 * a + 0.215_803_76 * b;
+    let m_ = l - 0.105_561_346 * a - 0.063_854_17 * b;
+    let s_ = l - 0.089_484_18 * a - 1.291_485_5 * b;
+    let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+    .map(linear_to_srgb)
+}
+
+// [l, a, b] to [l, chroma, hue in degrees]
+fn to_polar(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab;
+    [l, (a * a + b * b).sqrt(), b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+fn from_polar(lch: [f32; 3]) -> [f32; 3] {
+    let [l, c, h] = lch;
+    let h = h.to_radians();
+    [l, c * h.cos(), c * h.sin()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rgba(css: &str, expected: [f32; 4], tolerance: f32) {
+        let rgba = parse_color(css).unwrap_or_else(|_| panic!("{} did not parse", css));
+        for (actual, expected) in rgba.iter().zip(expected) {
+            assert!((actual - expected).abs() <= tolerance, "{} parsed to {:?}", css, rgba);
+        }
+    }
+
+    #[test]
+    fn hex_named_and_rgb_colors() {
+        assert_rgba("#f80", [1.0, 0x88 as f32 / 255.0, 0.0, 1.0], 1e-6);
+        assert_rgba("#ff880080", [1.0, 0x88 as f32 / 255.0, 0.0, 128.0 / 255.0], 1e-6);
+        assert_rgba(" RebeccaPurple ", [0x66 as f32 / 255.0, 0x33 as f32 / 255.0, 0x99 as f32 / 255.0, 1.0], 1e-6);
+        assert_rgba("transparent", [0.0; 4], 0.0);
+        assert_rgba("rgba(255, 0, 51, 0.5)", [1.0, 0.0, 0.2, 0.5], 1e-6);
+        assert_rgba("rgb(100% 0% 20% / 50%)", [1.0, 0.0, 0.2, 0.5], 1e-6);
+        assert_rgba("hsl(120deg 100% 25%)", [0.0, 0.5, 0.0, 1.0], 1e-6);
+        assert_rgba("hsl(0.5turn, 100%, 50%)", [0.0, 1.0, 1.0, 1.0], 1e-6);
+    }
+
+    #[test]
+    fn hwb_lab_and_oklab_colors() {
+        assert_rgba("hwb(120 0% 50%)", [0.0, 0.5, 0.0, 1.0], 1e-6);
+        // Whiteness and blackness adding up past 100% give a gray
+        assert_rgba("hwb(0 60% 60%)", [0.5, 0.5, 0.5, 1.0], 1e-6);
+        // CSS red in each space
+        assert_rgba("lab(54.29 80.8 69.89)", [1.0, 0.0, 0.0, 1.0], 2e-3);
+        assert_rgba("lch(54.29 106.84 40.86)", [1.0, 0.0, 0.0, 1.0], 2e-3);
+        assert_rgba("oklab(0.628 0.2249 0.1258 / 0.25)", [1.0, 0.0, 0.0, 0.25], 2e-3);
+        assert_rgba("oklch(62.8% 0.2577 29.23)", [1.0, 0.0, 0.0, 1.0], 2e-3);
+        assert_rgba("oklab(100% 0 none)", [1.0, 1.0, 1.0, 1.0], 1e-3);
+    }
+
+    // The Err values are JsValues, which only exist on wasm, so the helpers are checked directly
+    #[test]
+    fn malformed_colors_are_rejected() {
+        for hex in ["", "12", "ggg", "12345", "+1234"] {
+            assert!(parse_hex(hex).is_none(), "#{} should not parse", hex);
+        }
+        for (name, body) in [
+            ("rgb", "1 2"),
+            ("rgb", "1 2 3 4"),
+            ("rgb", "1, 2"),
+            ("rgb", "1 2 3 / x"),
+            ("rgb", "1, 2, 3 / 0.5"),
+            ("rgba", "1, 2, 3, / 0.5"),
+            ("hsl", "10, 50%, 50% / 1"),
+            ("rgb", "1 2, 3 / 0.5"),
+            ("hsl", "10% 50% 50%"),
+            ("oklab", "1 2deg 3"),
+            ("color", "srgb 1 0 0"),
+        ] {
+            assert!(parse_function(name, body).is_none(), "{}({}) should not parse", name, body);
+        }
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let colors = [
+            Color::new(1.0, 0.0, 0.0, None),
+            Color::new(0.2, 0.6, 0.4, Some(0.5)),
+            Color::new(0.9, 0.85, 0.1, None),
+            Color::new(0.5, 0.5, 0.5, None),
+        ];
+        type Conversion = (fn(&Color) -> Vec<f32>, fn(f32, f32, f32, Option<f32>) -> Color);
+        let spaces: [Conversion; 8] = [
+            (Color::to_linear, Color::from_linear),
+            (Color::to_hsl, Color::from_hsl),
+            (Color::to_hsv, Color::from_hsv),
+            (Color::to_hwb, Color::from_hwb),
+            (Color::to_lab, Color::from_lab),
+            (Color::to_lch, Color::from_lch),
+            (Color::to_oklab, Color::from_oklab),
+            (Color::to_oklch, Color::from_oklch),
+        ];
+
+        for color in colors {
+            for (to, from) in spaces {
+                let c = to(&color);
+                let back = from(c[0], c[1], c[2], Some(color.a));
+                for (actual, expected) in back.rgb().iter().zip(color.rgb()) {
+                    assert!((actual - expected).abs() < 1e-3, "{:?} came back as {:?}", color, back);
+                }
+                assert_eq!(back.a, color.a);
+            }
+            assert_eq!(Color::parse(&color.to_hex()).unwrap().to_hex(), color.to_hex());
+            assert_eq!(Color::parse(&color.to_css()).unwrap().to_css(), color.to_css());
+        }
+    }
+}
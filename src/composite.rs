@@ -0,0 +1,436 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlFramebuffer, WebGlTexture};
+
+use crate::antialias::blit;
+
+// Texture unit the backdrop copy is bound to, units 0 and 1 hold paints and the glyph atlas
+pub(crate) const BACKDROP_TEXTURE_UNIT: u32 = 2;
+
+// Shared by the shape and sprite fragment shaders, inserted in place of "#pragma composite".
+// composite() takes the premultiplied source color and returns the fragment output
+pub(crate) const COMPOSITE_GLSL: &str = r#"
+// 0 = fixed-function blending, otherwise a separable blend mode evaluated against a copy of
+// the framebuffer: 1 multiply, 2 overlay, 3 darken, 4 lighten, 5 color-dodge, 6 color-burn,
+// 7 hard-light, 8 soft-light, 9 difference, 10 exclusion
+uniform int uBlendMode;
+uniform sampler2D uBackdrop;
+
+float hardLight(float cb, float cs) {
+    if (cs <= 0.5) {
+        return cb * 2.0 * cs;
+    }
+    float s = 2.0 * cs - 1.0;
+    return cb + s - cb * s;
+}
+
+float blendChannel(float cb, float cs) {
+    if (uBlendMode == 1) {
+        return cb * cs;
+    } else if (uBlendMode == 2) {
+        return hardLight(cs, cb);
+    } else if (uBlendMode == 3) {
+        return min(cb, cs);
+    } else if (uBlendMode == 4) {
+        return max(cb, cs);
+    } else if (uBlendMode == 5) {
+        if (cb == 0.0) {
+            return 0.0;
+        }
+        return cs >= 1.0 ? 1.0 : min(1.0, cb / (1.0 - cs));
+    } else if (uBlendMode == 6) {
+        if (cb >= 1.0) {
+            return 1.0;
+        }
+        return cs <= 0.0 ? 0.0 : 1.0 - min(1.0, (1.0 - cb) / cs);
+    } else if (uBlendMode == 7) {
+        return hardLight(cb, cs);
+    } else if (uBlendMode == 8) {
+        if (cs <= 0.5) {
+            return cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb);
+        }
+        float d = cb <= 0.25 ? ((16.0 * cb - 12.0) * cb + 4.0) * cb : sqrt(cb);
+        return cb + (2.0 * cs - 1.0) * (d - cb);
+    } else if (uBlendMode == 9) {
+        return abs(cb - cs);
+    }
+    return cb + cs - 2.0 * cb * cs;
+}
+
+vec4 composite(vec4 color) {
+    if (uBlendMode == 0) {
+        return color;
+    }
+
+    // W3C compositing: mix the blended color in where the backdrop is opaque, then source-over
+    vec4 backdrop = texelFetch(uBackdrop, ivec2(gl_FragCoord.xy), 0);
+    vec3 cb = backdrop.a > 0.0 ? backdrop.rgb / backdrop.a : vec3(0.0);
+    vec3 source = color.a > 0.0 ? color.rgb / color.a : vec3(0.0);
+    vec3 blended = vec3(blendChannel(cb.r, source.r), blendChannel(cb.g, source.g), blendChannel(cb.b, source.b));
+    vec3 cs = mix(source, clamp(blended, 0.0, 1.0), backdrop.a);
+    return vec4(color.a * cs + (1.0 - color.a) * backdrop.rgb, color.a + backdrop.a * (1.0 - color.a));
+}
+"#;
+
+// How drawn pixels combine with the canvas, the values of globalCompositeOperation
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum CompositeOperation {
+    #[default]
+    SourceOver,
+    SourceIn,
+    SourceOut,
+    SourceAtop,
+    DestinationOver,
+    DestinationIn,
+    DestinationOut,
+    DestinationAtop,
+    Copy,
+    Xor,
+    Lighter,
+    Screen,
+    Multiply,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+}
+
+impl CompositeOperation {
+    pub(crate) fn parse(operation: &str) -> Result<CompositeOperation, JsValue> {
+        Ok(match operation {
+            "source-over" => CompositeOperation::SourceOver,
+            "source-in" => CompositeOperation::SourceIn,
+            "source-out" => CompositeOperation::SourceOut,
+            "source-atop" => CompositeOperation::SourceAtop,
+            "destination-over" => CompositeOperation::DestinationOver,
+            "destination-in" => CompositeOperation::DestinationIn,
+            "destination-out" => CompositeOperation::DestinationOut,
+            "destination-atop" => CompositeOperation::DestinationAtop,
+            "copy" => CompositeOperation::Copy,
+            "xor" => CompositeOperation::Xor,
+            "lighter" | "additive" => CompositeOperation::Lighter,
+            "screen" => CompositeOperation::Screen,
+            "multiply" => CompositeOperation::Multiply,
+            "overlay" => CompositeOperation::Overlay,
+            "darken" => CompositeOperation::Darken,
+            "lighten" => CompositeOperation::Lighten,
+            "color-dodge" => CompositeOperation::ColorDodge,
+            "color-burn" => CompositeOperation::ColorBurn,
+            "hard-light" => CompositeOperation::HardLight,
+            "soft-light" => CompositeOperation::SoftLight,
+            "difference" => CompositeOperation::Difference,
+            "exclusion" => CompositeOperation::Exclusion,
+            _ => return Err(JsValue::from_str(&format!("Invalid composite operation \"{}\"", operation))),
+        })
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CompositeOperation::SourceOver => "source-over",
+            CompositeOperation::SourceIn => "source-in",
+            CompositeOperation::SourceOut => "source-out",
+            CompositeOperation::SourceAtop => "source-atop",
+            CompositeOperation::DestinationOver => "destination-over",
+            CompositeOperation::DestinationIn => "destination-in",
+            CompositeOperation::DestinationOut => "destination-out",
+            CompositeOperation::DestinationAtop => "destination-atop",
+            CompositeOperation::Copy => "copy",
+            CompositeOperation::Xor => "xor",
+            CompositeOperation::Lighter => "lighter",
+            CompositeOperation::Screen => "screen",
+            CompositeOperation::Multiply => "multiply",
+            CompositeOperation::Overlay => "overlay",
+            CompositeOperation::Darken => "darken",
+            CompositeOperation::Lighten => "lighten",
+            CompositeOperation::ColorDodge => "color-dodge",
+            CompositeOperation::ColorBurn => "color-burn",
+            CompositeOperation::HardLight => "hard-light",
+            CompositeOperation::SoftLight => "soft-light",
+            CompositeOperation::Difference => "difference",
+            CompositeOperation::Exclusion => "exclusion",
+        }
+    }
+
+    // uBlendMode of the shaders, 0 for operations done by fixed-function blending
+    pub(crate) fn blend_mode(self) -> i32 {
+        match self {
+            CompositeOperation::Multiply => 1,
+            CompositeOperation::Overlay => 2,
+            CompositeOperation::Darken => 3,
+            CompositeOperation::Lighten => 4,
+            CompositeOperation::ColorDodge => 5,
+            CompositeOperation::ColorBurn => 6,
+            CompositeOperation::HardLight => 7,
+            CompositeOperation::SoftLight => 8,
+            CompositeOperation::Difference => 9,
+            CompositeOperation::Exclusion => 10,
+            _ => 0,
+        }
+    }
+
+    // The shader reads a copy of the framebuffer, so every shape needs a fresh copy
+    pub(crate) fn reads_backdrop(self) -> bool {
+        self.blend_mode() != 0
+    }
+
+    // Porter-Duff factors (source RGB, destination RGB, source alpha, destination alpha) for
+    // premultiplied fragment colors. Like every draw call here, operations only affect the
+    // pixels covered by the shape
+    fn blend_factors(self) -> (u32, u32, u32, u32) {
+        use WebGl2RenderingContext as Gl;
+        let (src, dst) = match self {
+            CompositeOperation::SourceOver => (Gl::ONE, Gl::ONE_MINUS_SRC_ALPHA),
+            CompositeOperation::SourceIn => (Gl::DST_ALPHA, Gl::ZERO),
+            CompositeOperation::SourceOut => (Gl::ONE_MINUS_DST_ALPHA, Gl::ZERO),
+            CompositeOperation::SourceAtop => (Gl::DST_ALPHA, Gl::ONE_MINUS_SRC_ALPHA),
+            CompositeOperation::DestinationOver => (Gl::ONE_MINUS_DST_ALPHA, Gl::ONE),
+            CompositeOperation::DestinationIn => (Gl::ZERO, Gl::SRC_ALPHA),
+            CompositeOperation::DestinationOut => (Gl::ZERO, Gl::ONE_MINUS_SRC_ALPHA),
+            CompositeOperation::DestinationAtop => (Gl::ONE_MINUS_DST_ALPHA, Gl::SRC_ALPHA),
+            CompositeOperation::Copy => (Gl::ONE, Gl::ZERO),
+            CompositeOperation::Xor => (Gl::ONE_MINUS_DST_ALPHA, Gl::ONE_MINUS_SRC_ALPHA),
+            CompositeOperation::Lighter => (Gl::ONE, Gl::ONE),
+            // Screen is cs + cb - cs * cb on premultiplied colors
+            CompositeOperation::Screen => return (Gl::ONE, Gl::ONE_MINUS_SRC_COLOR, Gl::ONE, Gl::ONE_MINUS_SRC_ALPHA),
+            // The shader outputs the final color
+            _ => (Gl::ONE, Gl::ZERO),
+        };
+        (src, dst, src, dst)
+    }
+}
+
+// Sets the blend state of draw calls and keeps the framebuffer copy that blend-mode shaders
+// read from, since WebGL2 cannot read the framebuffer while drawing to it
+pub(crate) struct Compositor {
+    backdrop: Option<WebGlTexture>,
+    backdrop_size: (u32, u32),
+    // Drawing buffer size in device pixels
+    viewport: (u32, u32),
+    // The scene is drawn into an sRGB target, copies need a backdrop with the same encoding
+    srgb: bool,
+    // The multisampled scene framebuffer and its resolve framebuffer while drawing with MSAA.
+    // Texture copies cannot read multisampled pixels, they are resolved first
+    multisampled: Option<(WebGlFramebuffer, WebGlFramebuffer)>,
+    applied: Option<CompositeOperation>,
+}
+
+impl Compositor {
+    pub(crate) fn new() -> Compositor {
+        Compositor {
+            backdrop: None,
+            backdrop_size: (0, 0),
+            viewport: (1, 1),
+            srgb: false,
+            multisampled: None,
+            applied: None,
+        }
+    }
+
+    pub(crate) fn set_viewport(&mut self, width: u32, height: u32) {
+        self.viewport = (width, height);
+    }
+
+    // Match the encoding of the framebuffer the scene is drawn into
+    pub(crate) fn set_srgb(&mut self, gl: &WebGl2RenderingContext, srgb: bool) {
+        if self.srgb != srgb {
+            self.srgb = srgb;
+            gl.delete_texture(self.backdrop.take().as_ref());
+        }
+    }
+
+    // Read backdrops through the resolve framebuffer while the scene is multisampled
+    pub(crate) fn set_multisampled(&mut self, framebuffers: Option<(WebGlFramebuffer, WebGlFramebuffer)>) {
+        self.multisampled = framebuffers;
+    }
+
+    // Set the blend state for an operation
+    pub(crate) fn apply(&mut self, gl: &WebGl2RenderingContext, operation: CompositeOperation) {
+        if self.applied == Some(operation) {
+            return;
+        }
+        self.applied = Some(operation);
+
+        let (src_rgb, dst_rgb, src_alpha, dst_alpha) = operation.blend_factors();
+        gl.enable(WebGl2RenderingContext::BLEND);
+        gl.blend_equation(WebGl2RenderingContext::FUNC_ADD);
+        gl.blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+    }
+
+    // Copy the framebuffer pixels under a world-space box [x0, y0, x1, y1] into the backdrop
+    // texture and bind it for the blend-mode shaders
+    pub(crate) fn copy_backdrop(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        bounds: [f32; 4],
+        matrix: &[f32; 16],
+    ) -> Result<(), JsValue> {
+        let area = self.device_bounds(bounds, matrix);
+        self.copy_backdrop_area(gl, area)
+    }
+
+    // Copy a device pixel box [x0, y0, x1, y1] (bottom-left origin) into the backdrop texture
+    pub(crate) fn copy_backdrop_area(&mut self, gl: &WebGl2RenderingContext, area: [i32; 4]) -> Result<(), JsValue> {
+        if self.backdrop.is_none() || self.backdrop_size != self.viewport {
+            self.allocate_backdrop(gl)?;
+        }
+
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0 + BACKDROP_TEXTURE_UNIT);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.backdrop.as_ref());
+
+        let [x0, y0, x1, y1] = area;
+        if x1 > x0 && y1 > y0 {
+            if let Some((scene, resolve)) = &self.multisampled {
+                gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(scene));
+                gl.bind_framebuffer(WebGl2RenderingContext::DRAW_FRAMEBUFFER, Some(resolve));
+                let scissor = gl.is_enabled(WebGl2RenderingContext::SCISSOR_TEST);
+                gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+                blit(gl, area);
+                if scissor {
+                    gl.enable(WebGl2RenderingContext::SCISSOR_TEST);
+                }
+                gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(resolve));
+            }
+            gl.copy_tex_sub_image_2d(WebGl2RenderingContext::TEXTURE_2D, 0, x0, y0, x0, y0, x1 - x0, y1 - y0);
+            if let Some((scene, _)) = &self.multisampled {
+                gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(scene));
+            }
+        }
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+        Ok(())
+    }
+
+    // Back to the straight-alpha blending the polygon scene is drawn with
+    pub(crate) fn finish(&mut self, gl: &WebGl2RenderingContext) {
+        gl.enable(WebGl2RenderingContext::BLEND);
+        gl.blend_equation(WebGl2RenderingContext::FUNC_ADD);
+        gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+        self.applied = None;
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        gl.delete_texture(self.backdrop.take().as_ref());
+    }
+
+    fn allocate_backdrop(&mut self, gl: &WebGl2RenderingContext) -> Result<(), JsValue> {
+        gl.delete_texture(self.backdrop.take().as_ref());
+
+        let texture = gl.create_texture().ok_or("Failed to create backdrop texture")?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        let format = if self.srgb {
+            WebGl2RenderingContext::SRGB8_ALPHA8
+        } else {
+            WebGl2RenderingContext::RGBA8
+        };
+        gl.tex_storage_2d(
+            WebGl2RenderingContext::TEXTURE_2D,
+            1,
+            format,
+            self.viewport.0 as i32,
+            self.viewport.1 as i32,
+        );
+        for parameter in [WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::TEXTURE_MAG_FILTER] {
+            gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, parameter, WebGl2RenderingContext::NEAREST as i32);
+        }
+
+        self.backdrop = Some(texture);
+        self.backdrop_size = self.viewport;
+
+        Ok(())
+    }
+
+    // Device pixel box [x0, y0, x1, y1] (bottom-left origin) covering a world-space box
+    fn device_bounds(&self, bounds: [f32; 4], matrix: &[f32; 16]) -> [i32; 4] {
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        let corners = [(bounds[0], bounds[1]), (bounds[2], bounds[1]), (bounds[2], bounds[3]), (bounds[0], bounds[3])];
+
+        let mut device = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        for (x, y) in corners {
+            let w = matrix[3] * x + matrix[7] * y + matrix[15];
+            let dx = ((matrix[0] * x + matrix[4] * y + matrix[12]) / w + 1.0) * 0.5 * width;
+            let dy = ((matrix[1] * x + matrix[5] * y + matrix[13]) / w + 1.0) * 0.5 * height;
+            device = [device[0].min(dx), device[1].min(dy), device[2].max(dx), device[3].max(dy)];
+        }
+
+        // One extra pixel for antialiased edges
+        [
+            (device[0].floor() - 1.0).clamp(0.0, width) as i32,
+            (device[1].floor() - 1.0).clamp(0.0, height) as i32,
+            (device[2].ceil() + 1.0).clamp(0.0, width) as i32,
+            (device[3].ceil() + 1.0).clamp(0.0, height) as i32,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATIONS: [&str; 22] = [
+        "source-over",
+        "source-in",
+        "source-out",
+        "source-atop",
+        "destination-over",
+        "destination-in",
+        "destination-out",
+        "destination-atop",
+        "copy",
+        "xor",
+        "lighter",
+        "screen",
+        "multiply",
+        "overlay",
+        "darken",
+        "lighten",
+        "color-dodge",
+        "color-burn",
+        "hard-light",
+        "soft-light",
+        "difference",
+        "exclusion",
+    ];
+
+    #[test]
+    fn operations_round_trip_through_their_names() {
+        for name in OPERATIONS {
+            assert_eq!(CompositeOperation::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(CompositeOperation::parse("additive").unwrap(), CompositeOperation::Lighter);
+    }
+
+    #[test]
+    fn only_separable_blend_modes_read_the_backdrop() {
+        let mut modes = Vec::new();
+        for name in OPERATIONS {
+            let operation = CompositeOperation::parse(name).unwrap();
+            if operation.reads_backdrop() {
+                modes.push(operation.blend_mode());
+            }
+        }
+        // Every mode of the shader, each once
+        assert_eq!(modes, (1..=10).collect::<Vec<_>>());
+        assert!(!CompositeOperation::Screen.reads_backdrop());
+    }
+
+    #[test]
+    fn device_bounds_cover_the_box_and_stay_in_the_viewport() {
+        let mut compositor = Compositor::new();
+        compositor.set_viewport(100, 50);
+        // Maps [0, 100] x [0, 50] onto clip space
+        let mut matrix = [0.0; 16];
+        matrix[0] = 2.0 / 100.0;
+        matrix[5] = 2.0 / 50.0;
+        matrix[10] = 1.0;
+        matrix[12] = -1.0;
+        matrix[13] = -1.0;
+        matrix[15] = 1.0;
+
+        assert_eq!(compositor.device_bounds([10.2, 20.5, 30.7, 40.0], &matrix), [9, 19, 32, 41]);
+        assert_eq!(compositor.device_bounds([-20.0, -20.0, 200.0, 200.0], &matrix), [0, 0, 100, 50]);
+    }
+}
@@ -0,0 +1,734 @@
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    WebGl2RenderingContext, WebGlFramebuffer, WebGlProgram, WebGlUniformLocation, WebGlVertexArrayObject
+};
+
+use crate::clip::{Clip, Clipper, CLIP_BIT};
+use crate::color::parse_color;
+use crate::composite::{CompositeOperation, Compositor, BACKDROP_TEXTURE_UNIT};
+use crate::pipeline::{expand_shader, ColorPipeline};
+use crate::postprocess::{create_pass_program, RenderTarget};
+use crate::shadow::{device_box, Shadow, AREA_VERTEX_SRC};
+use crate::{compile_shader, link_program};
+
+// Texture unit of the blurred alpha read by drop shadows, next to the backdrop copy
+const SHADOW_TEXTURE_UNIT: u32 = 3;
+
+// Blurs wider than this standard deviation in device pixels space their taps apart
+const MAX_SIGMA: f32 = 256.0;
+
+// 4x5 color matrix over straight-alpha colors, clamped and premultiplied again afterwards
+const COLOR_MATRIX_FRAGMENT_SRC: &str = r#"
+uniform mat4 uMatrix;
+uniform vec4 uOffset;
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    vec4 straight = color.a > 0.0 ? vec4(color.rgb / color.a, color.a) : vec4(0.0);
+    vec4 result = clamp(uMatrix * straight + uOffset, 0.0, 1.0);
+    outColor = vec4(result.rgb * result.a, result.a);
+}
+"#;
+
+// Separable gaussian over premultiplied colors, uDirection is one texel along the pass
+const BLUR_FRAGMENT_SRC: &str = r#"
+uniform vec2 uDirection;
+uniform float uSigma;
+
+void main() {
+    if (uSigma < 0.01) {
+        outColor = texture(uTexture, vUv);
+        return;
+    }
+
+    // Taps out to three standard deviations, at most 64 of them on each side
+    float extent = ceil(uSigma * 3.0);
+    float stride = max(1.0, extent / 64.0);
+    vec4 sum = texture(uTexture, vUv);
+    float total = 1.0;
+    for (int i = 1; i <= 64; i++) {
+        float x = float(i) * stride;
+        if (x > extent) {
+            break;
+        }
+        float weight = exp(-0.5 * x * x / (uSigma * uSigma));
+        sum += (texture(uTexture, vUv + uDirection * x) + texture(uTexture, vUv - uDirection * x)) * weight;
+        total += 2.0 * weight;
+    }
+    outColor = sum / total;
+}
+"#;
+
+// The layer over its own shadow: blurred alpha shifted by the offset, tinted with the color
+const DROP_SHADOW_FRAGMENT_SRC: &str = r#"
+uniform sampler2D uShadow;
+// Offset in UV space
+uniform vec2 uOffset;
+// Straight-alpha color in the space of the color pipeline
+uniform vec4 uColor;
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    float alpha = texture(uShadow, vUv - uOffset).a;
+    vec4 shadow = vec4(uColor.rgb * uColor.a, uColor.a) * alpha;
+    outColor = color + shadow * (1.0 - color.a);
+}
+"#;
+
+// Draw the filtered layer into the scene with the composite operation of the shapes
+const PRESENT_FRAGMENT_SRC: &str = r#"#version 300 es
+precision highp float;
+
+in vec2 vUv;
+
+uniform sampler2D uLayer;
+
+#pragma composite
+
+out vec4 outColor;
+
+void main() {
+    outColor = composite(texture(uLayer, vUv));
+}
+"#;
+
+// One function of a filter list
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum FilterFunction {
+    // Standard deviation in CSS pixels
+    Blur(f32),
+    // Row-major 4x5 matrix: each output channel is a weighted sum of r, g, b, a plus a constant
+    ColorMatrix([f32; 20]),
+    DropShadow(Shadow),
+}
+
+// A parsed CSS filter value, like the filter property of CanvasRenderingContext2D
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Filter {
+    css: String,
+    functions: Vec<FilterFunction>,
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter {
+            css: "none".to_string(),
+            functions: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    // Parse "none" or a space-separated list of blur(), brightness(), contrast(),
+    // drop-shadow(), grayscale(), hue-rotate(), invert(), opacity(), saturate() and sepia()
+    pub(crate) fn parse(css: &str) -> Result<Filter, JsValue> {
+        let input = css.trim().to_ascii_lowercase();
+        let invalid = || JsValue::from_str(&format!("Invalid filter \"{}\"", css));
+        if input == "none" {
+            return Ok(Filter::default());
+        }
+
+        let mut functions = Vec::new();
+        let mut rest = input.as_str();
+        while !rest.is_empty() {
+            let open = rest.find('(').ok_or_else(invalid)?;
+            let name = rest[..open].trim();
+            let close = matching_paren(rest, open).ok_or_else(invalid)?;
+            if name == "url" {
+                return Err(JsValue::from_str("SVG filter references (url()) are not supported"));
+            }
+            functions.push(parse_function(name, rest[open + 1..close].trim()).ok_or_else(invalid)?);
+            rest = rest[close + 1..].trim_start();
+        }
+
+        if functions.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Filter { css: css.trim().to_string(), functions })
+    }
+
+    pub(crate) fn is_none(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    pub(crate) fn css(&self) -> &str {
+        &self.css
+    }
+}
+
+// Index of the parenthesis closing the one at `open`
+fn matching_paren(input: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in input[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_function(name: &str, args: &str) -> Option<FilterFunction> {
+    // Amounts default to 1 when omitted, the functions that interpolate towards a fixed
+    // result stop at 1
+    let amount = |max: f32| -> Option<f32> {
+        if args.is_empty() {
+            return Some(1.0);
+        }
+        let value = match args.strip_suffix('%') {
+            Some(percent) => parse_number(percent)? / 100.0,
+            None => parse_number(args)?,
+        };
+        (value >= 0.0).then(|| value.min(max))
+    };
+
+    let function = match name {
+        "blur" => FilterFunction::Blur(if args.is_empty() { 0.0 } else { parse_length(args).filter(|v| *v >= 0.0)? }),
+        "brightness" => {
+            let a = amount(f32::INFINITY)?;
+            FilterFunction::ColorMatrix(component_transfer([a, a, a, 1.0], [0.0; 4]))
+        }
+        "contrast" => {
+            let a = amount(f32::INFINITY)?;
+            let intercept = 0.5 - 0.5 * a;
+            FilterFunction::ColorMatrix(component_transfer([a, a, a, 1.0], [intercept, intercept, intercept, 0.0]))
+        }
+        "grayscale" => {
+            let a = 1.0 - amount(1.0)?;
+            FilterFunction::ColorMatrix(rgb_matrix([
+                0.2126 + 0.7874 * a, 0.7152 - 0.7152 * a, 0.0722 - 0.0722 * a,
+                0.2126 - 0.2126 * a, 0.7152 + 0.2848 * a, 0.0722 - 0.0722 * a,
+                0.2126 - 0.2126 * a, 0.7152 - 0.7152 * a, 0.0722 + 0.9278 * a,
+            ]))
+        }
+        "sepia" => {
+            let a = 1.0 - amount(1.0)?;
+            FilterFunction::ColorMatrix(rgb_matrix([
+                0.393 + 0.607 * a, 0.769 - 0.769 * a, 0.189 - 0.189 * a,
+                0.349 - 0.349 * a, 0.686 + 0.314 * a, 0.168 - 0.168 * a,
+                0.272 - 0.272 * a, 0.534 - 0.534 * a, 0.131 + 0.869 * a,
+            ]))
+        }
+        "saturate" => {
+            let s = amount(f32::INFINITY)?;
+            FilterFunction::ColorMatrix(rgb_matrix([
+                0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s,
+                0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s,
+                0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s,
+            ]))
+        }
+        "hue-rotate" => {
+            let angle = if args.is_empty() { 0.0 } else { parse_angle(args)? };
+            let (sin, cos) = angle.to_radians().sin_cos();
+            FilterFunction::ColorMatrix(rgb_matrix([
+                0.213 + cos * 0.787 - sin * 0.213, 0.715 - cos * 0.715 - sin * 0.715, 0.072 - cos * 0.072 + sin * 0.928,
+                0.213 - cos * 0.213 + sin * 0.143, 0.715 + cos * 0.285 + sin * 0.140, 0.072 - cos * 0.072 - sin * 0.283,
+                0.213 - cos * 0.213 - sin * 0.787, 0.715 - cos * 0.715 + sin * 0.715, 0.072 + cos * 0.928 + sin * 0.072,
+            ]))
+        }
+        "invert" => {
+            let a = amount(1.0)?;
+            let slope = 1.0 - 2.0 * a;
+            FilterFunction::ColorMatrix(component_transfer([slope, slope, slope, 1.0], [a, a, a, 0.0]))
+        }
+        "opacity" => FilterFunction::ColorMatrix(component_transfer([1.0, 1.0, 1.0, amount(1.0)?], [0.0; 4])),
+        "drop-shadow" => FilterFunction::DropShadow(parse_drop_shadow(args)?),
+        _ => return None,
+    };
+    Some(function)
+}
+
+// Two or three lengths (offsets and blur) with an optional color before or after them
+fn parse_drop_shadow(args: &str) -> Option<Shadow> {
+    let tokens = split_tokens(args);
+    let color_first = tokens.first().is_some_and(|token| parse_length(token).is_none());
+    let (color, lengths) = if color_first {
+        (Some(tokens[0].to_string()), &tokens[1..])
+    } else {
+        let count = tokens.iter().take_while(|token| parse_length(token).is_some()).count();
+        let color = (count < tokens.len()).then(|| tokens[count..].join(" "));
+        (color, &tokens[..count])
+    };
+
+    let lengths = lengths.iter().map(|token| parse_length(token)).collect::<Option<Vec<_>>>()?;
+    if !(2..=3).contains(&lengths.len()) || lengths.get(2).is_some_and(|blur| *blur < 0.0) {
+        return None;
+    }
+
+    let color = match color.as_deref() {
+        None | Some("currentcolor") => [0.0, 0.0, 0.0, 1.0],
+        Some(color) => parse_color(color).ok()?,
+    };
+    Some(Shadow {
+        color,
+        blur: lengths.get(2).copied().unwrap_or(0.0),
+        offset_x: lengths[0],
+        offset_y: lengths[1],
+        glow: false,
+    })
+}
+
+// Split on whitespace outside parentheses, so "rgb(0 0 0 / 50%)" stays one token
+fn split_tokens(input: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if let Some(first) = start.take() {
+                tokens.push(&input[first..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(first) = start {
+        tokens.push(&input[first..]);
+    }
+    tokens
+}
+
+fn parse_number(value: &str) -> Option<f32> {
+    value.trim().parse::<f32>().ok().filter(|value| value.is_finite())
+}
+
+// CSS pixels, a bare number is only allowed for zero
+fn parse_length(token: &str) -> Option<f32> {
+    match token.strip_suffix("px") {
+        Some(value) => parse_number(value),
+        None => parse_number(token).filter(|value| *value == 0.0),
+    }
+}
+
+// Degrees, a bare number is only allowed for zero
+fn parse_angle(token: &str) -> Option<f32> {
+    for (unit, degrees) in [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / std::f32::consts::PI), ("turn", 360.0)] {
+        if let Some(value) = token.strip_suffix(unit) {
+            return parse_number(value).map(|value| value * degrees);
+        }
+    }
+    parse_number(token).filter(|value| *value == 0.0)
+}
+
+// Per-channel linear function, like feComponentTransfer type="linear"
+fn component_transfer(slope: [f32; 4], intercept: [f32; 4]) -> [f32; 20] {
+    let mut matrix = [0.0; 20];
+    for i in 0..4 {
+        matrix[i * 5 + i] = slope[i];
+        matrix[i * 5 + 4] = intercept[i];
+    }
+    matrix
+}
+
+// Row-major 3x3 matrix on the color channels, alpha unchanged
+fn rgb_matrix(rgb: [f32; 9]) -> [f32; 20] {
+    let mut matrix = [0.0; 20];
+    for row in 0..3 {
+        matrix[row * 5..row * 5 + 3].copy_from_slice(&rgb[row * 3..row * 3 + 3]);
+    }
+    matrix[18] = 1.0;
+    matrix
+}
+
+// The two targets other than `source`
+fn others(source: usize) -> (usize, usize) {
+    match source {
+        0 => (1, 2),
+        1 => (2, 0),
+        _ => (0, 1),
+    }
+}
+
+// Draws shapes with a filter: each draw call is rendered into an offscreen layer, the filter
+// functions run over it as fullscreen passes limited to the box they can reach, and the
+// result is composited into the scene with the clip and composite operation of the shapes
+pub(crate) struct FilterRenderer {
+    vao: WebGlVertexArrayObject,
+    matrix_program: WebGlProgram,
+    matrix_location: Option<WebGlUniformLocation>,
+    matrix_offset_location: Option<WebGlUniformLocation>,
+    blur_program: WebGlProgram,
+    blur_direction_location: Option<WebGlUniformLocation>,
+    blur_sigma_location: Option<WebGlUniformLocation>,
+    drop_shadow_program: WebGlProgram,
+    drop_shadow_location: Option<WebGlUniformLocation>,
+    drop_shadow_offset_location: Option<WebGlUniformLocation>,
+    drop_shadow_color_location: Option<WebGlUniformLocation>,
+    present_program: WebGlProgram,
+    present_rect_location: Option<WebGlUniformLocation>,
+    present_layer_location: Option<WebGlUniformLocation>,
+    present_blend_mode_location: Option<WebGlUniformLocation>,
+    present_backdrop_location: Option<WebGlUniformLocation>,
+    // The layer (with a stencil buffer for path fills) and two targets the passes ping-pong
+    // between, created with the first filtered shape
+    targets: Option<[RenderTarget; 3]>,
+    // Drawing buffer size in device pixels and device pixels per CSS pixel
+    viewport: (u32, u32),
+    pixel_ratio: f32,
+    // Framebuffer the scene was drawn into when the layer started
+    scene: Option<WebGlFramebuffer>,
+    // Functions applied to the current layer and the device pixel box [x0, y0, x1, y1] they reach
+    steps: Vec<FilterFunction>,
+    area: [i32; 4],
+}
+
+impl FilterRenderer {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<FilterRenderer, JsValue> {
+        let matrix_program = create_pass_program(gl, COLOR_MATRIX_FRAGMENT_SRC)?;
+        let blur_program = create_pass_program(gl, BLUR_FRAGMENT_SRC)?;
+        let drop_shadow_program = create_pass_program(gl, DROP_SHADOW_FRAGMENT_SRC)?;
+
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, AREA_VERTEX_SRC)?;
+        let fragment_src = expand_shader(PRESENT_FRAGMENT_SRC);
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, &fragment_src)?;
+        let present_program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let vao = gl.create_vertex_array().ok_or("Failed to create filter vertex array")?;
+
+        Ok(FilterRenderer {
+            vao,
+            matrix_location: gl.get_uniform_location(&matrix_program, "uMatrix"),
+            matrix_offset_location: gl.get_uniform_location(&matrix_program, "uOffset"),
+            matrix_program,
+            blur_direction_location: gl.get_uniform_location(&blur_program, "uDirection"),
+            blur_sigma_location: gl.get_uniform_location(&blur_program, "uSigma"),
+            blur_program,
+            drop_shadow_location: gl.get_uniform_location(&drop_shadow_program, "uShadow"),
+            drop_shadow_offset_location: gl.get_uniform_location(&drop_shadow_program, "uOffset"),
+            drop_shadow_color_location: gl.get_uniform_location(&drop_shadow_program, "uColor"),
+            drop_shadow_program,
+            present_rect_location: gl.get_uniform_location(&present_program, "uRect"),
+            present_layer_location: gl.get_uniform_location(&present_program, "uLayer"),
+            present_blend_mode_location: gl.get_uniform_location(&present_program, "uBlendMode"),
+            present_backdrop_location: gl.get_uniform_location(&present_program, "uBackdrop"),
+            present_program,
+            targets: None,
+            viewport: (1, 1),
+            pixel_ratio: 1.0,
+            scene: None,
+            steps: Vec::new(),
+            area: [0, 0, 0, 0],
+        })
+    }
+
+    pub(crate) fn set_viewport(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        width: u32,
+        height: u32,
+        pixel_ratio: f32,
+    ) -> Result<(), JsValue> {
+        self.viewport = (width, height);
+        self.pixel_ratio = pixel_ratio;
+        if let Some(targets) = self.targets.as_mut() {
+            for target in targets.iter_mut() {
+                target.resize(gl, width, height)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Redirect drawing into the cleared layer for shapes under world-space `bounds`. A shadow
+    // is applied after the filter, like a final drop-shadow(). The stencil is cleared to the
+    // clip bit so path fills work whatever clip the scene has
+    pub(crate) fn begin(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        filter: &Filter,
+        shadow: Option<&Shadow>,
+        bounds: [f32; 4],
+        matrix: &[f32; 16],
+    ) -> Result<(), JsValue> {
+        if self.targets.is_none() {
+            let (width, height) = self.viewport;
+            self.targets = Some([
+                RenderTarget::with_stencil(gl, width, height)?,
+                RenderTarget::new(gl, width, height)?,
+                RenderTarget::new(gl, width, height)?,
+            ]);
+        }
+
+        self.scene = gl
+            .get_parameter(WebGl2RenderingContext::FRAMEBUFFER_BINDING)?
+            .dyn_into::<WebGlFramebuffer>()
+            .ok();
+        self.steps = filter.functions.clone();
+        self.steps.extend(shadow.map(|shadow| FilterFunction::DropShadow(*shadow)));
+
+        // Blurs spread the layer, drop shadows add a shifted and spread copy of it
+        let mut area = device_box(bounds, matrix, self.viewport);
+        for step in &self.steps {
+            match step {
+                FilterFunction::Blur(deviation) => {
+                    let extent = (self.device_sigma(*deviation) * 3.0).ceil();
+                    area = [area[0] - extent, area[1] - extent, area[2] + extent, area[3] + extent];
+                }
+                FilterFunction::DropShadow(shadow) => {
+                    let extent = (self.device_sigma(shadow.blur * 0.5) * 3.0).ceil();
+                    let (dx, dy) = self.device_offset(shadow);
+                    let (dx, dy) = (dx as f32, dy as f32);
+                    area = [
+                        area[0].min(area[0] + dx - extent),
+                        area[1].min(area[1] + dy - extent),
+                        area[2].max(area[2] + dx + extent),
+                        area[3].max(area[3] + dy + extent),
+                    ];
+                }
+                FilterFunction::ColorMatrix(_) => {}
+            }
+        }
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        self.area = [
+            (area[0] - 1.0).clamp(0.0, width) as i32,
+            (area[1] - 1.0).clamp(0.0, height) as i32,
+            (area[2] + 1.0).clamp(0.0, width) as i32,
+            (area[3] + 1.0).clamp(0.0, height) as i32,
+        ];
+
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        gl.clear_stencil(CLIP_BIT);
+        gl.stencil_mask(0xFF);
+        if let Some(targets) = &self.targets {
+            // Blur taps reach past the box, so the whole targets are cleared
+            for target in targets[1..].iter() {
+                target.bind(gl);
+                gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+            }
+            targets[0].bind(gl);
+            gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::STENCIL_BUFFER_BIT);
+        }
+
+        Ok(())
+    }
+
+    // Run the filter over the layer drawn since begin, then composite it into the scene.
+    // Programs and vertex arrays are left unbound
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn finish(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        pipeline: ColorPipeline,
+        composite: CompositeOperation,
+        clip: &Rc<Clip>,
+        matrix: &[f32; 16],
+        clipper: &mut Clipper,
+        compositor: &mut Compositor,
+    ) -> Result<(), JsValue> {
+        let Some(targets) = &self.targets else {
+            return Ok(());
+        };
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        let [x0, y0, x1, y1] = self.area;
+
+        gl.disable(WebGl2RenderingContext::BLEND);
+        gl.disable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.enable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.scissor(x0, y0, (x1 - x0).max(0), (y1 - y0).max(0));
+        gl.bind_vertex_array(Some(&self.vao));
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+        let mut source = 0;
+        for step in &self.steps {
+            let (first, second) = others(source);
+            match step {
+                FilterFunction::ColorMatrix(m) => {
+                    // The uniform is column-major
+                    let columns = [
+                        m[0], m[5], m[10], m[15], m[1], m[6], m[11], m[16], m[2], m[7], m[12], m[17], m[3], m[8],
+                        m[13], m[18],
+                    ];
+                    gl.use_program(Some(&self.matrix_program));
+                    gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, &columns);
+                    gl.uniform4f(self.matrix_offset_location.as_ref(), m[4], m[9], m[14], m[19]);
+                    draw_pass(gl, targets, source, first);
+                    source = first;
+                }
+                FilterFunction::Blur(deviation) => {
+                    self.blur(gl, targets, [source, first, second], self.device_sigma(*deviation));
+                    source = second;
+                }
+                FilterFunction::DropShadow(shadow) => {
+                    // Blur a copy, then draw the layer over it
+                    self.blur(gl, targets, [source, first, second], self.device_sigma(shadow.blur * 0.5));
+                    let (dx, dy) = self.device_offset(shadow);
+                    let color = ColorPipeline { premultiplied: false, ..pipeline }.encode(shadow.color);
+                    gl.use_program(Some(&self.drop_shadow_program));
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE0 + SHADOW_TEXTURE_UNIT);
+                    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[second].texture()));
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+                    gl.uniform1i(self.drop_shadow_location.as_ref(), SHADOW_TEXTURE_UNIT as i32);
+                    gl.uniform2f(self.drop_shadow_offset_location.as_ref(), dx as f32 / width, dy as f32 / height);
+                    gl.uniform4f(self.drop_shadow_color_location.as_ref(), color[0], color[1], color[2], color[3]);
+                    draw_pass(gl, targets, source, first);
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE0 + SHADOW_TEXTURE_UNIT);
+                    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+                    source = first;
+                }
+            }
+        }
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.enable(WebGl2RenderingContext::BLEND);
+
+        // Back to the scene, clipped and composited like the shapes themselves
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, self.scene.as_ref());
+        gl.viewport(0, 0, self.viewport.0 as i32, self.viewport.1 as i32);
+        clipper.invalidate();
+        clipper.apply(gl, clip, matrix);
+        compositor.apply(gl, composite);
+        if x1 > x0 && y1 > y0 {
+            if composite.reads_backdrop() {
+                compositor.copy_backdrop_area(gl, self.area)?;
+            }
+
+            gl.use_program(Some(&self.present_program));
+            gl.bind_vertex_array(Some(&self.vao));
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[source].texture()));
+            gl.uniform1i(self.present_layer_location.as_ref(), 0);
+            gl.uniform1i(self.present_backdrop_location.as_ref(), BACKDROP_TEXTURE_UNIT as i32);
+            gl.uniform1i(self.present_blend_mode_location.as_ref(), composite.blend_mode());
+            gl.uniform4f(
+                self.present_rect_location.as_ref(),
+                x0 as f32 / width,
+                y0 as f32 / height,
+                x1 as f32 / width,
+                y1 as f32 / height,
+            );
+            gl.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        }
+
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+        gl.bind_vertex_array(None);
+        self.scene = None;
+
+        Ok(())
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        if let Some(targets) = self.targets.take() {
+            for target in &targets {
+                target.delete(gl);
+            }
+        }
+        gl.delete_program(Some(&self.matrix_program));
+        gl.delete_program(Some(&self.blur_program));
+        gl.delete_program(Some(&self.drop_shadow_program));
+        gl.delete_program(Some(&self.present_program));
+        gl.delete_vertex_array(Some(&self.vao));
+    }
+
+    // Horizontal pass from the first target into the second, vertical pass into the third
+    fn blur(&self, gl: &WebGl2RenderingContext, targets: &[RenderTarget; 3], order: [usize; 3], sigma: f32) {
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        gl.use_program(Some(&self.blur_program));
+        gl.uniform1f(self.blur_sigma_location.as_ref(), sigma);
+        gl.uniform2f(self.blur_direction_location.as_ref(), 1.0 / width, 0.0);
+        draw_pass(gl, targets, order[0], order[1]);
+        gl.uniform2f(self.blur_direction_location.as_ref(), 0.0, 1.0 / height);
+        draw_pass(gl, targets, order[1], order[2]);
+    }
+
+    // Standard deviation in device pixels of a blur given in CSS pixels
+    fn device_sigma(&self, deviation: f32) -> f32 {
+        (deviation * self.pixel_ratio).min(MAX_SIGMA)
+    }
+
+    // Shadow offset in device pixels. Offsets point down the screen in CSS pixels while the
+    // framebuffer origin is at the bottom
+    fn device_offset(&self, shadow: &Shadow) -> (i32, i32) {
+        (
+            (shadow.offset_x * self.pixel_ratio).round() as i32,
+            -(shadow.offset_y * self.pixel_ratio).round() as i32,
+        )
+    }
+}
+
+// Draw a fullscreen pass of the bound program from one target into another. Passes sample
+// the source on unit 0, which every pass program reads as uTexture by default
+fn draw_pass(gl: &WebGl2RenderingContext, targets: &[RenderTarget; 3], source: usize, destination: usize) {
+    targets[destination].bind(gl);
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[source].texture()));
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_matrix(css: &str) -> [f32; 20] {
+        match Filter::parse(css).unwrap().functions[..] {
+            [FilterFunction::ColorMatrix(matrix)] => matrix,
+            _ => panic!("{} is not a single color matrix", css),
+        }
+    }
+
+    fn assert_matrix(actual: [f32; 20], expected: [f32; 20]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-3, "{:?} is not {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn filter_lists_are_parsed_in_order() {
+        let filter = Filter::parse(" Blur(2px) drop-shadow(rgb(0 0 0 / 50%) 1px 2px 3px) opacity(50%) ").unwrap();
+        assert_eq!(filter.css(), "Blur(2px) drop-shadow(rgb(0 0 0 / 50%) 1px 2px 3px) opacity(50%)");
+        assert_eq!(filter.functions.len(), 3);
+        assert_eq!(filter.functions[0], FilterFunction::Blur(2.0));
+        assert_eq!(
+            filter.functions[1],
+            FilterFunction::DropShadow(Shadow {
+                color: [0.0, 0.0, 0.0, 0.5],
+                blur: 3.0,
+                offset_x: 1.0,
+                offset_y: 2.0,
+                glow: false,
+            })
+        );
+        assert!(Filter::parse("none").unwrap().is_none());
+    }
+
+    #[test]
+    fn neutral_amounts_give_the_identity() {
+        let identity = component_transfer([1.0; 4], [0.0; 4]);
+        for css in ["grayscale(0)", "sepia(0%)", "saturate(1)", "hue-rotate(0)", "hue-rotate(1turn)", "brightness()", "invert(0)"] {
+            assert_matrix(color_matrix(css), identity);
+        }
+        // Amounts past 1 stop at the full effect
+        assert_eq!(color_matrix("invert(250%)"), color_matrix("invert(1)"));
+        assert_matrix(color_matrix("invert(1)"), component_transfer([-1.0, -1.0, -1.0, 1.0], [1.0, 1.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn malformed_functions_are_rejected() {
+        for (name, args) in [
+            ("blur", "2"),
+            ("blur", "-1px"),
+            ("opacity", "-10%"),
+            ("hue-rotate", "90"),
+            ("drop-shadow", "1px"),
+            ("drop-shadow", "1px 2px -3px"),
+            ("swirl", "1"),
+        ] {
+            assert!(parse_function(name, args).is_none(), "{}({}) should not parse", name, args);
+        }
+        assert_eq!(matching_paren("a(b(c)d)e", 1), Some(7));
+        assert_eq!(matching_paren("a(b(c)", 1), None);
+    }
+}
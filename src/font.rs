@@ -0,0 +1,1018 @@
+use crate::cff::Cff;
+
+// Composite glyphs nesting deeper than this are rejected
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+// Outline segment in font units, y pointing up
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Segment {
+    Line([f32; 2], [f32; 2]),
+    Quad([f32; 2], [f32; 2], [f32; 2]),
+    Cubic([f32; 2], [f32; 2], [f32; 2], [f32; 2]),
+}
+
+// Closed contours of a glyph
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Outline {
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl Outline {
+    // Bounds of the control points as (x_min, y_min, x_max, y_max), None for empty glyphs
+    pub(crate) fn bounds(&self) -> Option<[f32; 4]> {
+        let mut bounds: Option<[f32; 4]> = None;
+        let mut include = |p: [f32; 2]| {
+            let b = bounds.get_or_insert([p[0], p[1], p[0], p[1]]);
+            b[0] = b[0].min(p[0]);
+            b[1] = b[1].min(p[1]);
+            b[2] = b[2].max(p[0]);
+            b[3] = b[3].max(p[1]);
+        };
+
+        for segment in &self.segments {
+            match *segment {
+                Segment::Line(a, b) => {
+                    include(a);
+                    include(b);
+                }
+                Segment::Quad(a, b, c) => {
+                    include(a);
+                    include(b);
+                    include(c);
+                }
+                Segment::Cubic(a, b, c, d) => {
+                    include(a);
+                    include(b);
+                    include(c);
+                    include(d);
+                }
+            }
+        }
+
+        bounds
+    }
+
+    // Flatten to line segments after scaling, curves deviate at most `tolerance` from the lines
+    pub(crate) fn lines(&self, scale: f32, tolerance: f32) -> Vec<([f32; 2], [f32; 2])> {
+        let s = |p: [f32; 2]| [p[0] * scale, p[1] * scale];
+        let mut lines = Vec::new();
+
+        for segment in &self.segments {
+            match *segment {
+                Segment::Line(a, b) => lines.push((s(a), s(b))),
+                Segment::Quad(a, b, c) => {
+                    let (a, b, c) = (s(a), s(b), s(c));
+                    let dd = length([a[0] - 2.0 * b[0] + c[0], a[1] - 2.0 * b[1] + c[1]]);
+                    let steps = ((dd / (8.0 * tolerance)).sqrt().ceil() as usize).clamp(1, 64);
+                    let mut previous = a;
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        let p = [
+                            u * u * a[0] + 2.0 * u * t * b[0] + t * t * c[0],
+                            u * u * a[1] + 2.0 * u * t * b[1] + t * t * c[1],
+                        ];
+                        lines.push((previous, p));
+                        previous = p;
+                    }
+                }
+                Segment::Cubic(a, b, c, d) => {
+                    let (a, b, c, d) = (s(a), s(b), s(c), s(d));
+                    let dd = length([a[0] - 2.0 * b[0] + c[0], a[1] - 2.0 * b[1] + c[1]])
+                        .max(length([b[0] - 2.0 * c[0] + d[0], b[1] - 2.0 * c[1] + d[1]]));
+                    let steps = ((0.75 * dd / tolerance).sqrt().ceil() as usize).clamp(1, 64);
+                    let mut previous = a;
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        let p = [
+                            u * u * u * a[0] + 3.0 * u * u * t * b[0] + 3.0 * u * t * t * c[0] + t * t * t * d[0],
+                            u * u * u * a[1] + 3.0 * u * u * t * b[1] + 3.0 * u * t * t * c[1] + t * t * t * d[1],
+                        ];
+                        lines.push((previous, p));
+                        previous = p;
+                    }
+                }
+            }
+        }
+
+        lines
+    }
+}
+
+fn length(v: [f32; 2]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+// Collects segments into closed contours
+#[derive(Default)]
+pub(crate) struct OutlineBuilder {
+    outline: Outline,
+    start: [f32; 2],
+    current: [f32; 2],
+    open: bool,
+}
+
+impl OutlineBuilder {
+    pub(crate) fn move_to(&mut self, p: [f32; 2]) {
+        self.close();
+        self.start = p;
+        self.current = p;
+        self.open = true;
+    }
+
+    pub(crate) fn line_to(&mut self, p: [f32; 2]) {
+        self.outline.segments.push(Segment::Line(self.current, p));
+        self.current = p;
+    }
+
+    pub(crate) fn quad_to(&mut self, control: [f32; 2], p: [f32; 2]) {
+        self.outline.segments.push(Segment::Quad(self.current, control, p));
+        self.current = p;
+    }
+
+    pub(crate) fn cubic_to(&mut self, c1: [f32; 2], c2: [f32; 2], p: [f32; 2]) {
+        self.outline.segments.push(Segment::Cubic(self.current, c1, c2, p));
+        self.current = p;
+    }
+
+    pub(crate) fn close(&mut self) {
+        if self.open && self.current != self.start {
+            self.outline.segments.push(Segment::Line(self.current, self.start));
+        }
+        self.current = self.start;
+        self.open = false;
+    }
+
+    pub(crate) fn finish(mut self) -> Outline {
+        self.close();
+        self.outline
+    }
+}
+
+// Big-endian readers returning None past the end of the data
+pub(crate) fn u8_at(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub(crate) fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Glyph outlines: TrueType quadratic contours or a CFF table with cubic charstrings
+enum Outlines {
+    TrueType { loca: usize, glyf: usize, long_loca: bool },
+    Cff(Cff),
+}
+
+// Character map subtable
+enum CharacterMap {
+    // Segment mapping to delta values (BMP only)
+    Format4(usize),
+    // Segmented coverage (full Unicode)
+    Format12(usize),
+}
+
+// A parsed TrueType or OpenType font. Tables are read lazily from the font bytes
+pub(crate) struct Font {
+    data: Vec<u8>,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    line_gap: f32,
+    glyph_count: u16,
+    hmtx: usize,
+    h_metric_count: u16,
+    cmap: CharacterMap,
+    outlines: Outlines,
+    // Format 0 subtable of the legacy kern table: (pair count, first pair offset)
+    kern_pairs: Option<(usize, usize)>,
+    // GPOS pair adjustment subtables of the "kern" feature
+    gpos_pair_tables: Vec<usize>,
+}
+
+impl Font {
+    // Parse a .ttf/.otf file, or the first font of a .ttc collection
+    pub(crate) fn parse(data: Vec<u8>) -> Result<Font, String> {
+        let mut base = 0;
+        if data.get(0..4) == Some(b"ttcf") {
+            base = u32_at(&data, 12).ok_or("Truncated font collection header")? as usize;
+        }
+
+        let version = u32_at(&data, base).ok_or("Font data is too short")?;
+        let is_cff = match version {
+            0x0001_0000 | 0x7472_7565 => false, // 1.0 or "true"
+            0x4F54_544F => true,                // "OTTO"
+            _ => return Err("Not a TrueType or OpenType font".to_string()),
+        };
+
+        let table_count = u16_at(&data, base + 4).ok_or("Truncated font header")? as usize;
+        let find_table = |tag: &[u8; 4]| -> Option<usize> {
+            (0..table_count).find_map(|i| {
+                let record = base + 12 + i * 16;
+                if data.get(record..record + 4)? == tag {
+                    u32_at(&data, record + 8).map(|offset| offset as usize)
+                } else {
+                    None
+                }
+            })
+        };
+
+        let head = find_table(b"head").ok_or("Font has no head table")?;
+        let hhea = find_table(b"hhea").ok_or("Font has no hhea table")?;
+        let maxp = find_table(b"maxp").ok_or("Font has no maxp table")?;
+        let hmtx = find_table(b"hmtx").ok_or("Font has no hmtx table")?;
+        let cmap_table = find_table(b"cmap").ok_or("Font has no cmap table")?;
+
+        let units_per_em = u16_at(&data, head + 18).ok_or("Truncated head table")?;
+        if units_per_em == 0 {
+            return Err("Font has zero units per em".to_string());
+        }
+        let long_loca = i16_at(&data, head + 50).ok_or("Truncated head table")? != 0;
+
+        let ascender = i16_at(&data, hhea + 4).ok_or("Truncated hhea table")? as f32;
+        let descender = i16_at(&data, hhea + 6).ok_or("Truncated hhea table")? as f32;
+        let line_gap = i16_at(&data, hhea + 8).ok_or("Truncated hhea table")? as f32;
+        let h_metric_count = u16_at(&data, hhea + 34).ok_or("Truncated hhea table")?;
+        if h_metric_count == 0 {
+            return Err("Font has no horizontal metrics".to_string());
+        }
+
+        let glyph_count = u16_at(&data, maxp + 4).ok_or("Truncated maxp table")?;
+        let cmap = parse_cmap(&data, cmap_table)?;
+
+        let outlines = if is_cff {
+            let cff = find_table(b"CFF ").ok_or("OpenType font has no CFF table (CFF2 is not supported)")?;
+            Outlines::Cff(Cff::parse(&data, cff)?)
+        } else {
+            Outlines::TrueType {
+                loca: find_table(b"loca").ok_or("Font has no loca table")?,
+                glyf: find_table(b"glyf").ok_or("Font has no glyf table")?,
+                long_loca,
+            }
+        };
+
+        let kern_pairs = find_table(b"kern").and_then(|kern| parse_kern(&data, kern));
+        let gpos_pair_tables = find_table(b"GPOS")
+            .map(|gpos| parse_gpos_kern(&data, gpos))
+            .unwrap_or_default();
+
+        Ok(Font {
+            data,
+            units_per_em: units_per_em as f32,
+            ascender,
+            descender,
+            line_gap,
+            glyph_count,
+            hmtx,
+            h_metric_count,
+            cmap,
+            outlines,
+            kern_pairs,
+            gpos_pair_tables,
+        })
+    }
+
+    pub(crate) fn units_per_em(&self) -> f32 {
+        self.units_per_em
+    }
+
+    // Ascender above the baseline, in ems
+    pub(crate) fn ascender(&self) -> f32 {
+        self.ascender / self.units_per_em
+    }
+
+    // Descender relative to the baseline in ems, negative below it
+    pub(crate) fn descender(&self) -> f32 {
+        self.descender / self.units_per_em
+    }
+
+    // Distance between consecutive baselines, in ems
+    pub(crate) fn line_spacing(&self) -> f32 {
+        (self.ascender - self.descender + self.line_gap) / self.units_per_em
+    }
+
+    // Glyph for a character, 0 (.notdef) when the font does not cover it
+    pub(crate) fn glyph_index(&self, c: char) -> u16 {
+        let code = c as u32;
+        let glyph = match self.cmap {
+            CharacterMap::Format4(table) => cmap4_lookup(&self.data, table, code),
+            CharacterMap::Format12(table) => cmap12_lookup(&self.data, table, code),
+        };
+        glyph.filter(|glyph| *glyph < self.glyph_count).unwrap_or(0)
+    }
+
+    // Horizontal advance in ems
+    pub(crate) fn advance(&self, glyph: u16) -> f32 {
+        let index = glyph.min(self.h_metric_count - 1) as usize;
+        u16_at(&self.data, self.hmtx + index * 4).unwrap_or(0) as f32 / self.units_per_em
+    }
+
+    // Kerning adjustment between two glyphs in ems, from GPOS when present, else the kern table
+    pub(crate) fn kerning(&self, left: u16, right: u16) -> f32 {
+        let units = if !self.gpos_pair_tables.is_empty() {
+            self.gpos_pair_tables
+                .iter()
+                .find_map(|&table| gpos_pair_adjustment(&self.data, table, left, right))
+                .unwrap_or(0)
+        } else if let Some((count, pairs)) = self.kern_pairs {
+            kern_lookup(&self.data, count, pairs, left, right).unwrap_or(0)
+        } else {
+            0
+        };
+
+        units as f32 / self.units_per_em
+    }
+
+    // Outline of a glyph in font units
+    pub(crate) fn outline(&self, glyph: u16) -> Result<Outline, String> {
+        if glyph >= self.glyph_count {
+            return Err(format!("Glyph {} is out of range", glyph));
+        }
+
+        match &self.outlines {
+            Outlines::TrueType { .. } => {
+                let mut builder = OutlineBuilder::default();
+                self.glyf_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], &mut builder, 0)?;
+                Ok(builder.finish())
+            }
+            Outlines::Cff(cff) => cff.outline(&self.data, glyph),
+        }
+    }
+
+    // Byte range of a glyph in the glyf table, None for empty glyphs
+    fn glyf_range(&self, glyph: u16) -> Option<(usize, usize)> {
+        let Outlines::TrueType { loca, glyf, long_loca } = self.outlines else {
+            return None;
+        };
+
+        let index = glyph as usize;
+        let (start, end) = if long_loca {
+            (u32_at(&self.data, loca + index * 4)? as usize, u32_at(&self.data, loca + index * 4 + 4)? as usize)
+        } else {
+            (
+                u16_at(&self.data, loca + index * 2)? as usize * 2,
+                u16_at(&self.data, loca + index * 2 + 2)? as usize * 2,
+            )
+        };
+
+        if end <= start {
+            return None;
+        }
+        Some((glyf.checked_add(start)?, glyf.checked_add(end)?))
+    }
+
+    // Append a TrueType glyph transformed by [a, b, c, d, dx, dy]
+    fn glyf_outline(
+        &self,
+        glyph: u16,
+        transform: [f32; 6],
+        builder: &mut OutlineBuilder,
+        depth: u32,
+    ) -> Result<(), String> {
+        let Some((start, end)) = self.glyf_range(glyph) else {
+            return Ok(());
+        };
+        let data = self.data.get(start..end).ok_or("Glyph data is out of bounds")?;
+        let contour_count = i16_at(data, 0).ok_or("Truncated glyph header")?;
+
+        if contour_count >= 0 {
+            return simple_glyph(data, contour_count as usize, transform, builder);
+        }
+
+        if depth >= MAX_COMPONENT_DEPTH {
+            return Err("Composite glyph nesting is too deep".to_string());
+        }
+
+        // Composite glyph: a list of transformed component glyphs
+        const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const WE_HAVE_A_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+        const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+        let truncated = || "Truncated composite glyph".to_string();
+        let f2dot14 = |offset: usize| i16_at(data, offset).map(|value| value as f32 / 16384.0);
+        let mut offset = 10;
+        loop {
+            let flags = u16_at(data, offset).ok_or_else(truncated)?;
+            let component = u16_at(data, offset + 2).ok_or_else(truncated)?;
+            offset += 4;
+
+            let (arg1, arg2) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                let args = (
+                    i16_at(data, offset).ok_or_else(truncated)? as f32,
+                    i16_at(data, offset + 2).ok_or_else(truncated)? as f32,
+                );
+                offset += 4;
+                args
+            } else {
+                let args = (
+                    u8_at(data, offset).ok_or_else(truncated)? as i8 as f32,
+                    u8_at(data, offset + 1).ok_or_else(truncated)? as i8 as f32,
+                );
+                offset += 2;
+                args
+            };
+
+            let (mut a, mut b, mut c, mut d) = (1.0, 0.0, 0.0, 1.0);
+            if flags & WE_HAVE_A_SCALE != 0 {
+                a = f2dot14(offset).ok_or_else(truncated)?;
+                d = a;
+                offset += 2;
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                a = f2dot14(offset).ok_or_else(truncated)?;
+                d = f2dot14(offset + 2).ok_or_else(truncated)?;
+                offset += 4;
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                a = f2dot14(offset).ok_or_else(truncated)?;
+                b = f2dot14(offset + 2).ok_or_else(truncated)?;
+                c = f2dot14(offset + 4).ok_or_else(truncated)?;
+                d = f2dot14(offset + 6).ok_or_else(truncated)?;
+                offset += 8;
+            }
+
+            // Point-matched components are rare, they are placed without an offset
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 { (arg1, arg2) } else { (0.0, 0.0) };
+
+            // Compose the component transform with the parent one
+            let [pa, pb, pc, pd, pdx, pdy] = transform;
+            let combined = [
+                pa * a + pc * b,
+                pb * a + pd * b,
+                pa * c + pc * d,
+                pb * c + pd * d,
+                pa * dx + pc * dy + pdx,
+                pb * dx + pd * dy + pdy,
+            ];
+            self.glyf_outline(component, combined, builder, depth + 1)?;
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Decode the contours of a simple TrueType glyph
+fn simple_glyph(
+    data: &[u8],
+    contour_count: usize,
+    transform: [f32; 6],
+    builder: &mut OutlineBuilder,
+) -> Result<(), String> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let truncated = || "Truncated glyph outline".to_string();
+
+    let mut end_points = Vec::with_capacity(contour_count);
+    for i in 0..contour_count {
+        end_points.push(u16_at(data, 10 + i * 2).ok_or_else(truncated)? as usize);
+    }
+    let point_count = match end_points.last() {
+        Some(last) => last + 1,
+        None => return Ok(()),
+    };
+
+    let instruction_length = u16_at(data, 10 + contour_count * 2).ok_or_else(truncated)? as usize;
+    let mut offset = 12 + contour_count * 2 + instruction_length;
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = u8_at(data, offset).ok_or_else(truncated)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let count = u8_at(data, offset).ok_or_else(truncated)?;
+            offset += 1;
+            for _ in 0..count {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    // Coordinates are deltas, stored first for x then for y
+    let mut read_axis = |short: u8, same_or_positive: u8| -> Result<Vec<f32>, String> {
+        let mut values = Vec::with_capacity(point_count);
+        let mut value = 0i32;
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = u8_at(data, offset).ok_or_else(truncated)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += i16_at(data, offset).ok_or_else(truncated)? as i32;
+                offset += 2;
+            }
+            values.push(value as f32);
+        }
+        Ok(values)
+    };
+    let xs = read_axis(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_axis(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let [a, b, c, d, dx, dy] = transform;
+    let point = |i: usize| [a * xs[i] + c * ys[i] + dx, b * xs[i] + d * ys[i] + dy];
+    let midpoint = |p: [f32; 2], q: [f32; 2]| [(p[0] + q[0]) * 0.5, (p[1] + q[1]) * 0.5];
+
+    let mut start = 0;
+    for &end in &end_points {
+        if end < start || end >= point_count {
+            return Err("Invalid glyph contour".to_string());
+        }
+        let indices: Vec<usize> = (start..=end).collect();
+        start = end + 1;
+
+        let on_curve = |i: usize| flags[i] & ON_CURVE != 0;
+
+        // Start at an on-curve point, or between two off-curve points when there is none
+        let first_on = indices.iter().position(|&i| on_curve(i));
+        let (origin, rotation) = match first_on {
+            Some(position) => (point(indices[position]), position),
+            None => (midpoint(point(indices[0]), point(indices[indices.len() - 1])), 0),
+        };
+        builder.move_to(origin);
+
+        let mut control: Option<[f32; 2]> = None;
+        let count = indices.len();
+        let skip = usize::from(first_on.is_some());
+        for k in skip..=count {
+            let i = indices[(rotation + k) % count];
+            let p = if k == count { origin } else { point(i) };
+            let is_on = k == count || on_curve(i);
+
+            match (is_on, control) {
+                (true, None) => builder.line_to(p),
+                (true, Some(ctrl)) => {
+                    builder.quad_to(ctrl, p);
+                    control = None;
+                }
+                (false, None) => control = Some(p),
+                (false, Some(ctrl)) => {
+                    // Two off-curve points in a row imply an on-curve point between them
+                    let mid = midpoint(ctrl, p);
+                    builder.quad_to(ctrl, mid);
+                    control = Some(p);
+                }
+            }
+        }
+        builder.close();
+    }
+
+    Ok(())
+}
+
+// Pick a Unicode subtable, preferring full coverage (format 12) over BMP-only (format 4)
+fn parse_cmap(data: &[u8], cmap: usize) -> Result<CharacterMap, String> {
+    let count = u16_at(data, cmap + 2).ok_or("Truncated cmap table")? as usize;
+    let mut format4 = None;
+
+    for i in 0..count {
+        let record = cmap + 4 + i * 8;
+        let platform = u16_at(data, record).ok_or("Truncated cmap table")?;
+        let encoding = u16_at(data, record + 2).ok_or("Truncated cmap table")?;
+        let offset = cmap + u32_at(data, record + 4).ok_or("Truncated cmap table")? as usize;
+
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if !unicode {
+            continue;
+        }
+
+        match u16_at(data, offset) {
+            Some(12) => return Ok(CharacterMap::Format12(offset)),
+            Some(4) => format4 = format4.or(Some(offset)),
+            _ => {}
+        }
+    }
+
+    format4
+        .map(CharacterMap::Format4)
+        .ok_or_else(|| "Font has no Unicode character map".to_string())
+}
+
+fn cmap4_lookup(data: &[u8], table: usize, code: u32) -> Option<u16> {
+    if code > 0xFFFF {
+        return None;
+    }
+    let code = code as u16;
+
+    let seg_count_x2 = u16_at(data, table + 6)? as usize;
+    let end_codes = table + 14;
+    let start_codes = end_codes + seg_count_x2 + 2;
+    let id_deltas = start_codes + seg_count_x2;
+    let id_range_offsets = id_deltas + seg_count_x2;
+
+    // Segments are sorted by end code
+    let (mut low, mut high) = (0, seg_count_x2 / 2);
+    while low < high {
+        let mid = (low + high) / 2;
+        if u16_at(data, end_codes + mid * 2)? < code {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let segment = low;
+    if segment >= seg_count_x2 / 2 {
+        return None;
+    }
+
+    let start = u16_at(data, start_codes + segment * 2)?;
+    if code < start {
+        return None;
+    }
+
+    let delta = u16_at(data, id_deltas + segment * 2)?;
+    let range_offset = u16_at(data, id_range_offsets + segment * 2)? as usize;
+    if range_offset == 0 {
+        return Some(code.wrapping_add(delta));
+    }
+
+    let glyph_offset = id_range_offsets + segment * 2 + range_offset + (code - start) as usize * 2;
+    let glyph = u16_at(data, glyph_offset)?;
+    if glyph == 0 {
+        return None;
+    }
+    Some(glyph.wrapping_add(delta))
+}
+
+fn cmap12_lookup(data: &[u8], table: usize, code: u32) -> Option<u16> {
+    let group_count = u32_at(data, table + 12)? as usize;
+
+    let (mut low, mut high) = (0, group_count);
+    while low < high {
+        let mid = (low + high) / 2;
+        let group = table + 16 + mid * 12;
+        let start = u32_at(data, group)?;
+        let end = u32_at(data, group + 4)?;
+        if code < start {
+            high = mid;
+        } else if code > end {
+            low = mid + 1;
+        } else {
+            let glyph = u32_at(data, group + 8)?.checked_add(code - start)?;
+            return u16::try_from(glyph).ok();
+        }
+    }
+
+    None
+}
+
+// Find the horizontal format 0 subtable of a version 0 kern table
+fn parse_kern(data: &[u8], kern: usize) -> Option<(usize, usize)> {
+    if u16_at(data, kern)? != 0 {
+        return None;
+    }
+
+    let count = u16_at(data, kern + 2)? as usize;
+    let mut offset = kern + 4;
+    for _ in 0..count {
+        let length = u16_at(data, offset + 2)? as usize;
+        let coverage = u16_at(data, offset + 4)?;
+        let format = coverage >> 8;
+        let horizontal = coverage & 0x1 != 0;
+        let cross_stream = coverage & 0x4 != 0;
+        if format == 0 && horizontal && !cross_stream {
+            return Some((u16_at(data, offset + 6)? as usize, offset + 14));
+        }
+        offset += length;
+    }
+
+    None
+}
+
+fn kern_lookup(data: &[u8], count: usize, pairs: usize, left: u16, right: u16) -> Option<i16> {
+    let key = ((left as u32) << 16) | right as u32;
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        let pair = pairs + mid * 6;
+        let pair_key = u32_at(data, pair)?;
+        match pair_key.cmp(&key) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => return i16_at(data, pair + 4),
+        }
+    }
+
+    None
+}
+
+// Collect the pair adjustment subtables (lookup type 2) used by the "kern" feature
+fn parse_gpos_kern(data: &[u8], gpos: usize) -> Vec<usize> {
+    let mut tables = Vec::new();
+    let Some(feature_list) = u16_at(data, gpos + 6).map(|offset| gpos + offset as usize) else {
+        return tables;
+    };
+    let Some(lookup_list) = u16_at(data, gpos + 8).map(|offset| gpos + offset as usize) else {
+        return tables;
+    };
+
+    let mut lookups: Vec<u16> = Vec::new();
+    let feature_count = u16_at(data, feature_list).unwrap_or(0) as usize;
+    for i in 0..feature_count {
+        let record = feature_list + 2 + i * 6;
+        if data.get(record..record + 4) != Some(b"kern") {
+            continue;
+        }
+        let Some(feature) = u16_at(data, record + 4).map(|offset| feature_list + offset as usize) else {
+            continue;
+        };
+        let index_count = u16_at(data, feature + 2).unwrap_or(0) as usize;
+        for j in 0..index_count {
+            if let Some(index) = u16_at(data, feature + 4 + j * 2) {
+                if !lookups.contains(&index) {
+                    lookups.push(index);
+                }
+            }
+        }
+    }
+    lookups.sort_unstable();
+
+    let lookup_count = u16_at(data, lookup_list).unwrap_or(0);
+    for index in lookups.into_iter().filter(|index| *index < lookup_count) {
+        let Some(lookup) = u16_at(data, lookup_list + 2 + index as usize * 2).map(|offset| lookup_list + offset as usize) else {
+            continue;
+        };
+        let lookup_type = u16_at(data, lookup).unwrap_or(0);
+        let subtable_count = u16_at(data, lookup + 4).unwrap_or(0) as usize;
+
+        for j in 0..subtable_count {
+            let Some(mut subtable) = u16_at(data, lookup + 6 + j * 2).map(|offset| lookup + offset as usize) else {
+                continue;
+            };
+
+            // Extension subtables point to the real subtable with a 32-bit offset
+            let mut subtable_type = lookup_type;
+            if lookup_type == 9 {
+                subtable_type = u16_at(data, subtable + 2).unwrap_or(0);
+                subtable += u32_at(data, subtable + 4).unwrap_or(0) as usize;
+            }
+
+            if subtable_type == 2 {
+                tables.push(subtable);
+            }
+        }
+    }
+
+    tables
+}
+
+// X advance adjustment of a GPOS PairPos subtable, None when the pair is not covered
+fn gpos_pair_adjustment(data: &[u8], table: usize, left: u16, right: u16) -> Option<i16> {
+    let format = u16_at(data, table)?;
+    let coverage = table + u16_at(data, table + 2)? as usize;
+    let value_format1 = u16_at(data, table + 4)?;
+    let value_format2 = u16_at(data, table + 6)?;
+    let coverage_index = coverage_index(data, coverage, left)?;
+
+    let value1_size = value_format1.count_ones() as usize * 2;
+    let value2_size = value_format2.count_ones() as usize * 2;
+
+    // Only the x advance of the first glyph affects horizontal layout
+    let x_advance = |record: usize| -> Option<i16> {
+        if value_format1 & 0x0004 == 0 {
+            return Some(0);
+        }
+        let skipped = (value_format1 & 0x0003).count_ones() as usize * 2;
+        i16_at(data, record + skipped)
+    };
+
+    match format {
+        1 => {
+            let pair_set_count = u16_at(data, table + 8)? as usize;
+            if coverage_index >= pair_set_count {
+                return None;
+            }
+            let pair_set = table + u16_at(data, table + 10 + coverage_index * 2)? as usize;
+            let pair_count = u16_at(data, pair_set)? as usize;
+            let record_size = 2 + value1_size + value2_size;
+
+            // Pair value records are sorted by second glyph
+            let (mut low, mut high) = (0, pair_count);
+            while low < high {
+                let mid = (low + high) / 2;
+                let record = pair_set + 2 + mid * record_size;
+                let second = u16_at(data, record)?;
+                match second.cmp(&right) {
+                    std::cmp::Ordering::Less => low = mid + 1,
+                    std::cmp::Ordering::Greater => high = mid,
+                    std::cmp::Ordering::Equal => return x_advance(record + 2),
+                }
+            }
+            None
+        }
+        2 => {
+            let class_def1 = table + u16_at(data, table + 8)? as usize;
+            let class_def2 = table + u16_at(data, table + 10)? as usize;
+            let class1_count = u16_at(data, table + 12)? as usize;
+            let class2_count = u16_at(data, table + 14)? as usize;
+
+            let class1 = glyph_class(data, class_def1, left)? as usize;
+            let class2 = glyph_class(data, class_def2, right)? as usize;
+            if class1 >= class1_count || class2 >= class2_count {
+                return None;
+            }
+
+            let record = table + 16 + (class1 * class2_count + class2) * (value1_size + value2_size);
+            x_advance(record)
+        }
+        _ => None,
+    }
+}
+
+fn coverage_index(data: &[u8], coverage: usize, glyph: u16) -> Option<usize> {
+    match u16_at(data, coverage)? {
+        1 => {
+            let count = u16_at(data, coverage + 2)? as usize;
+            let (mut low, mut high) = (0, count);
+            while low < high {
+                let mid = (low + high) / 2;
+                let value = u16_at(data, coverage + 4 + mid * 2)?;
+                match value.cmp(&glyph) {
+                    std::cmp::Ordering::Less => low = mid + 1,
+                    std::cmp::Ordering::Greater => high = mid,
+                    std::cmp::Ordering::Equal => return Some(mid),
+                }
+            }
+            None
+        }
+        2 => {
+            let count = u16_at(data, coverage + 2)? as usize;
+            (0..count).find_map(|i| {
+                let range = coverage + 4 + i * 6;
+                let start = u16_at(data, range)?;
+                let end = u16_at(data, range + 2)?;
+                if (start..=end).contains(&glyph) {
+                    Some(u16_at(data, range + 4)? as usize + (glyph - start) as usize)
+                } else {
+                    None
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+// Class of a glyph in a ClassDef table, glyphs not listed are class 0
+fn glyph_class(data: &[u8], class_def: usize, glyph: u16) -> Option<u16> {
+    match u16_at(data, class_def)? {
+        1 => {
+            let start = u16_at(data, class_def + 2)?;
+            let count = u16_at(data, class_def + 4)?;
+            if glyph < start || glyph - start >= count {
+                return Some(0);
+            }
+            u16_at(data, class_def + 6 + (glyph - start) as usize * 2)
+        }
+        2 => {
+            let count = u16_at(data, class_def + 2)? as usize;
+            for i in 0..count {
+                let range = class_def + 4 + i * 6;
+                let start = u16_at(data, range)?;
+                let end = u16_at(data, range + 2)?;
+                if (start..=end).contains(&glyph) {
+                    return u16_at(data, range + 4);
+                }
+            }
+            Some(0)
+        }
+        _ => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes()).collect()
+    }
+
+    // A TrueType font with a 500 x 700 square for 'A' and a composite glyph placing the square
+    // at (100, 50) for 'B'
+    fn test_font() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[8..10].copy_from_slice(&100i16.to_be_bytes());
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+        let maxp = u16s(&[0, 0x5000, 3]);
+        let hmtx = u16s(&[250, 0, 600, 0]);
+
+        // Format 4 with segments for 'A'..'B' and the final 0xFFFF one
+        let mut cmap = u16s(&[0, 1, 3, 1, 0, 12]);
+        cmap.extend(u16s(&[4, 32, 0, 4, 4, 1, 0]));
+        cmap.extend(u16s(&[66, 0xFFFF, 0, 65, 0xFFFF, 1u16.wrapping_sub(65), 1, 0, 0]));
+
+        let mut square = u16s(&[1, 0, 0, 500, 700, 3, 0]);
+        square.extend([0x01; 4]);
+        square.extend(u16s(&[0, 500, 0, (-500i16) as u16, 0, 0, 700, 0]));
+        let composite = u16s(&[(-1i16) as u16, 100, 50, 600, 750, 0x0003, 1, 100, 50]);
+        let mut glyf = square.clone();
+        glyf.extend(&composite);
+        let loca = u16s(&[0, 0, square.len() as u16 / 2, glyf.len() as u16 / 2]);
+
+        let tables: [(&[u8; 4], Vec<u8>); 7] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut font = u16s(&[1, 0, tables.len() as u16, 0, 0, 0]);
+        let mut offset = font.len() + tables.len() * 16;
+        for (tag, table) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            font.extend(table);
+        }
+        font
+    }
+
+    #[test]
+    fn truetype_metrics_and_character_map() {
+        let font = Font::parse(test_font()).unwrap();
+        assert_eq!(font.units_per_em(), 1000.0);
+        assert_eq!(font.ascender(), 0.8);
+        assert_eq!(font.descender(), -0.2);
+        assert!((font.line_spacing() - 1.1).abs() < 1e-6);
+
+        assert_eq!(font.glyph_index('A'), 1);
+        assert_eq!(font.glyph_index('B'), 2);
+        assert_eq!(font.glyph_index('C'), 0);
+        assert_eq!(font.glyph_index('\u{1F600}'), 0);
+
+        // Glyphs past the last long metric share its advance
+        assert_eq!(font.advance(0), 0.25);
+        assert_eq!(font.advance(1), 0.6);
+        assert_eq!(font.advance(2), 0.6);
+        assert_eq!(font.kerning(1, 2), 0.0);
+    }
+
+    #[test]
+    fn simple_and_composite_outlines() {
+        let font = Font::parse(test_font()).unwrap();
+        let square = font.outline(1).unwrap();
+        assert_eq!(square.segments.len(), 4);
+        assert_eq!(square.bounds(), Some([0.0, 0.0, 500.0, 700.0]));
+
+        let moved = font.outline(2).unwrap();
+        assert_eq!(moved.bounds(), Some([100.0, 50.0, 600.0, 750.0]));
+        assert!(font.outline(0).unwrap().segments.is_empty());
+        assert!(font.outline(3).is_err());
+    }
+
+    #[test]
+    fn truncated_fonts_are_rejected() {
+        let data = test_font();
+        for length in [0, 3, 12, 40, 100] {
+            assert!(Font::parse(data[..length].to_vec()).is_err(), "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn format_12_groups_map_past_the_bmp() {
+        // One group mapping U+1F600..U+1F602 to glyphs 7..9
+        let mut table = u16s(&[12, 0, 0, 0, 0, 0, 0, 1]);
+        for value in [0x1F600u32, 0x1F602, 7] {
+            table.extend(value.to_be_bytes());
+        }
+        assert_eq!(cmap12_lookup(&table, 0, 0x1F601), Some(8));
+        assert_eq!(cmap12_lookup(&table, 0, 0x1F603), None);
+        assert_eq!(cmap12_lookup(&table, 0, 'A' as u32), None);
+
+        // A start glyph that would wrap past u32::MAX maps nothing
+        let mut table = u16s(&[12, 0, 0, 0, 0, 0, 0, 1]);
+        for value in [0u32, 0x10FFFF, u32::MAX - 2] {
+            table.extend(value.to_be_bytes());
+        }
+        assert_eq!(cmap12_lookup(&table, 0, 'A' as u32), None);
+    }
+}
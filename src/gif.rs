@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+
+// Animated GIF encoding of captured frames. All frames share one palette quantized from
+// every frame with median cut, so colors stay stable across the loop. GIF transparency is
+// all or nothing, pixels under half opacity become the transparent index
+
+// Colors are counted in buckets of 5 bits per channel
+const BUCKET_BITS: u32 = 5;
+const BUCKET_COUNT: usize = 1 << (BUCKET_BITS * 3);
+const PALETTE_SIZE: usize = 256;
+// GIF codes are at most 12 bits wide
+const MAX_CODE: u16 = (1 << 12) - 1;
+
+// Encode frames of the same size as a GIF that loops forever, delays in centiseconds
+pub(crate) fn encode_gif(width: u32, height: u32, frames: &[Vec<u8>], delays: &[u16]) -> Vec<u8> {
+    let histogram = Histogram::new(frames);
+    let transparent = histogram.transparent;
+    let palette = histogram.palette(if transparent { PALETTE_SIZE - 1 } else { PALETTE_SIZE });
+    let transparent_index = if transparent { palette.len() as u8 } else { 0 };
+    let mut lookup = vec![u16::MAX; BUCKET_COUNT];
+
+    let mut out = b"GIF89a".to_vec();
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    // Global color table of 256 entries, 8 bits per primary
+    out.extend_from_slice(&[0xf7, 0, 0]);
+    for index in 0..PALETTE_SIZE {
+        out.extend_from_slice(&palette.get(index).copied().unwrap_or([0; 3]));
+    }
+    // Loop forever
+    out.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    let mut previous: Option<Vec<u8>> = None;
+    for (frame, &delay) in frames.iter().zip(delays) {
+        let indices: Vec<u8> = frame
+            .chunks_exact(4)
+            .map(|pixel| {
+                if pixel[3] < 128 {
+                    return transparent_index;
+                }
+                let bucket = bucket(pixel);
+                if lookup[bucket] == u16::MAX {
+                    lookup[bucket] = nearest(&palette, bucket_color(bucket)) as u16;
+                }
+                lookup[bucket] as u8
+            })
+            .collect();
+
+        // Opaque frames only replace the rectangle that changed since the previous frame. With
+        // transparency every frame is drawn whole onto a cleared canvas
+        let (x, y, region_width, region_height) = match &previous {
+            Some(previous) if !transparent => changed_region(width, height, previous, &indices),
+            _ => (0, 0, width, height),
+        };
+        let disposal = if transparent { 2 } else { 1 };
+
+        // Graphic control extension: disposal, transparency, delay and transparent index
+        out.extend_from_slice(&[0x21, 0xf9, 4, disposal << 2 | transparent as u8]);
+        out.extend_from_slice(&delay.to_le_bytes());
+        out.extend_from_slice(&[transparent_index, 0]);
+
+        // Image descriptor without a local color table
+        out.push(0x2c);
+        for value in [x, y, region_width, region_height] {
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        out.push(0);
+
+        let region: Vec<u8> = (y..y + region_height)
+            .flat_map(|row| {
+                let start = (row * width + x) as usize;
+                indices[start..start + region_width as usize].iter().copied()
+            })
+            .collect();
+        out.push(8);
+        for block in lzw(&region).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+
+        previous = Some(indices);
+    }
+
+    out.push(0x3b);
+    out
+}
+
+// Bounding box of the pixels that differ as (x, y, width, height), a single pixel when the
+// frames are identical since GIF frames cannot be empty
+fn changed_region(width: u32, height: u32, previous: &[u8], current: &[u8]) -> (u32, u32, u32, u32) {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+    for (index, (a, b)) in previous.iter().zip(current).enumerate() {
+        if a != b {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    if min_x > max_x {
+        return (0, 0, 1, 1);
+    }
+    (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)
+}
+
+fn bucket(pixel: &[u8]) -> usize {
+    let shift = 8 - BUCKET_BITS;
+    ((pixel[0] as usize >> shift) << (BUCKET_BITS * 2))
+        | ((pixel[1] as usize >> shift) << BUCKET_BITS)
+        | (pixel[2] as usize >> shift)
+}
+
+// Center of a bucket in 8-bit channels
+fn bucket_color(bucket: usize) -> [u8; 3] {
+    let mask = (1 << BUCKET_BITS) - 1;
+    let shift = 8 - BUCKET_BITS;
+    let channel = |value: usize| ((value & mask) << shift | 1 << (shift - 1)) as u8;
+    [channel(bucket >> (BUCKET_BITS * 2)), channel(bucket >> BUCKET_BITS), channel(bucket)]
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> usize {
+    let distance = |entry: &[u8; 3]| {
+        entry
+            .iter()
+            .zip(&color)
+            .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    (0..palette.len()).min_by_key(|&index| distance(&palette[index])).unwrap_or(0)
+}
+
+// Pixel counts and channel sums of the opaque pixels in every bucket
+struct Histogram {
+    counts: Vec<u32>,
+    sums: Vec<[u64; 3]>,
+    transparent: bool,
+}
+
+impl Histogram {
+    fn new(frames: &[Vec<u8>]) -> Self {
+        let mut histogram = Histogram {
+            counts: vec![0; BUCKET_COUNT],
+            sums: vec![[0; 3]; BUCKET_COUNT],
+            transparent: false,
+        };
+        for pixel in frames.iter().flat_map(|frame| frame.chunks_exact(4)) {
+            if pixel[3] < 128 {
+                histogram.transparent = true;
+                continue;
+            }
+            let bucket = bucket(pixel);
+            histogram.counts[bucket] += 1;
+            for (sum, &channel) in histogram.sums[bucket].iter_mut().zip(pixel) {
+                *sum += channel as u64;
+            }
+        }
+        histogram
+    }
+
+    // Median cut: keep splitting the box with the most pixels times its widest channel range
+    // at the pixel median of that channel, then use the mean color of each box
+    fn palette(&self, size: usize) -> Vec<[u8; 3]> {
+        let used: Vec<usize> = (0..BUCKET_COUNT).filter(|&bucket| self.counts[bucket] > 0).collect();
+        if used.is_empty() {
+            return vec![[0; 3]];
+        }
+        let mut boxes = vec![used];
+
+        while boxes.len() < size {
+            let Some((index, channel)) = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, buckets)| buckets.len() > 1)
+                .map(|(index, buckets)| {
+                    let (channel, range) = widest_channel(buckets);
+                    let pixels: u64 = buckets.iter().map(|&bucket| self.counts[bucket] as u64).sum();
+                    (index, channel, pixels * range as u64)
+                })
+                .max_by_key(|&(_, _, score)| score)
+                .map(|(index, channel, _)| (index, channel))
+            else {
+                break;
+            };
+
+            let mut buckets = boxes.swap_remove(index);
+            buckets.sort_unstable_by_key(|&bucket| bucket_color(bucket)[channel]);
+            let total: u64 = buckets.iter().map(|&bucket| self.counts[bucket] as u64).sum();
+            let mut seen = 0;
+            let median = buckets
+                .iter()
+                .position(|&bucket| {
+                    seen += self.counts[bucket] as u64;
+                    seen * 2 >= total
+                })
+                .unwrap_or(0);
+            let upper = buckets.split_off((median + 1).min(buckets.len() - 1));
+            boxes.push(buckets);
+            boxes.push(upper);
+        }
+
+        boxes
+            .iter()
+            .map(|buckets| {
+                let mut sum = [0u64; 3];
+                let mut count = 0u64;
+                for &bucket in buckets {
+                    for (total, value) in sum.iter_mut().zip(self.sums[bucket]) {
+                        *total += value;
+                    }
+                    count += self.counts[bucket] as u64;
+                }
+                sum.map(|total| (total / count.max(1)) as u8)
+            })
+            .collect()
+    }
+}
+
+// Channel with the largest spread of bucket colors, and that spread
+fn widest_channel(buckets: &[usize]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = buckets.iter().map(|&bucket| bucket_color(bucket)[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+// Variable width LZW with 8-bit literals, codes packed from the least significant bit
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let mut writer = LzwWriter {
+        out: Vec::new(),
+        buffer: 0,
+        count: 0,
+        width: 9,
+        newest: LzwWriter::END,
+        table: HashMap::with_capacity(MAX_CODE as usize),
+    };
+
+    writer.write(LzwWriter::CLEAR);
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        let Some(code) = prefix else {
+            prefix = Some(index as u16);
+            continue;
+        };
+        if let Some(&entry) = writer.table.get(&(code, index)) {
+            prefix = Some(entry);
+            continue;
+        }
+        writer.write(code);
+        if writer.next_code() {
+            writer.table.insert((code, index), writer.newest);
+        }
+        prefix = Some(index as u16);
+    }
+    if let Some(code) = prefix {
+        writer.write(code);
+        writer.next_code();
+    }
+    writer.write(LzwWriter::END);
+    writer.finish()
+}
+
+struct LzwWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+    // Current code width in bits and the newest code in the table
+    width: u32,
+    newest: u16,
+    // Codes of the strings seen so far, keyed by the code of the string without its last
+    // index and that index
+    table: HashMap<(u16, u8), u16>,
+}
+
+impl LzwWriter {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+
+    fn write(&mut self, code: u16) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += self.width;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Advance to the code of the next table entry, false when the table was full and has
+    // been cleared instead. The width grows once the newest code needs the extra bit, the
+    // timing decoders expect
+    fn next_code(&mut self) -> bool {
+        self.newest += 1;
+        if self.newest == 1 << self.width {
+            self.width += 1;
+        }
+        if self.newest == MAX_CODE {
+            self.write(Self::CLEAR);
+            self.width = 9;
+            self.newest = Self::END;
+            self.table.clear();
+            return false;
+        }
+        true
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Variable width LZW decoder with 8-bit literals, the way GIF readers implement it
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let (mut position, mut width) = (0, 9);
+        let mut read = |width: usize| {
+            let code = (0..width).fold(0, |code, i| {
+                let bit = data[(position + i) / 8] >> ((position + i) % 8) & 1;
+                code | (bit as usize) << i
+            });
+            position += width;
+            code
+        };
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut previous: Option<usize> = None;
+        let mut out = Vec::new();
+        loop {
+            let code = read(width);
+            if code == LzwWriter::CLEAR as usize {
+                table = (0..=255).map(|index| vec![index]).chain([vec![], vec![]]).collect();
+                width = 9;
+                previous = None;
+                continue;
+            }
+            if code == LzwWriter::END as usize {
+                return out;
+            }
+            let entry = match previous {
+                None => table[code].clone(),
+                Some(previous) => {
+                    let mut entry = if code < table.len() { table[code].clone() } else { table[previous].clone() };
+                    assert!(code <= table.len(), "code {} before it was defined", code);
+                    if code == table.len() {
+                        entry.push(table[previous][0]);
+                    }
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                    if table.len() == 1 << width && width < 12 {
+                        width += 1;
+                    }
+                    entry
+                }
+            };
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    struct Frame {
+        delay: u16,
+        disposal: u8,
+        transparent: Option<u8>,
+        region: (usize, usize, usize, usize),
+        indices: Vec<u8>,
+    }
+
+    // Split a GIF written by encode_gif into its global palette and frames
+    fn decode(gif: &[u8]) -> ((usize, usize), Vec<[u8; 3]>, Vec<Frame>) {
+        let number = |at: usize| u16::from_le_bytes([gif[at], gif[at + 1]]);
+        assert_eq!(gif[..6], *b"GIF89a");
+        let size = (number(6) as usize, number(8) as usize);
+        assert_eq!(gif[10], 0xf7);
+        let palette: Vec<[u8; 3]> = gif[13..13 + 768].chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+        let mut at = 13 + 768;
+        assert_eq!(gif[at..at + 19], *b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        at += 19;
+
+        let mut frames = Vec::new();
+        while gif[at] != 0x3b {
+            assert_eq!(gif[at..at + 3], [0x21, 0xf9, 4]);
+            let flags = gif[at + 3];
+            let delay = number(at + 4);
+            let transparent = (flags & 1 == 1).then_some(gif[at + 6]);
+            at += 8;
+            assert_eq!(gif[at], 0x2c);
+            let region = (number(at + 1) as usize, number(at + 3) as usize, number(at + 5) as usize, number(at + 7) as usize);
+            assert_eq!(gif[at + 9], 0, "no local color table");
+            assert_eq!(gif[at + 10], 8, "8-bit minimum code size");
+            at += 11;
+            let mut data = Vec::new();
+            while gif[at] != 0 {
+                let length = gif[at] as usize;
+                data.extend_from_slice(&gif[at + 1..at + 1 + length]);
+                at += 1 + length;
+            }
+            at += 1;
+            let indices = unlzw(&data);
+            assert_eq!(indices.len(), region.2 * region.3);
+            frames.push(Frame { delay, disposal: flags >> 2 & 7, transparent, region, indices });
+        }
+        assert_eq!(at, gif.len() - 1);
+        (size, palette, frames)
+    }
+
+    // Replay the frames onto a canvas and return what a viewer shows for each of them
+    fn play(gif: &[u8]) -> Vec<Vec<[u8; 4]>> {
+        let ((width, height), palette, frames) = decode(gif);
+        let mut canvas = vec![[0u8; 4]; width * height];
+        let mut shown = Vec::new();
+        for frame in &frames {
+            let (x, y, region_width, _) = frame.region;
+            for (i, &index) in frame.indices.iter().enumerate() {
+                if Some(index) == frame.transparent {
+                    continue;
+                }
+                let [r, g, b] = palette[index as usize];
+                canvas[(y + i / region_width) * width + x + i % region_width] = [r, g, b, 255];
+            }
+            shown.push(canvas.clone());
+            if frame.disposal == 2 {
+                canvas.fill([0; 4]);
+            }
+        }
+        shown
+    }
+
+    fn pixels(rgba: &[u8]) -> Vec<[u8; 4]> {
+        rgba.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect()
+    }
+
+    #[test]
+    fn lzw_round_trips_across_table_resets() {
+        // Noise fills the 12-bit table several times over, the runs make long strings
+        let mut state = 1u32;
+        let mut data = Vec::new();
+        for run in 0..3000 {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let byte = (state >> 24) as u8;
+            data.extend(std::iter::repeat_n(byte, if run % 3 == 0 { 40 } else { 1 }));
+        }
+        data.extend((0..40_000).map(|i| (i * 7 % 251) as u8));
+        for length in [0, 1, 2, 300, data.len()] {
+            assert_eq!(unlzw(&lzw(&data[..length])), data[..length]);
+        }
+    }
+
+    #[test]
+    fn few_colors_survive_quantization_exactly() {
+        let colors = [[255, 0, 0, 255], [0, 128, 0, 255], [10, 20, 30, 255], [250, 250, 250, 255]];
+        let frames: Vec<Vec<u8>> = (0..3)
+            .map(|frame| (0..12 * 8).flat_map(|i| colors[(i / 5 + frame) % colors.len()]).collect())
+            .collect();
+        let gif = encode_gif(12, 8, &frames, &[4, 3, 5]);
+
+        let (_, _, decoded) = decode(&gif);
+        assert_eq!(decoded.iter().map(|frame| frame.delay).collect::<Vec<_>>(), [4, 3, 5]);
+        assert!(decoded.iter().all(|frame| frame.disposal == 1 && frame.transparent.is_none()));
+        for (shown, frame) in play(&gif).iter().zip(&frames) {
+            assert_eq!(*shown, pixels(frame));
+        }
+    }
+
+    #[test]
+    fn unchanged_pixels_are_left_out_of_later_frames() {
+        let first = [40, 40, 40, 255].repeat(10 * 10);
+        let mut second = first.clone();
+        second[(3 * 10 + 4) * 4..(3 * 10 + 7) * 4].copy_from_slice(&[200, 200, 200, 255].repeat(3));
+        second[(6 * 10 + 5) * 4] = 90;
+        let gif = encode_gif(10, 10, &[first.clone(), second.clone(), second.clone()], &[1; 3]);
+
+        let (_, _, decoded) = decode(&gif);
+        let regions: Vec<_> = decoded.iter().map(|frame| frame.region).collect();
+        assert_eq!(regions, [(0, 0, 10, 10), (4, 3, 3, 4), (0, 0, 1, 1)]);
+        let shown = play(&gif);
+        assert_eq!(shown[1], shown[2]);
+        assert_eq!(shown[1][3 * 10 + 4], [200, 200, 200, 255]);
+        assert_eq!(shown[1][6 * 10 + 5], [90, 40, 40, 255]);
+    }
+
+    #[test]
+    fn many_colors_are_approximated_closely() {
+        // A hue and brightness sweep with far more colors than the palette holds
+        let (width, height) = (96, 64);
+        let frame: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| [(x * 255 / width) as u8, (y * 255 / height) as u8, ((x + y) * 255 / (width + height)) as u8, 255]))
+            .flatten()
+            .collect();
+        let gif = encode_gif(width as u32, height as u32, std::slice::from_ref(&frame), &[10]);
+
+        let shown = &play(&gif)[0];
+        for (shown, original) in shown.iter().zip(pixels(&frame)) {
+            for channel in 0..3 {
+                assert!((shown[channel] as i32 - original[channel] as i32).abs() <= 24, "{:?} for {:?}", shown, original);
+            }
+        }
+        let average_error: f64 = shown
+            .iter()
+            .zip(pixels(&frame))
+            .map(|(a, b)| (0..3).map(|c| (a[c] as f64 - b[c] as f64).abs()).sum::<f64>())
+            .sum::<f64>()
+            / (width * height * 3) as f64;
+        assert!(average_error < 4.0, "{}", average_error);
+    }
+
+    #[test]
+    fn translucent_pixels_become_the_transparent_index() {
+        let frames = vec![
+            [[255, 0, 0, 255], [0, 0, 255, 100], [0, 255, 0, 128], [0, 0, 0, 0]].concat(),
+            [[0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 0, 0], [0, 255, 0, 200]].concat(),
+        ];
+        let gif = encode_gif(2, 2, &frames, &[2, 2]);
+
+        let (_, palette, decoded) = decode(&gif);
+        for frame in &decoded {
+            assert_eq!((frame.disposal, frame.region), (2, (0, 0, 2, 2)));
+            assert_eq!(frame.transparent, Some(2));
+        }
+        assert_eq!(palette[..2], [[255, 0, 0], [0, 255, 0]]);
+        let shown = play(&gif);
+        assert_eq!(shown[0], [[255, 0, 0, 255], [0; 4], [0, 255, 0, 255], [0; 4]]);
+        assert_eq!(shown[1], [[0; 4], [255, 0, 0, 255], [0; 4], [0, 255, 0, 255]]);
+    }
+}
@@ -0,0 +1,150 @@
+use wasm_bindgen::prelude::*;
+
+use crate::color::parse_color;
+
+// Maximum number of color stops evaluated by the shape shader
+pub(crate) const MAX_GRADIENT_STOPS: usize = 16;
+
+// How a gradient behaves outside of the [0, 1] range
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SpreadMode {
+    // Extend the first and last colors (CanvasRenderingContext2D behavior)
+    Pad,
+    // Tile the gradient
+    Repeat,
+    // Tile the gradient, mirroring every other repetition
+    Reflect,
+}
+
+// Geometry of a gradient, in the coordinate space it was created in
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum GradientKind {
+    Linear { x0: f32, y0: f32, x1: f32, y1: f32 },
+    Radial { x0: f32, y0: f32, r0: f32, x1: f32, y1: f32, r1: f32 },
+    Conic { start_angle: f32, x: f32, y: f32 },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct ColorStop {
+    pub(crate) offset: f32,
+    pub(crate) color: [f32; 4],
+}
+
+// Gradient fill/stroke style, created with Canvas2D::create_*_gradient.
+// Like CanvasGradient the colors are interpolated without premultiplying alpha, unless the
+// canvas uses premultiplied alpha.
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Gradient {
+    kind: GradientKind,
+    stops: Vec<ColorStop>,
+    spread: SpreadMode,
+}
+
+#[wasm_bindgen]
+impl Gradient {
+    // Add a color stop, offset must be in [0, 1]. Stops sharing an offset are kept in
+    // insertion order, which produces a hard transition
+    #[wasm_bindgen]
+    pub fn add_color_stop(&mut self, offset: f32, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+        self.insert_stop(offset, [r, g, b, a])
+    }
+
+    // Add a color stop with a CSS color string, like CanvasGradient.addColorStop
+    #[wasm_bindgen]
+    pub fn add_css_color_stop(&mut self, offset: f32, color: &str) -> Result<(), JsValue> {
+        self.insert_stop(offset, parse_color(color)?)
+    }
+
+    // Choose pad (default), repeat or reflect
+    #[wasm_bindgen]
+    pub fn set_spread_mode(&mut self, spread: SpreadMode) {
+        self.spread = spread;
+    }
+
+    #[wasm_bindgen]
+    pub fn get_spread_mode(&self) -> SpreadMode {
+        self.spread
+    }
+
+    #[wasm_bindgen]
+    pub fn stop_count(&self) -> u32 {
+        self.stops.len() as u32
+    }
+}
+
+impl Gradient {
+    fn new(kind: GradientKind) -> Gradient {
+        Gradient {
+            kind,
+            stops: Vec::new(),
+            spread: SpreadMode::Pad,
+        }
+    }
+
+    pub(crate) fn linear(x0: f32, y0: f32, x1: f32, y1: f32) -> Gradient {
+        Gradient::new(GradientKind::Linear { x0, y0, x1, y1 })
+    }
+
+    pub(crate) fn radial(x0: f32, y0: f32, r0: f32, x1: f32, y1: f32, r1: f32) -> Gradient {
+        Gradient::new(GradientKind::Radial { x0, y0, r0, x1, y1, r1 })
+    }
+
+    pub(crate) fn conic(start_angle: f32, x: f32, y: f32) -> Gradient {
+        Gradient::new(GradientKind::Conic { start_angle, x, y })
+    }
+
+    pub(crate) fn kind(&self) -> GradientKind {
+        self.kind
+    }
+
+    pub(crate) fn stops(&self) -> &[ColorStop] {
+        &self.stops
+    }
+
+    pub(crate) fn spread(&self) -> SpreadMode {
+        self.spread
+    }
+
+    pub(crate) fn replace_stops(&mut self, stops: Vec<ColorStop>) {
+        self.stops = stops;
+    }
+
+    fn insert_stop(&mut self, offset: f32, color: [f32; 4]) -> Result<(), JsValue> {
+        if !(0.0..=1.0).contains(&offset) {
+            return Err(JsValue::from_str("Color stop offset must be between 0 and 1"));
+        }
+
+        if self.stops.len() >= MAX_GRADIENT_STOPS {
+            return Err(JsValue::from_str(&format!("Gradients support at most {} color stops", MAX_GRADIENT_STOPS)));
+        }
+
+        // Insert after any stop with the same offset
+        let index = self.stops.iter().position(|stop| stop.offset > offset).unwrap_or(self.stops.len());
+        self.stops.insert(index, ColorStop { offset, color });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_are_sorted_and_ties_keep_insertion_order() {
+        let mut gradient = Gradient::linear(0.0, 0.0, 100.0, 0.0);
+        gradient.add_color_stop(1.0, 0.0, 0.0, 1.0, 1.0).unwrap();
+        gradient.add_color_stop(0.5, 1.0, 0.0, 0.0, 1.0).unwrap();
+        gradient.add_color_stop(0.0, 0.0, 0.0, 0.0, 1.0).unwrap();
+        gradient.add_color_stop(0.5, 0.0, 1.0, 0.0, 1.0).unwrap();
+
+        let offsets: Vec<f32> = gradient.stops().iter().map(|stop| stop.offset).collect();
+        assert_eq!(offsets, vec![0.0, 0.5, 0.5, 1.0]);
+        // The hard transition at 0.5 goes from red to green
+        assert_eq!(gradient.stops()[1].color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(gradient.stops()[2].color, [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(gradient.spread(), SpreadMode::Pad);
+    }
+}
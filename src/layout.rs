@@ -0,0 +1,843 @@
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::font::Font;
+use crate::text::TextRenderer;
+
+// Used when the font has no glyph for U+2026
+const FALLBACK_ELLIPSIS: &str = "...";
+
+// Simplified line breaking classes, a subset of Unicode UAX #14
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum BreakClass {
+    // Hard line breaks
+    Mandatory,
+    Space,
+    ZeroWidthSpace,
+    // Non-breaking spaces and joiners
+    Glue,
+    // Break allowed after
+    Hyphen,
+    // No break after
+    Open,
+    // No break before
+    Close,
+    // Punctuation that never starts a line
+    NoBreakBefore,
+    // CJK and emoji, break allowed between any two
+    Ideographic,
+    // Combining marks attach to the previous character
+    Combining,
+    Other,
+}
+
+fn break_class(c: char) -> BreakClass {
+    match c {
+        '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}' => BreakClass::Mandatory,
+        ' ' | '\t' => BreakClass::Space,
+        '\u{200B}' => BreakClass::ZeroWidthSpace,
+        '\u{A0}' | '\u{2007}' | '\u{2011}' | '\u{202F}' | '\u{2060}' | '\u{FEFF}' => BreakClass::Glue,
+        '-' | '\u{AD}' | '\u{2010}' | '\u{2012}' | '\u{2013}' | '\u{2014}' => BreakClass::Hyphen,
+        '(' | '[' | '{' | '\u{AB}' | '\u{3008}' | '\u{300A}' | '\u{300C}' | '\u{300E}' | '\u{3010}' | '\u{FF08}'
+        | '\u{FF3B}' | '\u{FF5B}' => BreakClass::Open,
+        ')' | ']' | '}' | '\u{BB}' | '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300B}' | '\u{300D}' | '\u{300F}'
+        | '\u{3011}' | '\u{FF09}' | '\u{FF0C}' | '\u{FF0E}' | '\u{FF3D}' | '\u{FF5D}' => BreakClass::Close,
+        '!' | '?' | ',' | '.' | ':' | ';' | '/' | '%' | '\u{2026}' | '\u{203C}' | '\u{30FC}' | '\u{FF01}'
+        | '\u{FF1F}' => BreakClass::NoBreakBefore,
+        '\u{300}'..='\u{36F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' | '\u{200D}' | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}' | '\u{FE20}'..='\u{FE2F}' => BreakClass::Combining,
+        '\u{2E80}'..='\u{2FFF}'
+        | '\u{3040}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF00}'..='\u{FFEF}'
+        | '\u{1F300}'..='\u{1FAFF}'
+        | '\u{20000}'..='\u{3FFFD}' => BreakClass::Ideographic,
+        _ => BreakClass::Other,
+    }
+}
+
+// Line break opportunity before a character
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Break {
+    None,
+    Allowed,
+    Mandatory,
+}
+
+// Break opportunities before every character, following the main UAX #14 rules
+fn line_breaks(chars: &[char]) -> Vec<Break> {
+    let mut breaks = vec![Break::None; chars.len()];
+    // Class of the last character that is not a combining mark, and the one before it
+    let mut last: Option<BreakClass> = None;
+    let mut before_last: Option<BreakClass> = None;
+
+    for (i, &c) in chars.iter().enumerate() {
+        let class = break_class(c);
+
+        if let Some(previous) = last {
+            breaks[i] = match (previous, class) {
+                // CR LF is a single hard break
+                (BreakClass::Mandatory, _) if chars[i - 1] == '\r' && c == '\n' => Break::None,
+                (BreakClass::Mandatory, _) => Break::Mandatory,
+                (_, BreakClass::Mandatory | BreakClass::Space | BreakClass::ZeroWidthSpace | BreakClass::Combining) => {
+                    Break::None
+                }
+                (BreakClass::ZeroWidthSpace, _) => Break::Allowed,
+                (BreakClass::Glue, _) | (_, BreakClass::Glue) => Break::None,
+                (_, BreakClass::Close | BreakClass::NoBreakBefore) => Break::None,
+                (BreakClass::Open, _) => Break::None,
+                (BreakClass::Space, _) => Break::Allowed,
+                // Break after a hyphen inside a word, but keep "-5" together
+                (BreakClass::Hyphen, BreakClass::Other | BreakClass::Ideographic) => {
+                    if matches!(before_last, None | Some(BreakClass::Space)) {
+                        Break::None
+                    } else {
+                        Break::Allowed
+                    }
+                }
+                (BreakClass::Ideographic, _) | (_, BreakClass::Ideographic) => Break::Allowed,
+                (BreakClass::Close | BreakClass::NoBreakBefore, BreakClass::Other) if is_cjk_punctuation(chars[i - 1]) => {
+                    Break::Allowed
+                }
+                _ => Break::None,
+            };
+        }
+
+        if class != BreakClass::Combining {
+            before_last = last;
+            last = Some(class);
+        }
+    }
+
+    breaks
+}
+
+// Fullwidth punctuation behaves like ideographs after it
+fn is_cjk_punctuation(c: char) -> bool {
+    ('\u{3000}'..='\u{303F}').contains(&c) || ('\u{FF00}'..='\u{FFEF}').contains(&c)
+}
+
+// Simplified bidirectional character types
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum BidiClass {
+    Left,
+    Right,
+    EuropeanNumber,
+    ArabicNumber,
+    Neutral,
+    NonSpacingMark,
+}
+
+fn bidi_class(c: char) -> BidiClass {
+    match c {
+        '0'..='9' => BidiClass::EuropeanNumber,
+        '\u{660}'..='\u{669}' | '\u{6F0}'..='\u{6F9}' => BidiClass::ArabicNumber,
+        '\u{300}'..='\u{36F}' | '\u{591}'..='\u{5BD}' | '\u{610}'..='\u{61A}' | '\u{64B}'..='\u{65F}' | '\u{200D}'
+        | '\u{FE00}'..='\u{FE0F}' => BidiClass::NonSpacingMark,
+        '\u{590}'..='\u{8FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}' | '\u{10800}'..='\u{10FFF}'
+        | '\u{1E800}'..='\u{1EFFF}' => BidiClass::Right,
+        _ if c.is_whitespace() || c.is_ascii_punctuation() || c.is_control() => BidiClass::Neutral,
+        '\u{A0}'..='\u{BF}' | '\u{D7}' | '\u{F7}' | '\u{2000}'..='\u{2BFF}' | '\u{3000}'..='\u{303F}'
+        | '\u{FE30}'..='\u{FE4F}' => BidiClass::Neutral,
+        _ => BidiClass::Left,
+    }
+}
+
+// Embedding level of every character for a paragraph, a simplified version of the Unicode
+// Bidirectional Algorithm without explicit embeddings: weak types (W1, W7), neutrals (N1, N2)
+// and implicit levels (I1, I2)
+fn bidi_levels(chars: &[char], base_level: u8) -> Vec<u8> {
+    let mut classes: Vec<BidiClass> = chars.iter().map(|c| bidi_class(*c)).collect();
+
+    // W1: marks take the type of the previous character
+    let mut previous = if base_level % 2 == 1 { BidiClass::Right } else { BidiClass::Left };
+    for class in classes.iter_mut() {
+        if *class == BidiClass::NonSpacingMark {
+            *class = previous;
+        }
+        previous = *class;
+    }
+
+    // W7: European numbers following left-to-right text (or at the start of an LTR paragraph) are L
+    let mut strong = if base_level % 2 == 1 { BidiClass::Right } else { BidiClass::Left };
+    for class in classes.iter_mut() {
+        match *class {
+            BidiClass::Left | BidiClass::Right => strong = *class,
+            BidiClass::EuropeanNumber if strong == BidiClass::Left => *class = BidiClass::Left,
+            _ => {}
+        }
+    }
+
+    // N1, N2: neutrals between characters of the same direction take it, others take the
+    // paragraph direction. Numbers count as right-to-left here
+    let embedding = if base_level % 2 == 1 { BidiClass::Right } else { BidiClass::Left };
+    let direction = |class: BidiClass| match class {
+        BidiClass::Left => Some(BidiClass::Left),
+        BidiClass::Right | BidiClass::EuropeanNumber | BidiClass::ArabicNumber => Some(BidiClass::Right),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < classes.len() {
+        if direction(classes[i]).is_some() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < classes.len() && direction(classes[i]).is_none() {
+            i += 1;
+        }
+        let before = if start == 0 { embedding } else { direction(classes[start - 1]).unwrap_or(embedding) };
+        let after = classes.get(i).and_then(|class| direction(*class)).unwrap_or(embedding);
+        let resolved = if before == after { before } else { embedding };
+        for class in &mut classes[start..i] {
+            *class = resolved;
+        }
+    }
+
+    // I1, I2: implicit levels
+    classes
+        .iter()
+        .map(|class| match (base_level % 2 == 1, class) {
+            (false, BidiClass::Right) => base_level + 1,
+            (false, BidiClass::EuropeanNumber | BidiClass::ArabicNumber) => base_level + 2,
+            (true, BidiClass::Left | BidiClass::EuropeanNumber | BidiClass::ArabicNumber) => base_level + 1,
+            _ => base_level,
+        })
+        .collect()
+}
+
+// Visual order of items from their levels (rule L2): from the highest level down to the
+// lowest odd level, reverse every run at that level or higher
+fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let Some(&highest) = levels.iter().max() else {
+        return order;
+    };
+    let lowest_odd = levels.iter().copied().filter(|level| level % 2 == 1).min().unwrap_or(highest + 1);
+
+    let mut level = highest;
+    while level >= lowest_odd && level > 0 {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+        level -= 1;
+    }
+
+    order
+}
+
+// Mirrored glyph for characters in right-to-left runs (rule L4)
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '\u{AB}' => '\u{BB}',
+        '\u{BB}' => '\u{AB}',
+        '\u{2039}' => '\u{203A}',
+        '\u{203A}' => '\u{2039}',
+        _ => c,
+    }
+}
+
+// Alignment of paragraph lines within the layout width
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum ParagraphAlign {
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    // Stretch the spaces so lines fill the width, the last line is aligned to the start
+    Justify,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Direction {
+    Ltr,
+    Rtl,
+    // From the first strong character
+    Auto,
+}
+
+// A run of text sharing a font, size and optional color
+#[derive(Clone, Debug)]
+struct Span {
+    text: String,
+    font: String,
+    size: f32,
+    color: Option<[f32; 4]>,
+}
+
+// Rich text paragraph: spans with their own font, size and color, laid out with
+// Canvas2D::layout_paragraph and drawn with Canvas2D::fill_paragraph
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Paragraph {
+    spans: Vec<Span>,
+    max_width: Option<f32>,
+    max_lines: Option<u32>,
+    align: ParagraphAlign,
+    direction: Direction,
+    wrap: bool,
+    // None disables truncation marks
+    ellipsis: Option<String>,
+    line_height: f32,
+}
+
+impl Default for Paragraph {
+    fn default() -> Paragraph {
+        Paragraph::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Paragraph {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Paragraph {
+        Paragraph {
+            spans: Vec::new(),
+            max_width: None,
+            max_lines: None,
+            align: ParagraphAlign::Start,
+            direction: Direction::Auto,
+            wrap: true,
+            ellipsis: Some("\u{2026}".to_string()),
+            line_height: 1.0,
+        }
+    }
+
+    // Append text in a loaded font. Color is [r, g, b, a], the fill style is used without it
+    #[wasm_bindgen]
+    pub fn add_span(&mut self, text: &str, font: &str, size: f32, color: Option<Vec<f32>>) -> Result<(), JsValue> {
+        if !(size > 0.0 && size.is_finite()) {
+            return Err(JsValue::from_str("Font size must be positive"));
+        }
+
+        let color = match color.as_deref() {
+            None => None,
+            Some([r, g, b, a]) => Some([*r, *g, *b, *a]),
+            Some(_) => return Err(JsValue::from_str("Span color must have 4 components")),
+        };
+
+        self.spans.push(Span {
+            text: text.to_string(),
+            font: font.to_string(),
+            size,
+            color,
+        });
+
+        Ok(())
+    }
+
+    // Wrap lines to this width in CSS pixels, None for unbounded lines
+    #[wasm_bindgen]
+    pub fn set_max_width(&mut self, max_width: Option<f32>) {
+        self.max_width = max_width.filter(|width| *width > 0.0 && width.is_finite());
+    }
+
+    // Truncate after this many lines, ending the last one with the ellipsis
+    #[wasm_bindgen]
+    pub fn set_max_lines(&mut self, max_lines: Option<u32>) {
+        self.max_lines = max_lines.filter(|lines| *lines > 0);
+    }
+
+    // "start" (default), "end", "left", "right", "center" or "justify"
+    #[wasm_bindgen]
+    pub fn set_align(&mut self, align: &str) -> Result<(), JsValue> {
+        self.align = match align {
+            "start" => ParagraphAlign::Start,
+            "end" => ParagraphAlign::End,
+            "left" => ParagraphAlign::Left,
+            "right" => ParagraphAlign::Right,
+            "center" => ParagraphAlign::Center,
+            "justify" => ParagraphAlign::Justify,
+            _ => return Err(JsValue::from_str(&format!("Invalid paragraph align \"{}\"", align))),
+        };
+        Ok(())
+    }
+
+    // "ltr", "rtl" or "auto" (default, from the first strong character)
+    #[wasm_bindgen]
+    pub fn set_direction(&mut self, direction: &str) -> Result<(), JsValue> {
+        self.direction = match direction {
+            "ltr" => Direction::Ltr,
+            "rtl" => Direction::Rtl,
+            "auto" => Direction::Auto,
+            _ => return Err(JsValue::from_str(&format!("Invalid text direction \"{}\"", direction))),
+        };
+        Ok(())
+    }
+
+    // Without wrapping, lines longer than the max width are truncated with the ellipsis
+    #[wasm_bindgen]
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    // Text marking truncated lines, "…" by default, None to cut lines without a mark
+    #[wasm_bindgen]
+    pub fn set_ellipsis(&mut self, ellipsis: Option<String>) {
+        self.ellipsis = ellipsis;
+    }
+
+    // Multiplier of the font line spacing, 1 by default
+    #[wasm_bindgen]
+    pub fn set_line_height(&mut self, line_height: f32) {
+        if line_height > 0.0 && line_height.is_finite() {
+            self.line_height = line_height;
+        }
+    }
+}
+
+impl Paragraph {
+    pub(crate) fn span_colors(&self) -> Vec<Option<[f32; 4]>> {
+        self.spans.iter().map(|span| span.color).collect()
+    }
+}
+
+// A positioned glyph, relative to the top-left corner of the paragraph
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct GlyphPosition {
+    pub(crate) glyph: u16,
+    // Origin of the glyph on the baseline
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) size: f32,
+    // Index of the span the glyph comes from
+    pub(crate) span: usize,
+    // Index of the character (code point) in the concatenated span text, None for ellipsis glyphs
+    pub(crate) index: Option<usize>,
+    #[serde(skip)]
+    pub(crate) font: usize,
+}
+
+// Geometry of a laid out line, relative to the top-left corner of the paragraph
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LineMetrics {
+    pub(crate) x: f32,
+    pub(crate) top: f32,
+    pub(crate) baseline: f32,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    pub(crate) ascent: f32,
+    pub(crate) descent: f32,
+    // Range of characters (code points) on the line
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) glyphs: Vec<GlyphPosition>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ParagraphLayout {
+    pub(crate) width: f32,
+    pub(crate) height: f32,
+    pub(crate) lines: Vec<LineMetrics>,
+    // Text was cut by max_lines or by a max width without wrapping
+    pub(crate) truncated: bool,
+    pub(crate) rtl: bool,
+}
+
+// A character with its resolved font and advance
+#[derive(Copy, Clone, Debug)]
+struct Item {
+    c: char,
+    span: usize,
+    font: usize,
+    size: f32,
+    // Advance in pixels, including kerning with the next character of the same span
+    advance: f32,
+    level: u8,
+    index: Option<usize>,
+}
+
+impl Item {
+    fn is_trailing_space(&self) -> bool {
+        matches!(break_class(self.c), BreakClass::Space | BreakClass::Mandatory | BreakClass::ZeroWidthSpace)
+    }
+}
+
+// A line in logical order before alignment
+struct LogicalLine {
+    items: Vec<Item>,
+    start: usize,
+    end: usize,
+    // Ended by a hard break or the end of the text, not justified
+    hard: bool,
+}
+
+// Lay out a paragraph with the fonts loaded in the text renderer
+pub(crate) fn layout_paragraph(text: &TextRenderer, paragraph: &Paragraph) -> Result<ParagraphLayout, JsValue> {
+    let fonts = paragraph
+        .spans
+        .iter()
+        .map(|span| {
+            text.find_font(&span.font)
+                .ok_or_else(|| JsValue::from_str(&format!("No font loaded under \"{}\"", span.font)))
+        })
+        .collect::<Result<Vec<usize>, JsValue>>()?;
+    let font = |index: usize| text.font(index).ok_or_else(|| JsValue::from_str("Invalid font"));
+
+    // Flatten the spans into characters
+    let mut items: Vec<Item> = Vec::new();
+    for (span_index, span) in paragraph.spans.iter().enumerate() {
+        let span_font = font(fonts[span_index])?;
+        let first = items.len();
+        for c in span.text.chars() {
+            items.push(Item {
+                c,
+                span: span_index,
+                font: fonts[span_index],
+                size: span.size,
+                advance: advance(span_font, c, span.size),
+                level: 0,
+                index: Some(items.len()),
+            });
+        }
+
+        // Kerning between neighbors of the same span goes into the advance of the first one
+        for i in first..items.len().saturating_sub(1) {
+            let left = span_font.glyph_index(items[i].c);
+            let right = span_font.glyph_index(items[i + 1].c);
+            items[i].advance += span_font.kerning(left, right) * span.size;
+        }
+    }
+
+    let chars: Vec<char> = items.iter().map(|item| item.c).collect();
+    let rtl = match paragraph.direction {
+        Direction::Ltr => false,
+        Direction::Rtl => true,
+        Direction::Auto => chars
+            .iter()
+            .map(|c| bidi_class(*c))
+            .find(|class| matches!(class, BidiClass::Left | BidiClass::Right))
+            == Some(BidiClass::Right),
+    };
+    let base_level = u8::from(rtl);
+    for (item, level) in items.iter_mut().zip(bidi_levels(&chars, base_level)) {
+        item.level = level;
+    }
+
+    let breaks = line_breaks(&chars);
+    let mut lines = break_lines(&items, &breaks, paragraph);
+
+    // Truncation by line count, or by width when lines do not wrap
+    let mut truncated = false;
+    if let Some(max_lines) = paragraph.max_lines {
+        if lines.len() > max_lines as usize {
+            lines.truncate(max_lines as usize);
+            truncated = true;
+            if let Some(last) = lines.last_mut() {
+                add_ellipsis(text, paragraph, last, base_level)?;
+            }
+        }
+    }
+    if !paragraph.wrap {
+        if let Some(max_width) = paragraph.max_width {
+            for line in lines.iter_mut() {
+                if visible_width(&line.items) > max_width {
+                    truncated = true;
+                    add_ellipsis(text, paragraph, line, base_level)?;
+                }
+            }
+        }
+    }
+
+    // Position the lines
+    let widest = lines.iter().map(|line| visible_width(&line.items)).fold(0.0, f32::max);
+    let layout_width = paragraph.max_width.unwrap_or(widest);
+    let default_metrics = match paragraph.spans.first() {
+        Some(span) => {
+            let span_font = font(fonts[0])?;
+            (
+                span_font.ascender() * span.size,
+                -span_font.descender() * span.size,
+                span_font.line_spacing() * span.size,
+            )
+        }
+        None => (0.0, 0.0, 0.0),
+    };
+
+    let mut laid_out = Vec::with_capacity(lines.len());
+    let mut top = 0.0;
+    for line in &lines {
+        // Trailing spaces do not count for alignment
+        let visible = line.items.len() - line.items.iter().rev().take_while(|item| item.is_trailing_space()).count();
+        let items = &line.items[..visible];
+
+        let (mut ascent, mut descent, mut spacing) = if items.is_empty() { default_metrics } else { (0.0, 0.0, 0.0) };
+        for item in items {
+            let item_font = font(item.font)?;
+            ascent = f32::max(ascent, item_font.ascender() * item.size);
+            descent = f32::max(descent, -item_font.descender() * item.size);
+            spacing = f32::max(spacing, item_font.line_spacing() * item.size);
+        }
+        let height = spacing * paragraph.line_height;
+        let baseline = top + (height - (ascent + descent)) * 0.5 + ascent;
+
+        let width = visible_width(items);
+        let free = layout_width - width;
+        let justify = paragraph.align == ParagraphAlign::Justify && !line.hard && free > 0.0;
+        let space_count = items.iter().filter(|item| item.c == ' ').count();
+        let space_extra = if justify && space_count > 0 { free / space_count as f32 } else { 0.0 };
+
+        let x = match (paragraph.align, rtl) {
+            _ if space_extra > 0.0 => 0.0,
+            (ParagraphAlign::Left, _) | (ParagraphAlign::Start | ParagraphAlign::Justify, false) | (ParagraphAlign::End, true) => 0.0,
+            (ParagraphAlign::Right, _) | (ParagraphAlign::Start | ParagraphAlign::Justify, true) | (ParagraphAlign::End, false) => free,
+            (ParagraphAlign::Center, _) => free * 0.5,
+        };
+
+        let levels: Vec<u8> = items.iter().map(|item| item.level).collect();
+        let mut glyphs = Vec::with_capacity(items.len());
+        let mut pen = x;
+        for index in visual_order(&levels) {
+            let item = items[index];
+            if break_class(item.c) == BreakClass::Mandatory {
+                continue;
+            }
+            let c = if item.level % 2 == 1 { mirror(item.c) } else { item.c };
+            glyphs.push(GlyphPosition {
+                glyph: font(item.font)?.glyph_index(c),
+                x: pen,
+                y: baseline,
+                size: item.size,
+                span: item.span,
+                index: item.index,
+                font: item.font,
+            });
+            pen += item.advance;
+            if item.c == ' ' {
+                pen += space_extra;
+            }
+        }
+
+        laid_out.push(LineMetrics {
+            x,
+            top,
+            baseline,
+            width: if space_extra > 0.0 { layout_width } else { width },
+            height,
+            ascent,
+            descent,
+            start: line.start,
+            end: line.end,
+            glyphs,
+        });
+        top += height;
+    }
+
+    Ok(ParagraphLayout {
+        width: layout_width,
+        height: top,
+        lines: laid_out,
+        truncated,
+        rtl,
+    })
+}
+
+fn advance(font: &Font, c: char, size: f32) -> f32 {
+    match break_class(c) {
+        BreakClass::Mandatory | BreakClass::ZeroWidthSpace | BreakClass::Combining => 0.0,
+        // Tabs advance like a space
+        BreakClass::Space => font.advance(font.glyph_index(' ')) * size,
+        _ => font.advance(font.glyph_index(c)) * size,
+    }
+}
+
+// Width of items without their trailing spaces
+fn visible_width(items: &[Item]) -> f32 {
+    let trailing = items.iter().rev().take_while(|item| item.is_trailing_space()).count();
+    items[..items.len() - trailing].iter().map(|item| item.advance).sum()
+}
+
+// Greedy line breaking at the break opportunities, characters are split as a last resort
+fn break_lines(items: &[Item], breaks: &[Break], paragraph: &Paragraph) -> Vec<LogicalLine> {
+    let max_width = if paragraph.wrap { paragraph.max_width } else { None };
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut opportunity: Option<usize> = None;
+
+    let push = |lines: &mut Vec<LogicalLine>, start: usize, end: usize, hard: bool| {
+        lines.push(LogicalLine {
+            items: items[start..end].to_vec(),
+            start,
+            end,
+            hard,
+        });
+    };
+
+    let mut i = 0;
+    while i < items.len() {
+        if i > start && breaks[i] == Break::Mandatory {
+            push(&mut lines, start, i, true);
+            start = i;
+            opportunity = None;
+        } else if i > start && breaks[i] == Break::Allowed {
+            opportunity = Some(i);
+        }
+
+        if let Some(max_width) = max_width {
+            if i > start && visible_width(&items[start..=i]) > max_width {
+                // Break at the last opportunity, or before this character if there is none
+                let end = opportunity.filter(|end| *end > start).unwrap_or(i);
+                push(&mut lines, start, end, false);
+                start = end;
+                opportunity = None;
+                // Characters after the break are measured again on the new line
+                i = end;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+    push(&mut lines, start, items.len(), true);
+
+    lines
+}
+
+// Cut a line so it fits the max width together with the ellipsis, and append the ellipsis
+fn add_ellipsis(text: &TextRenderer, paragraph: &Paragraph, line: &mut LogicalLine, base_level: u8) -> Result<(), JsValue> {
+    let Some(ellipsis) = paragraph.ellipsis.as_deref() else {
+        if let Some(max_width) = paragraph.max_width {
+            while visible_width(&line.items) > max_width && line.items.pop().is_some() {}
+        }
+        return Ok(());
+    };
+
+    // The ellipsis uses the style of the last character, or of the first span
+    let Some(template) = line.items.last().copied().or_else(|| {
+        let span = paragraph.spans.first()?;
+        Some(Item {
+            c: ' ',
+            span: 0,
+            font: text.find_font(&span.font)?,
+            size: span.size,
+            advance: 0.0,
+            level: base_level,
+            index: None,
+        })
+    }) else {
+        return Ok(());
+    };
+    let font = text.font(template.font).ok_or("Invalid font")?;
+
+    let ellipsis = if ellipsis == "\u{2026}" && font.glyph_index('\u{2026}') == 0 { FALLBACK_ELLIPSIS } else { ellipsis };
+    let marks: Vec<Item> = ellipsis
+        .chars()
+        .map(|c| Item {
+            c,
+            advance: advance(font, c, template.size),
+            level: base_level,
+            index: None,
+            ..template
+        })
+        .collect();
+    let marks_width: f32 = marks.iter().map(|item| item.advance).sum();
+
+    if let Some(max_width) = paragraph.max_width {
+        while !line.items.is_empty() && visible_width(&line.items) + marks_width > max_width {
+            line.items.pop();
+        }
+    }
+    while line.items.last().is_some_and(|item| item.is_trailing_space()) {
+        line.items.pop();
+    }
+
+    line.end = line.start + line.items.len();
+    line.items.extend(marks);
+    line.hard = true;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every character ten pixels wide
+    fn items(text: &str) -> Vec<Item> {
+        text.chars()
+            .map(|c| Item {
+                c,
+                span: 0,
+                font: 0,
+                size: 10.0,
+                advance: 10.0,
+                level: 0,
+                index: None,
+            })
+            .collect()
+    }
+
+    fn lines(text: &str, max_width: Option<f32>) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut paragraph = Paragraph::new();
+        paragraph.set_max_width(max_width);
+        break_lines(&items(text), &line_breaks(&chars), &paragraph)
+            .iter()
+            .map(|line| line.items.iter().map(|item| item.c).collect())
+            .collect()
+    }
+
+    #[test]
+    fn mandatory_breaks_end_lines() {
+        assert_eq!(lines("one\ntwo\r\nthree", None), vec!["one\n", "two\r\n", "three"]);
+        assert_eq!(lines("a\n\nb", None), vec!["a\n", "\n", "b"]);
+    }
+
+    #[test]
+    fn lines_break_at_spaces_and_hyphens() {
+        assert_eq!(lines("the quick brown fox", Some(100.0)), vec!["the quick ", "brown fox"]);
+        assert_eq!(lines("well-known", Some(60.0)), vec!["well-", "known"]);
+        // A leading minus stays with its number
+        let breaks = line_breaks(&['a', ' ', '-', '5']);
+        assert_eq!(breaks[3], Break::None);
+    }
+
+    #[test]
+    fn long_words_overflow_by_characters() {
+        assert_eq!(lines("abcdefghij xy", Some(40.0)), vec!["abcd", "efgh", "ij ", "xy"]);
+    }
+
+    #[test]
+    fn ideographs_break_between_characters() {
+        assert_eq!(lines("\u{4F60}\u{597D}\u{4E16}\u{754C}", Some(20.0)), vec!["\u{4F60}\u{597D}", "\u{4E16}\u{754C}"]);
+    }
+
+    fn visual(text: &str, base_level: u8) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        visual_order(&bidi_levels(&chars, base_level)).iter().map(|&i| chars[i]).collect()
+    }
+
+    #[test]
+    fn mixed_direction_text_is_reordered() {
+        // Hebrew letters inside English text are reversed in place
+        assert_eq!(visual("ab \u{5D0}\u{5D1}\u{5D2} cd", 0), "ab \u{5D2}\u{5D1}\u{5D0} cd");
+        // In a right-to-left paragraph the English run keeps its order but moves to the end
+        assert_eq!(visual("\u{5D0}\u{5D1} ab", 1), "ab \u{5D1}\u{5D0}");
+        // Numbers in right-to-left text stay left-to-right
+        assert_eq!(visual("\u{5D0} 123 \u{5D1}", 1), "\u{5D1} 123 \u{5D0}");
+        assert_eq!(mirror('('), ')');
+    }
+}
@@ -1,10 +1,75 @@
+use std::rc::Rc;
+
 use wasm_bindgen::prelude::*;
 use web_sys::{
-    WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlBuffer, WebGlVertexArrayObject
+    HtmlCanvasElement, WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlBuffer, WebGlVertexArrayObject
 };
 use js_sys::{Float32Array, Object, Reflect, Array};
 use wasm_bindgen::JsCast;
 
+mod antialias;
+mod atlas;
+mod batch;
+mod camera;
+// WebGPU geometry builder, used from Rust rather than through wasm-bindgen
+#[cfg(feature = "webgpu")]
+pub mod canvas2d;
+mod capture;
+mod cff;
+mod clock;
+mod clip;
+mod color;
+mod composite;
+mod filter;
+mod font;
+mod gif;
+mod gradient;
+mod layout;
+mod palette;
+mod path;
+mod pipeline;
+mod png;
+mod postprocess;
+mod primitives;
+mod recording;
+mod shadow;
+mod sprites;
+mod state;
+mod svg;
+mod svg_path;
+mod text;
+mod texture;
+
+use antialias::{Antialiasing, MultisampleTarget};
+use batch::{circle_points, segments_for_radius, Paint, ShapeBatch, CURVE_TOLERANCE};
+pub use camera::{Camera2D, CoordinateSystem};
+use capture::FrameCapture;
+use clip::{Clip, ClipPath, Clipper};
+use clock::{Clock, ClockMode};
+pub use color::Color;
+use color::{parse_color, srgb_to_linear};
+use composite::{CompositeOperation, Compositor};
+use filter::{Filter, FilterRenderer};
+pub use gradient::{Gradient, SpreadMode};
+use gradient::{ColorStop, GradientKind};
+pub use layout::Paragraph;
+pub use palette::Palette;
+pub use path::Path2D;
+use path::FillRule;
+use pipeline::{ColorPipeline, LinearOutput};
+use png::encode_png;
+use postprocess::PostProcessor;
+use primitives::{Primitive, PrimitiveBatch, PrimitiveShape};
+use recording::{Layer, RecordedClip, RecordedImage, RecordedPaint, Recording};
+use shadow::{Shadow, ShadowRenderer};
+use sprites::SpriteBatch;
+use state::DrawingState;
+pub use svg::SvgDocument;
+pub use text::TextMetrics;
+use text::{TextAlign, TextBaseline, TextRenderer};
+pub use texture::Pattern;
+use texture::{Repetition, TextureCache};
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
@@ -26,6 +91,8 @@ const DEFAULT_ELEMENT_COUNT: usize = 10;
 const SEGMENTS: usize = 30; // Number of segments to approximate a shape
 const MIN_POLYGON_SIDES: usize = 3;
 const VERTICES_PER_POLYGON: usize = SEGMENTS + 2; // Center point + segments + repeat first point
+const POLYGON_RADIUS: f32 = 0.018; // Polygon radius in layout units (half the shortest canvas side)
+const SCENE_GRADIENT_STEPS: usize = 8; // Gradient stops per polygon in SVG exports, minus one
 
 // Shader sources
 const VERTEX_SHADER_SRC: &str = r#"#version 300 es
@@ -36,7 +103,10 @@ in float sideCount;
 
 uniform mat4 uMatrix;
 uniform float uTime;
-uniform float uAspectRatio;
+uniform vec2 uResolution;
+uniform vec2 uLayoutOrigin;
+uniform float uLayoutYAxis;
+uniform float uPolygonRadius;
 uniform int uElementCount;
 
 out vec4 vColor;
@@ -63,49 +133,30 @@ void main() {
     float cosVal = cos(rotationAngle);
     float sinVal = sin(rotationAngle);
     
-    // Apply transformations to create a perfectly proportioned shape
-    vec4 offsetPosition = position;
-    
     // Apply slight rotation to each polygon
-    float originalX = offsetPosition.x;
-    float originalY = offsetPosition.y;
-    offsetPosition.x = originalX * cosVal - originalY * sinVal;
-    offsetPosition.y = originalX * sinVal + originalY * cosVal;
-    
-    // First apply the polygon scaling
-    float baseScale = 0.15;
-    
-    // Make the polygons perfectly proportioned by applying aspect ratio correction
-    if (uAspectRatio >= 1.0) {
-        // Wide screen - correct the x coordinate
-        offsetPosition.x *= baseScale; 
-        offsetPosition.y *= baseScale;
-    } else {
-        // Tall screen - correct the y coordinate
-        offsetPosition.x *= baseScale;
-        offsetPosition.y *= baseScale * uAspectRatio;
-    }
+    vec2 local = vec2(
+        position.x * cosVal - position.y * sinVal,
+        position.x * sinVal + position.y * cosVal
+    );
     
-    // Then add the positional offsets - use xOffset directly for centered row
-    offsetPosition.x += xOffset + xWobble;
-    offsetPosition.y += yOffset;
+    // Apply the polygon scaling (unit circle vertices)
+    local *= uPolygonRadius;
     
-    // Apply aspect ratio correction to maintain position spacing
-    if (uAspectRatio >= 1.0) {
-        // Wide screen
-        offsetPosition.x /= uAspectRatio;
-    } else {
-        // Tall screen - already handled
-    }
+    // Layout units are half the shortest side of the canvas, so the row keeps
+    // its proportions at any aspect ratio. The row is laid out around the
+    // canvas center in CSS pixels and the camera maps pixels to clip space
+    float unit = min(uResolution.x, uResolution.y) * 0.5;
+    vec2 layout = (local + vec2(xOffset + xWobble, yOffset)) * unit;
+    vec2 world = uLayoutOrigin + vec2(layout.x, layout.y * uLayoutYAxis);
     
     // Set the final position
-    gl_Position = uMatrix * offsetPosition;
+    gl_Position = uMatrix * vec4(world, 0.0, 1.0);
     
     // Pass color to fragment shader
     vColor = color;
     
     // Calculate distance from center for fragment shader effects
-    vDistFromCenter = length(position.xy) / 0.12; // Normalized distance
+    vDistFromCenter = length(position.xy); // Normalized distance
 }
 "#;
 
@@ -116,6 +167,8 @@ precision highp float;
 in vec4 vColor;
 in float vDistFromCenter;
 uniform float uTime;
+// Drawing into the linear-light target, colors are given in sRGB
+uniform bool uLinear;
 
 out vec4 outColor;
 
@@ -128,6 +181,9 @@ void main() {
     
     // Combine effects but keep solid colors
     vec3 finalColor = vColor.rgb * pulse * shimmer;
+    if (uLinear) {
+        finalColor = mix(finalColor / 12.92, pow((finalColor + 0.055) / 1.055, vec3(2.4)), step(0.04045, finalColor));
+    }
     
     // Use full opacity for solid colors
     outColor = vec4(finalColor, 1.0);
@@ -179,19 +235,70 @@ pub struct Canvas2D {
     vao: WebGlVertexArrayObject,
     time_location: Option<web_sys::WebGlUniformLocation>,
     matrix_location: Option<web_sys::WebGlUniformLocation>,
-    aspect_ratio_location: Option<web_sys::WebGlUniformLocation>,
+    resolution_location: Option<web_sys::WebGlUniformLocation>,
+    layout_origin_location: Option<web_sys::WebGlUniformLocation>,
+    layout_y_axis_location: Option<web_sys::WebGlUniformLocation>,
+    polygon_radius_location: Option<web_sys::WebGlUniformLocation>,
     element_count_location: Option<web_sys::WebGlUniformLocation>,
-    start_time: f64,
-    last_frame_time: f64,
+    linear_location: Option<web_sys::WebGlUniformLocation>,
+    // Scene time the animated scenes, effects and render() deltas follow
+    clock: Clock,
+    // Drawing size in CSS pixels, the backbuffer is this size times the pixel ratio
     width: u32,
     height: u32,
+    pixel_ratio: f32,
+    // Follow window.devicePixelRatio instead of a fixed ratio
+    auto_pixel_ratio: bool,
+    // Also set the CSS width and height of the canvas element, off so page styles keep control
+    style_size: bool,
     element_count: u32,
+    // Colors of the polygon scene, evenly spaced OKLCH hues when unset
+    polygon_palette: Option<Palette>,
+    // Colors the polygons of the scene were created with, one per element
+    scene_colors: Vec<[f32; 4]>,
+    camera: Camera2D,
+    // Immediate-mode shapes drawn on top of the polygon scene
+    shapes: ShapeBatch,
+    // Styles and clip of the next drawing calls, and the states pushed by save
+    state: DrawingState,
+    saved_states: Vec<DrawingState>,
+    // Applies clip regions with the scissor box and the stencil buffer
+    clipper: Clipper,
+    // Blend state of composite operations and the backdrop copy of blend modes
+    compositor: Compositor,
+    // Offscreen coverage and blur passes of shape shadows, and the layers of filtered shapes
+    shadows: ShadowRenderer,
+    filters: FilterRenderer,
+    // Premultiplied alpha and linear-light blending options, and the sRGB scene target
+    // used while blending in linear light
+    pipeline: ColorPipeline,
+    linear_output: Option<LinearOutput>,
+    // Edge antialiasing mode, and the multisampled scene target of MSAA
+    antialiasing: Antialiasing,
+    multisample: Option<MultisampleTarget>,
+    // Uploaded images used by draw_image and patterns
+    textures: TextureCache,
+    // Circles, rounded rects and other shapes shaded with distance functions, drawn between
+    // the shapes and the sprites
+    primitives: PrimitiveBatch,
+    // Atlas-packed sprites drawn on top of the shapes
+    sprites: SpriteBatch,
+    // Loaded fonts and their glyph atlas, text is drawn with the shapes
+    text: TextRenderer,
+    post_processor: Option<PostProcessor>,
+    // Drawing calls kept as vector shapes for export_svg, between start_recording and stop_recording
+    recording: Option<Recording>,
+    recording_active: bool,
+    // Frames rendered at a fixed time step for export_gif and export_apng
+    frame_capture: Option<FrameCapture>,
     is_disposed: bool,
 }
 
 #[wasm_bindgen]
 impl Canvas2D {
-    // Initialize a new Canvas2D context
+    // Initialize a new Canvas2D context, width and height are in CSS pixels.
+    // The device pixel ratio is detected from the window and the canvas backbuffer
+    // is sized in device pixels
     #[wasm_bindgen]
     pub fn init(gl: WebGl2RenderingContext, width: u32, height: u32) -> Result<Canvas2D, JsValue> {
         console_log!("Initializing Canvas2D GPU Renderer");
@@ -207,39 +314,78 @@ impl Canvas2D {
         // Get uniform locations
         let time_location = gl.get_uniform_location(&program, "uTime");
         let matrix_location = gl.get_uniform_location(&program, "uMatrix");
-        let aspect_ratio_location = gl.get_uniform_location(&program, "uAspectRatio");
+        let resolution_location = gl.get_uniform_location(&program, "uResolution");
+        let layout_origin_location = gl.get_uniform_location(&program, "uLayoutOrigin");
+        let layout_y_axis_location = gl.get_uniform_location(&program, "uLayoutYAxis");
+        let polygon_radius_location = gl.get_uniform_location(&program, "uPolygonRadius");
         let element_count_location = gl.get_uniform_location(&program, "uElementCount");
+        let linear_location = gl.get_uniform_location(&program, "uLinear");
         
         // Create and bind VAO
         let vao = gl.create_vertex_array().ok_or("Failed to create vertex array")?;
         gl.bind_vertex_array(Some(&vao));
         
-        // Setup viewport
-        gl.viewport(0, 0, width as i32, height as i32);
+        // Create the immediate-mode shape renderer
+        let shapes = ShapeBatch::new(&gl)?;
+        let primitives = PrimitiveBatch::new(&gl)?;
+        let sprites = SpriteBatch::new(&gl)?;
+        let text = TextRenderer::new(&gl);
+        let clipper = Clipper::new(&gl)?;
+        let shadows = ShadowRenderer::new(&gl)?;
+        let filters = FilterRenderer::new(&gl)?;
         
         // Enable alpha blending
         gl.enable(WebGl2RenderingContext::BLEND);
         gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
         
-        // Get initial time
-        let performance = web_sys::window().unwrap().performance().unwrap();
-        let start_time = performance.now();
-        
-        Ok(Canvas2D {
+        let mut canvas = Canvas2D {
             gl,
             program,
             vao,
             time_location,
             matrix_location,
-            aspect_ratio_location,
+            resolution_location,
+            layout_origin_location,
+            layout_y_axis_location,
+            polygon_radius_location,
             element_count_location,
-            start_time,
-            last_frame_time: start_time,
+            linear_location,
+            clock: Clock::new(),
             width,
             height,
+            pixel_ratio: detect_pixel_ratio(),
+            auto_pixel_ratio: true,
+            style_size: false,
             element_count: 0,
+            polygon_palette: None,
+            scene_colors: Vec::new(),
+            camera: Camera2D::new(width as f32, height as f32),
+            shapes,
+            state: DrawingState::default(),
+            saved_states: Vec::new(),
+            clipper,
+            compositor: Compositor::new(),
+            shadows,
+            filters,
+            pipeline: ColorPipeline::default(),
+            linear_output: None,
+            antialiasing: Antialiasing::None,
+            multisample: None,
+            textures: TextureCache::new(),
+            primitives,
+            sprites,
+            text,
+            post_processor: None,
+            recording: None,
+            recording_active: false,
+            frame_capture: None,
             is_disposed: false,
-        })
+        };
+        
+        // Setup backbuffer and viewport
+        canvas.apply_backbuffer_size()?;
+        
+        Ok(canvas)
     }
     
     // Draw polygons with increasing sides
@@ -324,7 +470,7 @@ impl Canvas2D {
         }
         
         // Setup the buffers for the polygons
-        setup_polygon_buffers(&self.gl, &self.program, count as usize)?;
+        self.scene_colors = setup_polygon_buffers(&self.gl, &self.program, &self.vao, count as usize, self.polygon_palette.as_ref())?;
         self.element_count = count;
         
         Ok(())
@@ -332,11 +478,14 @@ impl Canvas2D {
     
     // Clear the canvas
     #[wasm_bindgen]
-    pub fn clear(&self, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
+    pub fn clear(&mut self, r: f32, g: f32, b: f32, a: f32) -> Result<(), JsValue> {
         if self.is_disposed {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
+        if let Some(recording) = self.active_recording() {
+            recording.clear();
+        }
         self.gl.clear_color(r, g, b, a);
         self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
         
@@ -350,16 +499,51 @@ impl Canvas2D {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
-        if self.element_count == 0 {
+        if self.element_count == 0 && self.shapes.is_empty() && self.primitives.is_empty() && self.sprites.is_empty() {
             return Ok(0.0); // Nothing to render
         }
         
-        // Get current time
-        let performance = web_sys::window().unwrap().performance().unwrap();
-        let current_time = performance.now();
-        let elapsed = (current_time - self.start_time) / 1000.0; // Convert to seconds
-        let delta_time = (current_time - self.last_frame_time) / 1000.0;
-        self.last_frame_time = current_time;
+        // Advance the clock, in seconds
+        let (mut elapsed, mut delta_time) = self.clock.tick();
+        
+        // Captured frames follow the fixed step of the capture instead of the clock
+        if let Some(capture) = self.frame_capture.as_ref().filter(|capture| capture.is_active()) {
+            (elapsed, delta_time) = capture.next_time();
+        }
+        
+        // Keep the polygons of this frame while recording
+        if self.recording_active {
+            let polygons = self.scene_polygons(elapsed as f32);
+            if let Some(recording) = self.recording.as_mut() {
+                recording.set_scene(polygons);
+            }
+        }
+        
+        // Render into the offscreen buffer when post-processing is active
+        // Blending in linear light renders into the sRGB target first, it is presented
+        // (through the effects when active) at the end of the frame
+        let post_active = self.post_processor.as_ref().is_some_and(|post| post.is_active());
+        if let Some(linear) = &self.linear_output {
+            linear.begin(&self.gl);
+        } else if post_active {
+            if let Some(post) = self.post_processor.as_mut() {
+                post.begin(&self.gl)?;
+            }
+        }
+        
+        // Draw into the multisampled target, unless the scene goes straight to a canvas that
+        // already antialiases
+        let context_antialias = self
+            .gl
+            .get_context_attributes()
+            .and_then(|attributes| attributes.get_antialias())
+            .unwrap_or(false);
+        let offscreen = self.linear_output.is_some() || post_active;
+        let mut multisample = self.multisample.as_mut().filter(|_| offscreen || !context_antialias);
+        if let Some(target) = multisample.as_mut() {
+            target.begin(&self.gl)?;
+            self.compositor.set_multisampled(Some(target.framebuffers()));
+        }
         
         // Use our shader program
         self.gl.use_program(Some(&self.program));
@@ -372,12 +556,25 @@ impl Canvas2D {
             self.gl.uniform1f(Some(time_loc), elapsed as f32);
         }
         
-        // Calculate aspect ratio
-        let aspect_ratio = self.width as f32 / self.height as f32;
+        if let Some(linear_loc) = &self.linear_location {
+            self.gl.uniform1i(Some(linear_loc), self.pipeline.linear as i32);
+        }
+        
+        // Update resolution uniform (CSS pixels)
+        if let Some(resolution_loc) = &self.resolution_location {
+            self.gl.uniform2f(Some(resolution_loc), self.width as f32, self.height as f32);
+        }
         
-        // Update aspect ratio uniform
-        if let Some(aspect_ratio_loc) = &self.aspect_ratio_location {
-            self.gl.uniform1f(Some(aspect_ratio_loc), aspect_ratio);
+        // Center the polygon row on the canvas in the active coordinate system
+        let (origin_x, origin_y, y_axis) = layout_origin(&self.camera, self.width, self.height);
+        if let Some(layout_origin_loc) = &self.layout_origin_location {
+            self.gl.uniform2f(Some(layout_origin_loc), origin_x, origin_y);
+        }
+        if let Some(layout_y_axis_loc) = &self.layout_y_axis_location {
+            self.gl.uniform1f(Some(layout_y_axis_loc), y_axis);
+        }
+        if let Some(polygon_radius_loc) = &self.polygon_radius_location {
+            self.gl.uniform1f(Some(polygon_radius_loc), POLYGON_RADIUS);
         }
         
         // Update element count uniform
@@ -385,21 +582,21 @@ impl Canvas2D {
             self.gl.uniform1i(Some(element_count_loc), self.element_count as i32);
         }
         
-        // Create and update projection matrix
+        // Upload the camera view-projection matrix
         if let Some(matrix_loc) = &self.matrix_location {
-            // Just identity matrix for now
-            let identity = [
-                1.0, 0.0, 0.0, 0.0,
-                0.0, 1.0, 0.0, 0.0,
-                0.0, 0.0, 1.0, 0.0,
-                0.0, 0.0, 0.0, 1.0,
-            ];
-            self.gl.uniform_matrix4fv_with_f32_array(Some(matrix_loc), false, &identity);
+            self.gl.uniform_matrix4fv_with_f32_array(Some(matrix_loc), false, &self.camera.matrix());
         }
         
         // Clear the canvas with a nice gradient-like dark background
-        let bg_time = (elapsed * 0.1).sin() * 0.02 + 0.05;
-        self.gl.clear_color(bg_time as f32, bg_time as f32 * 0.8, bg_time as f32 * 1.2, 1.0);
+        let bg_time = (elapsed * 0.1).sin() as f32 * 0.02 + 0.05;
+        let mut background = [bg_time, bg_time * 0.8, bg_time * 1.2];
+        if let Some(recording) = self.recording.as_mut().filter(|_| self.recording_active) {
+            recording.set_background([background[0], background[1], background[2], 1.0]);
+        }
+        if self.pipeline.linear {
+            background = background.map(srgb_to_linear);
+        }
+        self.gl.clear_color(background[0], background[1], background[2], 1.0);
         self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
         
         // Draw the polygons
@@ -413,203 +610,2016 @@ impl Canvas2D {
             );
         }
         
+        // Draw the shapes queued since the last frame on top
+        let matrix = self.camera.matrix();
+        self.shapes.flush(
+            &self.gl,
+            &matrix,
+            self.text.atlas(),
+            &mut self.clipper,
+            &mut self.compositor,
+            &mut self.shadows,
+            &mut self.filters,
+        )?;
+        let pixel_size = 1.0 / (self.camera.get_zoom() * self.pixel_ratio).max(1e-6);
+        self.primitives.flush(&self.gl, &matrix, pixel_size, &mut self.clipper, &mut self.compositor)?;
+        self.sprites.flush(&self.gl, &matrix, &mut self.clipper, &mut self.compositor)?;
+        self.clipper.finish(&self.gl);
+        self.compositor.finish(&self.gl);
+        self.text.end_frame();
+        
+        if let Some(target) = multisample {
+            target.resolve(&self.gl);
+            self.compositor.set_multisampled(None);
+        }
+        
+        // Encode the linear-light frame to sRGB, into the effect input or the canvas
+        if let Some(linear) = &self.linear_output {
+            if post_active {
+                if let Some(post) = self.post_processor.as_mut() {
+                    post.begin(&self.gl)?;
+                }
+            } else {
+                let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+                self.gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+                self.gl.viewport(0, 0, backbuffer_width as i32, backbuffer_height as i32);
+            }
+            linear.present(&self.gl);
+        }
+        
+        // Apply the effect chain and present the result
+        if post_active {
+            if let Some(post) = &self.post_processor {
+                post.finish(&self.gl, elapsed as f32)?;
+            }
+        }
+        
+        // Keep the finished frame while capturing
+        if self.frame_capture.as_ref().is_some_and(|capture| capture.is_active()) {
+            let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+            let frame = self.read_pixels(0, 0, backbuffer_width, backbuffer_height)?;
+            if let Some(capture) = self.frame_capture.as_mut() {
+                if capture.size() != (backbuffer_width, backbuffer_height) {
+                    capture.stop();
+                    return Err(JsValue::from_str("Canvas was resized during frame capture"));
+                }
+                capture.push(frame).map_err(|err| JsValue::from_str(&err))?;
+            }
+        }
+        
         Ok(delta_time * 1000.0) // Return the delta time in milliseconds
     }
     
-    // Resize the canvas
+    // Choose how time advances between frames: "realtime" follows the wall clock,
+    // "manual" only moves with set_time and "fixed" advances `step` seconds (1/60 by
+    // default) every rendered frame. Manual and fixed time render the same frames on every
+    // run, and neither needs a window
     #[wasm_bindgen]
-    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
-        if self.is_disposed {
-            return Err(JsValue::from_str("Canvas has been disposed"));
+    pub fn set_clock(&mut self, mode: &str, step: Option<f64>) -> Result<(), JsValue> {
+        self.clock.set_mode(ClockMode::parse(mode, step)?);
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_clock(&self) -> String {
+        self.clock.mode().name().to_string()
+    }
+    
+    // Jump to a scene time in seconds, in any mode and also while paused. The next render()
+    // returns the jump as its delta
+    #[wasm_bindgen]
+    pub fn set_time(&mut self, seconds: f64) -> Result<(), JsValue> {
+        if !seconds.is_finite() {
+            return Err(JsValue::from_str("Time must be a finite number of seconds"));
         }
         
-        self.width = width;
-        self.height = height;
-        self.gl.viewport(0, 0, width as i32, height as i32);
-        
+        self.clock.set_time(seconds);
         Ok(())
     }
     
-    // Get current element count
+    // Scene time of the last rendered frame, or as set since, in seconds
     #[wasm_bindgen]
-    pub fn get_element_count(&self) -> u32 {
-        self.element_count
+    pub fn get_time(&self) -> f64 {
+        self.clock.time()
     }
     
-    // Dispose of resources
+    // Stop time from advancing, frames still render with the time they are paused at
     #[wasm_bindgen]
-    pub fn dispose(&mut self) -> Result<(), JsValue> {
-        if self.is_disposed {
-            return Ok(());
+    pub fn set_paused(&mut self, paused: bool) {
+        self.clock.set_paused(paused);
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+    
+    // Multiply the real-time and fixed steps, 0.5 plays at half speed and negative scales
+    // play backwards
+    #[wasm_bindgen]
+    pub fn set_time_scale(&mut self, scale: f64) -> Result<(), JsValue> {
+        if !scale.is_finite() {
+            return Err(JsValue::from_str("Time scale must be a finite number"));
         }
         
-        // Delete WebGL resources
-        self.gl.delete_program(Some(&self.program));
-        self.gl.delete_vertex_array(Some(&self.vao));
-        
-        self.is_disposed = true;
-        console_log!("Canvas2D GPU Renderer disposed");
-        
+        self.clock.set_scale(scale);
         Ok(())
     }
+    
+    #[wasm_bindgen]
+    pub fn get_time_scale(&self) -> f64 {
+        self.clock.scale()
+    }
 
-    // Add new methods for creative examples
-
-    // Draw a particle system
+    // Read back a rectangle of the last rendered frame as RGBA bytes, rows from top to bottom
+    // with straight alpha. The rectangle is in device pixels of the backbuffer with the origin
+    // at the top left, pixels outside the canvas read as transparent black. Unless the context
+    // was created with preserveDrawingBuffer, call it right after render(), before the browser
+    // presents the frame and clears the drawing buffer
     #[wasm_bindgen]
-    pub fn draw_particles(&mut self, count: u32, options: JsValue) -> Result<(), JsValue> {
+    pub fn read_pixels(&self, x: i32, y: i32, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
         if self.is_disposed {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
-        // Extract particle options from JsValue
-        let mut particle_size: f32 = 3.0;
-        let mut max_speed: f32 = 2.0;
+        let size = (width as usize).checked_mul(height as usize).and_then(|count| count.checked_mul(4));
+        let mut pixels = vec![0u8; size.ok_or("Pixel area is too large to read")?];
+        if pixels.is_empty() {
+            return Ok(pixels);
+        }
         
-        if !options.is_null() && !options.is_undefined() {
-            if let Ok(size) = Reflect::get(&options, &JsValue::from_str("particle_size")) {
-                if !size.is_null() && !size.is_undefined() {
-                    if let Some(value) = size.as_f64() {
-                        particle_size = value as f32;
-                    }
-                }
-            }
-            
-            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("max_speed")) {
-                if !speed.is_null() && !speed.is_undefined() {
-                    if let Some(value) = speed.as_f64() {
-                        max_speed = value as f32;
+        // WebGL rows start at the bottom of the canvas
+        let (_, backbuffer_height) = self.backbuffer_size();
+        let gl_y = backbuffer_height as i32 - y - height as i32;
+        self.gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        self.gl.read_pixels_with_opt_u8_array(
+            x,
+            gl_y,
+            width as i32,
+            height as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        )?;
+        
+        let stride = width as usize * 4;
+        for row in 0..height as usize / 2 {
+            let (top, bottom) = pixels.split_at_mut((height as usize - 1 - row) * stride);
+            top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+        
+        // The drawing buffer holds premultiplied colors unless the context opted out
+        let premultiplied = self
+            .gl
+            .get_context_attributes()
+            .and_then(|attributes| attributes.get_premultiplied_alpha())
+            .unwrap_or(true);
+        if premultiplied {
+            for pixel in pixels.chunks_exact_mut(4) {
+                let alpha = pixel[3] as u32;
+                if alpha > 0 && alpha < 255 {
+                    for channel in &mut pixel[..3] {
+                        *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
                     }
                 }
             }
         }
         
-        // Call internal particle system setup (for future implementation)
-        console_log!("Particle system with {} particles, size={}, speed={}", count, particle_size, max_speed);
-        
-        // Just use polygon buffers for now, will be replaced with proper particle implementation
-        setup_polygon_buffers(&self.gl, &self.program, count as usize)?;
-        self.element_count = count;
-        
-        Ok(())
+        Ok(pixels)
     }
-
-    // Draw a flow field
+    
+    // Encode the last rendered frame as PNG bytes at the full backbuffer resolution, with the
+    // same timing requirement as read_pixels
     #[wasm_bindgen]
-    pub fn draw_flow_field(&mut self, resolution: u32, options: JsValue) -> Result<(), JsValue> {
+    pub fn capture_png(&self) -> Result<Vec<u8>, JsValue> {
+        let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+        let pixels = self.read_pixels(0, 0, backbuffer_width, backbuffer_height)?;
+        Ok(encode_png(backbuffer_width, backbuffer_height, &pixels))
+    }
+    
+    // Capture the next frame_count rendered frames for export_gif and export_apng, dropping
+    // any earlier capture. While capturing, frame i is rendered at start_time + i / fps
+    // seconds whatever the clock is set to (options: fps, default 30, and start_time,
+    // default 0) and render() returns the fixed step, so animations driven by its delta
+    // export the same loop every time. Calls to render() with nothing to draw are not captured.
+    // The frames are kept uncompressed and may take at most 512 MiB
+    #[wasm_bindgen]
+    pub fn start_frame_capture(&mut self, frame_count: u32, options: JsValue) -> Result<(), JsValue> {
         if self.is_disposed {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
-        // Extract flow field options from JsValue
-        let mut flow_scale: f32 = 0.2;
-        let mut flow_speed: f32 = 0.5;
-        
-        if !options.is_null() && !options.is_undefined() {
-            if let Ok(scale) = Reflect::get(&options, &JsValue::from_str("flow_scale")) {
-                if !scale.is_null() && !scale.is_undefined() {
-                    if let Some(value) = scale.as_f64() {
-                        flow_scale = value as f32;
-                    }
-                }
-            }
-            
-            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("flow_speed")) {
-                if !speed.is_null() && !speed.is_undefined() {
-                    if let Some(value) = speed.as_f64() {
-                        flow_speed = value as f32;
-                    }
-                }
-            }
+        let fps = get_f32_option(&options, "fps").unwrap_or(30.0);
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err(JsValue::from_str("Frame rate must be a positive number"));
+        }
+        let start_time = get_f64_option(&options, "start_time").unwrap_or(0.0);
+        if !start_time.is_finite() {
+            return Err(JsValue::from_str("Start time must be a finite number"));
         }
         
-        console_log!("Flow field with resolution {}, scale={}, speed={}", resolution, flow_scale, flow_speed);
-        
-        // For now, just use polygon buffers until we implement proper flow field
-        setup_polygon_buffers(&self.gl, &self.program, resolution as usize)?;
-        self.element_count = resolution;
-        
+        let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+        let capture = FrameCapture::new(frame_count, fps, start_time, backbuffer_width, backbuffer_height);
+        self.frame_capture = Some(capture.map_err(|err| JsValue::from_str(&err))?);
         Ok(())
     }
-
-    // Draw a cellular automata grid
+    
+    // Stop capturing early, the frames captured so far stay available
     #[wasm_bindgen]
-    pub fn draw_cellular_automata(&mut self, grid_size: u32, options: JsValue) -> Result<(), JsValue> {
+    pub fn stop_frame_capture(&mut self) {
+        if let Some(capture) = self.frame_capture.as_mut() {
+            capture.stop();
+        }
+    }
+    
+    // Whether render() still adds frames, false once frame_count frames were captured
+    #[wasm_bindgen]
+    pub fn is_capturing_frames(&self) -> bool {
+        self.frame_capture.as_ref().is_some_and(|capture| capture.is_active())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_captured_frame_count(&self) -> u32 {
+        self.frame_capture.as_ref().map_or(0, |capture| capture.len() as u32)
+    }
+    
+    // Encode the captured frames as a looping GIF. All frames share a 256 color palette,
+    // pixels under half opacity become transparent. GIF delays are in hundredths of a
+    // second and browsers slow down delays under 2, so 50 fps is the fastest that plays back
+    // at the captured rate
+    #[wasm_bindgen]
+    pub fn export_gif(&self) -> Result<Vec<u8>, JsValue> {
+        let capture = self.captured_frames()?;
+        let (width, height) = capture.size();
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(JsValue::from_str("Canvas is too large for a GIF"));
+        }
+        Ok(capture.gif())
+    }
+    
+    // Encode the captured frames as a looping APNG with full color and alpha
+    #[wasm_bindgen]
+    pub fn export_apng(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.captured_frames()?.apng())
+    }
+    
+    // Resize the canvas. Width and height are in CSS pixels; when a device pixel
+    // ratio is given it replaces automatic detection, otherwise the ratio is
+    // re-read from the window (it changes with browser zoom and across monitors)
+    #[wasm_bindgen]
+    pub fn resize(&mut self, width: u32, height: u32, pixel_ratio: Option<f32>) -> Result<(), JsValue> {
         if self.is_disposed {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
-        // Extract cellular automata options from JsValue
-        let mut sim_speed: f32 = 8.0;
-        
-        if !options.is_null() && !options.is_undefined() {
-            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("sim_speed")) {
-                if !speed.is_null() && !speed.is_undefined() {
-                    if let Some(value) = speed.as_f64() {
-                        sim_speed = value as f32;
-                    }
-                }
-            }
+        if let Some(ratio) = pixel_ratio {
+            self.set_ratio(ratio)?;
+            self.auto_pixel_ratio = false;
+        } else if self.auto_pixel_ratio {
+            self.pixel_ratio = detect_pixel_ratio();
         }
         
-        console_log!("Cellular automata with grid size {}, sim_speed={}", grid_size, sim_speed);
-        
-        // For now, just use polygon buffers until we implement proper cellular automata
-        setup_polygon_buffers(&self.gl, &self.program, (grid_size/16) as usize)?;
-        self.element_count = grid_size / 16;
+        self.width = width;
+        self.height = height;
         
-        Ok(())
+        self.apply_backbuffer_size()
     }
-
-    // Draw a fractal tree
+    
+    // Let resizes also set the CSS width and height of the canvas element to the drawing size.
+    // Off by default: only the backbuffer attributes change and the page's stylesheet decides
+    // how large the canvas is shown, which keeps percentage and flex layouts working
     #[wasm_bindgen]
-    pub fn draw_fractal_tree(&mut self, max_depth: u32, options: JsValue) -> Result<(), JsValue> {
+    pub fn set_style_size(&mut self, enabled: bool) -> Result<(), JsValue> {
         if self.is_disposed {
             return Err(JsValue::from_str("Canvas has been disposed"));
         }
         
-        // Extract fractal tree options from JsValue
-        let mut branch_count: u32 = 3;
-        let mut wind_strength: f32 = 0.15;
+        self.style_size = enabled;
+        self.apply_backbuffer_size()
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_style_size(&self) -> bool {
+        self.style_size
+    }
+    
+    // Set the device pixel ratio used for the backbuffer, or pass nothing to go
+    // back to automatic detection from window.devicePixelRatio
+    #[wasm_bindgen]
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: Option<f32>) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
         
-        if !options.is_null() && !options.is_undefined() {
-            if let Ok(branches) = Reflect::get(&options, &JsValue::from_str("branch_count")) {
-                if !branches.is_null() && !branches.is_undefined() {
-                    if let Some(value) = branches.as_f64() {
-                        branch_count = value as u32;
-                    }
-                }
+        match pixel_ratio {
+            Some(ratio) => {
+                self.set_ratio(ratio)?;
+                self.auto_pixel_ratio = false;
             }
-            
-            if let Ok(wind) = Reflect::get(&options, &JsValue::from_str("wind_strength")) {
-                if !wind.is_null() && !wind.is_undefined() {
-                    if let Some(value) = wind.as_f64() {
-                        wind_strength = value as f32;
-                    }
-                }
+            None => {
+                self.pixel_ratio = detect_pixel_ratio();
+                self.auto_pixel_ratio = true;
             }
         }
         
-        console_log!("Fractal tree with depth {}, branches={}, wind={}", max_depth, branch_count, wind_strength);
+        self.apply_backbuffer_size()
+    }
+    
+    // Get the device pixel ratio used for the backbuffer
+    #[wasm_bindgen]
+    pub fn get_pixel_ratio(&self) -> f32 {
+        self.pixel_ratio
+    }
+    
+    // Choose between CSS-pixel coordinates with a top-left origin (the default,
+    // matching CanvasRenderingContext2D) and a centered, y-up coordinate system
+    #[wasm_bindgen]
+    pub fn set_coordinate_system(&mut self, coordinate_system: CoordinateSystem) {
+        self.camera.coordinate_system = coordinate_system;
+    }
+    
+    // Color the polygon scene with a palette. Takes effect the next time the polygons are created
+    #[wasm_bindgen]
+    pub fn set_polygon_palette(&mut self, palette: &Palette) {
+        self.polygon_palette = Some(palette.clone());
+    }
+    
+    // Go back to evenly spaced OKLCH hues for the polygon scene
+    #[wasm_bindgen]
+    pub fn reset_polygon_palette(&mut self) {
+        self.polygon_palette = None;
+    }
+    
+    // Get current element count
+    #[wasm_bindgen]
+    pub fn get_element_count(&self) -> u32 {
+        self.element_count
+    }
+    
+    // Dispose of resources
+    #[wasm_bindgen]
+    pub fn dispose(&mut self) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Ok(());
+        }
         
-        // For now, just use polygon buffers until we implement proper fractal tree
-        let element_count = (2u32.pow(max_depth) - 1) / (branch_count - 1);
-        let element_count = element_count.min(100); // Reasonable limit
+        // Delete WebGL resources
+        if let Some(mut post) = self.post_processor.take() {
+            post.delete(&self.gl);
+        }
+        self.shapes.delete(&self.gl);
+        self.clipper.delete(&self.gl);
+        self.compositor.delete(&self.gl);
+        self.shadows.delete(&self.gl);
+        self.filters.delete(&self.gl);
+        if let Some(linear) = self.linear_output.take() {
+            linear.delete(&self.gl);
+        }
+        if let Some(multisample) = self.multisample.take() {
+            multisample.delete(&self.gl);
+        }
+        self.textures.clear();
+        self.primitives.delete(&self.gl);
+        self.sprites.delete(&self.gl);
+        self.text.delete(&self.gl);
+        self.gl.delete_program(Some(&self.program));
+        self.gl.delete_vertex_array(Some(&self.vao));
+        self.frame_capture = None;
         
-        setup_polygon_buffers(&self.gl, &self.program, element_count as usize)?;
-        self.element_count = element_count;
+        self.is_disposed = true;
+        console_log!("Canvas2D GPU Renderer disposed");
         
         Ok(())
     }
-}
 
-// Setup buffers for polygons with increasing number of sides
-fn setup_polygon_buffers(gl: &WebGl2RenderingContext, program: &WebGlProgram, num_polygons: usize) -> Result<(), JsValue> {
-    // Create vertices for all polygons
-    let mut all_vertices = Vec::with_capacity(num_polygons * VERTICES_PER_POLYGON * 3);
+    // Set both the fill and stroke styles to a flat RGBA color
+    #[wasm_bindgen]
+    pub fn set_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.state.fill_style = Paint::Color([r, g, b, a]);
+        self.state.stroke_style = Paint::Color([r, g, b, a]);
+    }
+    
+    // Set the fill style to a flat RGBA color
+    #[wasm_bindgen]
+    pub fn set_fill_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.state.fill_style = Paint::Color([r, g, b, a]);
+    }
+    
+    // Set the stroke style to a flat RGBA color
+    #[wasm_bindgen]
+    pub fn set_stroke_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.state.stroke_style = Paint::Color([r, g, b, a]);
+    }
+    
+    // Set the fill style to a CSS color string, e.g. "#ff8000", "rgb(255 128 0 / 50%)",
+    // "hsl(30deg 100% 50%)", "oklch(0.7 0.19 50)" or "orange"
+    #[wasm_bindgen]
+    pub fn set_fill_style(&mut self, color: &str) -> Result<(), JsValue> {
+        self.state.fill_style = Paint::Color(parse_color(color)?);
+        Ok(())
+    }
+    
+    // Set the stroke style to a CSS color string
+    #[wasm_bindgen]
+    pub fn set_stroke_style(&mut self, color: &str) -> Result<(), JsValue> {
+        self.state.stroke_style = Paint::Color(parse_color(color)?);
+        Ok(())
+    }
+    
+    // Use a gradient as the fill style. The gradient is copied, stops added
+    // afterwards need another call
+    #[wasm_bindgen]
+    pub fn set_fill_gradient(&mut self, gradient: &Gradient) {
+        self.state.fill_style = Paint::Gradient(gradient.clone());
+    }
+    
+    // Use a gradient as the stroke style
+    #[wasm_bindgen]
+    pub fn set_stroke_gradient(&mut self, gradient: &Gradient) {
+        self.state.stroke_style = Paint::Gradient(gradient.clone());
+    }
+    
+    // Set the stroke width in CSS pixels
+    #[wasm_bindgen]
+    pub fn set_line_width(&mut self, width: f32) {
+        // Like CanvasRenderingContext2D, invalid values are ignored
+        if width > 0.0 && width.is_finite() {
+            self.state.line_width = width;
+        }
+    }
+    
+    // Create a linear gradient between (x0, y0) and (x1, y1)
+    #[wasm_bindgen]
+    pub fn create_linear_gradient(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> Gradient {
+        Gradient::linear(x0, y0, x1, y1)
+    }
+    
+    // Create a two-circle radial gradient
+    #[wasm_bindgen]
+    pub fn create_radial_gradient(&self, x0: f32, y0: f32, r0: f32, x1: f32, y1: f32, r1: f32) -> Result<Gradient, JsValue> {
+        if r0 < 0.0 || r1 < 0.0 {
+            return Err(JsValue::from_str("Radial gradient radii must not be negative"));
+        }
+        
+        Ok(Gradient::radial(x0, y0, r0, x1, y1, r1))
+    }
+    
+    // Create a conic gradient around (x, y), starting at start_angle (radians, clockwise)
+    #[wasm_bindgen]
+    pub fn create_conic_gradient(&self, start_angle: f32, x: f32, y: f32) -> Gradient {
+        Gradient::conic(start_angle, x, y)
+    }
+    
+    // Fill a rectangle with the fill style
+    #[wasm_bindgen]
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.record_fill(FillRule::NonZero, || {
+            let mut path = Path2D::new();
+            path.rect(x, y, width, height);
+            path
+        });
+        self.shapes.fill_rect(x, y, width, height, &self.state.fill_style);
+    }
+    
+    // Outline a rectangle with the stroke style
+    #[wasm_bindgen]
+    pub fn stroke_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.record_stroke(|| {
+            let mut path = Path2D::new();
+            path.rect(x, y, width, height);
+            path
+        });
+        let corners = [(x, y), (x + width, y), (x + width, y + height), (x, y + height)];
+        self.shapes.stroke_polyline(&corners, true, self.state.line_width, &self.state.stroke_style);
+    }
+    
+    // Fill a circle with the fill style
+    #[wasm_bindgen]
+    pub fn fill_circle(&mut self, x: f32, y: f32, radius: f32) {
+        self.record_fill(FillRule::NonZero, || circle_path(x, y, radius));
+        let points = circle_points(x, y, radius, self.segments_for(radius));
+        self.shapes.fill_convex(&points, &self.state.fill_style);
+    }
+    
+    // Outline a circle with the stroke style
+    #[wasm_bindgen]
+    pub fn stroke_circle(&mut self, x: f32, y: f32, radius: f32) {
+        self.record_stroke(|| circle_path(x, y, radius));
+        let points = circle_points(x, y, radius, self.segments_for(radius));
+        self.shapes.stroke_polyline(&points, true, self.state.line_width, &self.state.stroke_style);
+    }
+    
+    // Draw a line segment with the stroke style and line width
+    #[wasm_bindgen]
+    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.record_stroke(|| {
+            let mut path = Path2D::new();
+            path.move_to(x1, y1);
+            path.line_to(x2, y2);
+            path
+        });
+        self.shapes.stroke_polyline(&[(x1, y1), (x2, y2)], false, self.state.line_width, &self.state.stroke_style);
+    }
+    
+    // Fill a path with the fill style. fill_rule is "nonzero" (default) or "evenodd".
+    // Concave and self-intersecting paths need a context created with { stencil: true }
+    #[wasm_bindgen]
+    pub fn fill_path(&mut self, path: &Path2D, fill_rule: Option<String>) -> Result<(), JsValue> {
+        let rule = FillRule::parse(fill_rule.as_deref())?;
+        let contours = path.flatten(self.curve_tolerance());
+        self.record_fill(rule, || path.clone());
+        
+        // Convex paths need no stencil pass
+        if let Some(points) = path::as_convex(&contours) {
+            self.shapes.fill_convex(&points, &self.state.fill_style);
+            return Ok(());
+        }
+        
+        if !self.clipper.has_stencil() {
+            return Err(JsValue::from_str(
+                "Filling concave paths needs a stencil buffer, create the WebGL2 context with { stencil: true }",
+            ));
+        }
+        self.shapes.fill_path(&contours, rule, &self.state.fill_style);
+        
+        Ok(())
+    }
+    
+    // Outline every subpath of a path with the stroke style and line width
+    #[wasm_bindgen]
+    pub fn stroke_path(&mut self, path: &Path2D) {
+        self.record_stroke(|| path.clone());
+        for contour in path.flatten(self.curve_tolerance()) {
+            self.shapes.stroke_polyline(&contour.points, contour.closed, self.state.line_width, &self.state.stroke_style);
+        }
+    }
+    
+    // Draw an SVG document with its top-left corner at (x, y), tessellated at the current
+    // zoom. Options: width and height (stretch the document, the other one keeps the aspect
+    // ratio when only one is given) or scale. Uses its own fills and strokes, not the fill
+    // and stroke styles, and needs no stencil buffer
+    #[wasm_bindgen]
+    pub fn draw_svg(&mut self, document: &SvgDocument, x: f32, y: f32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+    
+        let (width, height) = (document.width().max(1e-6), document.height().max(1e-6));
+        let scale = get_f32_option(&options, "scale").unwrap_or(1.0);
+        let (sx, sy) = match (get_f32_option(&options, "width"), get_f32_option(&options, "height")) {
+            (Some(w), Some(h)) => (w / width, h / height),
+            (Some(w), None) => (w / width, w / width),
+            (None, Some(h)) => (h / height, h / height),
+            (None, None) => (scale, scale),
+        };
+    
+        let transform = [sx, 0.0, 0.0, sy, x, y];
+        let layer = self.recording_layer();
+        if let Some(recording) = self.active_recording() {
+            document.record(transform, recording, &layer);
+        }
+        let mesh = document.tessellate(transform, self.curve_tolerance());
+        self.shapes.fill_mesh(&mesh.positions, &mesh.colors, &mesh.indices);
+        Ok(())
+    }
+    
+    // How the next drawing calls combine with the canvas, like globalCompositeOperation:
+    // "source-over" (default), "source-in", "source-out", "source-atop", "destination-over",
+    // "destination-in", "destination-out", "destination-atop", "copy", "xor", "lighter"
+    // (alias "additive"), or the blend modes "screen", "multiply", "overlay", "darken",
+    // "lighten", "color-dodge", "color-burn", "hard-light", "soft-light", "difference" and
+    // "exclusion". Operations only affect the pixels covered by the drawn shape
+    #[wasm_bindgen]
+    pub fn set_global_composite_operation(&mut self, operation: &str) -> Result<(), JsValue> {
+        self.state.composite = CompositeOperation::parse(operation)?;
+        self.sync_state();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_global_composite_operation(&self) -> String {
+        self.state.composite.name().to_string()
+    }
+    
+    // Color of the shadow cast by the next shapes, a CSS color like shadowColor. Shadows are
+    // only drawn when the color is not fully transparent and there is a blur or an offset
+    #[wasm_bindgen]
+    pub fn set_shadow_color(&mut self, color: &str) -> Result<(), JsValue> {
+        self.state.shadow.color = parse_color(color)?;
+        self.sync_state();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_shadow_color(&self) -> String {
+        Color::from_array(self.state.shadow.color).to_css()
+    }
+    
+    // Blur of the shadow in CSS pixels, like shadowBlur: the gaussian standard deviation is
+    // half of it. The camera zoom does not scale shadows
+    #[wasm_bindgen]
+    pub fn set_shadow_blur(&mut self, blur: f32) {
+        // Like CanvasRenderingContext2D, invalid values are ignored
+        if blur >= 0.0 && blur.is_finite() {
+            self.state.shadow.blur = blur;
+            self.sync_state();
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_shadow_blur(&self) -> f32 {
+        self.state.shadow.blur
+    }
+    
+    // Horizontal offset of the shadow in CSS pixels, positive to the right
+    #[wasm_bindgen]
+    pub fn set_shadow_offset_x(&mut self, offset: f32) {
+        if offset.is_finite() {
+            self.state.shadow.offset_x = offset;
+            self.sync_state();
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_shadow_offset_x(&self) -> f32 {
+        self.state.shadow.offset_x
+    }
+    
+    // Vertical offset of the shadow in CSS pixels, positive down the screen
+    #[wasm_bindgen]
+    pub fn set_shadow_offset_y(&mut self, offset: f32) {
+        if offset.is_finite() {
+            self.state.shadow.offset_y = offset;
+            self.sync_state();
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_shadow_offset_y(&self) -> f32 {
+        self.state.shadow.offset_y
+    }
+    
+    // Turn the shadow into an outer glow: the blurred halo is added to the canvas like
+    // "lighter" and left out of the shape itself, so it only shows around its edges
+    #[wasm_bindgen]
+    pub fn set_shadow_glow(&mut self, glow: bool) {
+        self.state.shadow.glow = glow;
+        self.sync_state();
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_shadow_glow(&self) -> bool {
+        self.state.shadow.glow
+    }
+    
+    // Outer glow of the given color and blur around the next shapes, centered on them
+    #[wasm_bindgen]
+    pub fn set_glow(&mut self, color: &str, blur: f32) -> Result<(), JsValue> {
+        if !(blur >= 0.0 && blur.is_finite()) {
+            return Err(JsValue::from_str("Glow blur must be a non-negative number"));
+        }
+        
+        self.state.shadow = Shadow {
+            color: parse_color(color)?,
+            blur,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            glow: true,
+        };
+        self.sync_state();
+        Ok(())
+    }
+    
+    // Stop drawing shadows and glows, like a transparent shadowColor
+    #[wasm_bindgen]
+    pub fn clear_shadow(&mut self) {
+        self.state.shadow = Shadow::default();
+        self.sync_state();
+    }
+    
+    // Filter the next shapes, text and images like the filter property: "none" or a list of
+    // blur(), brightness(), contrast(), drop-shadow(), grayscale(), hue-rotate(), invert(),
+    // opacity(), saturate() and sepia(). Each drawing call is rendered into a layer the
+    // filter runs over on the GPU before it is composited, so filters cost extra passes.
+    // Colors are filtered in the space of the color pipeline
+    #[wasm_bindgen]
+    pub fn set_filter(&mut self, filter: &str) -> Result<(), JsValue> {
+        self.state.filter = Rc::new(Filter::parse(filter)?);
+        self.sync_state();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_filter(&self) -> String {
+        self.state.filter.css().to_string()
+    }
+    
+    // Premultiply alpha throughout: vertex colors, gradient stops and images and sprites
+    // uploaded from now on are premultiplied before they are interpolated or filtered, which
+    // removes the dark fringes around translucent edges. Gradients then interpolate
+    // premultiplied colors instead of following CanvasGradient
+    #[wasm_bindgen]
+    pub fn set_premultiplied_alpha(&mut self, premultiplied: bool) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        self.pipeline.premultiplied = premultiplied;
+        self.textures.set_premultiply(premultiplied);
+        self.sync_pipeline();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_premultiplied_alpha(&self) -> bool {
+        self.pipeline.premultiplied
+    }
+    
+    // Blend and interpolate in linear light: the scene is drawn into an sRGB-encoded target
+    // and encoded for display at the end of the frame. Colors are still given in sRGB
+    #[wasm_bindgen]
+    pub fn set_linear_blending(&mut self, linear: bool) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        if linear && self.linear_output.is_none() {
+            let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+            self.linear_output = Some(LinearOutput::new(&self.gl, backbuffer_width, backbuffer_height)?);
+        } else if !linear {
+            if let Some(output) = self.linear_output.take() {
+                output.delete(&self.gl);
+            }
+        }
+        
+        self.pipeline.linear = linear;
+        self.compositor.set_srgb(&self.gl, linear);
+        let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+        if let Some(multisample) = self.multisample.as_mut() {
+            multisample.configure(&self.gl, backbuffer_width, backbuffer_height, linear);
+        }
+        self.sync_pipeline();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_linear_blending(&self) -> bool {
+        self.pipeline.linear
+    }
+    
+    // Smooth the edges of shapes: "none", "msaa" (resolving `samples` per pixel, 4 by default,
+    // limited to what the GPU supports) or "analytic" (a one device pixel feather around fills
+    // and strokes, fading out thin lines). MSAA also smooths sprites and text edges
+    #[wasm_bindgen]
+    pub fn set_antialiasing(&mut self, mode: &str, samples: Option<u32>) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let antialiasing = Antialiasing::parse(mode, samples)?;
+        if antialiasing == self.antialiasing {
+            return Ok(());
+        }
+        
+        if let Some(multisample) = self.multisample.take() {
+            multisample.delete(&self.gl);
+        }
+        if let Antialiasing::Msaa(samples) = antialiasing {
+            let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+            self.multisample = Some(MultisampleTarget::new(
+                &self.gl,
+                samples,
+                backbuffer_width,
+                backbuffer_height,
+                self.pipeline.linear,
+            )?);
+        }
+        
+        self.antialiasing = antialiasing;
+        self.sync_antialiasing();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_antialiasing(&self) -> String {
+        self.antialiasing.name().to_string()
+    }
+    
+    // Samples per pixel of MSAA as allocated, 0 in the other modes
+    #[wasm_bindgen]
+    pub fn get_antialiasing_samples(&self) -> u32 {
+        self.multisample.as_ref().map_or(0, |multisample| multisample.samples())
+    }
+    
+    // Push the drawing state (styles, line width, text settings, clip and composite operation) on a stack
+    #[wasm_bindgen]
+    pub fn save(&mut self) {
+        self.saved_states.push(self.state.clone());
+    }
+    
+    // Pop the drawing state pushed by the last save, does nothing if the stack is empty
+    #[wasm_bindgen]
+    pub fn restore(&mut self) {
+        if let Some(state) = self.saved_states.pop() {
+            self.state = state;
+            self.sync_state();
+        }
+    }
+    
+    // Intersect the clip region with a path, like CanvasRenderingContext2D.clip(path, fillRule).
+    // Axis-aligned rectangles use the scissor test, other paths need a context created
+    // with { stencil: true }. Use save and restore to undo a clip
+    #[wasm_bindgen]
+    pub fn clip(&mut self, path: &Path2D, fill_rule: Option<String>) -> Result<(), JsValue> {
+        let rule = FillRule::parse(fill_rule.as_deref())?;
+        let contours = path.flatten(self.curve_tolerance());
+        
+        let clip = match path::as_rect(&contours) {
+            Some(rect) => self.state.clip.with_rect(rect),
+            None => {
+                let triangles = path::stencil_fans(&contours);
+                if triangles.is_empty() {
+                    // Nothing is inside a path without area
+                    self.state.clip.with_rect([0.0, 0.0, 0.0, 0.0])
+                } else if self.clipper.has_stencil() {
+                    self.state.clip.with_path(ClipPath::new(triangles, rule))
+                } else {
+                    return Err(JsValue::from_str(
+                        "Clipping to paths needs a stencil buffer, create the WebGL2 context with { stencil: true }",
+                    ));
+                }
+            }
+        };
+        
+        self.state.clip = Rc::new(clip);
+        self.state.recorded_clip = Some(Rc::new(RecordedClip::new(path.clone(), rule, self.state.recorded_clip.take())));
+        self.sync_state();
+        
+        Ok(())
+    }
+    
+    // Intersect the clip region with a rectangle. Cheap: it only sets the scissor box
+    #[wasm_bindgen]
+    pub fn clip_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let rect = [x.min(x + width), y.min(y + height), width.abs(), height.abs()];
+        self.state.clip = Rc::new(self.state.clip.with_rect(rect));
+        let mut path = Path2D::new();
+        path.rect(rect[0], rect[1], rect[2], rect[3]);
+        self.state.recorded_clip = Some(Rc::new(RecordedClip::new(path, FillRule::NonZero, self.state.recorded_clip.take())));
+        self.sync_state();
+    }
+    
+    // Remove every clip region of the current drawing state
+    #[wasm_bindgen]
+    pub fn reset_clip(&mut self) {
+        self.state.clip = Rc::new(Clip::default());
+        self.state.recorded_clip = None;
+        self.sync_state();
+    }
+    
+    // Draw an image like CanvasRenderingContext2D.drawImage:
+    //   draw_image(image, dx, dy)
+    //   draw_image(image, dx, dy, dw, dh)
+    //   draw_image(image, sx, sy, sw, sh, dx, dy, dw, dh)
+    // The image is an HtmlImageElement, an ImageData or the name given to upload_image_rgba.
+    // Uploads are cached per source, call release_image after changing the pixels
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_image(
+        &mut self,
+        image: &JsValue,
+        a1: f32,
+        a2: f32,
+        a3: Option<f32>,
+        a4: Option<f32>,
+        a5: Option<f32>,
+        a6: Option<f32>,
+        a7: Option<f32>,
+        a8: Option<f32>,
+    ) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let texture = self.textures.resolve(&self.gl, image)?;
+        let image_width = texture.width() as f32;
+        let image_height = texture.height() as f32;
+        
+        let (source, destination) = match (a3, a4, a5, a6, a7, a8) {
+            (None, None, None, None, None, None) => (
+                [0.0, 0.0, image_width, image_height],
+                [a1, a2, image_width, image_height],
+            ),
+            (Some(dw), Some(dh), None, None, None, None) => (
+                [0.0, 0.0, image_width, image_height],
+                [a1, a2, dw, dh],
+            ),
+            (Some(sw), Some(sh), Some(dx), Some(dy), Some(dw), Some(dh)) => (
+                [a1, a2, sw, sh],
+                [dx, dy, dw, dh],
+            ),
+            _ => return Err(JsValue::from_str("draw_image expects 3, 5 or 9 arguments")),
+        };
+        
+        if let Some((source, destination)) = clip_image_rects(source, destination, image_width, image_height) {
+            let layer = self.recording_layer();
+            if let Some(recording) = self.active_recording() {
+                if let Some(href) = texture.href() {
+                    let image = RecordedImage {
+                        href,
+                        width: texture.width(),
+                        height: texture.height(),
+                    };
+                    let [x, y, width, height] = destination;
+                    let transform = [width / source[2], 0.0, 0.0, height / source[3], x, y];
+                    recording.image(image, source, transform, 1.0, &layer);
+                } else {
+                    recording.mark_unrecordable("an image uploaded before start_recording".to_string());
+                }
+            }
+            self.shapes.draw_image(&texture, source, destination);
+        }
+        
+        Ok(())
+    }
+    
+    // Create a pattern from an image, repetition is "repeat" (default), "repeat-x",
+    // "repeat-y" or "no-repeat"
+    #[wasm_bindgen]
+    pub fn create_pattern(&mut self, image: &JsValue, repetition: Option<String>) -> Result<Pattern, JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let repetition = Repetition::parse(repetition.as_deref().unwrap_or(""))?;
+        let texture = self.textures.resolve(&self.gl, image)?;
+        
+        Ok(Pattern::new(texture, repetition))
+    }
+    
+    // Use a pattern as the fill style
+    #[wasm_bindgen]
+    pub fn set_fill_pattern(&mut self, pattern: &Pattern) {
+        self.state.fill_style = Paint::Pattern(pattern.clone());
+    }
+    
+    // Use a pattern as the stroke style
+    #[wasm_bindgen]
+    pub fn set_stroke_pattern(&mut self, pattern: &Pattern) {
+        self.state.stroke_style = Paint::Pattern(pattern.clone());
+    }
+    
+    // Upload raw RGBA bytes (width * height * 4, rows top to bottom) under a name that can
+    // be passed to draw_image and create_pattern. Uploading again replaces the image
+    #[wasm_bindgen]
+    pub fn upload_image_rgba(&mut self, name: &str, width: u32, height: u32, pixels: &[u8]) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        self.textures.upload_rgba(&self.gl, name, width, height, pixels)
+    }
+    
+    // Upload a palette as a width x 1 lookup image (256 texels by default) under a name that
+    // can be passed to draw_image and create_pattern
+    #[wasm_bindgen]
+    pub fn upload_palette(&mut self, name: &str, palette: &Palette, width: Option<u32>) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let width = width.unwrap_or(256).max(1);
+        self.textures.upload_rgba(&self.gl, name, width, 1, &palette.to_lut(width))
+    }
+    
+    // Drop the cached texture of an image source, it is uploaded again on next use. Patterns
+    // and queued draws still using the old texture keep it until they are done
+    #[wasm_bindgen]
+    pub fn release_image(&mut self, image: &JsValue) {
+        if self.is_disposed {
+            return;
+        }
+        
+        self.textures.release(image);
+    }
+    
+    // Register an HtmlImageElement or ImageData as a sprite. Sprites are packed into shared
+    // atlas textures so thousands of them draw in a single instanced call
+    #[wasm_bindgen]
+    pub fn add_sprite(&mut self, name: &str, image: &JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        self.sprites.add(name, image)
+    }
+    
+    // Register raw RGBA bytes (width * height * 4, rows top to bottom) as a sprite
+    #[wasm_bindgen]
+    pub fn add_sprite_rgba(&mut self, name: &str, width: u32, height: u32, pixels: &[u8]) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        self.sprites.add_rgba(name, width, height, pixels)
+    }
+    
+    // Unregister a sprite, returns false if there was no sprite with that name
+    #[wasm_bindgen]
+    pub fn remove_sprite(&mut self, name: &str) -> bool {
+        self.sprites.remove(name)
+    }
+    
+    // Draw a shape evaluated per pixel with a distance function, exact at any zoom: "circle",
+    // "ellipse", "rounded-rect", "ring", "capsule" or "polygon", fitted in the box with its
+    // top-left corner at (x, y). Filled with the fill style color and bordered with the stroke
+    // style color. Options: rotation (radians, around the center), border (width, inside the
+    // edge), radius or radii (1 to 4 corner radii as in roundRect, also rounds polygons),
+    // inner_radius (rings, half the radius by default) and sides (polygons, 6 by default).
+    // Primitives are drawn above the shapes with one instanced call per clip and composite
+    // operation, shadows and filters do not apply to them
+    #[wasm_bindgen]
+    pub fn draw_primitive(
+        &mut self,
+        kind: &str,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        options: JsValue,
+    ) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let kind = Primitive::parse(kind)?;
+        let (Paint::Color(fill), Paint::Color(border)) = (&self.state.fill_style, &self.state.stroke_style) else {
+            return Err(JsValue::from_str("Primitives are drawn with flat fill and stroke colors"));
+        };
+        
+        let radius = width.abs().min(height.abs()) * 0.5;
+        let parameter = match kind {
+            Primitive::Ring => get_f32_option(&options, "inner_radius").unwrap_or(radius * 0.5),
+            Primitive::Polygon => get_f32_option(&options, "sides").unwrap_or(6.0),
+            _ => 0.0,
+        };
+        
+        let shape = PrimitiveShape {
+            kind,
+            center: (x + width * 0.5, y + height * 0.5),
+            half_size: (width * 0.5, height * 0.5),
+            rotation: get_f32_option(&options, "rotation").unwrap_or(0.0),
+            radii: corner_radii(&options)?,
+            border_width: get_f32_option(&options, "border").unwrap_or(0.0),
+            parameter,
+            fill: *fill,
+            border: *border,
+        };
+        let layer = self.plain_recording_layer();
+        if let Some(recording) = self.active_recording() {
+            recording.primitive(&shape, &layer);
+        }
+        self.primitives.draw(&shape);
+        Ok(())
+    }
+    
+    // Draw a sprite with its top-left corner at (x, y). Sprites are drawn above the shapes.
+    // Options: width, height (defaults to the image size), rotation (radians, around the
+    // center) and alpha
+    #[wasm_bindgen]
+    pub fn draw_sprite(&mut self, name: &str, x: f32, y: f32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let (image_width, image_height) = self
+            .sprites
+            .size(name)
+            .ok_or_else(|| JsValue::from_str(&format!("No sprite named \"{}\"", name)))?;
+        let width = get_f32_option(&options, "width").unwrap_or(image_width as f32);
+        let height = get_f32_option(&options, "height").unwrap_or(image_height as f32);
+        let rotation = get_f32_option(&options, "rotation").unwrap_or(0.0);
+        let alpha = get_f32_option(&options, "alpha").unwrap_or(1.0);
+        
+        self.sprites.draw(&self.gl, name, [x, y, width, height], rotation, [1.0, 1.0, 1.0, alpha])?;
+        self.record_sprite(name, [x, y, width, height], rotation, alpha);
+        Ok(())
+    }
+    
+    // Draw many copies of a sprite. Instances are packed as (x, y, rotation, scale) with
+    // (x, y) the center of the sprite
+    #[wasm_bindgen]
+    pub fn draw_sprites(&mut self, name: &str, instances: &[f32]) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        if !instances.len().is_multiple_of(4) {
+            return Err(JsValue::from_str("Sprite instances must be packed as x, y, rotation, scale"));
+        }
+        
+        let (image_width, image_height) = self
+            .sprites
+            .size(name)
+            .ok_or_else(|| JsValue::from_str(&format!("No sprite named \"{}\"", name)))?;
+        
+        for instance in instances.chunks_exact(4) {
+            let width = image_width as f32 * instance[3];
+            let height = image_height as f32 * instance[3];
+            let rect = [instance[0] - width * 0.5, instance[1] - height * 0.5, width, height];
+            self.sprites.draw(&self.gl, name, rect, instance[2], [1.0, 1.0, 1.0, 1.0])?;
+            self.record_sprite(name, rect, instance[2], 1.0);
+        }
+        
+        Ok(())
+    }
+    
+    // Load a TrueType/OpenType font (.ttf, .otf or the first font of a .ttc) under a name.
+    // The first loaded font becomes the current font
+    #[wasm_bindgen]
+    pub fn load_font(&mut self, name: &str, bytes: &[u8]) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let index = self.text.load_font(name, bytes)?;
+        if self.state.text_style.font.is_none() {
+            self.state.text_style.font = Some(index);
+        }
+        
+        Ok(())
+    }
+    
+    // Select a loaded font and its size in CSS pixels
+    #[wasm_bindgen]
+    pub fn set_font(&mut self, name: &str, size: f32) -> Result<(), JsValue> {
+        let index = self
+            .text
+            .find_font(name)
+            .ok_or_else(|| JsValue::from_str(&format!("No font loaded under \"{}\"", name)))?;
+        
+        if !(size > 0.0 && size.is_finite()) {
+            return Err(JsValue::from_str("Font size must be positive"));
+        }
+        
+        self.state.text_style.font = Some(index);
+        self.state.text_style.size = size;
+        
+        Ok(())
+    }
+    
+    // "start" (default), "end", "left", "right" or "center"
+    #[wasm_bindgen]
+    pub fn set_text_align(&mut self, align: &str) -> Result<(), JsValue> {
+        self.state.text_style.align = TextAlign::parse(align)?;
+        Ok(())
+    }
+    
+    // "top", "hanging", "middle", "alphabetic" (default), "ideographic" or "bottom"
+    #[wasm_bindgen]
+    pub fn set_text_baseline(&mut self, baseline: &str) -> Result<(), JsValue> {
+        self.state.text_style.baseline = TextBaseline::parse(baseline)?;
+        Ok(())
+    }
+    
+    // Distance between the baselines of multi-line text in CSS pixels,
+    // None uses the line spacing of the font
+    #[wasm_bindgen]
+    pub fn set_line_height(&mut self, line_height: Option<f32>) {
+        self.state.text_style.line_height = line_height.filter(|height| height.is_finite());
+    }
+    
+    // Fill text with the fill style. Lines are separated by '\n', max_width squeezes
+    // lines horizontally to fit
+    #[wasm_bindgen]
+    pub fn fill_text(&mut self, text: &str, x: f32, y: f32, max_width: Option<f32>) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        if self.recording_active {
+            let path = self.text.text_path(&self.state.text_style, text, x, y, max_width)?;
+            self.record_fill(FillRule::NonZero, || path);
+        }
+        self.text.draw(&self.gl, &mut self.shapes, &self.state.text_style, text, x, y, max_width, &self.state.fill_style, None)
+    }
+    
+    // Outline text with the stroke style and line width
+    #[wasm_bindgen]
+    pub fn stroke_text(&mut self, text: &str, x: f32, y: f32, max_width: Option<f32>) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        if self.recording_active {
+            let path = self.text.text_path(&self.state.text_style, text, x, y, max_width)?;
+            self.record_stroke(|| path);
+        }
+        self.text.draw(
+            &self.gl,
+            &mut self.shapes,
+            &self.state.text_style,
+            text,
+            x,
+            y,
+            max_width,
+            &self.state.stroke_style,
+            Some(self.state.line_width),
+        )
+    }
+    
+    // Measure text with the current font
+    #[wasm_bindgen]
+    pub fn measure_text(&self, text: &str) -> Result<TextMetrics, JsValue> {
+        self.text.measure(&self.state.text_style, text)
+    }
+    
+    // Lay out a paragraph without drawing it. Returns { width, height, truncated, rtl, lines },
+    // each line has { x, top, baseline, width, height, ascent, descent, start, end, glyphs }
+    // and each glyph { glyph, x, y, size, span, index }, relative to the top-left corner
+    #[wasm_bindgen]
+    pub fn layout_paragraph(&self, paragraph: &Paragraph) -> Result<JsValue, JsValue> {
+        let layout = layout::layout_paragraph(&self.text, paragraph)?;
+        serde_wasm_bindgen::to_value(&layout).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+    
+    // Draw a paragraph with its top-left corner at (x, y). Spans without a color
+    // use the fill style
+    #[wasm_bindgen]
+    pub fn fill_paragraph(&mut self, paragraph: &Paragraph, x: f32, y: f32) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        let layout = layout::layout_paragraph(&self.text, paragraph)?;
+        let colors = paragraph.span_colors();
+        
+        for line in &layout.lines {
+            for glyph in &line.glyphs {
+                let paint = match colors[glyph.span] {
+                    Some(color) => Paint::Color(color),
+                    None => self.state.fill_style.clone(),
+                };
+                if self.recording_active {
+                    let mut path = Path2D::new();
+                    self.text.glyph_path(glyph.font, glyph.glyph, [x + glyph.x, y + glyph.y], glyph.size, 1.0, &mut path)?;
+                    let layer = self.recording_layer();
+                    if let Some(recording) = self.active_recording() {
+                        recording.fill(path, FillRule::NonZero, &paint, &layer);
+                    }
+                }
+                self.text.draw_glyph(
+                    &self.gl,
+                    &mut self.shapes,
+                    glyph.font,
+                    glyph.glyph,
+                    [x + glyph.x, y + glyph.y],
+                    glyph.size,
+                    1.0,
+                    &paint,
+                    None,
+                )?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // Record the following drawing calls as vector shapes for export_svg, dropping any earlier
+    // recording. Fills, strokes, text outlines, SVG documents, primitives, images and sprites
+    // are kept with their clip, blend mode, filter and shadow, and the polygon scene as drawn
+    // by the last render(). Images and patterns are embedded by URL or as PNG data, conic
+    // gradients become fine wedges. Raw pixels uploaded before this call are not kept, so
+    // drawing them makes the recording unexportable. clear() starts over, and the background
+    // is the one the last render() cleared to
+    #[wasm_bindgen]
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::default());
+        self.recording_active = true;
+        self.textures.set_keep_sources(true);
+    }
+    
+    // Stop recording, the recorded shapes stay available to export_svg
+    #[wasm_bindgen]
+    pub fn stop_recording(&mut self) {
+        self.recording_active = false;
+        self.textures.set_keep_sources(false);
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_recording(&self) -> bool {
+        self.recording_active
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_recorded_shape_count(&self) -> u32 {
+        self.recording.as_ref().map_or(0, |recording| recording.len() as u32)
+    }
+    
+    // Serialize the recording as an SVG document the size of the canvas, seen through the
+    // current camera. Fails when something SVG cannot express was drawn since the last
+    // clear(): a glow shadow or a Porter-Duff composite operation other than source-over
+    // and lighter
+    #[wasm_bindgen]
+    pub fn export_svg(&self) -> Result<String, JsValue> {
+        let recording = self.recording.as_ref().ok_or("Nothing recorded, call start_recording first")?;
+        if let Some(content) = recording.unrecordable() {
+            return Err(JsValue::from_str(&format!("Unrecordable content was drawn while recording: {}", content)));
+        }
+        Ok(recording.to_svg(self.width, self.height, self.view_transform()))
+    }
+    
+    // The finished or stopped capture with at least one frame
+    fn captured_frames(&self) -> Result<&FrameCapture, JsValue> {
+        let capture = self.frame_capture.as_ref().filter(|capture| capture.len() > 0);
+        let capture = capture.ok_or("No frames captured, call start_frame_capture and render first")?;
+        if capture.is_active() {
+            return Err(JsValue::from_str("Frame capture is still running"));
+        }
+        Ok(capture)
+    }
+    
+    // The recording drawing calls are added to, None when not recording
+    fn active_recording(&mut self) -> Option<&mut Recording> {
+        self.recording.as_mut().filter(|_| self.recording_active)
+    }
+    
+    // Drawing state recorded with the following shapes
+    fn recording_layer(&self) -> Layer {
+        Layer {
+            clip: self.state.recorded_clip.clone(),
+            composite: self.state.composite,
+            filter: self.state.filter.clone(),
+            shadow: self.state.shadow,
+        }
+    }
+    
+    // Record a fill with the fill style, the path is only built while recording
+    fn record_fill(&mut self, rule: FillRule, path: impl FnOnce() -> Path2D) {
+        if !self.recording_active {
+            return;
+        }
+        let layer = self.recording_layer();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.fill(path(), rule, &self.state.fill_style, &layer);
+        }
+    }
+    
+    // Record a stroke with the stroke style and line width
+    fn record_stroke(&mut self, path: impl FnOnce() -> Path2D) {
+        if !self.recording_active {
+            return;
+        }
+        let layer = self.recording_layer();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.stroke(path(), self.state.line_width, &self.state.stroke_style, &layer);
+        }
+    }
+    
+    // Drawing state recorded with primitives and sprites, which shadows and filters skip
+    fn plain_recording_layer(&self) -> Layer {
+        Layer {
+            filter: Rc::default(),
+            shadow: Shadow::default(),
+            ..self.recording_layer()
+        }
+    }
+    
+    // Record a sprite drawn into a rect, rotated around the center of the rect
+    fn record_sprite(&mut self, name: &str, rect: [f32; 4], rotation: f32, alpha: f32) {
+        if !self.recording_active {
+            return;
+        }
+        let Some(((image_width, image_height), href)) = self.sprites.size(name).zip(self.sprites.href(name)) else {
+            return;
+        };
+        
+        // Texels scaled to the rect around the origin, rotated, then moved to the rect center
+        let [x, y, width, height] = rect;
+        let (half_width, half_height) = (width * 0.5, height * 0.5);
+        let (scale_x, scale_y) = (width / image_width as f32, height / image_height as f32);
+        let (sin, cos) = rotation.sin_cos();
+        let transform = [
+            cos * scale_x,
+            sin * scale_x,
+            -sin * scale_y,
+            cos * scale_y,
+            x + half_width - cos * half_width + sin * half_height,
+            y + half_height - sin * half_width - cos * half_height,
+        ];
+        let image = RecordedImage {
+            href,
+            width: image_width,
+            height: image_height,
+        };
+        let source = [0.0, 0.0, image_width as f32, image_height as f32];
+        let layer = self.plain_recording_layer();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.image(image, source, transform, alpha, &layer);
+        }
+    }
+    
+    // The polygon row render() draws at a scene time as world-space paths, following the
+    // vertex shader. The pulse and shimmer of the fragment shader become a radial gradient,
+    // exact at the center and the corners of each polygon
+    fn scene_polygons(&self, time: f32) -> Vec<(Path2D, RecordedPaint)> {
+        let count = self.scene_colors.len().min(self.element_count as usize);
+        let (origin_x, origin_y, y_axis) = layout_origin(&self.camera, self.width, self.height);
+        let unit = self.width.min(self.height) as f32 * 0.5;
+        let scale = POLYGON_RADIUS * unit;
+        let pulse = (time * 1.5).sin() * 0.15 + 0.85;
+        
+        let mut polygons = Vec::with_capacity(count);
+        for (index, color) in self.scene_colors[..count].iter().enumerate() {
+            // A row of one polygon divides by zero in the shader and is not drawn
+            let i = index as f32;
+            let x_offset = i * 2.0 / (count as f32 - 1.0) - 1.0;
+            if !x_offset.is_finite() {
+                continue;
+            }
+            let phase = i * 0.5;
+            let y_offset = (time * 1.5 + phase).sin() * 0.25;
+            let wobble = (time * 0.7 + phase * 1.3).sin() * 0.02;
+            let (sin, cos) = ((time * 0.3 + phase).sin() * 0.2).sin_cos();
+            let placement = [
+                cos * scale,
+                sin * scale * y_axis,
+                -sin * scale,
+                cos * scale * y_axis,
+                origin_x + (x_offset + wobble) * unit,
+                origin_y + y_offset * unit * y_axis,
+            ];
+            
+            let sides = (MIN_POLYGON_SIDES + index).min(SEGMENTS);
+            let mut path = Path2D::new();
+            for corner in 0..sides {
+                let (y, x) = (corner as f32 * std::f32::consts::TAU / sides as f32).sin_cos();
+                if corner == 0 {
+                    path.move_to(x, y);
+                } else {
+                    path.line_to(x, y);
+                }
+            }
+            path.close_path();
+            
+            // The shimmer follows the distance from the center in unit polygon coordinates
+            let stops = (0..=SCENE_GRADIENT_STEPS)
+                .map(|step| {
+                    let distance = step as f32 / SCENE_GRADIENT_STEPS as f32;
+                    let shimmer = (time * 3.0 + distance * 3.0).sin() * 0.1 + 0.9;
+                    let shade = pulse * shimmer;
+                    ColorStop {
+                        offset: distance,
+                        color: [color[0] * shade, color[1] * shade, color[2] * shade, 1.0],
+                    }
+                })
+                .collect();
+            let paint = RecordedPaint::Gradient {
+                kind: GradientKind::Radial { x0: 0.0, y0: 0.0, r0: 0.0, x1: 0.0, y1: 0.0, r1: 1.0 },
+                stops,
+                spread: SpreadMode::Pad,
+                transform: placement,
+            };
+            polygons.push((path.transformed(placement), paint));
+        }
+        polygons
+    }
+    
+    // Affine map from world coordinates to CSS pixels of the camera
+    fn view_transform(&self) -> [f32; 6] {
+        let origin = self.camera.world_to_screen_point(0.0, 0.0);
+        let x_axis = self.camera.world_to_screen_point(1.0, 0.0);
+        let y_axis = self.camera.world_to_screen_point(0.0, 1.0);
+        [
+            x_axis.0 - origin.0,
+            x_axis.1 - origin.1,
+            y_axis.0 - origin.0,
+            y_axis.1 - origin.1,
+            origin.0,
+            origin.1,
+        ]
+    }
+    
+    // Segment count for a circle, based on its size on screen in device pixels
+    fn segments_for(&self, radius: f32) -> usize {
+        segments_for_radius(radius.abs() * self.camera.get_zoom() * self.pixel_ratio)
+    }
+    
+    // Curve flattening tolerance in world units, CURVE_TOLERANCE device pixels on screen
+    fn curve_tolerance(&self) -> f32 {
+        CURVE_TOLERANCE / (self.camera.get_zoom() * self.pixel_ratio).max(1e-6)
+    }
+    
+    // Feather the following shapes over one device pixel at the current zoom in analytic mode
+    fn sync_antialiasing(&mut self) {
+        let feather = match self.antialiasing {
+            Antialiasing::Analytic => 1.0 / (self.camera.get_zoom() * self.pixel_ratio).max(1e-6),
+            _ => 0.0,
+        };
+        self.shapes.set_feather(feather);
+    }
+    
+    // Encode the colors of the following shapes, primitives and sprites for the color pipeline
+    fn sync_pipeline(&mut self) {
+        self.shapes.set_pipeline(self.pipeline);
+        self.primitives.set_pipeline(self.pipeline);
+        self.sprites.set_pipeline(self.pipeline);
+    }
+    
+    // Queue the following shapes, primitives and sprites with the clip, composite operation,
+    // shadow and filter of the state
+    fn sync_state(&mut self) {
+        self.shapes.set_clip(self.state.clip.clone());
+        self.primitives.set_clip(self.state.clip.clone());
+        self.sprites.set_clip(self.state.clip.clone());
+        self.shapes.set_composite(self.state.composite);
+        self.primitives.set_composite(self.state.composite);
+        self.sprites.set_composite(self.state.composite);
+        self.shapes.set_shadow(self.state.shadow);
+        self.shapes.set_filter(self.state.filter.clone());
+    }
+    
+    // Replace the camera (position, zoom and rotation), the viewport stays the canvas size
+    #[wasm_bindgen]
+    pub fn set_camera(&mut self, camera: &Camera2D) {
+        self.camera = *camera;
+        self.camera.set_viewport(self.width as f32, self.height as f32);
+        self.sync_antialiasing();
+    }
+    
+    // Get a copy of the current camera
+    #[wasm_bindgen]
+    pub fn get_camera(&self) -> Camera2D {
+        self.camera
+    }
+    
+    // Pan the camera by a drag of (dx, dy) screen pixels
+    #[wasm_bindgen]
+    pub fn pan_camera(&mut self, dx: f32, dy: f32) {
+        self.camera.pan(dx, dy);
+    }
+    
+    // Zoom the camera around a screen position, e.g. the mouse cursor
+    #[wasm_bindgen]
+    pub fn zoom_camera_at(&mut self, factor: f32, screen_x: f32, screen_y: f32) {
+        self.camera.zoom_at(factor, screen_x, screen_y);
+        self.sync_antialiasing();
+    }
+    
+    // Convert a screen position (e.g. from a mouse event) to world coordinates
+    #[wasm_bindgen]
+    pub fn screen_to_world(&self, screen_x: f32, screen_y: f32) -> Vec<f32> {
+        self.camera.screen_to_world(screen_x, screen_y)
+    }
+    
+    // Convert a world position to screen coordinates
+    #[wasm_bindgen]
+    pub fn world_to_screen(&self, world_x: f32, world_y: f32) -> Vec<f32> {
+        self.camera.world_to_screen(world_x, world_y)
+    }
+    
+    // Add a bloom pass (options: threshold, intensity, radius), returns the effect id
+    #[wasm_bindgen]
+    pub fn add_bloom(&mut self, options: JsValue) -> Result<u32, JsValue> {
+        let threshold = get_f32_option(&options, "threshold").unwrap_or(0.7);
+        let intensity = get_f32_option(&options, "intensity").unwrap_or(1.0);
+        let radius = get_f32_option(&options, "radius").unwrap_or(8.0);
+        
+        Ok(self.post_processor()?.add_bloom(threshold, intensity, radius))
+    }
+    
+    // Add a gaussian blur pass (options: radius in pixels), returns the effect id
+    #[wasm_bindgen]
+    pub fn add_blur(&mut self, options: JsValue) -> Result<u32, JsValue> {
+        let radius = get_f32_option(&options, "radius").unwrap_or(4.0);
+        
+        Ok(self.post_processor()?.add_blur(radius))
+    }
+    
+    // Add a vignette pass (options: strength, softness), returns the effect id
+    #[wasm_bindgen]
+    pub fn add_vignette(&mut self, options: JsValue) -> Result<u32, JsValue> {
+        let strength = get_f32_option(&options, "strength").unwrap_or(0.5);
+        let softness = get_f32_option(&options, "softness").unwrap_or(0.4);
+        
+        Ok(self.post_processor()?.add_vignette(strength, softness))
+    }
+    
+    // Add a chromatic aberration pass (options: amount in pixels), returns the effect id
+    #[wasm_bindgen]
+    pub fn add_chromatic_aberration(&mut self, options: JsValue) -> Result<u32, JsValue> {
+        let amount = get_f32_option(&options, "amount").unwrap_or(4.0);
+        
+        Ok(self.post_processor()?.add_chromatic_aberration(amount))
+    }
+    
+    // Add a color grading pass from a size^3 RGBA lookup table laid out as a
+    // (size * size) x size strip of blue slices, returns the effect id
+    #[wasm_bindgen]
+    pub fn add_color_grading(&mut self, lut: &[u8], size: u32, intensity: f32) -> Result<u32, JsValue> {
+        let gl = self.gl.clone();
+        self.post_processor()?.add_color_grading(&gl, lut, size, intensity)
+    }
+    
+    // Add a user-defined fullscreen pass. The fragment source is appended to a header
+    // declaring `vUv`, `uTexture`, `uResolution`, `uTime` and `outColor`, and must
+    // provide `main`. Returns the effect id
+    #[wasm_bindgen]
+    pub fn add_custom_pass(&mut self, fragment_src: &str) -> Result<u32, JsValue> {
+        let gl = self.gl.clone();
+        self.post_processor()?.add_custom_pass(&gl, fragment_src)
+    }
+    
+    // Toggle an effect without removing it from the chain
+    #[wasm_bindgen]
+    pub fn set_effect_enabled(&mut self, id: u32, enabled: bool) -> Result<(), JsValue> {
+        if !self.post_processor()?.set_enabled(id, enabled) {
+            return Err(JsValue::from_str(&format!("Unknown effect id {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    // Remove an effect from the chain
+    #[wasm_bindgen]
+    pub fn remove_effect(&mut self, id: u32) -> Result<(), JsValue> {
+        let gl = self.gl.clone();
+        if !self.post_processor()?.remove(&gl, id) {
+            return Err(JsValue::from_str(&format!("Unknown effect id {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    // Remove every effect, rendering goes straight to the canvas again
+    #[wasm_bindgen]
+    pub fn clear_effects(&mut self) -> Result<(), JsValue> {
+        let gl = self.gl.clone();
+        self.post_processor()?.clear(&gl);
+        
+        Ok(())
+    }
+    
+    fn set_ratio(&mut self, ratio: f32) -> Result<(), JsValue> {
+        if !is_valid_pixel_ratio(ratio) {
+            return Err(JsValue::from_str("Pixel ratio must be a positive number"));
+        }
+        
+        self.pixel_ratio = ratio;
+        Ok(())
+    }
+    
+    // Size the canvas backbuffer in device pixels, and its CSS size when asked to,
+    // then update everything that depends on it
+    fn apply_backbuffer_size(&mut self) -> Result<(), JsValue> {
+        let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+        
+        // The context may belong to an OffscreenCanvas, in which case the caller owns its size
+        if let Some(canvas) = self.gl.canvas().and_then(|canvas| canvas.dyn_into::<HtmlCanvasElement>().ok()) {
+            if canvas.width() != backbuffer_width {
+                canvas.set_width(backbuffer_width);
+            }
+            if canvas.height() != backbuffer_height {
+                canvas.set_height(backbuffer_height);
+            }
+            
+            if self.style_size {
+                let style = canvas.style();
+                style.set_property("width", &format!("{}px", self.width))?;
+                style.set_property("height", &format!("{}px", self.height))?;
+            }
+        }
+        
+        // Drawing stays in CSS pixels, only the viewport uses device pixels
+        self.gl.viewport(0, 0, backbuffer_width as i32, backbuffer_height as i32);
+        self.clipper.set_viewport(backbuffer_width, backbuffer_height);
+        self.compositor.set_viewport(backbuffer_width, backbuffer_height);
+        self.shadows.set_viewport(&self.gl, backbuffer_width, backbuffer_height, self.pixel_ratio)?;
+        self.filters.set_viewport(&self.gl, backbuffer_width, backbuffer_height, self.pixel_ratio)?;
+        self.camera.set_viewport(self.width as f32, self.height as f32);
+        
+        if let Some(linear) = self.linear_output.as_mut() {
+            linear.resize(&self.gl, backbuffer_width, backbuffer_height)?;
+        }
+        if let Some(multisample) = self.multisample.as_mut() {
+            multisample.configure(&self.gl, backbuffer_width, backbuffer_height, self.pipeline.linear);
+        }
+        self.sync_antialiasing();
+        
+        // Keep the ping-pong buffers matched to the backbuffer
+        if let Some(post) = self.post_processor.as_mut() {
+            post.resize(&self.gl, backbuffer_width, backbuffer_height)?;
+        }
+        
+        Ok(())
+    }
+    
+    // Size of the drawing buffer in device pixels
+    fn backbuffer_size(&self) -> (u32, u32) {
+        (
+            ((self.width as f32 * self.pixel_ratio).round() as u32).max(1),
+            ((self.height as f32 * self.pixel_ratio).round() as u32).max(1),
+        )
+    }
+    
+    // Lazily create the post-processing stack on first use
+    fn post_processor(&mut self) -> Result<&mut PostProcessor, JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        if self.post_processor.is_none() {
+            let (backbuffer_width, backbuffer_height) = self.backbuffer_size();
+            self.post_processor = Some(PostProcessor::new(&self.gl, backbuffer_width, backbuffer_height)?);
+        }
+        
+        Ok(self.post_processor.as_mut().unwrap())
+    }
+
+    // Add new methods for creative examples
+
+    // Draw a particle system
+    #[wasm_bindgen]
+    pub fn draw_particles(&mut self, count: u32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        // Extract particle options from JsValue
+        let mut particle_size: f32 = 3.0;
+        let mut max_speed: f32 = 2.0;
+        
+        if !options.is_null() && !options.is_undefined() {
+            if let Ok(size) = Reflect::get(&options, &JsValue::from_str("particle_size")) {
+                if !size.is_null() && !size.is_undefined() {
+                    if let Some(value) = size.as_f64() {
+                        particle_size = value as f32;
+                    }
+                }
+            }
+            
+            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("max_speed")) {
+                if !speed.is_null() && !speed.is_undefined() {
+                    if let Some(value) = speed.as_f64() {
+                        max_speed = value as f32;
+                    }
+                }
+            }
+        }
+        
+        // Call internal particle system setup (for future implementation)
+        console_log!("Particle system with {} particles, size={}, speed={}", count, particle_size, max_speed);
+        
+        // Just use polygon buffers for now, will be replaced with proper particle implementation
+        self.scene_colors = setup_polygon_buffers(&self.gl, &self.program, &self.vao, count as usize, self.polygon_palette.as_ref())?;
+        self.element_count = count;
+        
+        Ok(())
+    }
+
+    // Draw a flow field
+    #[wasm_bindgen]
+    pub fn draw_flow_field(&mut self, resolution: u32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        // Extract flow field options from JsValue
+        let mut flow_scale: f32 = 0.2;
+        let mut flow_speed: f32 = 0.5;
+        
+        if !options.is_null() && !options.is_undefined() {
+            if let Ok(scale) = Reflect::get(&options, &JsValue::from_str("flow_scale")) {
+                if !scale.is_null() && !scale.is_undefined() {
+                    if let Some(value) = scale.as_f64() {
+                        flow_scale = value as f32;
+                    }
+                }
+            }
+            
+            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("flow_speed")) {
+                if !speed.is_null() && !speed.is_undefined() {
+                    if let Some(value) = speed.as_f64() {
+                        flow_speed = value as f32;
+                    }
+                }
+            }
+        }
+        
+        console_log!("Flow field with resolution {}, scale={}, speed={}", resolution, flow_scale, flow_speed);
+        
+        // For now, just use polygon buffers until we implement proper flow field
+        self.scene_colors = setup_polygon_buffers(&self.gl, &self.program, &self.vao, resolution as usize, self.polygon_palette.as_ref())?;
+        self.element_count = resolution;
+        
+        Ok(())
+    }
+
+    // Draw a cellular automata grid
+    #[wasm_bindgen]
+    pub fn draw_cellular_automata(&mut self, grid_size: u32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        // Extract cellular automata options from JsValue
+        let mut sim_speed: f32 = 8.0;
+        
+        if !options.is_null() && !options.is_undefined() {
+            if let Ok(speed) = Reflect::get(&options, &JsValue::from_str("sim_speed")) {
+                if !speed.is_null() && !speed.is_undefined() {
+                    if let Some(value) = speed.as_f64() {
+                        sim_speed = value as f32;
+                    }
+                }
+            }
+        }
+        
+        console_log!("Cellular automata with grid size {}, sim_speed={}", grid_size, sim_speed);
+        
+        // For now, just use polygon buffers until we implement proper cellular automata
+        self.scene_colors = setup_polygon_buffers(&self.gl, &self.program, &self.vao, (grid_size/16) as usize, self.polygon_palette.as_ref())?;
+        self.element_count = grid_size / 16;
+        
+        Ok(())
+    }
+
+    // Draw a fractal tree
+    #[wasm_bindgen]
+    pub fn draw_fractal_tree(&mut self, max_depth: u32, options: JsValue) -> Result<(), JsValue> {
+        if self.is_disposed {
+            return Err(JsValue::from_str("Canvas has been disposed"));
+        }
+        
+        // Extract fractal tree options from JsValue
+        let mut branch_count: u32 = 3;
+        let mut wind_strength: f32 = 0.15;
+        
+        if !options.is_null() && !options.is_undefined() {
+            if let Ok(branches) = Reflect::get(&options, &JsValue::from_str("branch_count")) {
+                if !branches.is_null() && !branches.is_undefined() {
+                    if let Some(value) = branches.as_f64() {
+                        branch_count = value as u32;
+                    }
+                }
+            }
+            
+            if let Ok(wind) = Reflect::get(&options, &JsValue::from_str("wind_strength")) {
+                if !wind.is_null() && !wind.is_undefined() {
+                    if let Some(value) = wind.as_f64() {
+                        wind_strength = value as f32;
+                    }
+                }
+            }
+        }
+        
+        console_log!("Fractal tree with depth {}, branches={}, wind={}", max_depth, branch_count, wind_strength);
+        
+        // For now, just use polygon buffers until we implement proper fractal tree
+        let element_count = (2u32.pow(max_depth) - 1) / (branch_count - 1);
+        let element_count = element_count.min(100); // Reasonable limit
+        
+        self.scene_colors = setup_polygon_buffers(&self.gl, &self.program, &self.vao, element_count as usize, self.polygon_palette.as_ref())?;
+        self.element_count = element_count;
+        
+        Ok(())
+    }
+}
+
+// Device pixels per CSS pixel, 1.0 when there is no window (e.g. in a worker)
+fn detect_pixel_ratio() -> f32 {
+    web_sys::window()
+        .map(|window| window.device_pixel_ratio() as f32)
+        .filter(|ratio| is_valid_pixel_ratio(*ratio))
+        .unwrap_or(1.0)
+}
+
+fn is_valid_pixel_ratio(ratio: f32) -> bool {
+    ratio > 0.0 && ratio.is_finite()
+}
+
+// Normalize negative sizes and clip the source rectangle to the image bounds, shrinking the
+// destination rectangle by the same proportion. Returns None when nothing is left to draw
+fn clip_image_rects(
+    source: [f32; 4],
+    destination: [f32; 4],
+    image_width: f32,
+    image_height: f32,
+) -> Option<([f32; 4], [f32; 4])> {
+    let normalize = |[x, y, w, h]: [f32; 4]| {
+        [x.min(x + w), y.min(y + h), w.abs(), h.abs()]
+    };
+    let [sx, sy, sw, sh] = normalize(source);
+    let [dx, dy, dw, dh] = normalize(destination);
+    
+    if sw == 0.0 || sh == 0.0 || dw == 0.0 || dh == 0.0 {
+        return None;
+    }
+    
+    let scale_x = dw / sw;
+    let scale_y = dh / sh;
+    
+    let x0 = sx.max(0.0);
+    let y0 = sy.max(0.0);
+    let x1 = (sx + sw).min(image_width);
+    let y1 = (sy + sh).min(image_height);
+    
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    
+    Some((
+        [x0, y0, x1 - x0, y1 - y0],
+        [
+            dx + (x0 - sx) * scale_x,
+            dy + (y0 - sy) * scale_y,
+            (x1 - x0) * scale_x,
+            (y1 - y0) * scale_y,
+        ],
+    ))
+}
+
+// Read an optional numeric field from a JavaScript options object
+fn get_f32_option(options: &JsValue, key: &str) -> Option<f32> {
+    get_f64_option(options, key).map(|value| value as f32)
+}
+
+// Read an optional numeric field at full precision, e.g. times that grow large
+fn get_f64_option(options: &JsValue, key: &str) -> Option<f64> {
+    if options.is_null() || options.is_undefined() {
+        return None;
+    }
+    
+    Reflect::get(options, &JsValue::from_str(key))
+        .ok()
+        .and_then(|value| value.as_f64())
+}
+
+// Closed circle outline for recordings
+fn circle_path(x: f32, y: f32, radius: f32) -> Path2D {
+    let mut path = Path2D::new();
+    let _ = path.arc(x, y, radius.abs(), 0.0, std::f32::consts::TAU, None);
+    path.close_path();
+    path
+}
+
+// Corner radii (top-left, top-right, bottom-right, bottom-left) from a `radius` number or a
+// `radii` array of 1 to 4 values expanded like CanvasRenderingContext2D.roundRect
+fn corner_radii(options: &JsValue) -> Result<[f32; 4], JsValue> {
+    if let Some(radius) = get_f32_option(options, "radius") {
+        return Ok([radius; 4]);
+    }
+    if options.is_null() || options.is_undefined() {
+        return Ok([0.0; 4]);
+    }
+    
+    let value = Reflect::get(options, &JsValue::from_str("radii"))?;
+    if value.is_undefined() {
+        return Ok([0.0; 4]);
+    }
+    let radii: Vec<f32> = value
+        .dyn_into::<Array>()
+        .map_err(|_| JsValue::from_str("Primitive radii must be an array of numbers"))?
+        .iter()
+        .map(|radius| radius.as_f64().map(|radius| radius as f32))
+        .collect::<Option<_>>()
+        .ok_or("Primitive radii must be an array of numbers")?;
+    Ok(match radii[..] {
+        [all] => [all; 4],
+        [a, b] => [a, b, a, b],
+        [a, b, c] => [a, b, c, b],
+        [a, b, c, d] => [a, b, c, d],
+        _ => return Err(JsValue::from_str("Primitive radii must hold 1 to 4 values")),
+    })
+}
+
+// Setup buffers for polygons with increasing number of sides, returns the polygon colors
+fn setup_polygon_buffers(
+    gl: &WebGl2RenderingContext,
+    program: &WebGlProgram,
+    vao: &WebGlVertexArrayObject,
+    num_polygons: usize,
+    palette: Option<&Palette>,
+) -> Result<Vec<[f32; 4]>, JsValue> {
+    // Attributes are recorded in the scene VAO, other passes bind their own
+    gl.bind_vertex_array(Some(vao));
+    
+    // Without a palette, evenly spaced OKLCH hues give every polygon the same perceived brightness
+    let default_palette;
+    let palette = match palette {
+        Some(palette) => palette,
+        None => {
+            default_palette = Palette::categorical(num_polygons.max(1) as u32, Some(0.72), Some(0.17))?;
+            &default_palette
+        }
+    };
+    
+    // Create vertices for all polygons
+    let mut all_vertices = Vec::with_capacity(num_polygons * VERTICES_PER_POLYGON * 3);
     let mut all_colors = Vec::with_capacity(num_polygons * VERTICES_PER_POLYGON * 4);
     let mut all_instance_indices = Vec::with_capacity(num_polygons * VERTICES_PER_POLYGON);
     let mut all_side_counts = Vec::with_capacity(num_polygons * VERTICES_PER_POLYGON);
+    let mut colors = Vec::with_capacity(num_polygons);
 
     // For each polygon
     for i in 0..num_polygons {
@@ -617,18 +2627,13 @@ fn setup_polygon_buffers(gl: &WebGl2RenderingContext, program: &WebGlProgram, nu
         let sides = MIN_POLYGON_SIDES + i;
         add_polygon_vertices(&mut all_vertices, sides);
         
-        // Set color for this polygon (RGB rainbow distribution)
-        let hue = (i as f32) / (num_polygons as f32);
-        let saturation = 0.9; // Slightly more vibrant
-        let lightness = 0.6;  // Slightly brighter
-        let (r, g, b) = hsl_to_rgb(hue, saturation, lightness);
+        // Set color for this polygon from the palette
+        let color = palette.element_color(i, num_polygons);
+        colors.push(color);
         
         // Add color for all vertices of this polygon
         for _ in 0..VERTICES_PER_POLYGON {
-            all_colors.push(r);
-            all_colors.push(g);
-            all_colors.push(b);
-            all_colors.push(1.0); // Alpha
+            all_colors.extend_from_slice(&color);
         }
         
         // Add instance index for all vertices of this polygon
@@ -734,7 +2739,16 @@ fn setup_polygon_buffers(gl: &WebGl2RenderingContext, program: &WebGlProgram, nu
     );
     gl.enable_vertex_attrib_array(side_count_attr_location);
     
-    Ok(())
+    Ok(colors)
+}
+
+// Center of the polygon row on a canvas in the active coordinate system, and the direction
+// of its y axis
+fn layout_origin(camera: &Camera2D, width: u32, height: u32) -> (f32, f32, f32) {
+    match camera.coordinate_system {
+        CoordinateSystem::TopLeft => (width as f32 * 0.5, height as f32 * 0.5, -1.0),
+        CoordinateSystem::Center => (0.0, 0.0, 1.0),
+    }
 }
 
 // Add vertices for a polygon with the specified number of sides
@@ -750,8 +2764,8 @@ fn add_polygon_vertices(vertices: &mut Vec<f32>, sides: usize) {
     // Generate points around the polygon
     for i in 0..=actual_sides {
         let angle = (i % actual_sides) as f32 * 2.0 * std::f32::consts::PI / (actual_sides as f32);
-        let x = angle.cos() * 0.12; // Radius 0.12
-        let y = angle.sin() * 0.12;
+        let x = angle.cos(); // Unit radius, scaled in the vertex shader
+        let y = angle.sin();
         
         vertices.push(x);
         vertices.push(y);
@@ -769,41 +2783,6 @@ fn add_polygon_vertices(vertices: &mut Vec<f32>, sides: usize) {
     }
 }
 
-// Convert HSL color to RGB
-fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
-    if s == 0.0 {
-        // Achromatic (gray)
-        return (l, l, l);
-    }
-    
-    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
-    let p = 2.0 * l - q;
-    
-    (
-        hue_to_rgb(p, q, h + 1.0 / 3.0),
-        hue_to_rgb(p, q, h),
-        hue_to_rgb(p, q, h - 1.0 / 3.0)
-    )
-}
-
-// Helper function for HSL to RGB conversion
-fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
-    if t < 0.0 { t += 1.0; }
-    if t > 1.0 { t -= 1.0; }
-    
-    if t < 1.0 / 6.0 {
-        return p + (q - p) * 6.0 * t;
-    }
-    if t < 1.0 / 2.0 {
-        return q;
-    }
-    if t < 2.0 / 3.0 {
-        return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
-    }
-    
-    p
-}
-
 // Helper function to compile a shader
 fn compile_shader(
     gl: &WebGl2RenderingContext,
@@ -824,9 +2803,11 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(gl
+        let error = gl
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        gl.delete_shader(Some(&shader));
+        Err(error)
     }
 }
 
@@ -851,8 +2832,46 @@ fn link_program(
     {
         Ok(program)
     } else {
-        Err(gl
+        let error = gl
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program")))
+            .unwrap_or_else(|| String::from("Unknown error creating program"));
+        gl.delete_program(Some(&program));
+        Err(error)
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_ratios_must_be_positive_and_finite() {
+        for ratio in [1.0, 1.25, 2.0, 3.5] {
+            assert!(is_valid_pixel_ratio(ratio));
+        }
+        for ratio in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(!is_valid_pixel_ratio(ratio));
+        }
+    }
+
+    #[test]
+    fn image_rects_are_clipped_to_the_image() {
+        // Fully inside: unchanged
+        assert_eq!(
+            clip_image_rects([0.0, 0.0, 10.0, 10.0], [5.0, 5.0, 20.0, 20.0], 10.0, 10.0),
+            Some(([0.0, 0.0, 10.0, 10.0], [5.0, 5.0, 20.0, 20.0]))
+        );
+        // The half of the source outside the image is dropped from the destination too
+        assert_eq!(
+            clip_image_rects([-10.0, 0.0, 20.0, 10.0], [0.0, 0.0, 40.0, 10.0], 10.0, 10.0),
+            Some(([0.0, 0.0, 10.0, 10.0], [20.0, 0.0, 20.0, 10.0]))
+        );
+        // Negative sizes are normalized
+        assert_eq!(
+            clip_image_rects([10.0, 10.0, -10.0, -10.0], [20.0, 20.0, -20.0, -20.0], 10.0, 10.0),
+            Some(([0.0, 0.0, 10.0, 10.0], [0.0, 0.0, 20.0, 20.0]))
+        );
+        assert_eq!(clip_image_rects([20.0, 0.0, 5.0, 5.0], [0.0, 0.0, 5.0, 5.0], 10.0, 10.0), None);
+        assert_eq!(clip_image_rects([0.0, 0.0, 0.0, 5.0], [0.0, 0.0, 5.0, 5.0], 10.0, 10.0), None);
+    }
+}
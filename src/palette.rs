@@ -0,0 +1,338 @@
+use wasm_bindgen::prelude::*;
+
+use crate::color::{oklab_to_rgb, parse_color, rgb_to_oklab, Color};
+use crate::gradient::{ColorStop, Gradient, MAX_GRADIENT_STOPS};
+
+// Evenly spaced samples of the matplotlib colormaps, as 0xRRGGBB
+const VIRIDIS: &[u32] = &[
+    0x440154, 0x482878, 0x3E4A89, 0x31688E, 0x26828E, 0x1F9E89, 0x35B779, 0x6DCD59, 0xB4DE2C, 0xFDE725,
+];
+const MAGMA: &[u32] = &[
+    0x000004, 0x180F3E, 0x451077, 0x721F81, 0x9F2F7F, 0xCD4071, 0xF1605D, 0xFD9567, 0xFEC98D, 0xFCFDBF,
+];
+const CIVIDIS: &[u32] = &[
+    0x00204D, 0x00336F, 0x39486B, 0x575C6D, 0x707173, 0x8A8779, 0xA69D75, 0xC4B56C, 0xE4CF5B, 0xFFEA46,
+];
+
+// Endpoints and midpoint of the diverging presets
+const RED_BLUE: [u32; 3] = [0xB2182B, 0xF7F7F7, 0x2166AC];
+const PURPLE_GREEN: [u32; 3] = [0x762A83, 0xF7F7F7, 0x1B7837];
+const BROWN_TEAL: [u32; 3] = [0x8C510A, 0xF5F5F5, 0x01665E];
+
+// Samples taken from the turbo polynomial approximation
+const TURBO_SAMPLES: usize = 16;
+
+// A list of colors used either as a continuous scale, interpolated in OKLab, or as a
+// discrete set of distinct colors picked by index
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    // Straight-alpha sRGB
+    colors: Vec<[f32; 4]>,
+    discrete: bool,
+}
+
+#[wasm_bindgen]
+impl Palette {
+    // A built-in scale: the perceptually uniform "viridis", "magma", "cividis" and "turbo", or
+    // the diverging "red-blue", "purple-green" and "brown-teal"
+    #[wasm_bindgen]
+    pub fn scale(name: &str) -> Result<Palette, JsValue> {
+        let colors = match name {
+            "viridis" => VIRIDIS.iter().map(|&rgb| hex(rgb)).collect(),
+            "magma" => MAGMA.iter().map(|&rgb| hex(rgb)).collect(),
+            "cividis" => CIVIDIS.iter().map(|&rgb| hex(rgb)).collect(),
+            "turbo" => (0..TURBO_SAMPLES).map(|i| turbo(i as f32 / (TURBO_SAMPLES - 1) as f32)).collect(),
+            "red-blue" => RED_BLUE.iter().map(|&rgb| hex(rgb)).collect(),
+            "purple-green" => PURPLE_GREEN.iter().map(|&rgb| hex(rgb)).collect(),
+            "brown-teal" => BROWN_TEAL.iter().map(|&rgb| hex(rgb)).collect(),
+            _ => return Err(JsValue::from_str(&format!("Unknown color scale \"{}\"", name))),
+        };
+        Ok(Palette { colors, discrete: false })
+    }
+
+    // A continuous scale through CSS colors, at least two
+    #[wasm_bindgen]
+    pub fn from_colors(colors: Vec<String>) -> Result<Palette, JsValue> {
+        if colors.len() < 2 {
+            return Err(JsValue::from_str("A color scale needs at least two colors"));
+        }
+
+        let colors = colors.iter().map(|color| parse_color(color)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Palette { colors, discrete: false })
+    }
+
+    // A diverging scale from `low` through a light neutral midpoint (or `mid`) to `high`
+    #[wasm_bindgen]
+    pub fn diverging(low: &str, high: &str, mid: Option<String>) -> Result<Palette, JsValue> {
+        let mid = match mid {
+            Some(mid) => parse_color(&mid)?,
+            None => hex(0xF7F7F7),
+        };
+        Ok(Palette {
+            colors: vec![parse_color(low)?, mid, parse_color(high)?],
+            discrete: false,
+        })
+    }
+
+    // `count` distinct colors of equal OKLCH lightness and chroma (defaults 0.7 and 0.15),
+    // with evenly spaced hues. Chroma is reduced where a hue would leave the sRGB gamut
+    #[wasm_bindgen]
+    pub fn categorical(count: u32, lightness: Option<f32>, chroma: Option<f32>) -> Result<Palette, JsValue> {
+        if count == 0 {
+            return Err(JsValue::from_str("A categorical palette needs at least one color"));
+        }
+
+        // NaN would survive the clamp and turn every color NaN
+        let lightness = lightness.filter(|lightness| !lightness.is_nan()).unwrap_or(0.7).clamp(0.0, 1.0);
+        let chroma = chroma.unwrap_or(0.15).max(0.0);
+        let colors = (0..count)
+            .map(|i| {
+                // Start at orange so small palettes avoid the muddy yellow-green range
+                let hue = (40.0 + 360.0 * i as f32 / count as f32).to_radians();
+                let [r, g, b] = in_gamut(lightness, chroma, hue);
+                [r, g, b, 1.0]
+            })
+            .collect();
+        Ok(Palette { colors, discrete: true })
+    }
+
+    // The same colors in the opposite order
+    #[wasm_bindgen]
+    pub fn reversed(&self) -> Palette {
+        let mut colors = self.colors.clone();
+        colors.reverse();
+        Palette { colors, discrete: self.discrete }
+    }
+
+    #[wasm_bindgen]
+    pub fn is_discrete(&self) -> bool {
+        self.discrete
+    }
+
+    #[wasm_bindgen]
+    pub fn color_count(&self) -> u32 {
+        self.colors.len() as u32
+    }
+
+    // Color at t in [0, 1]. Discrete palettes are split into equal bands
+    #[wasm_bindgen]
+    pub fn sample(&self, t: f32) -> Color {
+        Color::from_array(self.sample_rgba(t))
+    }
+
+    // Color of element `index` out of `count`: discrete palettes cycle through their colors,
+    // continuous scales are sampled evenly from end to end
+    #[wasm_bindgen]
+    pub fn color_at(&self, index: u32, count: u32) -> Color {
+        Color::from_array(self.element_color(index as usize, count as usize))
+    }
+
+    // Colors of `count` elements packed as RGBA floats, e.g. for per-vertex colors
+    #[wasm_bindgen]
+    pub fn colors(&self, count: u32) -> Vec<f32> {
+        (0..count as usize).flat_map(|i| self.element_color(i, count as usize)).collect()
+    }
+
+    // A width x 1 RGBA8 lookup texture of the scale, for upload_image_rgba or upload_palette
+    #[wasm_bindgen]
+    pub fn to_lut(&self, width: u32) -> Vec<u8> {
+        let width = width.max(1);
+        (0..width)
+            .flat_map(|x| {
+                // The first and last texels hold the ends of the scale
+                let t = if width == 1 { 0.5 } else { x as f32 / (width - 1) as f32 };
+                self.sample_rgba(t).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect()
+    }
+
+    // Replace the stops of a gradient with this palette. Continuous scales are sampled at
+    // MAX_GRADIENT_STOPS offsets, discrete palettes become hard-edged bands
+    #[wasm_bindgen]
+    pub fn apply_to_gradient(&self, gradient: &mut Gradient) -> Result<(), JsValue> {
+        let stops = if self.discrete {
+            let count = self.colors.len();
+            if count * 2 > MAX_GRADIENT_STOPS {
+                return Err(JsValue::from_str(&format!(
+                    "Gradients can show at most {} discrete colors",
+                    MAX_GRADIENT_STOPS / 2
+                )));
+            }
+            self.colors
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &color)| {
+                    [
+                        ColorStop { offset: i as f32 / count as f32, color },
+                        ColorStop { offset: (i + 1) as f32 / count as f32, color },
+                    ]
+                })
+                .collect()
+        } else {
+            (0..MAX_GRADIENT_STOPS)
+                .map(|i| {
+                    let offset = i as f32 / (MAX_GRADIENT_STOPS - 1) as f32;
+                    ColorStop { offset, color: self.sample_rgba(offset) }
+                })
+                .collect()
+        };
+
+        gradient.replace_stops(stops);
+        Ok(())
+    }
+}
+
+impl Palette {
+    pub(crate) fn element_color(&self, index: usize, count: usize) -> [f32; 4] {
+        if self.discrete {
+            self.colors[index % self.colors.len()]
+        } else if count <= 1 {
+            self.sample_rgba(0.5)
+        } else {
+            self.sample_rgba(index as f32 / (count - 1) as f32)
+        }
+    }
+
+    fn sample_rgba(&self, t: f32) -> [f32; 4] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let count = self.colors.len();
+        if self.discrete || count == 1 {
+            return self.colors[((t * count as f32) as usize).min(count - 1)];
+        }
+
+        let position = t * (count - 1) as f32;
+        let i = (position as usize).min(count - 2);
+        mix_oklab(self.colors[i], self.colors[i + 1], position - i as f32)
+    }
+}
+
+fn hex(rgb: u32) -> [f32; 4] {
+    let channel = |shift: u32| ((rgb >> shift) & 0xFF) as f32 / 255.0;
+    [channel(16), channel(8), channel(0), 1.0]
+}
+
+// Google's polynomial approximation of the turbo colormap
+fn turbo(t: f32) -> [f32; 4] {
+    let r = 0.135_721_38 + t * (4.615_392_6 + t * (-42.660_324 + t * (132.131_08 + t * (-152.942_4 + t * 59.286_38))));
+    let g = 0.091_402_61 + t * (2.194_188_4 + t * (4.842_966_6 + t * (-14.185_033 + t * (4.277_298_6 + t * 2.829_566))));
+    let b = 0.106_673_3 + t * (12.641_946 + t * (-60.582_05 + t * (110.362_77 + t * (-89.903_11 + t * 27.348_25))));
+    [r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), 1.0]
+}
+
+// Interpolate two sRGB colors in OKLab so the lightness changes evenly
+fn mix_oklab(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let la = rgb_to_oklab([a[0], a[1], a[2]]);
+    let lb = rgb_to_oklab([b[0], b[1], b[2]]);
+    let lab = [0, 1, 2].map(|i| la[i] + (lb[i] - la[i]) * t);
+    let [r, g, bl] = oklab_to_rgb(lab).map(|c| c.clamp(0.0, 1.0));
+    [r, g, bl, a[3] + (b[3] - a[3]) * t]
+}
+
+// The most saturated color up to `chroma` at an OKLCH lightness and hue that sRGB can show
+fn in_gamut(lightness: f32, chroma: f32, hue: f32) -> [f32; 3] {
+    let rgb = |c: f32| oklab_to_rgb([lightness, c * hue.cos(), c * hue.sin()]);
+    let fits = |rgb: [f32; 3]| rgb.iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c));
+
+    if fits(rgb(chroma)) {
+        return rgb(chroma).map(|c| c.clamp(0.0, 1.0));
+    }
+
+    let (mut low, mut high) = (0.0, chroma);
+    for _ in 0..16 {
+        let mid = (low + high) * 0.5;
+        if fits(rgb(mid)) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    rgb(low).map(|c| c.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: [f32; 4], b: [f32; 4]) -> f32 {
+        let la = rgb_to_oklab([a[0], a[1], a[2]]);
+        let lb = rgb_to_oklab([b[0], b[1], b[2]]);
+        (0..3).map(|i| (la[i] - lb[i]).powi(2)).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn categorical_colors_are_in_gamut_and_distinct() {
+        for count in [1, 2, 5, 8, 12] {
+            let palette = Palette::categorical(count, None, Some(0.3)).unwrap();
+            assert!(palette.is_discrete());
+            assert_eq!(palette.color_count(), count);
+            for (i, &a) in palette.colors.iter().enumerate() {
+                assert!(a.iter().all(|c| (0.0..=1.0).contains(c)));
+                // Equal lightness, as requested
+                assert!((rgb_to_oklab([a[0], a[1], a[2]])[0] - 0.7).abs() < 1e-3);
+                for &b in &palette.colors[i + 1..] {
+                    assert!(distance(a, b) > 0.02, "{:?} and {:?} look alike", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn categorical_palettes_default_a_nan_lightness() {
+        let nan = Palette::categorical(4, Some(f32::NAN), Some(f32::NAN)).unwrap();
+        let default = Palette::categorical(4, None, Some(0.0)).unwrap();
+        assert_eq!(nan.colors, default.colors);
+        assert!(nan.colors.iter().flatten().all(|c| (0.0..=1.0).contains(c)));
+    }
+
+    #[test]
+    fn in_gamut_reduces_chroma_only_when_needed() {
+        let hue = 150f32.to_radians();
+        // Low chroma fits as it is
+        let rgb = in_gamut(0.7, 0.05, hue);
+        let lab = rgb_to_oklab(rgb);
+        assert!(((lab[1] * lab[1] + lab[2] * lab[2]).sqrt() - 0.05).abs() < 1e-3);
+
+        // A chroma sRGB cannot show is reduced to the edge of the gamut, keeping the hue
+        let rgb = in_gamut(0.7, 0.4, hue);
+        assert!(rgb.iter().all(|c| (0.0..=1.0).contains(c)));
+        assert!(rgb.iter().any(|c| !(1e-3..=1.0 - 1e-3).contains(c)));
+        let lab = rgb_to_oklab(rgb);
+        assert!((lab[2].atan2(lab[1]) - hue).abs() < 1e-2);
+        assert!((lab[0] - 0.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lookup_tables_hold_the_scale_ends() {
+        let palette = Palette::from_colors(vec!["#ff0000".into(), "#0000ff".into()]).unwrap();
+        let lut = palette.to_lut(16);
+        assert_eq!(lut.len(), 16 * 4);
+        assert_eq!(lut[..4], [255, 0, 0, 255]);
+        assert_eq!(lut[lut.len() - 4..], [0, 0, 255, 255]);
+
+        let reversed = palette.reversed().to_lut(16);
+        assert_eq!(reversed[..4], [0, 0, 255, 255]);
+        assert_eq!(palette.to_lut(0).len(), 4);
+    }
+
+    #[test]
+    fn samples_are_clamped_and_discrete_palettes_are_banded() {
+        let scale = Palette::scale("viridis").unwrap();
+        let first = hex(VIRIDIS[0]);
+        let last = hex(*VIRIDIS.last().unwrap());
+        assert!(distance(scale.sample_rgba(-1.0), first) < 1e-3);
+        assert!(distance(scale.sample_rgba(f32::NAN), first) < 1e-3);
+        assert!(distance(scale.sample_rgba(2.0), last) < 1e-3);
+        // Lightness rises steadily between the samples
+        let lightness = |t: f32| {
+            let [r, g, b, _] = scale.sample_rgba(t);
+            rgb_to_oklab([r, g, b])[0]
+        };
+        assert!((1..=100).all(|i| lightness(i as f32 / 100.0) > lightness((i - 1) as f32 / 100.0)));
+
+        let discrete = Palette::categorical(4, None, None).unwrap();
+        assert_eq!(discrete.sample_rgba(0.3), discrete.colors[1]);
+        assert_eq!(discrete.sample_rgba(1.0), discrete.colors[3]);
+        assert_eq!(discrete.element_color(6, 10), discrete.colors[2]);
+    }
+}
@@ -0,0 +1,679 @@
+use std::f32::consts::PI;
+
+use wasm_bindgen::prelude::*;
+
+use crate::svg_path::{center_arc, format_number, parse_path_data, SvgSegment};
+
+// Arcs are split into cubic Béziers spanning at most a quarter turn
+const MAX_ARC_SEGMENT: f32 = PI * 0.5;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum PathVerb {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    QuadTo(f32, f32, f32, f32),
+    CubicTo(f32, f32, f32, f32, f32, f32),
+    Close,
+}
+
+// Winding rule deciding which areas of a path are inside
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    // "nonzero" (default) or "evenodd", like CanvasFillRule
+    pub(crate) fn parse(rule: Option<&str>) -> Result<FillRule, JsValue> {
+        match rule {
+            None | Some("nonzero") => Ok(FillRule::NonZero),
+            Some("evenodd") => Ok(FillRule::EvenOdd),
+            Some(other) => Err(JsValue::from_str(&format!("Invalid fill rule \"{}\"", other))),
+        }
+    }
+}
+
+// A flattened subpath
+#[derive(Clone, Debug)]
+pub(crate) struct Contour {
+    pub(crate) points: Vec<(f32, f32)>,
+    pub(crate) closed: bool,
+}
+
+// Reusable path made of lines, Bézier curves and arcs, mirroring the browser Path2D.
+// Coordinates are in CSS pixels like every other drawing call
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct Path2D {
+    verbs: Vec<PathVerb>,
+    // Start of the current subpath and the current point
+    start: Option<(f32, f32)>,
+    current: Option<(f32, f32)>,
+}
+
+#[wasm_bindgen]
+impl Path2D {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Path2D {
+        Path2D::default()
+    }
+
+    // Begin a new subpath at (x, y)
+    #[wasm_bindgen]
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.verbs.push(PathVerb::MoveTo(x, y));
+        self.start = Some((x, y));
+        self.current = Some((x, y));
+    }
+
+    // Straight line from the current point, starts a subpath when there is none
+    #[wasm_bindgen]
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        if self.current.is_none() {
+            self.move_to(x, y);
+            return;
+        }
+        self.verbs.push(PathVerb::LineTo(x, y));
+        self.current = Some((x, y));
+    }
+
+    #[wasm_bindgen]
+    pub fn quadratic_curve_to(&mut self, cpx: f32, cpy: f32, x: f32, y: f32) {
+        self.ensure_subpath(cpx, cpy);
+        self.verbs.push(PathVerb::QuadTo(cpx, cpy, x, y));
+        self.current = Some((x, y));
+    }
+
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn bezier_curve_to(&mut self, cp1x: f32, cp1y: f32, cp2x: f32, cp2y: f32, x: f32, y: f32) {
+        self.ensure_subpath(cp1x, cp1y);
+        self.verbs.push(PathVerb::CubicTo(cp1x, cp1y, cp2x, cp2y, x, y));
+        self.current = Some((x, y));
+    }
+
+    // Circular arc around (x, y) from start_angle to end_angle (radians, clockwise on screen),
+    // connected to the current point with a straight line
+    #[wasm_bindgen]
+    pub fn arc(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        counterclockwise: Option<bool>,
+    ) -> Result<(), JsValue> {
+        if radius < 0.0 {
+            return Err(JsValue::from_str("Arc radius must not be negative"));
+        }
+
+        let sweep = arc_sweep(start_angle, end_angle, counterclockwise.unwrap_or(false));
+        let start = (x + radius * start_angle.cos(), y + radius * start_angle.sin());
+        if self.current.is_some() {
+            self.line_to(start.0, start.1);
+        } else {
+            self.move_to(start.0, start.1);
+        }
+
+        let segments = (sweep.abs() / MAX_ARC_SEGMENT).ceil().max(1.0) as usize;
+        let step = sweep / segments as f32;
+        // Distance of the control points along the tangents, exact at the ends of the segment
+        let k = 4.0 / 3.0 * (step * 0.25).tan() * radius;
+        let mut angle = start_angle;
+        for _ in 0..segments {
+            let next = angle + step;
+            let (sin0, cos0) = angle.sin_cos();
+            let (sin1, cos1) = next.sin_cos();
+            self.bezier_curve_to(
+                x + radius * cos0 - k * sin0,
+                y + radius * sin0 + k * cos0,
+                x + radius * cos1 + k * sin1,
+                y + radius * sin1 - k * cos1,
+                x + radius * cos1,
+                y + radius * sin1,
+            );
+            angle = next;
+        }
+
+        Ok(())
+    }
+
+    // Closed rectangle subpath, the current point ends up at (x, y)
+    #[wasm_bindgen]
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.move_to(x, y);
+        self.line_to(x + width, y);
+        self.line_to(x + width, y + height);
+        self.line_to(x, y + height);
+        self.close_path();
+    }
+
+    // Connect the current point back to the start of the subpath
+    #[wasm_bindgen]
+    pub fn close_path(&mut self) {
+        if self.current.is_none() {
+            return;
+        }
+        self.verbs.push(PathVerb::Close);
+        self.current = self.start;
+    }
+
+    // Append the subpaths of another path
+    #[wasm_bindgen]
+    pub fn add_path(&mut self, path: &Path2D) {
+        self.verbs.extend_from_slice(&path.verbs);
+        if path.current.is_some() {
+            self.start = path.start;
+            self.current = path.current;
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.verbs.is_empty()
+    }
+
+    // Build a path from SVG path data (the `d` attribute), e.g. "M10 10 h80 v80 h-80 Z".
+    // Elliptical arcs become cubic Béziers
+    #[wasm_bindgen]
+    pub fn from_svg(data: &str) -> Result<Path2D, JsValue> {
+        let mut path = Path2D::new();
+        path.add_svg_segments(&parse_path_data(data).map_err(|message| JsValue::from_str(&message))?);
+        Ok(path)
+    }
+}
+
+impl Path2D {
+    // Curves need a current point, the first control point stands in for it
+    fn ensure_subpath(&mut self, x: f32, y: f32) {
+        if self.current.is_none() {
+            self.move_to(x, y);
+        }
+    }
+
+    // The path mapped through an affine transform [a, b, c, d, e, f] (x' = a x + c y + e,
+    // y' = b x + d y + f). Bézier control points transform exactly
+    pub(crate) fn transformed(&self, m: [f32; 6]) -> Path2D {
+        let map = |x: f32, y: f32| (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5]);
+        let verbs = self
+            .verbs
+            .iter()
+            .map(|verb| match *verb {
+                PathVerb::MoveTo(x, y) => {
+                    let (x, y) = map(x, y);
+                    PathVerb::MoveTo(x, y)
+                }
+                PathVerb::LineTo(x, y) => {
+                    let (x, y) = map(x, y);
+                    PathVerb::LineTo(x, y)
+                }
+                PathVerb::QuadTo(cx, cy, x, y) => {
+                    let ((cx, cy), (x, y)) = (map(cx, cy), map(x, y));
+                    PathVerb::QuadTo(cx, cy, x, y)
+                }
+                PathVerb::CubicTo(c1x, c1y, c2x, c2y, x, y) => {
+                    let ((c1x, c1y), (c2x, c2y), (x, y)) = (map(c1x, c1y), map(c2x, c2y), map(x, y));
+                    PathVerb::CubicTo(c1x, c1y, c2x, c2y, x, y)
+                }
+                PathVerb::Close => PathVerb::Close,
+            })
+            .collect();
+
+        Path2D {
+            verbs,
+            start: self.start.map(|(x, y)| map(x, y)),
+            current: self.current.map(|(x, y)| map(x, y)),
+        }
+    }
+
+    // The path as SVG path data with absolute commands
+    pub(crate) fn svg_data(&self) -> String {
+        let n = format_number;
+        let commands: Vec<String> = self
+            .verbs
+            .iter()
+            .map(|verb| match *verb {
+                PathVerb::MoveTo(x, y) => format!("M{} {}", n(x), n(y)),
+                PathVerb::LineTo(x, y) => format!("L{} {}", n(x), n(y)),
+                PathVerb::QuadTo(cx, cy, x, y) => format!("Q{} {} {} {}", n(cx), n(cy), n(x), n(y)),
+                PathVerb::CubicTo(c1x, c1y, c2x, c2y, x, y) => {
+                    format!("C{} {} {} {} {} {}", n(c1x), n(c1y), n(c2x), n(c2y), n(x), n(y))
+                }
+                PathVerb::Close => "Z".to_string(),
+            })
+            .collect();
+        commands.join(" ")
+    }
+
+    pub(crate) fn add_svg_segments(&mut self, segments: &[SvgSegment]) {
+        for segment in segments {
+            match *segment {
+                SvgSegment::MoveTo(x, y) => self.move_to(x, y),
+                SvgSegment::LineTo(x, y) => self.line_to(x, y),
+                SvgSegment::QuadTo(cx, cy, x, y) => self.quadratic_curve_to(cx, cy, x, y),
+                SvgSegment::CubicTo(c1x, c1y, c2x, c2y, x, y) => self.bezier_curve_to(c1x, c1y, c2x, c2y, x, y),
+                SvgSegment::ArcTo { rx, ry, rotation, large_arc, sweep, x, y } => {
+                    let from = self.current.unwrap_or((x, y));
+                    match center_arc(from, (x, y), (rx, ry), rotation, large_arc, sweep) {
+                        Some(arc) => {
+                            let (cx, cy) = arc.center;
+                            self.ellipse_segments(cx, cy, arc.radii, arc.rotation, arc.start_angle, arc.sweep);
+                            // Land exactly on the end point whatever the rounding
+                            if let Some(PathVerb::CubicTo(_, _, _, _, end_x, end_y)) = self.verbs.last_mut() {
+                                (*end_x, *end_y) = (x, y);
+                            }
+                            self.current = Some((x, y));
+                        }
+                        None if from != (x, y) => self.line_to(x, y),
+                        None => {}
+                    }
+                }
+                SvgSegment::Close => self.close_path(),
+            }
+        }
+    }
+
+    // Cubic Béziers along an ellipse rotated by `rotation`, from the current point at
+    // `start_angle` through `sweep` radians
+    fn ellipse_segments(&mut self, cx: f32, cy: f32, radii: (f32, f32), rotation: f32, start_angle: f32, sweep: f32) {
+        let (rx, ry) = radii;
+        let (sin, cos) = rotation.sin_cos();
+        let map = |u: f32, v: f32| (cx + rx * u * cos - ry * v * sin, cy + rx * u * sin + ry * v * cos);
+
+        let segments = (sweep.abs() / MAX_ARC_SEGMENT).ceil().max(1.0) as usize;
+        let step = sweep / segments as f32;
+        // Distance of the control points along the tangents of the unit circle
+        let k = 4.0 / 3.0 * (step * 0.25).tan();
+        let mut angle = start_angle;
+        for _ in 0..segments {
+            let next = angle + step;
+            let (sin0, cos0) = angle.sin_cos();
+            let (sin1, cos1) = next.sin_cos();
+            let control1 = map(cos0 - k * sin0, sin0 + k * cos0);
+            let control2 = map(cos1 + k * sin1, sin1 - k * cos1);
+            let end = map(cos1, sin1);
+            self.bezier_curve_to(control1.0, control1.1, control2.0, control2.1, end.0, end.1);
+            angle = next;
+        }
+    }
+
+    // Approximate the path with polylines, curves stay within tolerance of the exact shape
+    pub(crate) fn flatten(&self, tolerance: f32) -> Vec<Contour> {
+        let tolerance = tolerance.max(1e-4);
+        let mut contours: Vec<Contour> = Vec::new();
+        let mut points: Vec<(f32, f32)> = Vec::new();
+        let mut current = (0.0, 0.0);
+
+        let finish = |contours: &mut Vec<Contour>, points: &mut Vec<(f32, f32)>, closed: bool| {
+            if points.len() > 1 || (closed && !points.is_empty()) {
+                contours.push(Contour {
+                    points: std::mem::take(points),
+                    closed,
+                });
+            } else {
+                points.clear();
+            }
+        };
+
+        for verb in &self.verbs {
+            match *verb {
+                PathVerb::MoveTo(x, y) => {
+                    finish(&mut contours, &mut points, false);
+                    points.push((x, y));
+                    current = (x, y);
+                }
+                PathVerb::LineTo(x, y) => {
+                    points.push((x, y));
+                    current = (x, y);
+                }
+                PathVerb::QuadTo(cx, cy, x, y) => {
+                    let (p0, p1, p2) = (current, (cx, cy), (x, y));
+                    let dd = length(p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1);
+                    let segments = curve_segments(dd * 0.25, tolerance);
+                    for i in 1..=segments {
+                        let t = i as f32 / segments as f32;
+                        let mt = 1.0 - t;
+                        points.push((
+                            mt * mt * p0.0 + 2.0 * mt * t * p1.0 + t * t * p2.0,
+                            mt * mt * p0.1 + 2.0 * mt * t * p1.1 + t * t * p2.1,
+                        ));
+                    }
+                    current = (x, y);
+                }
+                PathVerb::CubicTo(c1x, c1y, c2x, c2y, x, y) => {
+                    let (p0, p1, p2, p3) = (current, (c1x, c1y), (c2x, c2y), (x, y));
+                    let dd = f32::max(
+                        length(p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1),
+                        length(p1.0 - 2.0 * p2.0 + p3.0, p1.1 - 2.0 * p2.1 + p3.1),
+                    );
+                    let segments = curve_segments(dd * 0.75, tolerance);
+                    for i in 1..=segments {
+                        let t = i as f32 / segments as f32;
+                        let mt = 1.0 - t;
+                        let (a, b, c, d) = (mt * mt * mt, 3.0 * mt * mt * t, 3.0 * mt * t * t, t * t * t);
+                        points.push((
+                            a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                            a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+                        ));
+                    }
+                    current = (x, y);
+                }
+                PathVerb::Close => {
+                    let start = points.first().copied();
+                    finish(&mut contours, &mut points, true);
+                    // Drawing continues from the start of the closed subpath
+                    if let Some(start) = start {
+                        points.push(start);
+                        current = start;
+                    }
+                }
+            }
+        }
+        finish(&mut contours, &mut points, false);
+
+        // Drop the duplicate closing point of explicitly closed subpaths
+        for contour in contours.iter_mut().filter(|contour| contour.closed) {
+            if contour.points.len() > 1 && contour.points.first() == contour.points.last() {
+                contour.points.pop();
+            }
+        }
+
+        contours
+    }
+}
+
+// The path as an axis-aligned rectangle [x, y, width, height] when it is exactly one
+pub(crate) fn as_rect(contours: &[Contour]) -> Option<[f32; 4]> {
+    let [contour] = contours else {
+        return None;
+    };
+    let [a, b, c, d] = contour.points[..] else {
+        return None;
+    };
+
+    let horizontal_first = a.1 == b.1 && b.0 == c.0 && c.1 == d.1 && d.0 == a.0;
+    let vertical_first = a.0 == b.0 && b.1 == c.1 && c.0 == d.0 && d.1 == a.1;
+    if !horizontal_first && !vertical_first {
+        return None;
+    }
+
+    let x = a.0.min(c.0);
+    let y = a.1.min(c.1);
+    Some([x, y, (a.0 - c.0).abs(), (a.1 - c.1).abs()])
+}
+
+// The single contour of a path without its repeated points when it is convex, so it can be
+// filled as a triangle fan
+pub(crate) fn as_convex(contours: &[Contour]) -> Option<Vec<(f32, f32)>> {
+    let [contour] = contours else {
+        return None;
+    };
+
+    // Arcs and curves often end a rounding error away from where they started
+    let mut points: Vec<(f32, f32)> = Vec::with_capacity(contour.points.len());
+    for &point in &contour.points {
+        if points.last().is_none_or(|last| !nearly_equal(*last, point)) {
+            points.push(point);
+        }
+    }
+    while points.len() > 1 && nearly_equal(points[0], points[points.len() - 1]) {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return None;
+    }
+
+    // Every turn goes the same way and the edges wind around exactly once
+    let mut sign = 0.0;
+    let mut angle = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let c = points[(i + 2) % points.len()];
+        let cross = (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0);
+        if cross.abs() > 1e-9 {
+            if sign != 0.0 && cross.signum() != sign {
+                return None;
+            }
+            sign = cross.signum();
+        }
+        let dot = (b.0 - a.0) * (c.0 - b.0) + (b.1 - a.1) * (c.1 - b.1);
+        angle += cross.atan2(dot);
+    }
+
+    ((angle.abs() - 2.0 * PI).abs() < 0.01).then_some(points)
+}
+
+// Triangle fans covering every closed contour, as a flat list of vertex coordinates. Drawn into
+// the stencil buffer with incrementing front faces and decrementing back faces, they leave the
+// winding number of every pixel
+pub(crate) fn stencil_fans(contours: &[Contour]) -> Vec<f32> {
+    let mut triangles = Vec::new();
+    for contour in contours {
+        let points = &contour.points;
+        for i in 1..points.len().saturating_sub(1) {
+            triangles.extend_from_slice(&[
+                points[0].0,
+                points[0].1,
+                points[i].0,
+                points[i].1,
+                points[i + 1].0,
+                points[i + 1].1,
+            ]);
+        }
+    }
+    triangles
+}
+
+// Bounding box [min x, min y, max x, max y] of the contours
+pub(crate) fn bounds(contours: &[Contour]) -> Option<[f32; 4]> {
+    let mut points = contours.iter().flat_map(|contour| contour.points.iter());
+    let first = points.next()?;
+    Some(points.fold([first.0, first.1, first.0, first.1], |b, p| {
+        [b[0].min(p.0), b[1].min(p.1), b[2].max(p.0), b[3].max(p.1)]
+    }))
+}
+
+// Triangles covering the inside of the contours under a fill rule, for renderers without a
+// stencil buffer. The area is cut into horizontal bands at every vertex and edge crossing,
+// inside each band the edges are straight and ordered so the inside spans are trapezoids
+pub(crate) fn triangulate(contours: &[Contour], rule: FillRule) -> (Vec<(f32, f32)>, Vec<u32>) {
+    // Top end, bottom end and winding direction of an edge
+    type Edge = ((f32, f32), (f32, f32), i32);
+
+    // Edges pointing down, with +1 for edges that went down and -1 for those that went up
+    let mut edges: Vec<Edge> = Vec::new();
+    for contour in contours {
+        let count = contour.points.len();
+        for i in 0..count {
+            let (a, b) = (contour.points[i], contour.points[(i + 1) % count]);
+            if a.1 < b.1 {
+                edges.push((a, b, 1));
+            } else if a.1 > b.1 {
+                edges.push((b, a, -1));
+            }
+        }
+    }
+
+    let x_at = |edge: &((f32, f32), (f32, f32), i32), y: f32| {
+        let ((x0, y0), (x1, y1), _) = *edge;
+        x0 + (x1 - x0) * ((y - y0) / (y1 - y0)).clamp(0.0, 1.0)
+    };
+
+    let mut ys: Vec<f32> = edges.iter().flat_map(|(top, bottom, _)| [top.1, bottom.1]).collect();
+    for (i, a) in edges.iter().enumerate() {
+        for b in &edges[i + 1..] {
+            let (top, bottom) = (a.0 .1.max(b.0 .1), a.1 .1.min(b.1 .1));
+            if top >= bottom {
+                continue;
+            }
+            // The edges cross where the order of their x changes within the shared span
+            let (d0, d1) = (x_at(a, top) - x_at(b, top), x_at(a, bottom) - x_at(b, bottom));
+            if d0 * d1 < 0.0 {
+                ys.push(top + (bottom - top) * d0 / (d0 - d1));
+            }
+        }
+    }
+    ys.sort_by(f32::total_cmp);
+    ys.dedup_by(|a, b| (*a - *b).abs() < 1e-5);
+
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    let mut active: Vec<(f32, f32, f32, i32)> = Vec::new();
+    for band in ys.windows(2) {
+        let (y0, y1) = (band[0], band[1]);
+        let middle = (y0 + y1) * 0.5;
+
+        // (x at the middle, x at the top, x at the bottom, winding) of the edges spanning the band
+        active.clear();
+        active.extend(
+            edges
+                .iter()
+                .filter(|(top, bottom, _)| top.1 <= middle && bottom.1 >= middle)
+                .map(|edge| (x_at(edge, middle), x_at(edge, y0), x_at(edge, y1), edge.2)),
+        );
+        active.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut winding = 0;
+        let mut left: Option<(f32, f32)> = None;
+        for &(_, top, bottom, direction) in &active {
+            winding += direction;
+            let inside = match rule {
+                FillRule::NonZero => winding != 0,
+                FillRule::EvenOdd => winding % 2 != 0,
+            };
+            match (inside, left) {
+                (true, None) => left = Some((top, bottom)),
+                (false, Some((left_top, left_bottom))) => {
+                    let base = positions.len() as u32;
+                    positions.extend_from_slice(&[(left_top, y0), (top, y0), (bottom, y1), (left_bottom, y1)]);
+                    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+                    left = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    (positions, indices)
+}
+
+// Signed sweep of an arc following the CanvasRenderingContext2D rules
+fn arc_sweep(start_angle: f32, end_angle: f32, counterclockwise: bool) -> f32 {
+    let full = 2.0 * PI;
+    let delta = end_angle - start_angle;
+    if !counterclockwise && delta >= full {
+        return full;
+    }
+    if counterclockwise && -delta >= full {
+        return -full;
+    }
+
+    let mut sweep = delta % full;
+    if !counterclockwise && sweep < 0.0 {
+        sweep += full;
+    } else if counterclockwise && sweep > 0.0 {
+        sweep -= full;
+    }
+    sweep
+}
+
+// Segments for a curve whose second differences are bounded by `deviation` (Wang's formula)
+fn curve_segments(deviation: f32, tolerance: f32) -> usize {
+    ((deviation / tolerance).sqrt().ceil() as usize).clamp(1, 256)
+}
+
+fn nearly_equal(a: (f32, f32), b: (f32, f32)) -> bool {
+    (a.0 - b.0).abs() <= 1e-4 * (1.0 + a.0.abs()) && (a.1 - b.1).abs() <= 1e-4 * (1.0 + a.1.abs())
+}
+
+fn length(x: f32, y: f32) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arcs_stay_within_the_tolerance() {
+        let mut path = Path2D::new();
+        path.arc(50.0, 50.0, 40.0, 0.0, 2.0 * PI, None).unwrap();
+        let contours = path.flatten(0.1);
+        assert_eq!(contours.len(), 1);
+        for &(x, y) in &contours[0].points {
+            assert!((length(x - 50.0, y - 50.0) - 40.0).abs() < 0.1);
+        }
+        // Chords between the points do not cut deeper than the tolerance either
+        let points = &contours[0].points;
+        for pair in points.windows(2) {
+            let middle = ((pair[0].0 + pair[1].0) * 0.5, (pair[0].1 + pair[1].1) * 0.5);
+            assert!(40.0 - length(middle.0 - 50.0, middle.1 - 50.0) < 0.1 + 1e-3);
+        }
+        assert!(as_convex(&contours).is_some());
+    }
+
+    #[test]
+    fn arc_sweeps_follow_the_canvas_rules() {
+        assert_eq!(arc_sweep(0.0, PI, false), PI);
+        assert_eq!(arc_sweep(0.0, PI, true), -PI);
+        assert_eq!(arc_sweep(0.0, 3.0 * PI, false), 2.0 * PI);
+        assert_eq!(arc_sweep(0.0, -0.5 * PI, false), 1.5 * PI);
+        assert!((arc_sweep(PI, 0.0, true) + PI).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rectangles_and_convex_shapes_are_recognized() {
+        let mut rect = Path2D::new();
+        rect.rect(10.0, 20.0, 30.0, -40.0);
+        let contours = rect.flatten(0.25);
+        assert_eq!(as_rect(&contours), Some([10.0, -20.0, 30.0, 40.0]));
+        assert_eq!(bounds(&contours), Some([10.0, -20.0, 40.0, 20.0]));
+
+        // A dart turns both ways
+        let mut dart = Path2D::new();
+        dart.move_to(0.0, 0.0);
+        dart.line_to(10.0, 5.0);
+        dart.line_to(0.0, 10.0);
+        dart.line_to(3.0, 5.0);
+        dart.close_path();
+        let contours = dart.flatten(0.25);
+        assert_eq!(as_rect(&contours), None);
+        assert_eq!(as_convex(&contours), None);
+        // Two triangles fan out from the first point
+        assert_eq!(stencil_fans(&contours).len(), 2 * 6);
+    }
+
+    #[test]
+    fn closing_continues_from_the_subpath_start() {
+        let mut path = Path2D::new();
+        path.move_to(0.0, 0.0);
+        path.line_to(10.0, 0.0);
+        path.line_to(10.0, 10.0);
+        path.close_path();
+        path.line_to(0.0, 10.0);
+        let contours = path.flatten(0.25);
+        assert_eq!(contours.len(), 2);
+        assert!(contours[0].closed);
+        assert_eq!(contours[0].points, vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        assert_eq!(contours[1].points, vec![(0.0, 0.0), (0.0, 10.0)]);
+        assert!(!contours[1].closed);
+    }
+
+    #[test]
+    fn svg_arcs_become_curves_ending_on_the_end_point() {
+        let mut path = Path2D::new();
+        path.add_svg_segments(&crate::svg_path::parse_path_data("M0 0 A5 5 0 0 1 10 0 a0 3 0 0 0 0 4").unwrap());
+        let contours = path.flatten(0.01);
+        let points = &contours[0].points;
+        assert_eq!(points.last(), Some(&(10.0, 4.0)));
+        assert_eq!(points[points.len() - 2], (10.0, 0.0));
+        // Clockwise on screen with y pointing down: a half circle above the chord
+        for &(x, y) in &points[..points.len() - 1] {
+            assert!((length(x - 5.0, y) - 5.0).abs() < 0.02);
+            assert!(y <= 1e-4);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlUniformLocation, WebGlVertexArrayObject};
+
+use crate::color::srgb_to_linear;
+use crate::composite::COMPOSITE_GLSL;
+use crate::postprocess::{create_pass_program, RenderTarget};
+
+// Shared by the shape and sprite fragment shaders, inserted in place of "#pragma pipeline".
+// Vertex colors and gradient stops arrive already encoded by ColorPipeline::encode, textures
+// are brought into the same space by textureColor()
+const PIPELINE_GLSL: &str = r#"
+// Colors are premultiplied by alpha before interpolation and filtering
+uniform bool uPremultiplied;
+// Colors are linear light, the framebuffer is sRGB encoded
+uniform bool uLinear;
+
+vec3 srgbToLinear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+}
+
+// A texel uploaded with or without premultiplied alpha, in the space of the pipeline
+vec4 textureColor(vec4 texel, bool premultiplied) {
+    if (premultiplied) {
+        texel.rgb = texel.a > 0.0 ? texel.rgb / texel.a : vec3(0.0);
+    }
+    if (uLinear) {
+        texel.rgb = srgbToLinear(texel.rgb);
+    }
+    if (uPremultiplied) {
+        texel.rgb *= texel.a;
+    }
+    return texel;
+}
+
+// Premultiplied color of a pipeline color scaled by its coverage
+vec4 premultiply(vec4 color, float coverage) {
+    if (uPremultiplied) {
+        return color * coverage;
+    }
+    return vec4(color.rgb * color.a, color.a) * coverage;
+}
+"#;
+
+// Encode the linear-light frame to sRGB. The target stores premultiplied linear colors, so
+// alpha is divided out before the transfer function and applied again after it
+const PRESENT_FRAGMENT_SRC: &str = r#"
+vec3 linearToSrgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    vec3 straight = color.a > 0.0 ? color.rgb / color.a : vec3(0.0);
+    outColor = vec4(linearToSrgb(clamp(straight, 0.0, 1.0)) * color.a, color.a);
+}
+"#;
+
+// Expand the shared GLSL of the shape and sprite fragment shaders
+pub(crate) fn expand_shader(src: &str) -> String {
+    src.replace("#pragma pipeline", PIPELINE_GLSL).replace("#pragma composite", COMPOSITE_GLSL)
+}
+
+// How colors are interpolated and blended. The default matches CanvasRenderingContext2D:
+// straight alpha, sRGB values blended as they are
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) struct ColorPipeline {
+    pub(crate) premultiplied: bool,
+    pub(crate) linear: bool,
+}
+
+impl ColorPipeline {
+    // Convert a straight-alpha sRGB color to the vertex color or gradient stop the shaders expect
+    pub(crate) fn encode(self, color: [f32; 4]) -> [f32; 4] {
+        let [mut r, mut g, mut b, a] = color;
+        if self.linear {
+            r = srgb_to_linear(r);
+            g = srgb_to_linear(g);
+            b = srgb_to_linear(b);
+        }
+        if self.premultiplied {
+            r *= a;
+            g *= a;
+            b *= a;
+        }
+        [r, g, b, a]
+    }
+
+    pub(crate) fn set_uniforms(
+        self,
+        gl: &WebGl2RenderingContext,
+        premultiplied_location: Option<&WebGlUniformLocation>,
+        linear_location: Option<&WebGlUniformLocation>,
+    ) {
+        gl.uniform1i(premultiplied_location, self.premultiplied as i32);
+        gl.uniform1i(linear_location, self.linear as i32);
+    }
+}
+
+// The sRGB scene target of linear-light blending and the pass that encodes it for display
+pub(crate) struct LinearOutput {
+    target: RenderTarget,
+    program: WebGlProgram,
+    vao: WebGlVertexArrayObject,
+    texture_location: Option<WebGlUniformLocation>,
+}
+
+impl LinearOutput {
+    pub(crate) fn new(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<LinearOutput, JsValue> {
+        let program = create_pass_program(gl, PRESENT_FRAGMENT_SRC)?;
+        let vao = gl.create_vertex_array().ok_or("Failed to create present vertex array")?;
+
+        Ok(LinearOutput {
+            target: RenderTarget::srgb_with_stencil(gl, width, height)?,
+            texture_location: gl.get_uniform_location(&program, "uTexture"),
+            program,
+            vao,
+        })
+    }
+
+    pub(crate) fn resize(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(), JsValue> {
+        self.target.resize(gl, width, height)
+    }
+
+    // Redirect the scene into the sRGB target
+    pub(crate) fn begin(&self, gl: &WebGl2RenderingContext) {
+        self.target.bind(gl);
+    }
+
+    // Draw the encoded frame into whatever framebuffer and viewport the caller bound
+    pub(crate) fn present(&self, gl: &WebGl2RenderingContext) {
+        gl.disable(WebGl2RenderingContext::BLEND);
+        gl.use_program(Some(&self.program));
+        gl.bind_vertex_array(Some(&self.vao));
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(self.target.texture()));
+        gl.uniform1i(self.texture_location.as_ref(), 0);
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+        gl.bind_vertex_array(None);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+        gl.enable(WebGl2RenderingContext::BLEND);
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        self.target.delete(gl);
+        gl.delete_program(Some(&self.program));
+        gl.delete_vertex_array(Some(&self.vao));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_pipeline_leaves_colors_alone() {
+        let color = [0.2, 0.5, 0.8, 0.5];
+        assert_eq!(ColorPipeline::default().encode(color), color);
+    }
+
+    #[test]
+    fn colors_are_linearized_then_premultiplied() {
+        let pipeline = ColorPipeline {
+            premultiplied: true,
+            linear: true,
+        };
+        let [r, g, b, a] = pipeline.encode([1.0, 0.5, 0.0, 0.5]);
+        assert_eq!((r, b, a), (0.5, 0.0, 0.5));
+        // sRGB 0.5 is about 21.4% linear light
+        assert!((g - 0.5 * 0.21404).abs() < 1e-4);
+    }
+
+    #[test]
+    fn the_transfer_function_is_continuous_and_monotonic() {
+        let below = srgb_to_linear(0.04045);
+        let above = srgb_to_linear(0.04045 + 1e-6);
+        assert!((above - below).abs() < 1e-5);
+
+        let mut previous = srgb_to_linear(0.0);
+        for i in 1..=255 {
+            let linear = srgb_to_linear(i as f32 / 255.0);
+            assert!(linear > previous);
+            previous = linear;
+        }
+        assert!((previous - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shaders_are_expanded_once() {
+        let src = expand_shader("#pragma pipeline\n#pragma composite\nvoid main() {}");
+        assert!(!src.contains("#pragma"));
+        assert_eq!(src.matches("vec4 textureColor(").count(), 1);
+        assert_eq!(src.matches("vec4 composite(").count(), 1);
+    }
+}
@@ -0,0 +1,599 @@
+// PNG and APNG encoding of RGBA frames read back from the canvas. Rows are filtered with the
+// filter that best predicts them and compressed with LZ77 and the fixed Huffman codes
+// of deflate, which keeps the encoder small while flat backgrounds and repeated shapes
+// still compress well
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Deflate looks back at most 32 KiB and matches at most 258 bytes
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// Candidates compared per position before taking the longest match found so far
+const MAX_CHAIN: usize = 48;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Encode 8-bit RGBA pixels, rows top to bottom with straight alpha, as a PNG file
+pub(crate) fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(rgba.len(), width as usize * height as usize * 4);
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header(width, height));
+    write_chunk(&mut out, b"IDAT", &zlib(&filter_rows(width, height, rgba)));
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+// The PNG encoding of the pixels as a base64 data URL, for embedding images in SVG exports
+pub(crate) fn png_data_url(width: u32, height: u32, rgba: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let png = encode_png(width, height, rgba);
+    let mut url = String::with_capacity(22 + png.len().div_ceil(3) * 4);
+    url.push_str("data:image/png;base64,");
+    for group in png.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                url.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                url.push('=');
+            }
+        }
+    }
+    url
+}
+
+// Encode frames of the same size as an APNG that loops forever, delays in milliseconds. The
+// first frame is also the still image shown by decoders without animation support
+pub(crate) fn encode_apng(width: u32, height: u32, frames: &[Vec<u8>], delays: &[u16]) -> Vec<u8> {
+    let mut out = SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header(width, height));
+
+    // Frame count, then 0 plays for an endless loop
+    let mut control = (frames.len() as u32).to_be_bytes().to_vec();
+    control.extend_from_slice(&0u32.to_be_bytes());
+    write_chunk(&mut out, b"acTL", &control);
+
+    // Frame control and frame data chunks share one sequence
+    let mut sequence = 0u32;
+    for (index, (frame, &delay)) in frames.iter().zip(delays).enumerate() {
+        let mut frame_control = sequence.to_be_bytes().to_vec();
+        frame_control.extend_from_slice(&width.to_be_bytes());
+        frame_control.extend_from_slice(&height.to_be_bytes());
+        // Offset of the frame region
+        frame_control.extend_from_slice(&[0; 8]);
+        frame_control.extend_from_slice(&delay.to_be_bytes());
+        frame_control.extend_from_slice(&1000u16.to_be_bytes());
+        // Leave the frame in place when done, and replace the canvas instead of blending over it
+        frame_control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut out, b"fcTL", &frame_control);
+        sequence += 1;
+
+        let data = zlib(&filter_rows(width, height, frame));
+        if index == 0 {
+            write_chunk(&mut out, b"IDAT", &data);
+        } else {
+            let mut frame_data = sequence.to_be_bytes().to_vec();
+            frame_data.extend_from_slice(&data);
+            write_chunk(&mut out, b"fdAT", &frame_data);
+            sequence += 1;
+        }
+    }
+
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+// Image header: size, 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+fn header(width: u32, height: u32) -> [u8; 13] {
+    let mut data = [0; 13];
+    data[0..4].copy_from_slice(&width.to_be_bytes());
+    data[4..8].copy_from_slice(&height.to_be_bytes());
+    data[8] = 8;
+    data[9] = 6;
+    data
+}
+
+// Length, type, data and the CRC of type and data
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Prefix every row with the filter whose output has the smallest sum of absolute values,
+// the heuristic the PNG specification recommends
+fn filter_rows(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    let mut out = Vec::with_capacity((stride + 1) * height as usize);
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    let zero_row = vec![0u8; stride];
+
+    for y in 0..height as usize {
+        let row = &rgba[y * stride..(y + 1) * stride];
+        let previous = if y > 0 { &rgba[(y - 1) * stride..y * stride] } else { &zero_row[..] };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+
+        for filter in 0..5u8 {
+            for i in 0..stride {
+                let left = if i >= 4 { row[i - 4] } else { 0 };
+                let up = previous[i];
+                let up_left = if i >= 4 { previous[i - 4] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                candidate[i] = row[i].wrapping_sub(predicted);
+            }
+            let cost = candidate.iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        out.push(best_filter);
+        out.extend_from_slice(&best);
+    }
+    out
+}
+
+// Predict from whichever neighbor is closest to left + up - up_left
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let to_left = (estimate - left as i16).abs();
+    let to_up = (estimate - up as i16).abs();
+    let to_up_left = (estimate - up_left as i16).abs();
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+// zlib stream: header for a 32 KiB window, one deflate block and the Adler-32 checksum
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.out.extend_from_slice(&[0x78, 0x01]);
+    deflate(data, &mut writer);
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// A single final block with the fixed Huffman codes
+fn deflate(data: &[u8], writer: &mut BitWriter) {
+    // BFINAL, then BTYPE 01
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let mut matcher = Matcher::new(data);
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = matcher.longest_match(position);
+        if length >= MIN_MATCH {
+            write_match(writer, length, distance);
+            for at in position..position + length {
+                matcher.insert(at);
+            }
+            position += length;
+        } else {
+            write_literal(writer, data[position] as u16);
+            matcher.insert(position);
+            position += 1;
+        }
+    }
+    write_literal(writer, 256);
+}
+
+// Finds earlier occurrences through hash chains of the three bytes starting at each
+// position. The chain links live in a ring the size of the window
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Matcher {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            previous: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, at: usize) -> usize {
+        let key = (self.data[at] as u32) << 16 | (self.data[at + 1] as u32) << 8 | self.data[at + 2] as u32;
+        (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, at: usize) {
+        if at + MIN_MATCH <= self.data.len() {
+            let key = self.hash(at);
+            self.previous[at % WINDOW_SIZE] = self.head[key];
+            self.head[key] = at;
+        }
+    }
+
+    // Longest match for the bytes at position within the window, as (length, distance)
+    fn longest_match(&self, position: usize) -> (usize, usize) {
+        if position + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+        let limit = (self.data.len() - position).min(MAX_MATCH);
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(position)];
+
+        for _ in 0..MAX_CHAIN {
+            // Links overwritten in the ring can point forward, those and anything out of
+            // the window end the search
+            if candidate == usize::MAX || candidate >= position || position - candidate > WINDOW_SIZE {
+                break;
+            }
+            let length = self.data[candidate..]
+                .iter()
+                .zip(&self.data[position..position + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, position - candidate);
+                if length == limit {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate % WINDOW_SIZE];
+        }
+        best
+    }
+}
+
+// Fixed code of a literal/length symbol
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.code(code, length);
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+    write_literal(writer, 257 + index as u16);
+    writer.bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+
+    let index = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+    writer.code(index as u16, 5);
+    writer.bits((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+}
+
+// Deflate packs bits from the least significant end, except Huffman codes which are
+// written starting from their most significant bit
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn code(&mut self, code: u16, length: u32) {
+        let reversed = (code.reverse_bits() >> (16 - length)) as u32;
+        self.bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b could overflow
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_base64(text: &str) -> Vec<u8> {
+        let value = |c: u8| match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            _ => 63,
+        };
+        let mut out = Vec::new();
+        for group in text.as_bytes().chunks(4) {
+            let digits: Vec<u8> = group.iter().copied().take_while(|&c| c != b'=').map(value).collect();
+            let bits = digits.iter().enumerate().fold(0u32, |bits, (i, &digit)| bits | (digit as u32) << (18 - 6 * i));
+            out.extend((0..digits.len() - 1).map(|i| (bits >> (16 - 8 * i)) as u8));
+        }
+        out
+    }
+
+    // Reads deflate bits from the least significant end of each byte
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| {
+                let bit = self.data[self.position / 8] >> (self.position % 8) & 1;
+                self.position += 1;
+                value | (bit as u32) << i
+            })
+        }
+
+        // A Huffman code of known length, stored from its most significant bit
+        fn code(&mut self, length: u32) -> u32 {
+            (0..length).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        // Literal/length symbol of the fixed Huffman code
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | self.bits(1)) - 0x190,
+            }
+        }
+    }
+
+    // Inflate a deflate stream of fixed Huffman blocks, the only kind the encoder writes
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader { data, position: 0 };
+        let mut out = Vec::new();
+        loop {
+            let last = reader.bits(1) == 1;
+            assert_eq!(reader.bits(2), 1, "not a fixed Huffman block");
+            loop {
+                let symbol = reader.symbol() as usize;
+                if symbol < 256 {
+                    out.push(symbol as u8);
+                    continue;
+                }
+                if symbol == 256 {
+                    break;
+                }
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32) as usize;
+                let index = reader.code(5) as usize;
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32) as usize;
+                assert!((MIN_MATCH..=MAX_MATCH).contains(&length));
+                assert!(distance <= out.len() && distance <= WINDOW_SIZE);
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+            if last {
+                return out;
+            }
+        }
+    }
+
+    // Check the zlib header and Adler-32 trailer, and return the inflated data
+    fn unzlib(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[0], 0x78, "deflate with a 32 KiB window");
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        let data = inflate(&stream[2..stream.len() - 4]);
+        assert_eq!(stream[stream.len() - 4..], adler32(&data).to_be_bytes());
+        data
+    }
+
+    // Undo the row filters of RGBA rows
+    fn unfilter(width: u32, height: u32, filtered: &[u8]) -> Vec<u8> {
+        let stride = width as usize * 4;
+        assert_eq!(filtered.len(), (stride + 1) * height as usize);
+        let mut rgba: Vec<u8> = Vec::with_capacity(stride * height as usize);
+        for row in filtered.chunks(stride + 1) {
+            let start = rgba.len();
+            for i in 0..stride {
+                let left = if i >= 4 { rgba[start + i - 4] } else { 0 };
+                let up = if start > 0 { rgba[start + i - stride] } else { 0 };
+                let up_left = if start > 0 && i >= 4 { rgba[start + i - stride - 4] } else { 0 };
+                let predicted = match row[0] {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    4 => paeth(left, up, up_left),
+                    filter => panic!("unknown filter {}", filter),
+                };
+                rgba.push(row[1 + i].wrapping_add(predicted));
+            }
+        }
+        rgba
+    }
+
+    // Chunks as (type, data), checking the signature, lengths and CRCs
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut rest = &png[8..];
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + length]));
+            chunks.push((rest[4..8].try_into().unwrap(), rest[8..8 + length].to_vec()));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    fn kinds(chunks: &[([u8; 4], Vec<u8>)]) -> Vec<&str> {
+        chunks.iter().map(|(kind, _)| std::str::from_utf8(kind).unwrap()).collect()
+    }
+
+    // A flat band, a gradient band and a band of noise, so every filter and both long and
+    // short matches show up
+    fn test_image(width: u32, height: u32, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let pixel = match y * 3 / height {
+                    0 => [30, 60, 90, 255],
+                    1 => [(x * 7) as u8, (y * 3) as u8, (x + y) as u8, (255 - x) as u8],
+                    _ => (state >> 8).to_le_bytes(),
+                };
+                rgba.extend_from_slice(&pixel);
+            }
+        }
+        rgba
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[]), 1);
+        // Long enough to need the modulo between blocks
+        assert_eq!(adler32(&[255; 6000]), 0xa497_59ea);
+    }
+
+    #[test]
+    fn pngs_round_trip_through_inflate() {
+        for (width, height) in [(1, 1), (37, 23), (160, 120)] {
+            let rgba = test_image(width, height, width);
+            let chunks = chunks(&encode_png(width, height, &rgba));
+            assert_eq!(kinds(&chunks), ["IHDR", "IDAT", "IEND"]);
+
+            let header = &chunks[0].1;
+            assert_eq!(header[..8], [width.to_be_bytes(), height.to_be_bytes()].concat());
+            assert_eq!(header[8..], [8, 6, 0, 0, 0]);
+            assert!(chunks[2].1.is_empty());
+            assert_eq!(unfilter(width, height, &unzlib(&chunks[1].1)), rgba);
+        }
+    }
+
+    #[test]
+    fn matches_stay_within_the_window() {
+        // Noise rows of 801 filtered bytes that repeat after 50 rows, further back than
+        // deflate reaches, so only the inflater's window check would notice a bad distance
+        let block = test_image(200, 150, 7)[200 * 100 * 4..].to_vec();
+        let rgba = block.repeat(2);
+        let png = encode_png(200, 100, &rgba);
+        assert_eq!(unfilter(200, 100, &unzlib(&chunks(&png)[1].1)), rgba);
+
+        // Identical rows are matched against the previous one
+        let rgba = block[..800].repeat(100);
+        let png = encode_png(200, 100, &rgba);
+        assert!(png.len() < rgba.len() / 20);
+        assert_eq!(unfilter(200, 100, &unzlib(&chunks(&png)[1].1)), rgba);
+    }
+
+    #[test]
+    fn apng_frames_share_one_sequence() {
+        let frames: Vec<Vec<u8>> = (0..3).map(|seed| test_image(9, 6, seed)).collect();
+        let chunks = chunks(&encode_apng(9, 6, &frames, &[40, 50, 60]));
+        assert_eq!(kinds(&chunks), ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 3, 0, 0, 0, 0]);
+
+        let number = |data: &[u8]| u32::from_be_bytes(data[..4].try_into().unwrap());
+        let controls: Vec<&Vec<u8>> = chunks.iter().filter(|(kind, _)| kind == b"fcTL").map(|(_, data)| data).collect();
+        for (control, delay) in controls.iter().zip([40u16, 50, 60]) {
+            assert_eq!(control[4..20], [[0, 0, 0, 9, 0, 0, 0, 6], [0; 8]].concat());
+            assert_eq!(control[20..], [delay.to_be_bytes(), 1000u16.to_be_bytes(), [0, 0]].concat());
+        }
+        let sequence: Vec<u32> = chunks
+            .iter()
+            .filter(|(kind, _)| kind == b"fcTL" || kind == b"fdAT")
+            .map(|(_, data)| number(data))
+            .collect();
+        assert_eq!(sequence, [0, 1, 2, 3, 4]);
+
+        assert_eq!(unfilter(9, 6, &unzlib(&chunks[3].1)), frames[0]);
+        assert_eq!(unfilter(9, 6, &unzlib(&chunks[5].1[4..])), frames[1]);
+        assert_eq!(unfilter(9, 6, &unzlib(&chunks[7].1[4..])), frames[2]);
+    }
+
+    #[test]
+    fn data_urls_hold_the_png_in_base64() {
+        // The PNG sizes leave every remainder of a three byte group, so all paddings show up
+        let mut remainders = Vec::new();
+        for width in 1..=8 {
+            let rgba: Vec<u8> = (0..width * 4).map(|i| (i * 37) as u8).collect();
+            let png = encode_png(width, 1, &rgba);
+            remainders.push(png.len() % 3);
+            let url = png_data_url(width, 1, &rgba);
+            let data = url.strip_prefix("data:image/png;base64,").unwrap();
+            assert_eq!(data.len() % 4, 0);
+            assert_eq!(decode_base64(data), png);
+        }
+        for remainder in 0..3 {
+            assert!(remainders.contains(&remainder));
+        }
+    }
+}
@@ -0,0 +1,769 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    WebGl2RenderingContext, WebGlFramebuffer, WebGlProgram, WebGlRenderbuffer, WebGlTexture,
+    WebGlVertexArrayObject
+};
+
+use crate::{compile_shader, link_program};
+
+// Fullscreen triangle generated from gl_VertexID, no vertex buffers needed
+const FULLSCREEN_VERTEX_SRC: &str = r#"#version 300 es
+out vec2 vUv;
+
+void main() {
+    vec2 pos = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    vUv = pos;
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+// Common header shared by every pass (including user-defined ones)
+const PASS_HEADER_SRC: &str = r#"#version 300 es
+precision highp float;
+
+in vec2 vUv;
+uniform sampler2D uTexture;
+uniform vec2 uResolution;
+uniform float uTime;
+
+out vec4 outColor;
+"#;
+
+// Blurs wider than this standard deviation in pixels space their taps apart
+const MAX_BLUR_SIGMA: f32 = 256.0;
+
+// Separable gaussian, uDirection is one texel along the pass
+const BLUR_FRAGMENT_SRC: &str = r#"
+uniform vec2 uDirection;
+uniform float uSigma;
+
+void main() {
+    if (uSigma < 0.01) {
+        outColor = texture(uTexture, vUv);
+        return;
+    }
+
+    // Taps out to three standard deviations, at most 64 of them on each side
+    float extent = ceil(uSigma * 3.0);
+    float stride = max(1.0, extent / 64.0);
+    vec4 sum = texture(uTexture, vUv);
+    float total = 1.0;
+    for (int i = 1; i <= 64; i++) {
+        float x = float(i) * stride;
+        if (x > extent) {
+            break;
+        }
+        float weight = exp(-0.5 * x * x / (uSigma * uSigma));
+        sum += (texture(uTexture, vUv + uDirection * x) + texture(uTexture, vUv - uDirection * x)) * weight;
+        total += 2.0 * weight;
+    }
+    outColor = sum / total;
+}
+"#;
+
+// Keep only the pixels brighter than the threshold
+const THRESHOLD_FRAGMENT_SRC: &str = r#"
+uniform float uThreshold;
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float knee = max(luminance - uThreshold, 0.0) / max(luminance, 0.0001);
+    outColor = vec4(color.rgb * knee, color.a);
+}
+"#;
+
+// Add the blurred highlights back on top of the scene
+const BLOOM_COMBINE_FRAGMENT_SRC: &str = r#"
+uniform sampler2D uBloom;
+uniform float uIntensity;
+
+void main() {
+    vec4 scene = texture(uTexture, vUv);
+    vec3 bloom = texture(uBloom, vUv).rgb * uIntensity;
+    outColor = vec4(scene.rgb + bloom, scene.a);
+}
+"#;
+
+// Darken the corners
+const VIGNETTE_FRAGMENT_SRC: &str = r#"
+uniform float uStrength;
+uniform float uSoftness;
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    vec2 centered = vUv - 0.5;
+    centered.x *= uResolution.x / uResolution.y;
+    float dist = length(centered);
+    // smoothstep needs edge0 < edge1, so the falloff is inverted afterwards
+    float softness = max(uSoftness, 0.001);
+    float vignette = 1.0 - smoothstep(0.8 - softness, 0.8, dist * (1.0 + uStrength));
+    outColor = vec4(color.rgb * mix(1.0 - uStrength, 1.0, vignette), color.a);
+}
+"#;
+
+// Split the color channels radially away from the center
+const CHROMATIC_ABERRATION_FRAGMENT_SRC: &str = r#"
+uniform float uAmount;
+
+void main() {
+    vec2 direction = (vUv - 0.5) * uAmount / uResolution;
+    float r = texture(uTexture, vUv + direction).r;
+    vec4 g = texture(uTexture, vUv);
+    float b = texture(uTexture, vUv - direction).b;
+    outColor = vec4(r, g.g, b, g.a);
+}
+"#;
+
+// 3D color lookup stored as a horizontal strip of `size` slices (size*size x size)
+const COLOR_GRADING_FRAGMENT_SRC: &str = r#"
+uniform sampler2D uLut;
+uniform float uLutSize;
+uniform float uIntensity;
+
+vec3 sampleSlice(vec2 rg, float slice) {
+    float n = uLutSize;
+    vec2 uv = vec2((slice * n + rg.x * (n - 1.0) + 0.5) / (n * n), (rg.y * (n - 1.0) + 0.5) / n);
+    return texture(uLut, uv).rgb;
+}
+
+void main() {
+    vec4 color = texture(uTexture, vUv);
+    vec3 c = clamp(color.rgb, 0.0, 1.0);
+    float blue = c.b * (uLutSize - 1.0);
+    float slice0 = floor(blue);
+    float slice1 = min(slice0 + 1.0, uLutSize - 1.0);
+    vec3 graded = mix(sampleSlice(c.rg, slice0), sampleSlice(c.rg, slice1), blue - slice0);
+    outColor = vec4(mix(color.rgb, graded, uIntensity), color.a);
+}
+"#;
+
+// Offscreen color target: a framebuffer with a single RGBA texture attachment, and a
+// depth-stencil renderbuffer when the scene is drawn into it with clipping
+pub(crate) struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    texture: WebGlTexture,
+    stencil: Option<WebGlRenderbuffer>,
+    // RGBA8, or SRGB8_ALPHA8 for a linear-light scene
+    internal_format: u32,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTarget {
+    pub(crate) fn new(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<RenderTarget, JsValue> {
+        RenderTarget::create(gl, width, height, WebGl2RenderingContext::RGBA8, false)
+    }
+
+    // Target with a stencil buffer, used for the scene so clip paths work with effects enabled
+    pub(crate) fn with_stencil(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<RenderTarget, JsValue> {
+        RenderTarget::create(gl, width, height, WebGl2RenderingContext::RGBA8, true)
+    }
+
+    // sRGB-encoded target with a stencil buffer: blending happens on linear values and
+    // sampling decodes back to linear
+    pub(crate) fn srgb_with_stencil(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<RenderTarget, JsValue> {
+        RenderTarget::create(gl, width, height, WebGl2RenderingContext::SRGB8_ALPHA8, true)
+    }
+
+    fn create(
+        gl: &WebGl2RenderingContext,
+        width: u32,
+        height: u32,
+        internal_format: u32,
+        stencil: bool,
+    ) -> Result<RenderTarget, JsValue> {
+        let texture = gl.create_texture().ok_or("Failed to create render target texture")?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        set_texture_params(gl, WebGl2RenderingContext::LINEAR);
+        allocate_texture(gl, width, height, internal_format)?;
+
+        let framebuffer = gl.create_framebuffer().ok_or("Failed to create framebuffer")?;
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            Some(&texture),
+            0,
+        );
+
+        let stencil = if stencil {
+            let renderbuffer = gl.create_renderbuffer().ok_or("Failed to create stencil renderbuffer")?;
+            allocate_stencil(gl, &renderbuffer, width, height);
+            gl.framebuffer_renderbuffer(
+                WebGl2RenderingContext::FRAMEBUFFER,
+                WebGl2RenderingContext::DEPTH_STENCIL_ATTACHMENT,
+                WebGl2RenderingContext::RENDERBUFFER,
+                Some(&renderbuffer),
+            );
+            Some(renderbuffer)
+        } else {
+            None
+        };
+
+        let status = gl.check_framebuffer_status(WebGl2RenderingContext::FRAMEBUFFER);
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+
+        if status != WebGl2RenderingContext::FRAMEBUFFER_COMPLETE {
+            gl.delete_framebuffer(Some(&framebuffer));
+            gl.delete_texture(Some(&texture));
+            gl.delete_renderbuffer(stencil.as_ref());
+            return Err(JsValue::from_str(&format!("Render target is incomplete (status 0x{:x})", status)));
+        }
+
+        Ok(RenderTarget {
+            framebuffer,
+            texture,
+            stencil,
+            internal_format,
+            width,
+            height,
+        })
+    }
+
+    // Reallocate the texture storage when the size changes
+    pub(crate) fn resize(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(), JsValue> {
+        if self.width == width && self.height == height {
+            return Ok(());
+        }
+
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+        allocate_texture(gl, width, height, self.internal_format)?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+        if let Some(stencil) = &self.stencil {
+            allocate_stencil(gl, stencil, width, height);
+        }
+
+        self.width = width;
+        self.height = height;
+
+        Ok(())
+    }
+
+    // Make this target the current draw destination
+    pub(crate) fn bind(&self, gl: &WebGl2RenderingContext) {
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    pub(crate) fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_texture(Some(&self.texture));
+        gl.delete_renderbuffer(self.stencil.as_ref());
+    }
+}
+
+// A single stage of the post-processing stack
+enum Effect {
+    Bloom { threshold: f32, intensity: f32, radius: f32 },
+    Blur { radius: f32 },
+    Vignette { strength: f32, softness: f32 },
+    ChromaticAberration { amount: f32 },
+    ColorGrading { lut: WebGlTexture, size: u32, intensity: f32 },
+    Custom { program: WebGlProgram },
+}
+
+struct EffectEntry {
+    id: u32,
+    enabled: bool,
+    effect: Effect,
+}
+
+// Runs the effect chain over the rendered scene using internally managed ping-pong buffers
+pub(crate) struct PostProcessor {
+    vao: WebGlVertexArrayObject,
+    blur_program: WebGlProgram,
+    threshold_program: WebGlProgram,
+    bloom_combine_program: WebGlProgram,
+    vignette_program: WebGlProgram,
+    chromatic_program: WebGlProgram,
+    color_grading_program: WebGlProgram,
+    effects: Vec<EffectEntry>,
+    next_id: u32,
+    // Full resolution ping-pong pair, the scene is always rendered into targets[0]
+    targets: Option<[RenderTarget; 2]>,
+    // Half resolution pair used by bloom
+    bloom_targets: Option<[RenderTarget; 2]>,
+    width: u32,
+    height: u32,
+}
+
+impl PostProcessor {
+    pub(crate) fn new(gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<PostProcessor, JsValue> {
+        let vao = gl.create_vertex_array().ok_or("Failed to create post-processing vertex array")?;
+
+        Ok(PostProcessor {
+            vao,
+            blur_program: create_pass_program(gl, BLUR_FRAGMENT_SRC)?,
+            threshold_program: create_pass_program(gl, THRESHOLD_FRAGMENT_SRC)?,
+            bloom_combine_program: create_pass_program(gl, BLOOM_COMBINE_FRAGMENT_SRC)?,
+            vignette_program: create_pass_program(gl, VIGNETTE_FRAGMENT_SRC)?,
+            chromatic_program: create_pass_program(gl, CHROMATIC_ABERRATION_FRAGMENT_SRC)?,
+            color_grading_program: create_pass_program(gl, COLOR_GRADING_FRAGMENT_SRC)?,
+            effects: Vec::new(),
+            next_id: 1,
+            targets: None,
+            bloom_targets: None,
+            width,
+            height,
+        })
+    }
+
+    // Whether the scene needs to be redirected into an offscreen target
+    pub(crate) fn is_active(&self) -> bool {
+        self.effects.iter().any(|entry| entry.enabled)
+    }
+
+    fn push(&mut self, effect: Effect) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.effects.push(EffectEntry { id, enabled: true, effect });
+        id
+    }
+
+    pub(crate) fn add_bloom(&mut self, threshold: f32, intensity: f32, radius: f32) -> u32 {
+        self.push(Effect::Bloom { threshold, intensity, radius })
+    }
+
+    pub(crate) fn add_blur(&mut self, radius: f32) -> u32 {
+        self.push(Effect::Blur { radius })
+    }
+
+    pub(crate) fn add_vignette(&mut self, strength: f32, softness: f32) -> u32 {
+        self.push(Effect::Vignette { strength, softness })
+    }
+
+    pub(crate) fn add_chromatic_aberration(&mut self, amount: f32) -> u32 {
+        self.push(Effect::ChromaticAberration { amount })
+    }
+
+    pub(crate) fn add_color_grading(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        lut: &[u8],
+        size: u32,
+        intensity: f32,
+    ) -> Result<u32, JsValue> {
+        // The texel count is checked in usize, size^3 * 4 overflows u32 from size 1626
+        let texels = (size as usize)
+            .checked_mul(size as usize)
+            .and_then(|count| count.checked_mul(size as usize))
+            .and_then(|count| count.checked_mul(4));
+        if size < 2 || texels != Some(lut.len()) {
+            return Err(JsValue::from_str("LUT data must contain size^3 RGBA texels"));
+        }
+        // The slices are laid side by side in one row, WebGL 2 guarantees at least 2048 texels
+        let max_texture_size = gl
+            .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|value| value.as_f64())
+            .map_or(2048, |size| size as usize);
+        if size as usize * size as usize > max_texture_size {
+            return Err(JsValue::from_str(&format!(
+                "LUT size {} needs a texture {} texels wide, the limit is {}",
+                size,
+                size as usize * size as usize,
+                max_texture_size
+            )));
+        }
+
+        let texture = gl.create_texture().ok_or("Failed to create LUT texture")?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        set_texture_params(gl, WebGl2RenderingContext::LINEAR);
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            WebGl2RenderingContext::RGBA8 as i32,
+            (size * size) as i32,
+            size as i32,
+            0,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(lut),
+        )?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+
+        Ok(self.push(Effect::ColorGrading { lut: texture, size, intensity }))
+    }
+
+    pub(crate) fn add_custom_pass(&mut self, gl: &WebGl2RenderingContext, fragment_src: &str) -> Result<u32, JsValue> {
+        let program = create_pass_program(gl, fragment_src)?;
+        Ok(self.push(Effect::Custom { program }))
+    }
+
+    pub(crate) fn set_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        match self.effects.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, gl: &WebGl2RenderingContext, id: u32) -> bool {
+        match self.effects.iter().position(|entry| entry.id == id) {
+            Some(index) => {
+                let entry = self.effects.remove(index);
+                delete_effect(gl, &entry.effect);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self, gl: &WebGl2RenderingContext) {
+        for entry in self.effects.drain(..) {
+            delete_effect(gl, &entry.effect);
+        }
+    }
+
+    // Keep the offscreen buffers in sync with the backbuffer size
+    pub(crate) fn resize(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(), JsValue> {
+        self.width = width;
+        self.height = height;
+
+        if let Some(targets) = self.targets.as_mut() {
+            for target in targets.iter_mut() {
+                target.resize(gl, width, height)?;
+            }
+        }
+
+        if let Some(targets) = self.bloom_targets.as_mut() {
+            for target in targets.iter_mut() {
+                target.resize(gl, (width / 2).max(1), (height / 2).max(1))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Redirect the scene rendering into the first ping-pong buffer
+    pub(crate) fn begin(&mut self, gl: &WebGl2RenderingContext) -> Result<(), JsValue> {
+        if self.targets.is_none() {
+            self.targets = Some([
+                RenderTarget::with_stencil(gl, self.width, self.height)?,
+                RenderTarget::new(gl, self.width, self.height)?,
+            ]);
+        }
+
+        let needs_bloom = self.effects.iter().any(|entry| entry.enabled && matches!(entry.effect, Effect::Bloom { .. }));
+        if needs_bloom && self.bloom_targets.is_none() {
+            let (w, h) = ((self.width / 2).max(1), (self.height / 2).max(1));
+            self.bloom_targets = Some([RenderTarget::new(gl, w, h)?, RenderTarget::new(gl, w, h)?]);
+        }
+
+        if let Some(targets) = &self.targets {
+            targets[0].bind(gl);
+        }
+
+        Ok(())
+    }
+
+    // Run every enabled effect, the last one writes to the default framebuffer
+    pub(crate) fn finish(&self, gl: &WebGl2RenderingContext, time: f32) -> Result<(), JsValue> {
+        let targets = match &self.targets {
+            Some(targets) => targets,
+            None => return Ok(()),
+        };
+
+        gl.disable(WebGl2RenderingContext::BLEND);
+        gl.bind_vertex_array(Some(&self.vao));
+
+        let active: Vec<&EffectEntry> = self.effects.iter().filter(|entry| entry.enabled).collect();
+        let mut current = 0;
+
+        for (i, entry) in active.iter().enumerate() {
+            let is_last = i + 1 == active.len();
+            let source = &targets[current];
+            let other = &targets[1 - current];
+            let destination = if is_last { None } else { Some(other) };
+
+            match &entry.effect {
+                Effect::Bloom { threshold, intensity, radius } => {
+                    if let Some(bloom) = &self.bloom_targets {
+                        // Bright pass at half resolution, then blur it
+                        let program = &self.threshold_program;
+                        self.draw_pass(gl, program, source, Some(&bloom[0]), time);
+                        gl.uniform1f(gl.get_uniform_location(program, "uThreshold").as_ref(), *threshold);
+                        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+                        self.blur(gl, &bloom[0], &bloom[1], Some(&bloom[0]), *radius * 0.5, time);
+
+                        // Combine the scene with the blurred highlights
+                        let program = &self.bloom_combine_program;
+                        self.draw_pass(gl, program, source, destination, time);
+                        gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+                        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(bloom[0].texture()));
+                        gl.uniform1i(gl.get_uniform_location(program, "uBloom").as_ref(), 1);
+                        gl.uniform1f(gl.get_uniform_location(program, "uIntensity").as_ref(), *intensity);
+                        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+                        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+                    }
+                    current = 1 - current;
+                }
+                Effect::Blur { radius } => {
+                    // Horizontal into the spare buffer, vertical back into the source (or the screen)
+                    let vertical_target = if is_last { None } else { Some(source) };
+                    self.blur(gl, source, other, vertical_target, *radius, time);
+                }
+                Effect::Vignette { strength, softness } => {
+                    let program = &self.vignette_program;
+                    self.draw_pass(gl, program, source, destination, time);
+                    gl.uniform1f(gl.get_uniform_location(program, "uStrength").as_ref(), *strength);
+                    gl.uniform1f(gl.get_uniform_location(program, "uSoftness").as_ref(), *softness);
+                    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+                    current = 1 - current;
+                }
+                Effect::ChromaticAberration { amount } => {
+                    let program = &self.chromatic_program;
+                    self.draw_pass(gl, program, source, destination, time);
+                    gl.uniform1f(gl.get_uniform_location(program, "uAmount").as_ref(), *amount);
+                    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+                    current = 1 - current;
+                }
+                Effect::ColorGrading { lut, size, intensity } => {
+                    let program = &self.color_grading_program;
+                    self.draw_pass(gl, program, source, destination, time);
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+                    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(lut));
+                    gl.uniform1i(gl.get_uniform_location(program, "uLut").as_ref(), 1);
+                    gl.uniform1f(gl.get_uniform_location(program, "uLutSize").as_ref(), *size as f32);
+                    gl.uniform1f(gl.get_uniform_location(program, "uIntensity").as_ref(), *intensity);
+                    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+                    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+                    current = 1 - current;
+                }
+                Effect::Custom { program } => {
+                    self.draw_pass(gl, program, source, destination, time);
+                    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+                    current = 1 - current;
+                }
+            }
+        }
+
+        // Restore the default state expected by the scene renderer
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.width as i32, self.height as i32);
+        gl.enable(WebGl2RenderingContext::BLEND);
+
+        Ok(())
+    }
+
+    // Two-pass separable gaussian blur: source -> scratch (horizontal) -> destination (vertical)
+    fn blur(
+        &self,
+        gl: &WebGl2RenderingContext,
+        source: &RenderTarget,
+        scratch: &RenderTarget,
+        destination: Option<&RenderTarget>,
+        radius: f32,
+        time: f32,
+    ) {
+        let program = &self.blur_program;
+        let direction = gl.get_uniform_location(program, "uDirection");
+        let sigma = gl.get_uniform_location(program, "uSigma");
+
+        self.draw_pass(gl, program, source, Some(scratch), time);
+        gl.uniform1f(sigma.as_ref(), blur_sigma(radius));
+        gl.uniform2f(direction.as_ref(), 1.0 / source.width as f32, 0.0);
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+
+        self.draw_pass(gl, program, scratch, destination, time);
+        gl.uniform2f(direction.as_ref(), 0.0, 1.0 / scratch.height as f32);
+        gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+    }
+
+    // Bind the destination, the program and the common uniforms of a pass
+    fn draw_pass(
+        &self,
+        gl: &WebGl2RenderingContext,
+        program: &WebGlProgram,
+        source: &RenderTarget,
+        destination: Option<&RenderTarget>,
+        time: f32,
+    ) {
+        match destination {
+            Some(target) => target.bind(gl),
+            None => {
+                gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+                gl.viewport(0, 0, self.width as i32, self.height as i32);
+            }
+        }
+
+        gl.use_program(Some(program));
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(source.texture()));
+        gl.uniform1i(gl.get_uniform_location(program, "uTexture").as_ref(), 0);
+        gl.uniform2f(
+            gl.get_uniform_location(program, "uResolution").as_ref(),
+            self.width as f32,
+            self.height as f32,
+        );
+        gl.uniform1f(gl.get_uniform_location(program, "uTime").as_ref(), time);
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        self.clear(gl);
+
+        for program in [
+            &self.blur_program,
+            &self.threshold_program,
+            &self.bloom_combine_program,
+            &self.vignette_program,
+            &self.chromatic_program,
+            &self.color_grading_program,
+        ] {
+            gl.delete_program(Some(program));
+        }
+
+        for targets in [self.targets.take(), self.bloom_targets.take()].into_iter().flatten() {
+            for target in targets.iter() {
+                target.delete(gl);
+            }
+        }
+
+        gl.delete_vertex_array(Some(&self.vao));
+    }
+}
+
+// Compile a fullscreen pass from a fragment body appended to the shared header
+pub(crate) fn create_pass_program(gl: &WebGl2RenderingContext, fragment_body: &str) -> Result<WebGlProgram, JsValue> {
+    let fragment_src = format!("{}{}", PASS_HEADER_SRC, fragment_body);
+    let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, FULLSCREEN_VERTEX_SRC)?;
+    let program = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, &fragment_src).and_then(|frag_shader| {
+        let program = link_program(gl, &vert_shader, &frag_shader);
+        gl.delete_shader(Some(&frag_shader));
+        program
+    });
+
+    // The shaders are no longer needed once linked, or when the pass failed to build
+    gl.delete_shader(Some(&vert_shader));
+
+    program.map_err(|error| JsValue::from_str(&error))
+}
+
+// Release the GPU resources owned by an effect
+// Standard deviation of a blur whose taps reach `radius` pixels of the target at three
+// standard deviations
+fn blur_sigma(radius: f32) -> f32 {
+    (radius.max(0.0) / 3.0).min(MAX_BLUR_SIGMA)
+}
+
+fn delete_effect(gl: &WebGl2RenderingContext, effect: &Effect) {
+    match effect {
+        Effect::ColorGrading { lut, .. } => gl.delete_texture(Some(lut)),
+        Effect::Custom { program } => gl.delete_program(Some(program)),
+        _ => {}
+    }
+}
+
+// Clamp-to-edge sampling with the given filter on the currently bound texture
+fn set_texture_params(gl: &WebGl2RenderingContext, filter: u32) {
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, filter as i32);
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, filter as i32);
+    gl.tex_parameteri(
+        WebGl2RenderingContext::TEXTURE_2D,
+        WebGl2RenderingContext::TEXTURE_WRAP_S,
+        WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+    );
+    gl.tex_parameteri(
+        WebGl2RenderingContext::TEXTURE_2D,
+        WebGl2RenderingContext::TEXTURE_WRAP_T,
+        WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+    );
+}
+
+// Allocate uninitialized 8-bit RGBA storage for the currently bound texture
+fn allocate_texture(gl: &WebGl2RenderingContext, width: u32, height: u32, internal_format: u32) -> Result<(), JsValue> {
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        0,
+        internal_format as i32,
+        width.max(1) as i32,
+        height.max(1) as i32,
+        0,
+        WebGl2RenderingContext::RGBA,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        None,
+    )
+}
+
+// (Re)allocate depth-stencil storage for a render target
+fn allocate_stencil(gl: &WebGl2RenderingContext, renderbuffer: &WebGlRenderbuffer, width: u32, height: u32) {
+    gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(renderbuffer));
+    gl.renderbuffer_storage(
+        WebGl2RenderingContext::RENDERBUFFER,
+        WebGl2RenderingContext::DEPTH24_STENCIL8,
+        width as i32,
+        height as i32,
+    );
+    gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+
+    // Effect bookkeeping only, none of the GL handles are used
+    fn processor() -> PostProcessor {
+        PostProcessor {
+            vao: JsValue::NULL.unchecked_into(),
+            blur_program: JsValue::NULL.unchecked_into(),
+            threshold_program: JsValue::NULL.unchecked_into(),
+            bloom_combine_program: JsValue::NULL.unchecked_into(),
+            vignette_program: JsValue::NULL.unchecked_into(),
+            chromatic_program: JsValue::NULL.unchecked_into(),
+            color_grading_program: JsValue::NULL.unchecked_into(),
+            effects: Vec::new(),
+            next_id: 1,
+            targets: None,
+            bloom_targets: None,
+            width: 800,
+            height: 600,
+        }
+    }
+
+    #[test]
+    fn effects_get_distinct_ids_and_can_be_toggled() {
+        let mut processor = processor();
+        assert!(!processor.is_active());
+
+        let bloom = processor.add_bloom(0.8, 1.0, 4.0);
+        let vignette = processor.add_vignette(0.5, 0.3);
+        assert_ne!(bloom, vignette);
+        assert!(processor.is_active());
+
+        assert!(processor.set_enabled(bloom, false));
+        assert!(processor.is_active());
+        assert!(processor.set_enabled(vignette, false));
+        assert!(!processor.is_active());
+        assert!(!processor.set_enabled(vignette + 1, true));
+    }
+
+    #[test]
+    fn blur_taps_cover_the_radius_without_gaps() {
+        assert_eq!(blur_sigma(-2.0), 0.0);
+        assert_eq!(blur_sigma(1e6), MAX_BLUR_SIGMA);
+        // The shader samples every `stride` texels out to the extent, 64 taps at most, so
+        // radii up to 64 pixels read every texel
+        for radius in [1.0, 4.0, 12.0, 64.0, 100.0, 500.0] {
+            let sigma = blur_sigma(radius);
+            let extent = (sigma * 3.0).ceil();
+            let stride = (extent / 64.0).max(1.0);
+            assert!(extent >= radius);
+            assert!((extent / stride).ceil() <= 64.0);
+            if radius <= 64.0 {
+                assert_eq!(stride, 1.0, "radius {}", radius);
+            }
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::rc::Rc;
+
+use js_sys::Float32Array;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlUniformLocation, WebGlVertexArrayObject};
+
+use crate::clip::{Clip, Clipper};
+use crate::composite::{CompositeOperation, Compositor, BACKDROP_TEXTURE_UNIT};
+use crate::pipeline::{expand_shader, ColorPipeline};
+use crate::{compile_shader, link_program};
+
+// Instance layout: center and half size (4), rotation, kind, border width and parameter (4),
+// corner radii (4), fill color (4), border color (4)
+const FLOATS_PER_INSTANCE: usize = 20;
+
+// One quad per instance, grown by two device pixels so the edge falloff fits
+const PRIMITIVE_VERTEX_SHADER_SRC: &str = r#"#version 300 es
+in vec4 aBox;
+in vec4 aShape;
+in vec4 aRadii;
+in vec4 aFill;
+in vec4 aBorder;
+
+uniform mat4 uMatrix;
+// World units per device pixel
+uniform float uPixelSize;
+
+out vec2 vLocal;
+flat out vec2 vHalfSize;
+flat out vec4 vShape;
+flat out vec4 vRadii;
+flat out vec4 vFill;
+flat out vec4 vBorder;
+
+void main() {
+    // Triangle strip corners: (0, 0), (1, 0), (0, 1), (1, 1)
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+    vec2 local = (corner * 2.0 - 1.0) * (aBox.zw + uPixelSize * 2.0);
+
+    float s = sin(aShape.x);
+    float c = cos(aShape.x);
+    vec2 world = aBox.xy + vec2(local.x * c - local.y * s, local.x * s + local.y * c);
+
+    vLocal = local;
+    vHalfSize = aBox.zw;
+    vShape = aShape;
+    vRadii = aRadii;
+    vFill = aFill;
+    vBorder = aBorder;
+    gl_Position = uMatrix * vec4(world, 0.0, 1.0);
+}
+"#;
+
+// Signed distances in world units (negative inside), turned into coverage over one pixel
+// with the screen-space derivative so edges stay sharp at any zoom
+const PRIMITIVE_FRAGMENT_SHADER_SRC: &str = r#"#version 300 es
+precision highp float;
+
+in vec2 vLocal;
+flat in vec2 vHalfSize;
+// rotation, kind, border width, kind parameter
+flat in vec4 vShape;
+// Corner radii: top-left, top-right, bottom-right, bottom-left
+flat in vec4 vRadii;
+flat in vec4 vFill;
+flat in vec4 vBorder;
+
+#pragma pipeline
+#pragma composite
+
+out vec4 outColor;
+
+float circle(vec2 p, float r) {
+    return length(p) - r;
+}
+
+// Approximation that is exact on the ellipse and good enough within a pixel of it
+float ellipse(vec2 p, vec2 r) {
+    float k0 = length(p / r);
+    float k1 = length(p / (r * r));
+    return k1 > 0.0 ? k0 * (k0 - 1.0) / k1 : -min(r.x, r.y);
+}
+
+float roundedRect(vec2 p, vec2 b, vec4 radii) {
+    float r = p.x > 0.0 ? (p.y > 0.0 ? radii.z : radii.y) : (p.y > 0.0 ? radii.w : radii.x);
+    vec2 q = abs(p) - b + r;
+    return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+}
+
+float ring(vec2 p, float outer, float inner) {
+    return abs(length(p) - (outer + inner) * 0.5) - (outer - inner) * 0.5;
+}
+
+// Segment along the long axis of the box, as thick as the short one
+float capsule(vec2 p, vec2 b) {
+    float r = min(b.x, b.y);
+    vec2 a = b - r;
+    return length(p - clamp(p, -a, a)) - r;
+}
+
+// Regular polygon with n sides and circumradius r, a vertex towards -y
+float polygon(vec2 p, float r, float n) {
+    float an = 3.14159265 / n;
+    vec2 acs = vec2(cos(an), sin(an));
+    float bn = mod(atan(p.x, -p.y), 2.0 * an) - an;
+    p = length(p) * vec2(cos(bn), abs(sin(bn)));
+    p -= r * acs;
+    p.y += clamp(-p.y, 0.0, r * acs.y);
+    return length(p) * sign(p.x);
+}
+
+float shapeDistance(vec2 p) {
+    vec2 b = vHalfSize;
+    float radius = min(b.x, b.y);
+    int kind = int(vShape.y + 0.5);
+    if (kind == 0) {
+        return circle(p, radius);
+    } else if (kind == 1) {
+        return ellipse(p, max(b, vec2(1e-6)));
+    } else if (kind == 2) {
+        return roundedRect(p, b, vRadii);
+    } else if (kind == 3) {
+        return ring(p, radius, vShape.w);
+    } else if (kind == 4) {
+        return capsule(p, b);
+    }
+    float rounding = vRadii.x;
+    return polygon(p, radius - rounding, vShape.w) - rounding;
+}
+
+void main() {
+    float d = shapeDistance(vLocal);
+    float aa = max(fwidth(d), 1e-6);
+    float shape = clamp(0.5 - d / aa, 0.0, 1.0);
+    // The border lies inside the edge, the fill inside the border
+    float fill = clamp(0.5 - (d + vShape.z) / aa, 0.0, 1.0);
+    vec4 color = mix(premultiply(vBorder, 1.0), premultiply(vFill, 1.0), vShape.z > 0.0 ? fill : 1.0);
+    outColor = composite(color * shape);
+}
+"#;
+
+// Shapes drawn as a quad with a distance function
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Primitive {
+    Circle,
+    Ellipse,
+    RoundedRect,
+    Ring,
+    Capsule,
+    Polygon,
+}
+
+impl Primitive {
+    pub(crate) fn parse(kind: &str) -> Result<Primitive, JsValue> {
+        Ok(match kind {
+            "circle" => Primitive::Circle,
+            "ellipse" => Primitive::Ellipse,
+            "rounded-rect" | "round-rect" => Primitive::RoundedRect,
+            "ring" => Primitive::Ring,
+            "capsule" => Primitive::Capsule,
+            "polygon" => Primitive::Polygon,
+            _ => return Err(JsValue::from_str(&format!("Invalid primitive \"{}\"", kind))),
+        })
+    }
+}
+
+// A primitive centered in a box, before it is packed into an instance
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct PrimitiveShape {
+    pub(crate) kind: Primitive,
+    pub(crate) center: (f32, f32),
+    pub(crate) half_size: (f32, f32),
+    pub(crate) rotation: f32,
+    // Corner radii (top-left, top-right, bottom-right, bottom-left) of rounded rects, the
+    // first one rounds polygon corners
+    pub(crate) radii: [f32; 4],
+    // Inset border, 0 for none
+    pub(crate) border_width: f32,
+    // Inner radius of rings, side count of polygons
+    pub(crate) parameter: f32,
+    pub(crate) fill: [f32; 4],
+    pub(crate) border: [f32; 4],
+}
+
+// Instances drawn with the same clip and composite operation
+struct PrimitiveRun {
+    first_instance: usize,
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    // World-space bounding box [x0, y0, x1, y1] of the primitives, for the backdrop copy
+    bounds: [f32; 4],
+}
+
+// Instanced renderer of circles, ellipses, rounded rects, rings, capsules and regular
+// polygons, each a single quad shaded with its signed distance function
+pub(crate) struct PrimitiveBatch {
+    program: WebGlProgram,
+    vao: WebGlVertexArrayObject,
+    instance_buffer: WebGlBuffer,
+    matrix_location: Option<WebGlUniformLocation>,
+    pixel_size_location: Option<WebGlUniformLocation>,
+    blend_mode_location: Option<WebGlUniformLocation>,
+    backdrop_location: Option<WebGlUniformLocation>,
+    premultiplied_location: Option<WebGlUniformLocation>,
+    linear_location: Option<WebGlUniformLocation>,
+    // (attribute location, size, offset in floats) of the per-instance attributes
+    attributes: Vec<(u32, i32, i32)>,
+    instances: Vec<f32>,
+    runs: Vec<PrimitiveRun>,
+    // Clip and composite operation of the primitives queued from now on
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    // Encoding of the instance colors
+    pipeline: ColorPipeline,
+}
+
+impl PrimitiveBatch {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<PrimitiveBatch, JsValue> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, PRIMITIVE_VERTEX_SHADER_SRC)?;
+        let fragment_src = expand_shader(PRIMITIVE_FRAGMENT_SHADER_SRC);
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, &fragment_src)?;
+        let program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let vao = gl.create_vertex_array().ok_or("Failed to create primitive vertex array")?;
+        gl.bind_vertex_array(Some(&vao));
+
+        let instance_buffer = gl.create_buffer().ok_or("Failed to create primitive instance buffer")?;
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&instance_buffer));
+
+        // Every attribute advances once per instance
+        let mut attributes = Vec::new();
+        for (name, size, offset) in [("aBox", 4, 0), ("aShape", 4, 4), ("aRadii", 4, 8), ("aFill", 4, 12), ("aBorder", 4, 16)] {
+            let location = gl.get_attrib_location(&program, name);
+            if location < 0 {
+                continue;
+            }
+            gl.enable_vertex_attrib_array(location as u32);
+            gl.vertex_attrib_divisor(location as u32, 1);
+            attributes.push((location as u32, size, offset));
+        }
+
+        gl.bind_vertex_array(None);
+
+        Ok(PrimitiveBatch {
+            matrix_location: gl.get_uniform_location(&program, "uMatrix"),
+            pixel_size_location: gl.get_uniform_location(&program, "uPixelSize"),
+            blend_mode_location: gl.get_uniform_location(&program, "uBlendMode"),
+            backdrop_location: gl.get_uniform_location(&program, "uBackdrop"),
+            premultiplied_location: gl.get_uniform_location(&program, "uPremultiplied"),
+            linear_location: gl.get_uniform_location(&program, "uLinear"),
+            program,
+            vao,
+            instance_buffer,
+            attributes,
+            instances: Vec::new(),
+            runs: Vec::new(),
+            clip: Rc::new(Clip::default()),
+            composite: CompositeOperation::SourceOver,
+            pipeline: ColorPipeline::default(),
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    // Clip the primitives queued after this call
+    pub(crate) fn set_clip(&mut self, clip: Rc<Clip>) {
+        self.clip = clip;
+    }
+
+    // Composite operation of the primitives queued after this call
+    pub(crate) fn set_composite(&mut self, composite: CompositeOperation) {
+        self.composite = composite;
+    }
+
+    // Color pipeline of the primitives queued after this call
+    pub(crate) fn set_pipeline(&mut self, pipeline: ColorPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    // Queue a primitive. Consecutive primitives with the same clip and composite operation
+    // are drawn with one instanced call
+    pub(crate) fn draw(&mut self, shape: &PrimitiveShape) {
+        let (half_width, half_height) = (shape.half_size.0.abs(), shape.half_size.1.abs());
+        if half_width <= 0.0 || half_height <= 0.0 {
+            return;
+        }
+
+        let radius = half_width.min(half_height);
+        let parameter = match shape.kind {
+            Primitive::Ring => shape.parameter.clamp(0.0, radius),
+            Primitive::Polygon => shape.parameter.round().max(3.0),
+            _ => shape.parameter,
+        };
+        let radii = match shape.kind {
+            Primitive::RoundedRect => shape.radii.map(|r| r.clamp(0.0, radius)),
+            Primitive::Polygon => [shape.radii[0].clamp(0.0, radius * 0.5), 0.0, 0.0, 0.0],
+            _ => [0.0; 4],
+        };
+
+        // Primitives blended against a backdrop copy each get their own run so they see each other
+        let reuse = self.runs.last().is_some_and(|run| {
+            Rc::ptr_eq(&run.clip, &self.clip) && run.composite == self.composite && !self.composite.reads_backdrop()
+        });
+        if !reuse {
+            self.runs.push(PrimitiveRun {
+                first_instance: self.instances.len() / FLOATS_PER_INSTANCE,
+                clip: self.clip.clone(),
+                composite: self.composite,
+                bounds: [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY],
+            });
+        }
+
+        // Any rotation stays within the circle around the box
+        if let Some(run) = self.runs.last_mut() {
+            let (cx, cy) = shape.center;
+            let extent = (half_width * half_width + half_height * half_height).sqrt();
+            let b = run.bounds;
+            run.bounds = [b[0].min(cx - extent), b[1].min(cy - extent), b[2].max(cx + extent), b[3].max(cy + extent)];
+        }
+
+        let kind = shape.kind as u32 as f32;
+        self.instances.extend_from_slice(&[shape.center.0, shape.center.1, half_width, half_height]);
+        self.instances.extend_from_slice(&[shape.rotation, kind, shape.border_width.clamp(0.0, radius), parameter]);
+        self.instances.extend_from_slice(&radii);
+        self.instances.extend_from_slice(&self.pipeline.encode(shape.fill));
+        self.instances.extend_from_slice(&self.pipeline.encode(shape.border));
+    }
+
+    // Draw the queued primitives, one instanced call per run. `pixel_size` is the size of a
+    // device pixel in world units
+    pub(crate) fn flush(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        matrix: &[f32; 16],
+        pixel_size: f32,
+        clipper: &mut Clipper,
+        compositor: &mut Compositor,
+    ) -> Result<(), JsValue> {
+        if self.instances.is_empty() {
+            self.runs.clear();
+            return Ok(());
+        }
+
+        gl.use_program(Some(&self.program));
+        gl.bind_vertex_array(Some(&self.vao));
+
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.instance_buffer));
+        let instance_array = Float32Array::from(&self.instances[..]);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &instance_array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+
+        gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, matrix);
+        gl.uniform1f(self.pixel_size_location.as_ref(), pixel_size);
+        gl.uniform1i(self.backdrop_location.as_ref(), BACKDROP_TEXTURE_UNIT as i32);
+        self.pipeline.set_uniforms(gl, self.premultiplied_location.as_ref(), self.linear_location.as_ref());
+
+        let instance_count = self.instances.len() / FLOATS_PER_INSTANCE;
+        for (i, run) in self.runs.iter().enumerate() {
+            let end = self.runs.get(i + 1).map_or(instance_count, |next| next.first_instance);
+            let count = end - run.first_instance;
+            if count == 0 {
+                continue;
+            }
+
+            // Redrawing the stencil mask switches to the clip program and its buffer
+            if clipper.apply(gl, &run.clip, matrix) {
+                gl.use_program(Some(&self.program));
+                gl.bind_vertex_array(Some(&self.vao));
+                gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.instance_buffer));
+            }
+
+            compositor.apply(gl, run.composite);
+            gl.uniform1i(self.blend_mode_location.as_ref(), run.composite.blend_mode());
+            if run.composite.reads_backdrop() {
+                compositor.copy_backdrop(gl, run.bounds, matrix)?;
+            }
+
+            // WebGL2 has no base instance, so point the attributes at the first instance of the run
+            self.bind_instances(gl, run.first_instance);
+            gl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4, count as i32);
+        }
+
+        gl.bind_vertex_array(None);
+
+        self.instances.clear();
+        self.runs.clear();
+
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, gl: &WebGl2RenderingContext) {
+        gl.delete_buffer(Some(&self.instance_buffer));
+        gl.delete_vertex_array(Some(&self.vao));
+        gl.delete_program(Some(&self.program));
+    }
+
+    fn bind_instances(&self, gl: &WebGl2RenderingContext, first_instance: usize) {
+        let float_size = std::mem::size_of::<f32>() as i32;
+        let stride = FLOATS_PER_INSTANCE as i32 * float_size;
+        let base = first_instance as i32 * stride;
+
+        for &(location, size, offset) in &self.attributes {
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                stride,
+                base + offset * float_size,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+
+    // Instances are packed on the CPU, the GL handles are never used
+    fn batch() -> PrimitiveBatch {
+        PrimitiveBatch {
+            program: JsValue::NULL.unchecked_into(),
+            vao: JsValue::NULL.unchecked_into(),
+            instance_buffer: JsValue::NULL.unchecked_into(),
+            matrix_location: None,
+            pixel_size_location: None,
+            blend_mode_location: None,
+            backdrop_location: None,
+            premultiplied_location: None,
+            linear_location: None,
+            attributes: Vec::new(),
+            instances: Vec::new(),
+            runs: Vec::new(),
+            clip: Rc::new(Clip::default()),
+            composite: CompositeOperation::SourceOver,
+            pipeline: ColorPipeline::default(),
+        }
+    }
+
+    fn shape(kind: Primitive) -> PrimitiveShape {
+        PrimitiveShape {
+            kind,
+            center: (50.0, 20.0),
+            half_size: (-30.0, 10.0),
+            rotation: 0.5,
+            radii: [4.0, 40.0, -1.0, 8.0],
+            border_width: 25.0,
+            parameter: 4.6,
+            fill: [1.0, 0.0, 0.0, 1.0],
+            border: [0.0, 0.0, 1.0, 0.5],
+        }
+    }
+
+    #[test]
+    fn parameters_are_clamped_to_the_shape() {
+        let mut batch = batch();
+        batch.draw(&shape(Primitive::RoundedRect));
+        batch.draw(&shape(Primitive::Polygon));
+        batch.draw(&shape(Primitive::Ring));
+        assert_eq!(batch.instances.len(), 3 * FLOATS_PER_INSTANCE);
+
+        let instance = |i: usize| &batch.instances[i * FLOATS_PER_INSTANCE..(i + 1) * FLOATS_PER_INSTANCE];
+        // Sizes are made positive and borders stay within the smaller half size
+        assert_eq!(instance(0)[..8], [50.0, 20.0, 30.0, 10.0, 0.5, Primitive::RoundedRect as u32 as f32, 10.0, 4.6]);
+        assert_eq!(instance(0)[8..12], [4.0, 10.0, 0.0, 8.0]);
+        // Polygons get a whole side count and one corner radius of at most half the radius
+        assert_eq!(instance(1)[7], 5.0);
+        assert_eq!(instance(1)[8..12], [4.0, 0.0, 0.0, 0.0]);
+        // Rings cannot be wider than the shape
+        assert_eq!(instance(2)[7], 4.6);
+        assert_eq!(instance(2)[12..], [1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn runs_split_on_state_changes() {
+        let mut batch = batch();
+        let circle = PrimitiveShape {
+            half_size: (10.0, 10.0),
+            ..shape(Primitive::Circle)
+        };
+        batch.draw(&circle);
+        batch.draw(&PrimitiveShape {
+            center: (-20.0, 0.0),
+            ..circle
+        });
+        assert_eq!(batch.runs.len(), 1);
+        let extent = 200f32.sqrt();
+        assert_eq!(batch.runs[0].bounds, [-20.0 - extent, -extent, 50.0 + extent, 20.0 + extent]);
+
+        // Blend modes see each primitive drawn before them
+        batch.set_composite(CompositeOperation::Multiply);
+        batch.draw(&circle);
+        batch.draw(&circle);
+        batch.set_clip(Rc::new(Clip::default()));
+        batch.set_composite(CompositeOperation::SourceOver);
+        batch.draw(&circle);
+        assert_eq!(batch.runs.iter().map(|run| run.first_instance).collect::<Vec<_>>(), [0, 2, 3, 4]);
+
+        // Empty shapes are skipped
+        batch.draw(&PrimitiveShape {
+            half_size: (0.0, 10.0),
+            ..circle
+        });
+        assert_eq!(batch.instances.len(), 5 * FLOATS_PER_INSTANCE);
+    }
+
+    #[test]
+    fn kinds_parse_from_their_names() {
+        for (name, kind) in [
+            ("circle", Primitive::Circle),
+            ("ellipse", Primitive::Ellipse),
+            ("rounded-rect", Primitive::RoundedRect),
+            ("round-rect", Primitive::RoundedRect),
+            ("ring", Primitive::Ring),
+            ("capsule", Primitive::Capsule),
+            ("polygon", Primitive::Polygon),
+        ] {
+            assert_eq!(Primitive::parse(name).unwrap(), kind);
+        }
+    }
+}
@@ -0,0 +1,1017 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::batch::{Paint, MITER_LIMIT};
+use crate::composite::CompositeOperation;
+use crate::filter::Filter;
+use crate::gradient::{ColorStop, GradientKind, SpreadMode};
+use crate::path::{bounds, FillRule, Path2D};
+use crate::primitives::{Primitive, PrimitiveShape};
+use crate::shadow::Shadow;
+use crate::svg::{apply, invert, LineCap, LineJoin, Transform, IDENTITY};
+use crate::svg_path::format_number;
+use crate::texture::Repetition;
+
+// Wedges a conic gradient is drawn with, one per degree
+const CONIC_WEDGES: usize = 360;
+// Tile size of a pattern along an axis it does not repeat on, far beyond any drawing
+const UNREPEATED_TILE: f32 = 1.0e6;
+
+// A clip region set by Canvas2D::clip or clip_rect, linked to the clip it was intersected
+// with. Kept in the drawing state so save and restore apply to recordings too
+#[derive(Debug)]
+pub(crate) struct RecordedClip {
+    path: Path2D,
+    rule: FillRule,
+    parent: Option<Rc<RecordedClip>>,
+}
+
+impl RecordedClip {
+    pub(crate) fn new(path: Path2D, rule: FillRule, parent: Option<Rc<RecordedClip>>) -> RecordedClip {
+        RecordedClip { path, rule, parent }
+    }
+}
+
+// Fill or stroke of a recorded shape, straight-alpha sRGB
+#[derive(Clone, Debug)]
+pub(crate) enum RecordedPaint {
+    Color([f32; 4]),
+    // Gradient coordinates are mapped to world space by the transform
+    Gradient {
+        kind: GradientKind,
+        stops: Vec<ColorStop>,
+        spread: SpreadMode,
+        transform: Transform,
+    },
+    // An image tiled from the origin at its natural size
+    Pattern {
+        image: RecordedImage,
+        repetition: Repetition,
+    },
+}
+
+impl RecordedPaint {
+    // None for the texture paint of draw_image, which is recorded as an image instead, and
+    // for patterns of raw pixels uploaded before recording started
+    fn from_paint(paint: &Paint) -> Option<RecordedPaint> {
+        match paint {
+            Paint::Color(color) => Some(RecordedPaint::Color(*color)),
+            Paint::Gradient(gradient) => Some(RecordedPaint::Gradient {
+                kind: gradient.kind(),
+                stops: gradient.stops().to_vec(),
+                spread: gradient.spread(),
+                transform: IDENTITY,
+            }),
+            Paint::Pattern(pattern) => {
+                let texture = pattern.texture();
+                Some(RecordedPaint::Pattern {
+                    image: RecordedImage {
+                        href: texture.href()?,
+                        width: texture.width(),
+                        height: texture.height(),
+                    },
+                    repetition: pattern.repetition(),
+                })
+            }
+            Paint::Image(_) => None,
+        }
+    }
+}
+
+// An image and its size in texels. Draws of the same image share the URL, so the document
+// embeds it once
+#[derive(Clone, Debug)]
+pub(crate) struct RecordedImage {
+    pub(crate) href: Rc<str>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RecordedStroke {
+    pub(crate) width: f32,
+    pub(crate) join: LineJoin,
+    pub(crate) cap: LineCap,
+    // Ratio of the miter length to the line width, like stroke-miterlimit
+    pub(crate) miter_limit: f32,
+}
+
+impl RecordedStroke {
+    // Strokes of the canvas: miter joins and butt caps. Past MITER_LIMIT SVG bevels the joins
+    // the canvas clamps
+    pub(crate) fn canvas(width: f32) -> RecordedStroke {
+        RecordedStroke {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: MITER_LIMIT * 0.5,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum RecordedShape {
+    Fill(FillRule),
+    Stroke(RecordedStroke),
+}
+
+// Drawing state a shape was recorded with
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Layer {
+    pub(crate) clip: Option<Rc<RecordedClip>>,
+    pub(crate) composite: CompositeOperation,
+    pub(crate) filter: Rc<Filter>,
+    pub(crate) shadow: Shadow,
+}
+
+#[derive(Clone, Debug)]
+enum Content {
+    Shape {
+        path: Path2D,
+        shape: RecordedShape,
+        paint: RecordedPaint,
+    },
+    // The source rectangle of an image in texels, mapped to world space by the transform
+    // with the top-left corner of the rectangle at the origin
+    Image {
+        image: RecordedImage,
+        source: [f32; 4],
+        transform: Transform,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Command {
+    content: Content,
+    opacity: f32,
+    layer: Layer,
+}
+
+// Drawing calls of Canvas2D kept as world-space vector shapes and images for SVG export
+#[derive(Debug, Default)]
+pub(crate) struct Recording {
+    commands: Vec<Command>,
+    // Polygons of the animated scene in the last rendered frame, below everything else
+    scene: Vec<Command>,
+    // Background color render() cleared the last frame with
+    background: Option<[f32; 4]>,
+    // The first content drawn since the last clear that SVG cannot express
+    unrecordable: Option<String>,
+}
+
+impl Recording {
+    pub(crate) fn len(&self) -> usize {
+        self.scene.len() + self.commands.len()
+    }
+
+    // Drop the shapes recorded so far, clear() is drawn over by the background of render()
+    pub(crate) fn clear(&mut self) {
+        self.commands.clear();
+        self.unrecordable = None;
+    }
+
+    pub(crate) fn set_background(&mut self, color: [f32; 4]) {
+        self.background = Some(color);
+    }
+
+    // Why the recording cannot be exported, if it cannot
+    pub(crate) fn unrecordable(&self) -> Option<&str> {
+        self.unrecordable.as_deref()
+    }
+
+    pub(crate) fn fill(&mut self, path: Path2D, rule: FillRule, paint: &Paint, layer: &Layer) {
+        match RecordedPaint::from_paint(paint) {
+            Some(paint) => self.push(path, RecordedShape::Fill(rule), paint, 1.0, layer),
+            None => self.mark_unrecordable("a shape filled with an image paint".to_string()),
+        }
+    }
+
+    pub(crate) fn stroke(&mut self, path: Path2D, width: f32, paint: &Paint, layer: &Layer) {
+        match RecordedPaint::from_paint(paint) {
+            Some(paint) => self.push(path, RecordedShape::Stroke(RecordedStroke::canvas(width)), paint, 1.0, layer),
+            None => self.mark_unrecordable("a shape stroked with an image paint".to_string()),
+        }
+    }
+
+    pub(crate) fn push(&mut self, path: Path2D, shape: RecordedShape, paint: RecordedPaint, opacity: f32, layer: &Layer) {
+        if path.is_empty() || opacity <= 0.0 {
+            return;
+        }
+        self.check_layer(layer);
+        let content = Content::Shape { path, shape, paint };
+        self.commands.push(Command { content, opacity, layer: layer.clone() });
+    }
+
+    // The source rectangle of an image drawn through a transform, see Content::Image
+    pub(crate) fn image(&mut self, image: RecordedImage, source: [f32; 4], transform: Transform, opacity: f32, layer: &Layer) {
+        if opacity <= 0.0 {
+            return;
+        }
+        self.check_layer(layer);
+        let content = Content::Image { image, source, transform };
+        self.commands.push(Command { content, opacity, layer: layer.clone() });
+    }
+
+    // Replace the filled polygons of the animated scene, which render() draws below the
+    // shapes of every frame
+    pub(crate) fn set_scene(&mut self, polygons: Vec<(Path2D, RecordedPaint)>) {
+        self.scene = polygons
+            .into_iter()
+            .map(|(path, paint)| Command {
+                content: Content::Shape { path, shape: RecordedShape::Fill(FillRule::NonZero), paint },
+                opacity: 1.0,
+                layer: Layer::default(),
+            })
+            .collect();
+    }
+
+    // Porter-Duff operations other than source-over and lighter, and glow shadows, have no
+    // SVG equivalent
+    fn check_layer(&mut self, layer: &Layer) {
+        let composite = layer.composite;
+        if layer.shadow.glow && layer.shadow.is_visible() {
+            self.mark_unrecordable("a glow shadow".to_string());
+        } else if !matches!(composite, CompositeOperation::SourceOver | CompositeOperation::Lighter) && composite.blend_mode() == 0 {
+            self.mark_unrecordable(format!("the \"{}\" composite operation", composite.name()));
+        }
+    }
+
+    pub(crate) fn mark_unrecordable(&mut self, what: String) {
+        self.unrecordable.get_or_insert(what);
+    }
+
+    // A distance function primitive as its outline: the border is the band between the
+    // outline and the outline inset by the border width
+    pub(crate) fn primitive(&mut self, shape: &PrimitiveShape, layer: &Layer) {
+        let (sin, cos) = shape.rotation.sin_cos();
+        let placement = [cos, sin, -sin, cos, shape.center.0, shape.center.1];
+        let Some(outline) = primitive_outline(shape, 0.0) else {
+            return;
+        };
+        let inset = (shape.border_width > 0.0).then(|| primitive_outline(shape, shape.border_width)).flatten();
+
+        if shape.border_width > 0.0 {
+            let mut border = outline.clone();
+            if let Some(inset) = &inset {
+                border.add_path(inset);
+            }
+            let paint = RecordedPaint::Color(shape.border);
+            self.push(border.transformed(placement), RecordedShape::Fill(FillRule::EvenOdd), paint, 1.0, layer);
+        }
+
+        let fill = if shape.border_width > 0.0 { inset } else { Some(outline) };
+        if let Some(fill) = fill {
+            let paint = RecordedPaint::Color(shape.fill);
+            self.push(fill.transformed(placement), RecordedShape::Fill(FillRule::EvenOdd), paint, 1.0, layer);
+        }
+    }
+
+    // Serialize as an SVG document of the given size in CSS pixels, world coordinates mapped
+    // through the view transform of the camera
+    pub(crate) fn to_svg(&self, width: u32, height: u32, view: Transform) -> String {
+        let mut writer = SvgWriter::default();
+        let mut body = String::new();
+        if let Some(color) = self.background {
+            let _ = writeln!(body, r#"<rect width="100%" height="100%" {}/>"#, color_attributes("fill", color, 1.0));
+        }
+
+        let _ = writeln!(body, r#"<g transform="{}">"#, matrix(view));
+        for command in self.scene.iter().chain(&self.commands) {
+            writer.command(command, &mut body);
+        }
+        body.push_str("</g>\n");
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        if !writer.defs.is_empty() {
+            let _ = write!(svg, "<defs>\n{}</defs>\n", writer.defs);
+        }
+        svg.push_str(&body);
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+// Definitions shared by the elements of a document: clip paths and images by identity,
+// gradients and patterns
+#[derive(Default)]
+struct SvgWriter {
+    defs: String,
+    clips: HashMap<*const RecordedClip, usize>,
+    images: HashMap<*const u8, usize>,
+    gradient_count: usize,
+    pattern_count: usize,
+}
+
+impl SvgWriter {
+    fn command(&mut self, command: &Command, out: &mut String) {
+        let element = match &command.content {
+            Content::Shape { path, shape, paint } => self.shape(path, *shape, paint, command.opacity),
+            Content::Image { image, source, transform } => self.image(image, *source, *transform, command.opacity),
+        };
+        if element.is_empty() {
+            return;
+        }
+
+        let layer = &command.layer;
+        let mut wrapper = String::new();
+        if let Some(clip) = &layer.clip {
+            let id = self.clip(clip);
+            let _ = write!(wrapper, r#" clip-path="url(#clip{})""#, id);
+        }
+        let style = layer_style(layer);
+        if !style.is_empty() {
+            let _ = write!(wrapper, r#" style="{}""#, style);
+        }
+
+        if wrapper.is_empty() {
+            let _ = writeln!(out, "{}", element);
+        } else {
+            let _ = writeln!(out, "<g{}>{}</g>", wrapper, element);
+        }
+    }
+
+    fn shape(&mut self, path: &Path2D, shape: RecordedShape, paint: &RecordedPaint, opacity: f32) -> String {
+        let attribute = match shape {
+            RecordedShape::Fill(_) => "fill",
+            RecordedShape::Stroke(_) => "stroke",
+        };
+        let url = |id: String| format!(r#"{}="url(#{})"{}"#, attribute, id, opacity_attribute(attribute, opacity));
+        let paint = match paint {
+            RecordedPaint::Color(color) => color_attributes(attribute, *color, opacity),
+            RecordedPaint::Gradient { kind: GradientKind::Conic { start_angle, x, y }, stops, transform, .. } => {
+                let Some(area) = painted_bounds(path, shape) else {
+                    return String::new();
+                };
+                url(self.conic(*start_angle, (*x, *y), stops, *transform, area))
+            }
+            RecordedPaint::Gradient { kind, stops, spread, transform } => url(self.gradient(*kind, stops, *spread, *transform)),
+            RecordedPaint::Pattern { image, repetition } => url(self.pattern(image, *repetition)),
+        };
+        let geometry = match shape {
+            RecordedShape::Fill(rule) => {
+                let rule = if rule == FillRule::EvenOdd { r#" fill-rule="evenodd""# } else { "" };
+                format!("{}{}", paint, rule)
+            }
+            RecordedShape::Stroke(stroke) => {
+                let join = match stroke.join {
+                    LineJoin::Miter => String::new(),
+                    LineJoin::Round => r#" stroke-linejoin="round""#.to_string(),
+                    LineJoin::Bevel => r#" stroke-linejoin="bevel""#.to_string(),
+                };
+                let cap = match stroke.cap {
+                    LineCap::Butt => "",
+                    LineCap::Round => r#" stroke-linecap="round""#,
+                    LineCap::Square => r#" stroke-linecap="square""#,
+                };
+                format!(
+                    r#"fill="none" {} stroke-width="{}" stroke-miterlimit="{}"{}{}"#,
+                    paint,
+                    format_number(stroke.width),
+                    format_number(stroke.miter_limit),
+                    join,
+                    cap
+                )
+            }
+        };
+        format!(r#"<path d="{}" {}/>"#, path.svg_data(), geometry)
+    }
+
+    // An image element placed by its transform, nested in a viewport that crops it when only
+    // part of the image is drawn
+    fn image(&mut self, image: &RecordedImage, source: [f32; 4], transform: Transform, opacity: f32) -> String {
+        let id = self.image_id(image);
+        let opacity = if opacity >= 1.0 { String::new() } else { format!(r#" opacity="{}""#, format_number(opacity)) };
+        let n = format_number;
+        let [x, y, width, height] = source;
+        if source == [0.0, 0.0, image.width as f32, image.height as f32] {
+            return format!(r##"<use href="#image{}" transform="{}"{}/>"##, id, matrix(transform), opacity);
+        }
+        format!(
+            r##"<g transform="{}"{}><svg width="{}" height="{}" viewBox="{} {} {} {}" preserveAspectRatio="none"><use href="#image{}"/></svg></g>"##,
+            matrix(transform),
+            opacity,
+            n(width),
+            n(height),
+            n(x),
+            n(y),
+            n(width),
+            n(height),
+            id
+        )
+    }
+
+    // Id of an image element, written the first time the image is used
+    fn image_id(&mut self, image: &RecordedImage) -> usize {
+        let key = Rc::as_ptr(&image.href) as *const u8;
+        if let Some(id) = self.images.get(&key) {
+            return *id;
+        }
+
+        let id = self.images.len();
+        self.images.insert(key, id);
+        let _ = writeln!(
+            self.defs,
+            r#"<image id="image{}" width="{}" height="{}" preserveAspectRatio="none" href="{}"/>"#,
+            id,
+            image.width,
+            image.height,
+            escape_attribute(&image.href)
+        );
+        id
+    }
+
+    // Write a pattern tiling an image from the origin and return its id
+    fn pattern(&mut self, image: &RecordedImage, repetition: Repetition) -> String {
+        let image_id = self.image_id(image);
+        let id = format!("pattern{}", self.pattern_count);
+        self.pattern_count += 1;
+        let tile = |repeats: bool, size: u32| format_number(if repeats { size as f32 } else { UNREPEATED_TILE });
+        let _ = writeln!(
+            self.defs,
+            r##"<pattern id="{}" patternUnits="userSpaceOnUse" width="{}" height="{}"><use href="#image{}"/></pattern>"##,
+            id,
+            tile(repetition.repeats_x(), image.width),
+            tile(repetition.repeats_y(), image.height),
+            image_id
+        );
+        id
+    }
+
+    // SVG has no conic gradients, they are written as a pattern of thin wedges around the
+    // center that covers the painted area. Returns the id of the pattern
+    fn conic(&mut self, start_angle: f32, center: (f32, f32), stops: &[ColorStop], transform: Transform, area: [f32; 4]) -> String {
+        let id = format!("pattern{}", self.pattern_count);
+        self.pattern_count += 1;
+        let n = format_number;
+
+        // Distance from the center to the farthest corner of the area in gradient space, with
+        // room for the wedges being polygons rather than arcs
+        let inverse = invert(transform).unwrap_or(IDENTITY);
+        let radius = [(area[0], area[1]), (area[2], area[1]), (area[0], area[3]), (area[2], area[3])]
+            .into_iter()
+            .map(|corner| {
+                let (x, y) = apply(inverse, corner);
+                (x - center.0).hypot(y - center.1)
+            })
+            .fold(0.0, f32::max)
+            * 1.01
+            + 1.0;
+        let transform = if transform == IDENTITY {
+            String::new()
+        } else {
+            format!(r#" patternTransform="{}""#, matrix(transform))
+        };
+        let _ = writeln!(
+            self.defs,
+            r#"<pattern id="{}" patternUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}"{}><g shape-rendering="crispEdges">"#,
+            id,
+            n(center.0 - radius),
+            n(center.1 - radius),
+            n(radius * 2.0),
+            n(radius * 2.0),
+            transform
+        );
+
+        // Neighboring wedges of the same color are merged into one polygon
+        let step = std::f32::consts::TAU / CONIC_WEDGES as f32;
+        let point = |wedge: usize| {
+            let (sin, cos) = (start_angle + wedge as f32 * step).sin_cos();
+            (center.0 + radius * cos, center.1 + radius * sin)
+        };
+        let color = |wedge: usize| {
+            let color = gradient_color(stops, (wedge as f32 + 0.5) / CONIC_WEDGES as f32);
+            color_attributes("fill", color, 1.0)
+        };
+        let mut first = 0;
+        while first < CONIC_WEDGES {
+            let fill = color(first);
+            let mut end = first + 1;
+            while end < CONIC_WEDGES && color(end) == fill {
+                end += 1;
+            }
+            let mut wedge = Path2D::new();
+            wedge.move_to(center.0, center.1);
+            for corner in first..=end {
+                let (x, y) = point(corner);
+                wedge.line_to(x, y);
+            }
+            wedge.close_path();
+            let _ = writeln!(self.defs, r#"<path d="{}" {}/>"#, wedge.svg_data(), fill);
+            first = end;
+        }
+        let _ = writeln!(self.defs, "</g></pattern>");
+        id
+    }
+
+    // Id of a clip path element, written with the clips it intersects the first time
+    fn clip(&mut self, clip: &Rc<RecordedClip>) -> usize {
+        if let Some(id) = self.clips.get(&Rc::as_ptr(clip)) {
+            return *id;
+        }
+
+        let parent = clip.parent.as_ref().map(|parent| self.clip(parent));
+        let id = self.clips.len();
+        self.clips.insert(Rc::as_ptr(clip), id);
+        let parent = parent.map_or(String::new(), |parent| format!(r#" clip-path="url(#clip{})""#, parent));
+        let rule = if clip.rule == FillRule::EvenOdd { r#" clip-rule="evenodd""# } else { "" };
+        let _ = writeln!(
+            self.defs,
+            r#"<clipPath id="clip{}" clipPathUnits="userSpaceOnUse"{}><path d="{}"{}/></clipPath>"#,
+            id,
+            parent,
+            clip.path.svg_data(),
+            rule
+        );
+        id
+    }
+
+    // Write a gradient element in user space and return its id
+    fn gradient(&mut self, kind: GradientKind, stops: &[ColorStop], spread: SpreadMode, transform: Transform) -> String {
+        let id = format!("gradient{}", self.gradient_count);
+        self.gradient_count += 1;
+        let n = format_number;
+
+        let (element, geometry) = match kind {
+            GradientKind::Linear { x0, y0, x1, y1 } => {
+                ("linearGradient", format!(r#"x1="{}" y1="{}" x2="{}" y2="{}""#, n(x0), n(y0), n(x1), n(y1)))
+            }
+            GradientKind::Radial { x0, y0, r0, x1, y1, r1 } => (
+                "radialGradient",
+                format!(
+                    r#"fx="{}" fy="{}" fr="{}" cx="{}" cy="{}" r="{}""#,
+                    n(x0),
+                    n(y0),
+                    n(r0),
+                    n(x1),
+                    n(y1),
+                    n(r1)
+                ),
+            ),
+            GradientKind::Conic { .. } => unreachable!("conic gradients are written as patterns"),
+        };
+        let spread = match spread {
+            SpreadMode::Pad => "",
+            SpreadMode::Repeat => r#" spreadMethod="repeat""#,
+            SpreadMode::Reflect => r#" spreadMethod="reflect""#,
+        };
+        let transform = if transform == IDENTITY {
+            String::new()
+        } else {
+            format!(r#" gradientTransform="{}""#, matrix(transform))
+        };
+
+        let _ = writeln!(self.defs, r#"<{} id="{}" gradientUnits="userSpaceOnUse" {}{}{}>"#, element, id, geometry, spread, transform);
+        for stop in stops {
+            let _ = writeln!(self.defs, r#"<stop offset="{}" {}/>"#, n(stop.offset), color_attributes("stop-color", stop.color, 1.0));
+        }
+        let _ = writeln!(self.defs, "</{}>", element);
+        id
+    }
+}
+
+// CSS of the composite operation, filter and shadow of a layer. Recordings with the
+// Porter-Duff operations and glow shadows SVG lacks are not exported, see check_layer
+fn layer_style(layer: &Layer) -> String {
+    let mut style = String::new();
+    let blend = match layer.composite {
+        CompositeOperation::Lighter => Some("plus-lighter"),
+        operation if operation.blend_mode() != 0 => Some(operation.name()),
+        _ => None,
+    };
+    if let Some(blend) = blend {
+        let _ = write!(style, "mix-blend-mode:{};", blend);
+    }
+
+    // Canvas shadows are cast by the filtered shape, like a trailing drop-shadow()
+    let mut filters = Vec::new();
+    if !layer.filter.is_none() {
+        filters.push(layer.filter.css().to_string());
+    }
+    let shadow = layer.shadow;
+    if shadow.is_visible() && !shadow.glow {
+        let [r, g, b, a] = shadow.color;
+        filters.push(format!(
+            "drop-shadow({}px {}px {}px rgba({}, {}, {}, {}))",
+            format_number(shadow.offset_x),
+            format_number(shadow.offset_y),
+            format_number(shadow.blur),
+            channel(r),
+            channel(g),
+            channel(b),
+            format_number(a)
+        ));
+    }
+    if !filters.is_empty() {
+        let _ = write!(style, "filter:{};", filters.join(" ").replace('"', "'"));
+    }
+    style
+}
+
+// A paint attribute with a hex color, and its opacity when not opaque
+fn color_attributes(attribute: &str, color: [f32; 4], opacity: f32) -> String {
+    let hex = format!("#{:02x}{:02x}{:02x}", channel(color[0]), channel(color[1]), channel(color[2]));
+    format!(r#"{}="{}"{}"#, attribute, hex, opacity_attribute(attribute, color[3] * opacity))
+}
+
+fn opacity_attribute(attribute: &str, opacity: f32) -> String {
+    if opacity >= 1.0 {
+        String::new()
+    } else {
+        format!(r#" {}-opacity="{}""#, attribute.trim_end_matches("-color"), format_number(opacity.max(0.0)))
+    }
+}
+
+fn channel(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn matrix(m: Transform) -> String {
+    let values: Vec<String> = m.iter().map(|value| format_number(*value)).collect();
+    format!("matrix({})", values.join(" "))
+}
+
+// Color of the stops at t in [0, 1], interpolated like the shape shader does
+fn gradient_color(stops: &[ColorStop], t: f32) -> [f32; 4] {
+    let Some(first) = stops.first() else {
+        return [0.0; 4];
+    };
+    let mut color = first.color;
+    for pair in stops.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if t >= from.offset {
+            color = if to.offset > from.offset {
+                let f = ((t - from.offset) / (to.offset - from.offset)).clamp(0.0, 1.0);
+                std::array::from_fn(|i| from.color[i] + (to.color[i] - from.color[i]) * f)
+            } else {
+                to.color
+            };
+        }
+    }
+    color
+}
+
+// World-space box a shape paints into, with a margin for the curves flattened away
+fn painted_bounds(path: &Path2D, shape: RecordedShape) -> Option<[f32; 4]> {
+    let [x0, y0, x1, y1] = bounds(&path.flatten(1.0))?;
+    let margin = match shape {
+        RecordedShape::Fill(_) => 1.0,
+        RecordedShape::Stroke(stroke) => 1.0 + stroke.width * 0.5 * stroke.miter_limit.max(1.0),
+    };
+    Some([x0 - margin, y0 - margin, x1 + margin, y1 + margin])
+}
+
+// Escape text for a double-quoted attribute value
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+}
+
+// Outline of a primitive centered on the origin before rotation, shrunk by `inset` like
+// the distance function. None when nothing is left
+fn primitive_outline(shape: &PrimitiveShape, inset: f32) -> Option<Path2D> {
+    let (half_width, half_height) = (shape.half_size.0.abs() - inset, shape.half_size.1.abs() - inset);
+    let radius = shape.half_size.0.abs().min(shape.half_size.1.abs());
+    let mut path = Path2D::new();
+    let circle = |path: &mut Path2D, radius: f32| {
+        path.move_to(radius, 0.0);
+        let _ = path.arc(0.0, 0.0, radius, 0.0, std::f32::consts::TAU, None);
+        path.close_path();
+    };
+
+    match shape.kind {
+        Primitive::Circle => {
+            if radius - inset <= 0.0 {
+                return None;
+            }
+            circle(&mut path, radius - inset);
+        }
+        Primitive::Ellipse => {
+            if half_width <= 0.0 || half_height <= 0.0 {
+                return None;
+            }
+            circle(&mut path, 1.0);
+            path = path.transformed([half_width, 0.0, 0.0, half_height, 0.0, 0.0]);
+        }
+        Primitive::RoundedRect => {
+            if half_width <= 0.0 || half_height <= 0.0 {
+                return None;
+            }
+            let radii = shape.radii.map(|r| (r - inset).clamp(0.0, half_width.min(half_height)));
+            let [top_left, top_right, bottom_right, bottom_left] = radii;
+            let quarter = std::f32::consts::FRAC_PI_2;
+            let corners = [
+                (half_width - top_right, -half_height + top_right, top_right, -quarter),
+                (half_width - bottom_right, half_height - bottom_right, bottom_right, 0.0),
+                (-half_width + bottom_left, half_height - bottom_left, bottom_left, quarter),
+                (-half_width + top_left, -half_height + top_left, top_left, 2.0 * quarter),
+            ];
+            for (x, y, r, start) in corners {
+                let _ = path.arc(x, y, r, start, start + quarter, None);
+            }
+            path.close_path();
+        }
+        Primitive::Ring => {
+            let (outer, inner) = (radius - inset, shape.parameter.clamp(0.0, radius) + inset);
+            if outer <= inner {
+                return None;
+            }
+            circle(&mut path, outer);
+            if inner > 0.0 {
+                circle(&mut path, inner);
+            }
+        }
+        Primitive::Capsule => {
+            let r = radius - inset;
+            if r <= 0.0 {
+                return None;
+            }
+            // The segment runs along the long axis of the box
+            let (ax, ay) = (shape.half_size.0.abs() - radius, shape.half_size.1.abs() - radius);
+            let half_turn = std::f32::consts::PI;
+            let start = if ax >= ay { -half_turn * 0.5 } else { 0.0 };
+            let _ = path.arc(ax, ay, r, start, start + half_turn, None);
+            let _ = path.arc(-ax, -ay, r, start + half_turn, start + 2.0 * half_turn, None);
+            path.close_path();
+        }
+        Primitive::Polygon => {
+            let sides = shape.parameter.round().max(3.0);
+            let angle = std::f32::consts::PI / sides;
+            let rounding = shape.radii[0].clamp(0.0, radius);
+            // Corners are arcs around a core polygon, an inset past the rounding shrinks the core
+            let core = radius - rounding - (inset - rounding).max(0.0) / angle.cos();
+            let rounding = (rounding - inset).max(0.0);
+            if core <= 0.0 && rounding <= 0.0 {
+                return None;
+            }
+
+            let sides = sides as usize;
+            for k in 0..sides {
+                // Vertex k lies (2k + 1) half-angles clockwise from -y
+                let vertex = (2 * k + 1) as f32 * angle;
+                let (x, y) = (core * vertex.sin(), -core * vertex.cos());
+                let edge_before = 2.0 * k as f32 * angle - std::f32::consts::FRAC_PI_2;
+                let edge_after = edge_before + 2.0 * angle;
+                let _ = path.arc(x, y, rounding, edge_before, edge_after, None);
+            }
+            path.close_path();
+        }
+    }
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient::Gradient;
+    use crate::svg::SvgDocument;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Path2D {
+        let mut path = Path2D::new();
+        path.rect(x, y, width, height);
+        path
+    }
+
+    // Area under the even-odd rule for outlines whose contours nest at most one level deep
+    fn outline_area(path: &Path2D) -> f32 {
+        let mut areas: Vec<f32> = path
+            .flatten(0.01)
+            .iter()
+            .map(|contour| {
+                let points = &contour.points;
+                let twice: f32 = (0..points.len())
+                    .map(|i| {
+                        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                        a.0 * b.1 - b.0 * a.1
+                    })
+                    .sum();
+                (twice * 0.5).abs()
+            })
+            .collect();
+        areas.sort_by(|a, b| b.total_cmp(a));
+        areas[0] - areas[1..].iter().sum::<f32>()
+    }
+
+    #[test]
+    fn recordings_read_back_as_svg() {
+        let mut recording = Recording::default();
+        recording.fill(rect(0.0, 0.0, 10.0, 10.0), FillRule::NonZero, &Paint::Color([1.0, 0.0, 0.0, 0.5]), &Layer::default());
+        recording.clear();
+        recording.set_background([0.0, 0.0, 0.0, 1.0]);
+        recording.fill(rect(1.0, 2.0, 3.0, 4.0), FillRule::EvenOdd, &Paint::Color([0.0, 0.0, 1.0, 1.0]), &Layer::default());
+        let mut gradient = Gradient::linear(0.0, 0.0, 10.0, 0.0);
+        gradient.replace_stops(vec![
+            ColorStop { offset: 0.0, color: [1.0, 1.0, 1.0, 1.0] },
+            ColorStop { offset: 1.0, color: [0.0, 0.0, 0.0, 1.0] },
+        ]);
+        recording.stroke(rect(0.0, 0.0, 10.0, 10.0), 2.0, &Paint::Gradient(gradient), &Layer::default());
+        // Nothing to see, nothing recorded
+        recording.push(Path2D::new(), RecordedShape::Fill(FillRule::NonZero), RecordedPaint::Color([1.0; 4]), 1.0, &Layer::default());
+        assert_eq!(recording.len(), 2);
+
+        let svg = recording.to_svg(40, 30, [2.0, 0.0, 0.0, 2.0, 5.0, 0.0]);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="30" viewBox="0 0 40 30">"#));
+        assert!(svg.contains(r##"<rect width="100%" height="100%" fill="#000000"/>"##));
+        assert!(svg.contains(r#"<g transform="matrix(2 0 0 2 5 0)">"#));
+        assert!(svg.contains(r##"fill="#0000ff" fill-rule="evenodd""##));
+        assert!(svg.contains(r#"<linearGradient id="gradient0" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="10" y2="0">"#));
+        assert!(svg.contains(r#"fill="none" stroke="url(#gradient0)" stroke-width="2""#));
+        assert!(!svg.contains("#ff0000"));
+
+        // The importer reads the background and both shapes back
+        let document = SvgDocument::parse(&svg).unwrap();
+        assert_eq!((document.width(), document.height()), (40.0, 30.0));
+        assert_eq!(document.shape_count(), 3);
+    }
+
+    #[test]
+    fn clips_are_defined_once_and_chained() {
+        let outer = Rc::new(RecordedClip::new(rect(0.0, 0.0, 100.0, 100.0), FillRule::NonZero, None));
+        let inner = Rc::new(RecordedClip::new(rect(10.0, 10.0, 20.0, 20.0), FillRule::EvenOdd, Some(outer.clone())));
+        let mut recording = Recording::default();
+        let paint = Paint::Color([0.0, 0.0, 0.0, 1.0]);
+        for clip in [&inner, &inner, &outer] {
+            let clipped = Layer { clip: Some(clip.clone()), ..Layer::default() };
+            recording.fill(rect(0.0, 0.0, 50.0, 50.0), FillRule::NonZero, &paint, &clipped);
+        }
+
+        let svg = recording.to_svg(100, 100, IDENTITY);
+        assert_eq!(svg.matches("<clipPath ").count(), 2);
+        assert!(svg.contains(r#"<clipPath id="clip1" clipPathUnits="userSpaceOnUse" clip-path="url(#clip0)">"#));
+        assert!(svg.contains(r#"clip-rule="evenodd""#));
+        assert_eq!(svg.matches(r#"<g clip-path="url(#clip1)">"#).count(), 2);
+        assert_eq!(svg.matches(r#"<g clip-path="url(#clip0)">"#).count(), 1);
+    }
+
+    #[test]
+    fn layers_become_css_blending_and_filters() {
+        let shadow = Shadow {
+            color: [0.0, 0.0, 0.0, 0.5],
+            blur: 4.0,
+            offset_x: 1.0,
+            offset_y: 2.0,
+            glow: false,
+        };
+        let blended = Layer {
+            composite: CompositeOperation::Multiply,
+            filter: Rc::new(Filter::parse("blur(2px)").unwrap()),
+            shadow,
+            ..Layer::default()
+        };
+        assert_eq!(
+            layer_style(&blended),
+            "mix-blend-mode:multiply;filter:blur(2px) drop-shadow(1px 2px 4px rgba(0, 0, 0, 0.5));"
+        );
+
+        // Glows and Porter-Duff operations have no SVG equivalent
+        let glow = Layer {
+            composite: CompositeOperation::DestinationOut,
+            shadow: Shadow { glow: true, ..shadow },
+            ..Layer::default()
+        };
+        assert_eq!(layer_style(&glow), "");
+        let lighter = Layer { composite: CompositeOperation::Lighter, ..Layer::default() };
+        assert_eq!(layer_style(&lighter), "mix-blend-mode:plus-lighter;");
+    }
+
+    #[test]
+    fn primitive_outlines_match_their_distance_functions() {
+        let shape = |kind: Primitive, half_size: (f32, f32)| PrimitiveShape {
+            kind,
+            center: (0.0, 0.0),
+            half_size,
+            rotation: 0.0,
+            radii: [5.0; 4],
+            border_width: 0.0,
+            parameter: 4.0,
+            fill: [1.0; 4],
+            border: [0.0; 4],
+        };
+        let pi = std::f32::consts::PI;
+        for (shape, inset, expected) in [
+            (shape(Primitive::Circle, (10.0, 20.0)), 0.0, pi * 100.0),
+            (shape(Primitive::Circle, (10.0, 20.0)), 2.0, pi * 64.0),
+            (shape(Primitive::Ellipse, (10.0, 20.0)), 0.0, pi * 200.0),
+            (shape(Primitive::RoundedRect, (10.0, 20.0)), 0.0, 800.0 - (4.0 - pi) * 25.0),
+            (shape(Primitive::Ring, (10.0, 10.0)), 0.0, pi * (100.0 - 16.0)),
+            (shape(Primitive::Ring, (10.0, 10.0)), 1.0, pi * (81.0 - 25.0)),
+            (shape(Primitive::Capsule, (10.0, 20.0)), 0.0, 20.0 * 20.0 + pi * 100.0),
+            (shape(Primitive::Capsule, (20.0, 10.0)), 0.0, 20.0 * 20.0 + pi * 100.0),
+        ] {
+            let area = outline_area(&primitive_outline(&shape, inset).unwrap());
+            assert!((area - expected).abs() < expected * 0.005, "{:?} inset {}: {} for {}", shape.kind, inset, area, expected);
+        }
+
+        // A square with sharp corners, circumradius 10
+        let square = PrimitiveShape { radii: [0.0; 4], ..shape(Primitive::Polygon, (10.0, 10.0)) };
+        assert!((outline_area(&primitive_outline(&square, 0.0).unwrap()) - 200.0).abs() < 0.5);
+        assert!(primitive_outline(&shape(Primitive::Circle, (10.0, 20.0)), 10.0).is_none());
+    }
+
+    fn square() -> Path2D {
+        let mut path = Path2D::new();
+        path.rect(0.0, 0.0, 10.0, 10.0);
+        path
+    }
+
+    fn image() -> RecordedImage {
+        RecordedImage {
+            href: "sprite.png?a=1&b=2".into(),
+            width: 8,
+            height: 4,
+        }
+    }
+
+    #[test]
+    fn images_are_embedded_once_and_cropped_to_their_source() {
+        let mut recording = Recording::default();
+        let image = image();
+        let layer = Layer::default();
+        recording.image(image.clone(), [0.0, 0.0, 8.0, 4.0], [2.0, 0.0, 0.0, 2.0, 5.0, 5.0], 1.0, &layer);
+        recording.image(image, [2.0, 0.0, 4.0, 4.0], IDENTITY, 0.5, &layer);
+
+        let svg = recording.to_svg(100, 100, IDENTITY);
+        assert_eq!(svg.matches("<image ").count(), 1);
+        assert!(svg.contains(r#"href="sprite.png?a=1&amp;b=2""#));
+        assert!(svg.contains(r##"<use href="#image0" transform="matrix(2 0 0 2 5 5)"/>"##));
+        assert!(svg.contains(r#"opacity="0.5"><svg width="4" height="4" viewBox="2 0 4 4""#));
+        assert_eq!(recording.len(), 2);
+    }
+
+    #[test]
+    fn patterns_tile_only_along_repeating_axes() {
+        let mut recording = Recording::default();
+        let image = image();
+        for repetition in [Repetition::Repeat, Repetition::RepeatX, Repetition::NoRepeat] {
+            let paint = RecordedPaint::Pattern { image: image.clone(), repetition };
+            recording.push(square(), RecordedShape::Fill(FillRule::NonZero), paint, 1.0, &Layer::default());
+        }
+
+        let svg = recording.to_svg(100, 100, IDENTITY);
+        assert_eq!(svg.matches("<image ").count(), 1);
+        assert!(svg.contains(r#"<pattern id="pattern0" patternUnits="userSpaceOnUse" width="8" height="4">"#));
+        assert!(svg.contains(r#"<pattern id="pattern1" patternUnits="userSpaceOnUse" width="8" height="1000000">"#));
+        assert!(svg.contains(r#"<pattern id="pattern2" patternUnits="userSpaceOnUse" width="1000000" height="1000000">"#));
+        assert!(svg.contains(r#"fill="url(#pattern2)""#));
+    }
+
+    #[test]
+    fn conic_gradients_become_wedges_in_the_colors_of_the_stops() {
+        let mut recording = Recording::default();
+        let paint = RecordedPaint::Gradient {
+            kind: GradientKind::Conic { start_angle: 0.0, x: 5.0, y: 5.0 },
+            stops: vec![
+                ColorStop { offset: 0.0, color: [1.0, 0.0, 0.0, 1.0] },
+                ColorStop { offset: 0.5, color: [1.0, 0.0, 0.0, 1.0] },
+                ColorStop { offset: 0.5, color: [0.0, 0.0, 1.0, 1.0] },
+                ColorStop { offset: 1.0, color: [0.0, 0.0, 1.0, 1.0] },
+            ],
+            spread: SpreadMode::Pad,
+            transform: IDENTITY,
+        };
+        recording.push(square(), RecordedShape::Fill(FillRule::NonZero), paint, 1.0, &Layer::default());
+
+        // Two flat halves merge into one wedge each
+        let svg = recording.to_svg(100, 100, IDENTITY);
+        assert!(svg.contains(r#"fill="url(#pattern0)""#));
+        assert_eq!(svg.matches(r##"fill="#ff0000""##).count(), 1);
+        assert_eq!(svg.matches(r##"fill="#0000ff""##).count(), 1);
+        assert!(!svg.contains("gradient0"));
+    }
+
+    #[test]
+    fn gradient_colors_match_the_shader() {
+        let stops = [
+            ColorStop { offset: 0.25, color: [0.0, 0.0, 0.0, 1.0] },
+            ColorStop { offset: 0.75, color: [1.0, 0.5, 0.0, 0.0] },
+        ];
+        assert_eq!(gradient_color(&stops, 0.0), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(gradient_color(&stops, 0.5), [0.5, 0.25, 0.0, 0.5]);
+        assert_eq!(gradient_color(&stops, 1.0), [1.0, 0.5, 0.0, 0.0]);
+        assert_eq!(gradient_color(&[], 0.5), [0.0; 4]);
+    }
+
+    #[test]
+    fn content_svg_cannot_express_blocks_the_export_until_cleared() {
+        let mut recording = Recording::default();
+        let paint = RecordedPaint::Color([1.0; 4]);
+        let blend = Layer { composite: CompositeOperation::Multiply, ..Layer::default() };
+        recording.push(square(), RecordedShape::Fill(FillRule::NonZero), paint.clone(), 1.0, &blend);
+        assert_eq!(recording.unrecordable(), None);
+
+        let erase = Layer { composite: CompositeOperation::DestinationOut, ..Layer::default() };
+        recording.push(square(), RecordedShape::Fill(FillRule::NonZero), paint.clone(), 1.0, &erase);
+        assert_eq!(recording.unrecordable(), Some(r#"the "destination-out" composite operation"#));
+
+        recording.clear();
+        assert_eq!(recording.unrecordable(), None);
+        let shadow = Shadow { color: [1.0; 4], blur: 4.0, glow: true, ..Shadow::default() };
+        let glow = Layer { shadow, ..Layer::default() };
+        recording.image(image(), [0.0, 0.0, 8.0, 4.0], IDENTITY, 1.0, &glow);
+        assert_eq!(recording.unrecordable(), Some("a glow shadow"));
+    }
+}
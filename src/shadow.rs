@@ -0,0 +1,418 @@
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    WebGl2RenderingContext, WebGlFramebuffer, WebGlProgram, WebGlUniformLocation, WebGlVertexArrayObject
+};
+
+use crate::clip::{Clip, Clipper, CLIP_BIT};
+use crate::composite::{CompositeOperation, Compositor};
+use crate::pipeline::ColorPipeline;
+use crate::postprocess::{create_pass_program, RenderTarget};
+use crate::{compile_shader, link_program};
+
+// Texture unit of the unblurred coverage read by the outer glow
+const COVERAGE_TEXTURE_UNIT: u32 = 3;
+
+// Longest blur kernel in taps on each side, wider blurs space their taps further apart
+const MAX_BLUR_TAPS: f32 = 64.0;
+
+// Separable gaussian over the alpha of the coverage, uDirection is one texel along the pass
+const SHADOW_BLUR_FRAGMENT_SRC: &str = r#"
+uniform vec2 uDirection;
+uniform float uSigma;
+
+void main() {
+    if (uSigma < 0.01) {
+        outColor = texture(uTexture, vUv);
+        return;
+    }
+
+    // Taps out to three standard deviations, at most 64 of them on each side
+    float extent = ceil(uSigma * 3.0);
+    float stride = max(1.0, extent / 64.0);
+    float sum = texture(uTexture, vUv).a;
+    float total = 1.0;
+    for (int i = 1; i <= 64; i++) {
+        float x = float(i) * stride;
+        if (x > extent) {
+            break;
+        }
+        float weight = exp(-0.5 * x * x / (uSigma * uSigma));
+        sum += (texture(uTexture, vUv + uDirection * x).a + texture(uTexture, vUv - uDirection * x).a) * weight;
+        total += 2.0 * weight;
+    }
+    outColor = vec4(0.0, 0.0, 0.0, sum / total);
+}
+"#;
+
+// Quad over a box of the framebuffer, uRect is [x0, y0, x1, y1] in UV space
+pub(crate) const AREA_VERTEX_SRC: &str = r#"#version 300 es
+uniform vec4 uRect;
+
+out vec2 vUv;
+
+void main() {
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+    vUv = mix(uRect.xy, uRect.zw, corner);
+    gl_Position = vec4(vUv * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+// Tint the blurred coverage with the shadow color. The glow leaves out the shape itself
+const SHADOW_FRAGMENT_SRC: &str = r#"#version 300 es
+precision highp float;
+
+in vec2 vUv;
+
+uniform sampler2D uShadow;
+uniform sampler2D uCoverage;
+// Shadow offset in UV space
+uniform vec2 uOffset;
+// Straight-alpha color in the space of the color pipeline
+uniform vec4 uColor;
+uniform bool uGlow;
+
+out vec4 outColor;
+
+void main() {
+    float alpha = texture(uShadow, vUv - uOffset).a;
+    if (uGlow) {
+        alpha *= 1.0 - texture(uCoverage, vUv).a;
+    }
+    outColor = vec4(uColor.rgb * uColor.a, uColor.a) * alpha;
+}
+"#;
+
+// Shadow properties of the drawing state, like shadowColor, shadowBlur, shadowOffsetX and
+// shadowOffsetY of CanvasRenderingContext2D. Blur and offsets are CSS pixels, unaffected by
+// the camera
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Shadow {
+    // Straight-alpha sRGB
+    pub(crate) color: [f32; 4],
+    pub(crate) blur: f32,
+    pub(crate) offset_x: f32,
+    pub(crate) offset_y: f32,
+    // Outer glow: added to the backdrop with "lighter" and left out of the shape itself
+    pub(crate) glow: bool,
+}
+
+impl Default for Shadow {
+    fn default() -> Shadow {
+        Shadow {
+            color: [0.0, 0.0, 0.0, 0.0],
+            blur: 0.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            glow: false,
+        }
+    }
+}
+
+impl Shadow {
+    // Shadows are only drawn when they would show, as in CanvasRenderingContext2D
+    pub(crate) fn is_visible(&self) -> bool {
+        if self.color[3] <= 0.0 {
+            return false;
+        }
+        if self.glow {
+            return self.blur > 0.0;
+        }
+        self.blur > 0.0 || self.offset_x != 0.0 || self.offset_y != 0.0
+    }
+}
+
+// Draws shadows of queued shapes: the shape coverage is rendered into an offscreen target,
+// blurred horizontally then vertically, and composited into the scene under the shape
+pub(crate) struct ShadowRenderer {
+    vao: WebGlVertexArrayObject,
+    blur_program: WebGlProgram,
+    blur_texture_location: Option<WebGlUniformLocation>,
+    blur_direction_location: Option<WebGlUniformLocation>,
+    blur_sigma_location: Option<WebGlUniformLocation>,
+    program: WebGlProgram,
+    rect_location: Option<WebGlUniformLocation>,
+    shadow_location: Option<WebGlUniformLocation>,
+    coverage_location: Option<WebGlUniformLocation>,
+    offset_location: Option<WebGlUniformLocation>,
+    color_location: Option<WebGlUniformLocation>,
+    glow_location: Option<WebGlUniformLocation>,
+    // Coverage (with a stencil buffer for path fills), horizontal pass and blurred coverage,
+    // created with the first shadow
+    targets: Option<[RenderTarget; 3]>,
+    // Drawing buffer size in device pixels and device pixels per CSS pixel
+    viewport: (u32, u32),
+    pixel_ratio: f32,
+    // Framebuffer the scene was drawn into when the shadow started
+    scene: Option<WebGlFramebuffer>,
+    // Device pixel box [x0, y0, x1, y1] of the blurred coverage
+    area: [i32; 4],
+}
+
+impl ShadowRenderer {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<ShadowRenderer, JsValue> {
+        let blur_program = create_pass_program(gl, SHADOW_BLUR_FRAGMENT_SRC)?;
+
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, AREA_VERTEX_SRC)?;
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, SHADOW_FRAGMENT_SRC)?;
+        let program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let vao = gl.create_vertex_array().ok_or("Failed to create shadow vertex array")?;
+
+        Ok(ShadowRenderer {
+            vao,
+            blur_texture_location: gl.get_uniform_location(&blur_program, "uTexture"),
+            blur_direction_location: gl.get_uniform_location(&blur_program, "uDirection"),
+            blur_sigma_location: gl.get_uniform_location(&blur_program, "uSigma"),
+            blur_program,
+            rect_location: gl.get_uniform_location(&program, "uRect"),
+            shadow_location: gl.get_uniform_location(&program, "uShadow"),
+            coverage_location: gl.get_uniform_location(&program, "uCoverage"),
+            offset_location: gl.get_uniform_location(&program, "uOffset"),
+            color_location: gl.get_uniform_location(&program, "uColor"),
+            glow_location: gl.get_uniform_location(&program, "uGlow"),
+            program,
+            targets: None,
+            viewport: (1, 1),
+            pixel_ratio: 1.0,
+            scene: None,
+            area: [0, 0, 0, 0],
+        })
+    }
+
+    pub(crate) fn set_viewport(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        width: u32,
+        height: u32,
+        pixel_ratio: f32,
+    ) -> Result<(), JsValue> {
+        self.viewport = (width, height);
+        self.pixel_ratio = pixel_ratio;
+        if let Some(targets) = self.targets.as_mut() {
+            for target in targets.iter_mut() {
+                target.resize(gl, width, height)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Redirect drawing into the cleared coverage target for the shape under world-space
+    // `bounds`. The stencil is cleared to the clip bit so path fills work whatever clip the
+    // scene has, the clip is applied when the shadow is composited
+    pub(crate) fn begin(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shadow: &Shadow,
+        bounds: [f32; 4],
+        matrix: &[f32; 16],
+    ) -> Result<(), JsValue> {
+        if self.targets.is_none() {
+            let (width, height) = self.viewport;
+            self.targets = Some([
+                RenderTarget::with_stencil(gl, width, height)?,
+                RenderTarget::new(gl, width, height)?,
+                RenderTarget::new(gl, width, height)?,
+            ]);
+        }
+
+        self.scene = gl
+            .get_parameter(WebGl2RenderingContext::FRAMEBUFFER_BINDING)?
+            .dyn_into::<WebGlFramebuffer>()
+            .ok();
+        let extent = (self.sigma(shadow) * 3.0).ceil() as i32 + 1;
+        let [x0, y0, x1, y1] = device_box(bounds, matrix, self.viewport).map(|v| v as i32);
+        self.area = self.clamp([x0 - extent, y0 - extent, x1 + extent, y1 + extent]);
+
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        gl.clear_stencil(CLIP_BIT);
+        gl.stencil_mask(0xFF);
+        if let Some(targets) = &self.targets {
+            // Blur taps reach past the box, so the whole targets are cleared
+            for target in targets[1..].iter() {
+                target.bind(gl);
+                gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+            }
+            targets[0].bind(gl);
+            gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::STENCIL_BUFFER_BIT);
+        }
+
+        Ok(())
+    }
+
+    // Blur the coverage drawn since begin, then composite the shadow into the scene with the
+    // clip of the shape. Programs and vertex arrays are left unbound
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn finish(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shadow: &Shadow,
+        pipeline: ColorPipeline,
+        clip: &Rc<Clip>,
+        matrix: &[f32; 16],
+        clipper: &mut Clipper,
+        compositor: &mut Compositor,
+    ) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let (width, height) = (self.viewport.0 as f32, self.viewport.1 as f32);
+        let [x0, y0, x1, y1] = self.area;
+
+        // Horizontal then vertical pass, limited to the box around the shape
+        gl.disable(WebGl2RenderingContext::BLEND);
+        gl.disable(WebGl2RenderingContext::STENCIL_TEST);
+        gl.enable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.scissor(x0, y0, (x1 - x0).max(0), (y1 - y0).max(0));
+        gl.use_program(Some(&self.blur_program));
+        gl.bind_vertex_array(Some(&self.vao));
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        gl.uniform1i(self.blur_texture_location.as_ref(), 0);
+        gl.uniform1f(self.blur_sigma_location.as_ref(), self.sigma(shadow));
+        for (source, destination, direction) in [(0, 1, [1.0 / width, 0.0]), (1, 2, [0.0, 1.0 / height])] {
+            targets[destination].bind(gl);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[source].texture()));
+            gl.uniform2f(self.blur_direction_location.as_ref(), direction[0], direction[1]);
+            gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 3);
+        }
+        gl.disable(WebGl2RenderingContext::SCISSOR_TEST);
+        gl.enable(WebGl2RenderingContext::BLEND);
+
+        // Back to the scene, clipped like the shape. Offsets point down the screen in CSS
+        // pixels while the framebuffer origin is at the bottom
+        gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, self.scene.as_ref());
+        gl.viewport(0, 0, self.viewport.0 as i32, self.viewport.1 as i32);
+        clipper.invalidate();
+        clipper.apply(gl, clip, matrix);
+        compositor.apply(gl, if shadow.glow { CompositeOperation::Lighter } else { CompositeOperation::SourceOver });
+
+        let dx = (shadow.offset_x * self.pixel_ratio).round() as i32;
+        let dy = -(shadow.offset_y * self.pixel_ratio).round() as i32;
+        let [sx0, sy0, sx1, sy1] = self.clamp([x0 + dx, y0 + dy, x1 + dx, y1 + dy]);
+        if sx1 > sx0 && sy1 > sy0 {
+            let color = ColorPipeline { premultiplied: false, ..pipeline }.encode(shadow.color);
+            gl.use_program(Some(&self.program));
+            gl.bind_vertex_array(Some(&self.vao));
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0 + COVERAGE_TEXTURE_UNIT);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[0].texture()));
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(targets[2].texture()));
+            gl.uniform1i(self.shadow_location.as_ref(), 0);
+            gl.uniform1i(self.coverage_location.as_ref(), COVERAGE_TEXTURE_UNIT as i32);
+            gl.uniform4f(
+                self.rect_location.as_ref(),
+                sx0 as f32 / width,
+                sy0 as f32 / height,
+                sx1 as f32 / width,
+                sy1 as f32 / height,
+            );
+            gl.uniform2f(self.offset_location.as_ref(), dx as f32 / width, dy as f32 / height);
+            gl.uniform4f(self.color_location.as_ref(), color[0], color[1], color[2], color[3]);
+            gl.uniform1i(self.glow_location.as_ref(), shadow.glow as i32);
+            gl.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0 + COVERAGE_TEXTURE_UNIT);
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        }
+
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+        gl.bind_vertex_array(None);
+        self.scene = None;
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        if let Some(targets) = self.targets.take() {
+            for target in &targets {
+                target.delete(gl);
+            }
+        }
+        gl.delete_program(Some(&self.blur_program));
+        gl.delete_program(Some(&self.program));
+        gl.delete_vertex_array(Some(&self.vao));
+    }
+
+    // Gaussian standard deviation in device pixels, half the blur as in CanvasRenderingContext2D.
+    // Beyond what the taps reach the blur is approximated with spaced taps
+    fn sigma(&self, shadow: &Shadow) -> f32 {
+        (shadow.blur.max(0.0) * 0.5 * self.pixel_ratio).min(MAX_BLUR_TAPS * 4.0)
+    }
+
+    fn clamp(&self, area: [i32; 4]) -> [i32; 4] {
+        let (width, height) = (self.viewport.0 as i32, self.viewport.1 as i32);
+        [
+            area[0].clamp(0, width),
+            area[1].clamp(0, height),
+            area[2].clamp(0, width),
+            area[3].clamp(0, height),
+        ]
+    }
+}
+
+// Device pixel box [x0, y0, x1, y1] (bottom-left origin) covering a world-space box, rounded
+// out to whole pixels and clamped to the viewport
+pub(crate) fn device_box(bounds: [f32; 4], matrix: &[f32; 16], viewport: (u32, u32)) -> [f32; 4] {
+    let (width, height) = (viewport.0 as f32, viewport.1 as f32);
+    let corners = [(bounds[0], bounds[1]), (bounds[2], bounds[1]), (bounds[2], bounds[3]), (bounds[0], bounds[3])];
+
+    let mut device = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+    for (x, y) in corners {
+        let w = matrix[3] * x + matrix[7] * y + matrix[15];
+        let dx = ((matrix[0] * x + matrix[4] * y + matrix[12]) / w + 1.0) * 0.5 * width;
+        let dy = ((matrix[1] * x + matrix[5] * y + matrix[13]) / w + 1.0) * 0.5 * height;
+        device = [device[0].min(dx), device[1].min(dy), device[2].max(dx), device[3].max(dy)];
+    }
+
+    // Infinite bounds of an empty command clamp to an empty box
+    [
+        device[0].floor().clamp(0.0, width),
+        device[1].floor().clamp(0.0, height),
+        device[2].ceil().clamp(0.0, width),
+        device[3].ceil().clamp(0.0, height),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadows_show_only_when_they_would_be_visible() {
+        let black = Shadow {
+            color: [0.0, 0.0, 0.0, 1.0],
+            ..Shadow::default()
+        };
+        assert!(!Shadow::default().is_visible());
+        assert!(!black.is_visible());
+        assert!(Shadow { offset_y: 2.0, ..black }.is_visible());
+        assert!(Shadow { blur: 2.0, ..black }.is_visible());
+        assert!(!Shadow { blur: 2.0, color: [0.0; 4], ..black }.is_visible());
+
+        // A glow never moves, only its blur shows
+        assert!(!Shadow { offset_x: 3.0, glow: true, ..black }.is_visible());
+        assert!(Shadow { blur: 3.0, glow: true, ..black }.is_visible());
+    }
+
+    #[test]
+    fn device_boxes_are_rounded_out_and_clamped() {
+        // Maps [0, 100] x [0, 50] onto clip space
+        let mut matrix = [0.0; 16];
+        matrix[0] = 2.0 / 100.0;
+        matrix[5] = 2.0 / 50.0;
+        matrix[10] = 1.0;
+        matrix[12] = -1.0;
+        matrix[13] = -1.0;
+        matrix[15] = 1.0;
+
+        assert_eq!(device_box([10.2, 20.5, 30.7, 40.0], &matrix, (100, 50)), [10.0, 20.0, 31.0, 40.0]);
+        assert_eq!(device_box([-20.0, 12.5, 200.0, 17.5], &matrix, (100, 50)), [0.0, 12.0, 100.0, 18.0]);
+        let empty = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        let [x0, y0, x1, y1] = device_box(empty, &matrix, (100, 50));
+        assert!(x1 <= x0 && y1 <= y0);
+    }
+}
@@ -0,0 +1,545 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use js_sys::Float32Array;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    HtmlImageElement, ImageData, WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture,
+    WebGlUniformLocation, WebGlVertexArrayObject
+};
+
+use crate::atlas::{Atlas, MAX_ATLAS_SIZE};
+use crate::clip::{Clip, Clipper};
+use crate::composite::{CompositeOperation, Compositor, BACKDROP_TEXTURE_UNIT};
+use crate::pipeline::{expand_shader, ColorPipeline};
+use crate::png::png_data_url;
+use crate::{compile_shader, link_program};
+
+// Instance layout: destination rect (4), source rect in atlas texels (4), color (4), rotation (1)
+const FLOATS_PER_INSTANCE: usize = 13;
+
+// Atlas pages alive at once, past this the least recently drawn page is evicted
+const MAX_ATLAS_PAGES: usize = 4;
+
+// Sprite vertex shader: one instance per sprite, the quad corners come from gl_VertexID
+const SPRITE_VERTEX_SHADER_SRC: &str = r#"#version 300 es
+in vec4 aRect;
+in vec4 aSource;
+in vec4 aColor;
+in float aRotation;
+
+uniform mat4 uMatrix;
+uniform vec2 uAtlasSize;
+
+out vec2 vUv;
+out vec4 vColor;
+
+void main() {
+    // Triangle strip corners: (0, 0), (1, 0), (0, 1), (1, 1)
+    vec2 corner = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+
+    // Rotate around the center of the destination rect
+    vec2 local = (corner - 0.5) * aRect.zw;
+    float s = sin(aRotation);
+    float c = cos(aRotation);
+    vec2 world = aRect.xy + aRect.zw * 0.5 + vec2(local.x * c - local.y * s, local.x * s + local.y * c);
+
+    // Sample from texel centers so linear filtering never leaves the sprite
+    vec2 texel = aSource.xy + 0.5 + corner * max(aSource.zw - 1.0, 0.0);
+    vUv = texel / uAtlasSize;
+    vColor = aColor;
+    gl_Position = uMatrix * vec4(world, 0.0, 1.0);
+}
+"#;
+
+const SPRITE_FRAGMENT_SHADER_SRC: &str = r#"#version 300 es
+precision highp float;
+
+in vec2 vUv;
+in vec4 vColor;
+
+uniform sampler2D uAtlas;
+uniform bool uAtlasPremultiplied;
+
+#pragma pipeline
+#pragma composite
+
+out vec4 outColor;
+
+void main() {
+    outColor = composite(premultiply(textureColor(texture(uAtlas, vUv), uAtlasPremultiplied) * vColor, 1.0));
+}
+"#;
+
+// Pixels of a sprite, kept so it can be uploaded again after its atlas page was evicted
+enum SpriteSource {
+    Image(HtmlImageElement),
+    ImageData(ImageData),
+    Rgba(Vec<u8>),
+}
+
+struct Sprite {
+    source: SpriteSource,
+    width: u32,
+    height: u32,
+    // Atlas page index and top-left texel, None when not resident
+    placement: Option<(usize, u32, u32)>,
+    // URL of the image in SVG exports, encoded on first use
+    href: OnceCell<Rc<str>>,
+}
+
+struct AtlasPage {
+    atlas: Atlas,
+    // Frame the page was last drawn from
+    last_used: u64,
+    // Sprites on this page were uploaded with premultiplied alpha
+    premultiplied: bool,
+}
+
+// Instances drawn from the same atlas page with the same clip and composite operation
+struct SpriteRun {
+    page: usize,
+    first_instance: usize,
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    // World-space bounding box [x0, y0, x1, y1] of the sprites, for the backdrop copy
+    bounds: [f32; 4],
+}
+
+// Instanced sprite renderer. Registered images are packed into shared atlas pages so
+// any number of sprites is drawn with one instanced call per page change
+pub(crate) struct SpriteBatch {
+    program: WebGlProgram,
+    vao: WebGlVertexArrayObject,
+    instance_buffer: WebGlBuffer,
+    matrix_location: Option<WebGlUniformLocation>,
+    atlas_size_location: Option<WebGlUniformLocation>,
+    atlas_location: Option<WebGlUniformLocation>,
+    atlas_premultiplied_location: Option<WebGlUniformLocation>,
+    blend_mode_location: Option<WebGlUniformLocation>,
+    backdrop_location: Option<WebGlUniformLocation>,
+    premultiplied_location: Option<WebGlUniformLocation>,
+    linear_location: Option<WebGlUniformLocation>,
+    // (attribute location, size, offset in floats) of the per-instance attributes
+    attributes: Vec<(u32, i32, i32)>,
+    max_atlas_size: u32,
+    sprites: HashMap<String, Sprite>,
+    pages: Vec<AtlasPage>,
+    instances: Vec<f32>,
+    runs: Vec<SpriteRun>,
+    frame: u64,
+    // Clip and composite operation of the sprites queued from now on
+    clip: Rc<Clip>,
+    composite: CompositeOperation,
+    // Encoding of sprite colors, and the alpha mode of newly opened atlas pages
+    pipeline: ColorPipeline,
+}
+
+impl SpriteBatch {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> Result<SpriteBatch, JsValue> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, SPRITE_VERTEX_SHADER_SRC)?;
+        let fragment_src = expand_shader(SPRITE_FRAGMENT_SHADER_SRC);
+        let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, &fragment_src)?;
+        let program = link_program(gl, &vert_shader, &frag_shader)?;
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let vao = gl.create_vertex_array().ok_or("Failed to create sprite vertex array")?;
+        gl.bind_vertex_array(Some(&vao));
+
+        let instance_buffer = gl.create_buffer().ok_or("Failed to create sprite instance buffer")?;
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&instance_buffer));
+
+        // Every attribute advances once per instance
+        let mut attributes = Vec::new();
+        for (name, size, offset) in [("aRect", 4, 0), ("aSource", 4, 4), ("aColor", 4, 8), ("aRotation", 1, 12)] {
+            let location = gl.get_attrib_location(&program, name);
+            if location < 0 {
+                continue;
+            }
+            gl.enable_vertex_attrib_array(location as u32);
+            gl.vertex_attrib_divisor(location as u32, 1);
+            attributes.push((location as u32, size, offset));
+        }
+
+        gl.bind_vertex_array(None);
+
+        let max_texture_size = gl
+            .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|value| value.as_f64())
+            .map_or(MAX_ATLAS_SIZE, |size| size as u32);
+
+        Ok(SpriteBatch {
+            matrix_location: gl.get_uniform_location(&program, "uMatrix"),
+            atlas_size_location: gl.get_uniform_location(&program, "uAtlasSize"),
+            atlas_location: gl.get_uniform_location(&program, "uAtlas"),
+            atlas_premultiplied_location: gl.get_uniform_location(&program, "uAtlasPremultiplied"),
+            blend_mode_location: gl.get_uniform_location(&program, "uBlendMode"),
+            backdrop_location: gl.get_uniform_location(&program, "uBackdrop"),
+            premultiplied_location: gl.get_uniform_location(&program, "uPremultiplied"),
+            linear_location: gl.get_uniform_location(&program, "uLinear"),
+            program,
+            vao,
+            instance_buffer,
+            attributes,
+            max_atlas_size: max_texture_size.min(MAX_ATLAS_SIZE),
+            sprites: HashMap::new(),
+            pages: Vec::new(),
+            instances: Vec::new(),
+            runs: Vec::new(),
+            frame: 0,
+            clip: Rc::new(Clip::default()),
+            composite: CompositeOperation::SourceOver,
+            pipeline: ColorPipeline::default(),
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    // Clip the sprites queued after this call
+    pub(crate) fn set_clip(&mut self, clip: Rc<Clip>) {
+        self.clip = clip;
+    }
+
+    // Composite operation of the sprites queued after this call
+    pub(crate) fn set_composite(&mut self, composite: CompositeOperation) {
+        self.composite = composite;
+    }
+
+    // Color pipeline of the sprites queued after this call. Sprites already packed keep the
+    // alpha mode of their page
+    pub(crate) fn set_pipeline(&mut self, pipeline: ColorPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    // Register an HtmlImageElement or ImageData under a name, replacing any previous sprite
+    pub(crate) fn add(&mut self, name: &str, image: &JsValue) -> Result<(), JsValue> {
+        let (source, width, height) = if let Some(image) = image.dyn_ref::<HtmlImageElement>() {
+            if !image.complete() || image.natural_width() == 0 {
+                return Err(JsValue::from_str("Image has not finished loading"));
+            }
+            (SpriteSource::Image(image.clone()), image.natural_width(), image.natural_height())
+        } else if let Some(data) = image.dyn_ref::<ImageData>() {
+            (SpriteSource::ImageData(data.clone()), data.width(), data.height())
+        } else {
+            return Err(JsValue::from_str("Unsupported sprite source, expected an HtmlImageElement or ImageData"));
+        };
+
+        self.insert(name, source, width, height)
+    }
+
+    // Register raw RGBA bytes (width * height * 4, rows top to bottom) under a name
+    pub(crate) fn add_rgba(&mut self, name: &str, width: u32, height: u32, pixels: &[u8]) -> Result<(), JsValue> {
+        let texels = (width as usize).checked_mul(height as usize).and_then(|count| count.checked_mul(4));
+        if width == 0 || height == 0 || texels != Some(pixels.len()) {
+            return Err(JsValue::from_str("Pixel data must contain width * height RGBA texels"));
+        }
+
+        self.insert(name, SpriteSource::Rgba(pixels.to_vec()), width, height)
+    }
+
+    // Forget a sprite. Its atlas space is reclaimed when the page is evicted
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.sprites.remove(name).is_some()
+    }
+
+    // Natural size of a registered sprite
+    pub(crate) fn size(&self, name: &str) -> Option<(u32, u32)> {
+        self.sprites.get(name).map(|sprite| (sprite.width, sprite.height))
+    }
+
+    // The image URL of a registered sprite, or a PNG data URL of its pixels
+    pub(crate) fn href(&self, name: &str) -> Option<Rc<str>> {
+        let sprite = self.sprites.get(name)?;
+        let href = sprite.href.get_or_init(|| match &sprite.source {
+            SpriteSource::Image(image) => image.src().into(),
+            SpriteSource::ImageData(data) => png_data_url(sprite.width, sprite.height, &data.data()).into(),
+            SpriteSource::Rgba(pixels) => png_data_url(sprite.width, sprite.height, pixels).into(),
+        });
+        Some(href.clone())
+    }
+
+    // Queue a sprite drawn into the destination rect, rotated around its center
+    pub(crate) fn draw(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        name: &str,
+        rect: [f32; 4],
+        rotation: f32,
+        color: [f32; 4],
+    ) -> Result<(), JsValue> {
+        let (page, x, y, width, height) = self.resident(gl, name)?;
+
+        // Sprites blended against a backdrop copy each get their own run so they see each other
+        let reuse = self.runs.last().is_some_and(|run| {
+            run.page == page
+                && Rc::ptr_eq(&run.clip, &self.clip)
+                && run.composite == self.composite
+                && !self.composite.reads_backdrop()
+        });
+        if !reuse {
+            self.runs.push(SpriteRun {
+                page,
+                first_instance: self.instances.len() / FLOATS_PER_INSTANCE,
+                clip: self.clip.clone(),
+                composite: self.composite,
+                bounds: [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY],
+            });
+        }
+
+        // Any rotation stays within the circle around the rect
+        if let Some(run) = self.runs.last_mut() {
+            let [x, y, w, h] = rect;
+            let (cx, cy) = (x + w * 0.5, y + h * 0.5);
+            let radius = 0.5 * (w * w + h * h).sqrt();
+            let b = run.bounds;
+            run.bounds = [b[0].min(cx - radius), b[1].min(cy - radius), b[2].max(cx + radius), b[3].max(cy + radius)];
+        }
+
+        self.instances.extend_from_slice(&rect);
+        self.instances.extend_from_slice(&[x as f32, y as f32, width as f32, height as f32]);
+        self.instances.extend_from_slice(&self.pipeline.encode(color));
+        self.instances.push(rotation);
+
+        Ok(())
+    }
+
+    // Draw the queued sprites, one instanced call per run of the same atlas page and clip
+    pub(crate) fn flush(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        matrix: &[f32; 16],
+        clipper: &mut Clipper,
+        compositor: &mut Compositor,
+    ) -> Result<(), JsValue> {
+        self.frame += 1;
+
+        if self.instances.is_empty() {
+            self.runs.clear();
+            return Ok(());
+        }
+
+        gl.use_program(Some(&self.program));
+        gl.bind_vertex_array(Some(&self.vao));
+
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.instance_buffer));
+        let instance_array = Float32Array::from(&self.instances[..]);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &instance_array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+
+        gl.uniform_matrix4fv_with_f32_array(self.matrix_location.as_ref(), false, matrix);
+        gl.uniform1i(self.atlas_location.as_ref(), 0);
+        gl.uniform1i(self.backdrop_location.as_ref(), BACKDROP_TEXTURE_UNIT as i32);
+        self.pipeline.set_uniforms(gl, self.premultiplied_location.as_ref(), self.linear_location.as_ref());
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+
+        let instance_count = self.instances.len() / FLOATS_PER_INSTANCE;
+        for (i, run) in self.runs.iter().enumerate() {
+            let end = self.runs.get(i + 1).map_or(instance_count, |next| next.first_instance);
+            let count = end - run.first_instance;
+            if count == 0 {
+                continue;
+            }
+
+            // Redrawing the stencil mask switches to the clip program and its buffer
+            if clipper.apply(gl, &run.clip, matrix) {
+                gl.use_program(Some(&self.program));
+                gl.bind_vertex_array(Some(&self.vao));
+                gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.instance_buffer));
+            }
+
+            compositor.apply(gl, run.composite);
+            gl.uniform1i(self.blend_mode_location.as_ref(), run.composite.blend_mode());
+            if run.composite.reads_backdrop() {
+                compositor.copy_backdrop(gl, run.bounds, matrix)?;
+            }
+
+            let page = &self.pages[run.page];
+            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(page.atlas.texture()));
+            gl.uniform2f(self.atlas_size_location.as_ref(), page.atlas.size() as f32, page.atlas.size() as f32);
+            gl.uniform1i(self.atlas_premultiplied_location.as_ref(), page.premultiplied as i32);
+
+            // WebGL2 has no base instance, so point the attributes at the first instance of the run
+            self.bind_instances(gl, run.first_instance);
+            gl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4, count as i32);
+        }
+
+        gl.bind_vertex_array(None);
+
+        self.instances.clear();
+        self.runs.clear();
+
+        Ok(())
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        for page in self.pages.drain(..) {
+            page.atlas.delete(gl);
+        }
+        self.sprites.clear();
+        gl.delete_buffer(Some(&self.instance_buffer));
+        gl.delete_vertex_array(Some(&self.vao));
+        gl.delete_program(Some(&self.program));
+    }
+
+    fn insert(&mut self, name: &str, source: SpriteSource, width: u32, height: u32) -> Result<(), JsValue> {
+        // Leave room for the atlas padding
+        if width + 2 > self.max_atlas_size || height + 2 > self.max_atlas_size {
+            return Err(JsValue::from_str(&format!(
+                "Sprite \"{}\" is larger than the maximum atlas size of {}px",
+                name, self.max_atlas_size
+            )));
+        }
+
+        self.sprites.insert(
+            name.to_string(),
+            Sprite {
+                source,
+                width,
+                height,
+                placement: None,
+                href: OnceCell::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    // Make sure a sprite is packed into an atlas page and return (page, x, y, width, height)
+    fn resident(&mut self, gl: &WebGl2RenderingContext, name: &str) -> Result<(usize, u32, u32, u32, u32), JsValue> {
+        let sprite = self
+            .sprites
+            .get(name)
+            .ok_or_else(|| JsValue::from_str(&format!("No sprite named \"{}\"", name)))?;
+        let (width, height) = (sprite.width, sprite.height);
+
+        if let Some((page, x, y)) = sprite.placement {
+            self.pages[page].last_used = self.frame;
+            return Ok((page, x, y, width, height));
+        }
+
+        let (page, x, y) = self.place(gl, width, height)?;
+        self.pages[page].last_used = self.frame;
+
+        let sprite = self.sprites.get_mut(name).ok_or("Sprite disappeared while packing")?;
+        let atlas = &self.pages[page];
+        upload_sprite(gl, atlas.atlas.texture(), atlas.premultiplied, sprite, x, y)?;
+        sprite.placement = Some((page, x, y));
+
+        Ok((page, x, y, width, height))
+    }
+
+    // Find room in an existing page, open a new page, or evict the least recently used one
+    fn place(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(usize, u32, u32), JsValue> {
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.atlas.allocate(gl, width, height, self.max_atlas_size)? {
+                return Ok((index, x, y));
+            }
+        }
+
+        let index = if self.pages.len() < MAX_ATLAS_PAGES {
+            self.pages.push(AtlasPage {
+                atlas: Atlas::new(gl)?,
+                last_used: self.frame,
+                premultiplied: self.pipeline.premultiplied,
+            });
+            self.pages.len() - 1
+        } else {
+            // Pages drawn from this frame still have queued instances and cannot be evicted
+            let (index, _) = self
+                .pages
+                .iter()
+                .enumerate()
+                .filter(|(_, page)| page.last_used < self.frame)
+                .min_by_key(|(_, page)| page.last_used)
+                .ok_or("Sprite atlases are full, too many distinct sprites drawn in one frame")?;
+
+            self.pages[index].atlas.reset(gl)?;
+            self.pages[index].premultiplied = self.pipeline.premultiplied;
+            for sprite in self.sprites.values_mut() {
+                if sprite.placement.is_some_and(|(page, _, _)| page == index) {
+                    sprite.placement = None;
+                }
+            }
+            index
+        };
+
+        let (x, y) = self.pages[index]
+            .atlas
+            .allocate(gl, width, height, self.max_atlas_size)?
+            .ok_or("Sprite does not fit in an empty atlas")?;
+
+        Ok((index, x, y))
+    }
+
+    fn bind_instances(&self, gl: &WebGl2RenderingContext, first_instance: usize) {
+        let float_size = std::mem::size_of::<f32>() as i32;
+        let stride = FLOATS_PER_INSTANCE as i32 * float_size;
+        let base = first_instance as i32 * stride;
+
+        for &(location, size, offset) in &self.attributes {
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                stride,
+                base + offset * float_size,
+            );
+        }
+    }
+}
+
+// Copy a sprite's pixels into its atlas rectangle
+fn upload_sprite(
+    gl: &WebGl2RenderingContext,
+    texture: &WebGlTexture,
+    premultiply: bool,
+    sprite: &Sprite,
+    x: u32,
+    y: u32,
+) -> Result<(), JsValue> {
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+    gl.pixel_storei(WebGl2RenderingContext::UNPACK_FLIP_Y_WEBGL, 0);
+    gl.pixel_storei(WebGl2RenderingContext::UNPACK_PREMULTIPLY_ALPHA_WEBGL, premultiply as i32);
+
+    match &sprite.source {
+        SpriteSource::Image(image) => gl.tex_sub_image_2d_with_u32_and_u32_and_html_image_element(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            x as i32,
+            y as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            image,
+        ),
+        SpriteSource::ImageData(data) => gl.tex_sub_image_2d_with_u32_and_u32_and_image_data(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            x as i32,
+            y as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            data,
+        ),
+        SpriteSource::Rgba(pixels) => gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            x as i32,
+            y as i32,
+            sprite.width as i32,
+            sprite.height as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(pixels),
+        ),
+    }
+}
@@ -0,0 +1,41 @@
+use std::rc::Rc;
+
+use crate::batch::Paint;
+use crate::clip::Clip;
+use crate::composite::CompositeOperation;
+use crate::filter::Filter;
+use crate::recording::RecordedClip;
+use crate::shadow::Shadow;
+use crate::text::TextStyle;
+
+// Properties saved and restored by Canvas2D::save and Canvas2D::restore, like the drawing
+// state of CanvasRenderingContext2D
+#[derive(Clone, Debug)]
+pub(crate) struct DrawingState {
+    pub(crate) fill_style: Paint,
+    pub(crate) stroke_style: Paint,
+    pub(crate) line_width: f32,
+    pub(crate) text_style: TextStyle,
+    pub(crate) clip: Rc<Clip>,
+    // Outline of the clip for recordings, None when nothing is clipped
+    pub(crate) recorded_clip: Option<Rc<RecordedClip>>,
+    pub(crate) composite: CompositeOperation,
+    pub(crate) shadow: Shadow,
+    pub(crate) filter: Rc<Filter>,
+}
+
+impl Default for DrawingState {
+    fn default() -> DrawingState {
+        DrawingState {
+            fill_style: Paint::Color([0.0, 0.0, 0.0, 1.0]),
+            stroke_style: Paint::Color([0.0, 0.0, 0.0, 1.0]),
+            line_width: 1.0,
+            text_style: TextStyle::default(),
+            clip: Rc::new(Clip::default()),
+            recorded_clip: None,
+            composite: CompositeOperation::SourceOver,
+            shadow: Shadow::default(),
+            filter: Rc::new(Filter::default()),
+        }
+    }
+}
@@ -0,0 +1,1366 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::color::parse_color;
+use crate::gradient::{ColorStop, GradientKind, SpreadMode};
+use crate::path::{self, FillRule, Path2D};
+use crate::recording::{Layer, RecordedPaint, RecordedShape, RecordedStroke, Recording};
+use crate::svg_path::parse_path_data;
+
+// Affine transform [a, b, c, d, e, f]: x' = a x + c y + e, y' = b x + d y + f
+pub(crate) type Transform = [f32; 6];
+
+pub(crate) const IDENTITY: Transform = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// Gradient-filled triangles are split until their edges span at most this fraction of the
+// shape, so colors interpolated between vertices follow the stops
+const GRADIENT_SUBDIVISIONS: f32 = 24.0;
+const MAX_SUBDIVISION_DEPTH: u32 = 16;
+
+// References between gradients deeper than this are treated as a cycle
+const MAX_HREF_DEPTH: usize = 8;
+
+// A parsed XML element with its attributes and child elements. Text content is dropped
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    // A presentation attribute, overridden by a declaration in the style attribute
+    fn property(&self, name: &str) -> Option<String> {
+        let declared = self.attribute("style").and_then(|style| {
+            style.split(';').rev().find_map(|declaration| {
+                let (key, value) = declaration.split_once(':')?;
+                (key.trim() == name).then(|| value.trim().trim_end_matches("!important").trim().to_string())
+            })
+        });
+        declared.or_else(|| self.attribute(name).map(|value| value.trim().to_string()))
+    }
+}
+
+// Fill or stroke of a shape: a flat straight-alpha sRGB color or a gradient mapped to
+// document space
+#[derive(Clone, Debug)]
+enum SvgPaint {
+    Color([f32; 4]),
+    Gradient(Rc<SvgGradient>),
+}
+
+#[derive(Debug)]
+struct SvgGradient {
+    kind: GradientKind,
+    stops: Vec<ColorStop>,
+    spread: SpreadMode,
+    // Document space to gradient space
+    inverse: Transform,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Debug)]
+struct Stroke {
+    paint: SvgPaint,
+    opacity: f32,
+    // Width in document units, scaled by the transform of the shape
+    width: f32,
+    join: LineJoin,
+    cap: LineCap,
+    miter_limit: f32,
+}
+
+// A shape of the document in document coordinates
+#[derive(Clone, Debug)]
+struct SvgItem {
+    path: Path2D,
+    fill: Option<(SvgPaint, f32)>,
+    fill_rule: FillRule,
+    stroke: Option<Stroke>,
+}
+
+// Inherited properties while walking the tree
+#[derive(Clone, Debug)]
+struct Style {
+    fill: Option<String>,
+    fill_opacity: f32,
+    fill_rule: FillRule,
+    stroke: Option<String>,
+    stroke_opacity: f32,
+    stroke_width: f32,
+    join: LineJoin,
+    cap: LineCap,
+    miter_limit: f32,
+    color: [f32; 4],
+    // Product of the opacity of the ancestors, applied to each shape on its own
+    opacity: f32,
+    transform: Transform,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            fill: Some("black".to_string()),
+            fill_opacity: 1.0,
+            fill_rule: FillRule::NonZero,
+            stroke: None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            color: [0.0, 0.0, 0.0, 1.0],
+            opacity: 1.0,
+            transform: IDENTITY,
+        }
+    }
+}
+
+// Triangles with a straight-alpha sRGB color per vertex
+#[derive(Debug, Default)]
+pub(crate) struct SvgMesh {
+    pub(crate) positions: Vec<(f32, f32)>,
+    pub(crate) colors: Vec<[f32; 4]>,
+    pub(crate) indices: Vec<u32>,
+}
+
+// A static SVG illustration: rect, circle, ellipse, line, polyline, polygon and path
+// elements in groups with transforms, filled and stroked with colors or linear and radial
+// gradients. Group opacity is applied to each shape separately, text, images, filters,
+// masks and <use> are ignored
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct SvgDocument {
+    width: f32,
+    height: f32,
+    items: Vec<SvgItem>,
+}
+
+#[wasm_bindgen]
+impl SvgDocument {
+    #[wasm_bindgen]
+    pub fn parse(text: &str) -> Result<SvgDocument, JsValue> {
+        let root = parse_xml(text).map_err(|message| JsValue::from_str(&message))?;
+        if local_name(&root.name) != "svg" {
+            return Err(JsValue::from_str("Expected an <svg> root element"));
+        }
+
+        let view_box = root.attribute("viewBox").and_then(|value| {
+            let numbers = parse_numbers(value);
+            (numbers.len() == 4 && numbers[2] > 0.0 && numbers[3] > 0.0)
+                .then(|| [numbers[0], numbers[1], numbers[2], numbers[3]])
+        });
+        // Percentages are relative to a container the document does not have, so they fall
+        // back to the viewBox size like a missing attribute
+        let root_length = |name: &str| {
+            root.attribute(name)
+                .filter(|value| !value.trim_end().ends_with('%'))
+                .and_then(|value| parse_length(value, 0.0))
+                .filter(|length| *length > 0.0)
+        };
+        let width = root_length("width").or(view_box.map(|view_box| view_box[2])).unwrap_or(300.0);
+        let height = root_length("height").or(view_box.map(|view_box| view_box[3])).unwrap_or(150.0);
+
+        let mut style = Style::default();
+        if let Some(view_box) = view_box {
+            let aspect = root.attribute("preserveAspectRatio").unwrap_or("xMidYMid meet");
+            style.transform = view_box_transform(view_box, width, height, aspect);
+        }
+
+        let mut gradients = HashMap::new();
+        collect_gradients(&root, &mut gradients);
+
+        let mut document = SvgDocument {
+            width,
+            height,
+            items: Vec::new(),
+        };
+        let viewport = view_box.map_or((width, height), |view_box| (view_box[2], view_box[3]));
+        let context = Context { root: &root, gradients: &gradients, viewport };
+        context.walk(&root, &style, &mut document.items)?;
+
+        Ok(document)
+    }
+
+    // Size of the illustration in CSS pixels, from the width and height attributes or the viewBox
+    #[wasm_bindgen]
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    #[wasm_bindgen]
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    #[wasm_bindgen]
+    pub fn shape_count(&self) -> u32 {
+        self.items.len() as u32
+    }
+}
+
+impl SvgDocument {
+    // Tessellate the document mapped through `transform`, flattening curves within
+    // `tolerance` units of the target space
+    pub(crate) fn tessellate(&self, transform: Transform, tolerance: f32) -> SvgMesh {
+        let mut mesh = SvgMesh::default();
+        let inverse = invert(transform).unwrap_or(IDENTITY);
+        let scale = transform_scale(transform);
+
+        for item in &self.items {
+            let contours = item.path.transformed(transform).flatten(tolerance);
+            if contours.is_empty() {
+                continue;
+            }
+
+            if let Some((paint, opacity)) = &item.fill {
+                let (positions, indices) = path::triangulate(&contours, item.fill_rule);
+                mesh.append(&positions, &indices, paint, *opacity, inverse, tolerance);
+            }
+
+            if let Some(stroke) = &item.stroke {
+                let width = stroke.width * scale;
+                if width <= 0.0 {
+                    continue;
+                }
+                let mut positions = Vec::new();
+                let mut indices = Vec::new();
+                for contour in &contours {
+                    stroke_contour(&contour.points, contour.closed, width, stroke, tolerance, &mut positions, &mut indices);
+                }
+                mesh.append(&positions, &indices, &stroke.paint, stroke.opacity, inverse, tolerance);
+            }
+        }
+
+        mesh
+    }
+
+    // Add the shapes of the document mapped through `transform` to a recording
+    pub(crate) fn record(&self, transform: Transform, recording: &mut Recording, layer: &Layer) {
+        let scale = transform_scale(transform);
+        let recorded_paint = |paint: &SvgPaint| match paint {
+            SvgPaint::Color(color) => RecordedPaint::Color(*color),
+            SvgPaint::Gradient(gradient) => RecordedPaint::Gradient {
+                kind: gradient.kind,
+                stops: gradient.stops.clone(),
+                spread: gradient.spread,
+                transform: multiply(transform, invert(gradient.inverse).unwrap_or(IDENTITY)),
+            },
+        };
+
+        for item in &self.items {
+            let path = item.path.transformed(transform);
+            if let Some((paint, opacity)) = &item.fill {
+                recording.push(path.clone(), RecordedShape::Fill(item.fill_rule), recorded_paint(paint), *opacity, layer);
+            }
+            if let Some(stroke) = &item.stroke {
+                let shape = RecordedShape::Stroke(RecordedStroke {
+                    width: stroke.width * scale,
+                    join: stroke.join,
+                    cap: stroke.cap,
+                    miter_limit: stroke.miter_limit,
+                });
+                recording.push(path, shape, recorded_paint(&stroke.paint), stroke.opacity, layer);
+            }
+        }
+    }
+}
+
+impl SvgMesh {
+    // Add triangles colored with a paint. `inverse` maps the triangle positions back to
+    // document space where gradients are defined
+    fn append(
+        &mut self,
+        positions: &[(f32, f32)],
+        indices: &[u32],
+        paint: &SvgPaint,
+        opacity: f32,
+        inverse: Transform,
+        tolerance: f32,
+    ) {
+        let base = self.positions.len() as u32;
+        match paint {
+            SvgPaint::Color(color) => {
+                let color = [color[0], color[1], color[2], color[3] * opacity];
+                self.positions.extend_from_slice(positions);
+                self.colors.extend(std::iter::repeat_n(color, positions.len()));
+                self.indices.extend(indices.iter().map(|index| base + index));
+            }
+            SvgPaint::Gradient(gradient) => {
+                let Some([x0, y0, x1, y1]) = bounds(positions) else {
+                    return;
+                };
+                let step = (((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt() / GRADIENT_SUBDIVISIONS).max(tolerance * 16.0);
+
+                let mut subdivided = Vec::new();
+                for triangle in indices.chunks_exact(3) {
+                    let corners = [positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]];
+                    subdivide(corners, step, MAX_SUBDIVISION_DEPTH, &mut subdivided);
+                }
+                for point in subdivided {
+                    let color = gradient.color_at(apply(inverse, point));
+                    self.indices.push(self.positions.len() as u32);
+                    self.positions.push(point);
+                    self.colors.push([color[0], color[1], color[2], color[3] * opacity]);
+                }
+            }
+        }
+    }
+}
+
+impl SvgGradient {
+    fn color_at(&self, point: (f32, f32)) -> [f32; 4] {
+        let (x, y) = apply(self.inverse, point);
+        let t = match self.kind {
+            GradientKind::Linear { x0, y0, x1, y1 } => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                if length > 0.0 {
+                    ((x - x0) * dx + (y - y0) * dy) / length
+                } else {
+                    0.0
+                }
+            }
+            GradientKind::Radial { x0, y0, r0, x1, y1, r1 } => radial_offset((x, y), (x0, y0, r0), (x1, y1, r1)),
+            GradientKind::Conic { .. } => 0.0,
+        };
+
+        let t = match self.spread {
+            SpreadMode::Pad => t.clamp(0.0, 1.0),
+            SpreadMode::Repeat => t - t.floor(),
+            SpreadMode::Reflect => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        };
+
+        let Some(first) = self.stops.first() else {
+            return [0.0; 4];
+        };
+        if t <= first.offset {
+            return first.color;
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if t <= b.offset {
+                let f = if b.offset > a.offset { (t - a.offset) / (b.offset - a.offset) } else { 1.0 };
+                return std::array::from_fn(|i| a.color[i] + (b.color[i] - a.color[i]) * f);
+            }
+        }
+        self.stops[self.stops.len() - 1].color
+    }
+}
+
+// Offset of a point on a gradient between two circles: the largest t whose interpolated
+// circle passes through the point with a non-negative radius
+fn radial_offset(point: (f32, f32), start: (f32, f32, f32), end: (f32, f32, f32)) -> f32 {
+    let (cdx, cdy, dr) = (end.0 - start.0, end.1 - start.1, end.2 - start.2);
+    let (pdx, pdy) = (point.0 - start.0, point.1 - start.1);
+    let a = cdx * cdx + cdy * cdy - dr * dr;
+    let b = pdx * cdx + pdy * cdy + start.2 * dr;
+    let c = pdx * pdx + pdy * pdy - start.2 * start.2;
+
+    if a.abs() < 1e-9 {
+        return if b.abs() < 1e-9 { 0.0 } else { c / (2.0 * b) };
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return 0.0;
+    }
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((b + root) / a, (b - root) / a);
+    let t = t0.max(t1);
+    if start.2 + t * dr >= 0.0 {
+        t
+    } else {
+        t0.min(t1)
+    }
+}
+
+// Halve a triangle across its longest edge until no edge is longer than `step`, emitting
+// the corners of the pieces
+fn subdivide(corners: [(f32, f32); 3], step: f32, depth: u32, out: &mut Vec<(f32, f32)>) {
+    let length = |(p, q): ((f32, f32), (f32, f32))| (p.0 - q.0).powi(2) + (p.1 - q.1).powi(2);
+    let [a, b, c] = corners;
+    // Rotate the corners so the longest edge runs from the first to the second
+    let [a, b, c] = if length((b, c)) >= length((a, b)).max(length((c, a))) {
+        [b, c, a]
+    } else if length((c, a)) >= length((a, b)) {
+        [c, a, b]
+    } else {
+        [a, b, c]
+    };
+    if length((a, b)) <= step * step || depth == 0 {
+        out.extend_from_slice(&[a, b, c]);
+        return;
+    }
+
+    let middle = ((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5);
+    subdivide([a, middle, c], step, depth - 1, out);
+    subdivide([middle, b, c], step, depth - 1, out);
+}
+
+// Triangles of a stroked polyline with SVG joins and caps
+fn stroke_contour(
+    points: &[(f32, f32)],
+    closed: bool,
+    width: f32,
+    stroke: &Stroke,
+    tolerance: f32,
+    positions: &mut Vec<(f32, f32)>,
+    indices: &mut Vec<u32>,
+) {
+    let mut path: Vec<(f32, f32)> = Vec::with_capacity(points.len());
+    for point in points {
+        if path.last().is_none_or(|last| (last.0 - point.0).abs() > 1e-6 || (last.1 - point.1).abs() > 1e-6) {
+            path.push(*point);
+        }
+    }
+    if closed && path.len() > 2 && path.first() == path.last() {
+        path.pop();
+    }
+
+    let half = width * 0.5;
+    let mut triangle = |a: (f32, f32), b: (f32, f32), c: (f32, f32)| {
+        let base = positions.len() as u32;
+        positions.extend_from_slice(&[a, b, c]);
+        indices.extend_from_slice(&[base, base + 1, base + 2]);
+    };
+
+    // A lone point only shows with round or square caps
+    if path.len() == 1 {
+        let p = path[0];
+        match stroke.cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let corners = [(p.0 - half, p.1 - half), (p.0 + half, p.1 - half), (p.0 + half, p.1 + half), (p.0 - half, p.1 + half)];
+                triangle(corners[0], corners[1], corners[2]);
+                triangle(corners[0], corners[2], corners[3]);
+            }
+            LineCap::Round => round_fan(p, half, 0.0, 2.0 * std::f32::consts::PI, tolerance, &mut triangle),
+        }
+        return;
+    }
+
+    let count = path.len();
+    let segment_count = if closed { count } else { count - 1 };
+    for i in 0..segment_count {
+        let (mut a, mut b) = (path[i], path[(i + 1) % count]);
+        let (nx, ny) = normal(a, b);
+        // Square caps extend the end segments by half the width
+        if !closed && stroke.cap == LineCap::Square {
+            let (tx, ty) = (ny, -nx);
+            if i == 0 {
+                a = (a.0 - tx * half, a.1 - ty * half);
+            }
+            if i == segment_count - 1 {
+                b = (b.0 + tx * half, b.1 + ty * half);
+            }
+        }
+        let corners = [
+            (a.0 + nx * half, a.1 + ny * half),
+            (b.0 + nx * half, b.1 + ny * half),
+            (b.0 - nx * half, b.1 - ny * half),
+            (a.0 - nx * half, a.1 - ny * half),
+        ];
+        triangle(corners[0], corners[1], corners[2]);
+        triangle(corners[0], corners[2], corners[3]);
+    }
+
+    let joins = if closed { 0..count } else { 1..count - 1 };
+    for i in joins {
+        let (previous, point, next) = (path[(i + count - 1) % count], path[i], path[(i + 1) % count]);
+        let (n0, n1) = (normal(previous, point), normal(point, next));
+        let cross = (point.0 - previous.0) * (next.1 - point.1) - (point.1 - previous.1) * (next.0 - point.0);
+        if cross.abs() < 1e-9 {
+            continue;
+        }
+
+        // The outer side is opposite to the turn direction
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let a = (point.0 + n0.0 * half * side, point.1 + n0.1 * half * side);
+        let b = (point.0 + n1.0 * half * side, point.1 + n1.1 * half * side);
+        match stroke.join {
+            LineJoin::Round => {
+                let start = (a.1 - point.1).atan2(a.0 - point.0);
+                let mut sweep = (b.1 - point.1).atan2(b.0 - point.0) - start;
+                if sweep > std::f32::consts::PI {
+                    sweep -= 2.0 * std::f32::consts::PI;
+                } else if sweep < -std::f32::consts::PI {
+                    sweep += 2.0 * std::f32::consts::PI;
+                }
+                round_fan(point, half, start, sweep, tolerance, &mut triangle);
+            }
+            LineJoin::Miter | LineJoin::Bevel => {
+                let (mx, my) = (n0.0 + n1.0, n0.1 + n1.1);
+                let m_len = (mx * mx + my * my).sqrt();
+                let miter = if m_len > 1e-6 { 2.0 / m_len } else { f32::INFINITY };
+                if stroke.join == LineJoin::Miter && miter <= stroke.miter_limit {
+                    let tip = (point.0 + mx / m_len * half * miter * side, point.1 + my / m_len * half * miter * side);
+                    triangle(point, a, tip);
+                    triangle(point, tip, b);
+                } else {
+                    triangle(point, a, b);
+                }
+            }
+        }
+    }
+
+    if !closed && stroke.cap == LineCap::Round {
+        for (end, inner) in [(path[0], path[1]), (path[count - 1], path[count - 2])] {
+            let start = (inner.1 - end.1).atan2(inner.0 - end.0) + std::f32::consts::FRAC_PI_2;
+            round_fan(end, half, start, std::f32::consts::PI, tolerance, &mut triangle);
+        }
+    }
+}
+
+// Fan of triangles around `center` from `start` through `sweep` radians
+fn round_fan(
+    center: (f32, f32),
+    radius: f32,
+    start: f32,
+    sweep: f32,
+    tolerance: f32,
+    triangle: &mut impl FnMut((f32, f32), (f32, f32), (f32, f32)),
+) {
+    let angle = if radius > tolerance { 2.0 * (1.0 - tolerance / radius).acos() } else { std::f32::consts::FRAC_PI_2 };
+    let segments = ((sweep.abs() / angle.max(1e-3)).ceil() as usize).clamp(1, 256);
+    let mut previous = (center.0 + radius * start.cos(), center.1 + radius * start.sin());
+    for i in 1..=segments {
+        let theta = start + sweep * i as f32 / segments as f32;
+        let point = (center.0 + radius * theta.cos(), center.1 + radius * theta.sin());
+        triangle(center, previous, point);
+        previous = point;
+    }
+}
+
+// State shared while converting the element tree
+struct Context<'a> {
+    // The outermost <svg>, placed by its viewBox rather than by x and y
+    root: &'a Element,
+    gradients: &'a HashMap<String, &'a Element>,
+    // Size of the viewBox (or the document), the reference of percentages
+    viewport: (f32, f32),
+}
+
+impl Context<'_> {
+    fn walk(&self, element: &Element, inherited: &Style, items: &mut Vec<SvgItem>) -> Result<(), JsValue> {
+        let name = local_name(&element.name);
+        if matches!(
+            name,
+            "defs" | "linearGradient" | "radialGradient" | "clipPath" | "mask" | "pattern" | "symbol" | "marker"
+                | "style" | "title" | "desc" | "metadata" | "text" | "image" | "filter" | "script"
+        ) {
+            return Ok(());
+        }
+        if element.property("display").as_deref() == Some("none") {
+            return Ok(());
+        }
+
+        let style = self.style(element, inherited)?;
+        match name {
+            "svg" | "g" | "a" | "switch" => {
+                for child in &element.children {
+                    self.walk(child, &style, items)?;
+                }
+            }
+            _ => {
+                let visible = !matches!(element.property("visibility").as_deref(), Some("hidden" | "collapse"));
+                if let Some(local) = self.geometry(element, name)? {
+                    if visible {
+                        items.extend(self.item(local, &style)?);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The style of an element from its inherited style and its own properties
+    fn style(&self, element: &Element, inherited: &Style) -> Result<Style, JsValue> {
+        let mut style = inherited.clone();
+        let number = |name: &str| element.property(name).and_then(|value| parse_length(&value, 0.0));
+
+        if let Some(color) = element.property("color") {
+            if color != "inherit" {
+                style.color = parse_color(&color)?;
+            }
+        }
+        if let Some(fill) = element.property("fill") {
+            if fill != "inherit" {
+                style.fill = Some(fill).filter(|fill| fill != "none");
+            }
+        }
+        if let Some(stroke) = element.property("stroke") {
+            if stroke != "inherit" {
+                style.stroke = Some(stroke).filter(|stroke| stroke != "none");
+            }
+        }
+        if let Some(opacity) = element.property("fill-opacity").and_then(|value| parse_opacity(&value)) {
+            style.fill_opacity = opacity;
+        }
+        if let Some(opacity) = element.property("stroke-opacity").and_then(|value| parse_opacity(&value)) {
+            style.stroke_opacity = opacity;
+        }
+        if let Some(opacity) = element.property("opacity").and_then(|value| parse_opacity(&value)) {
+            style.opacity *= opacity;
+        }
+        if let Some(width) = number("stroke-width") {
+            style.stroke_width = width.max(0.0);
+        }
+        if let Some(limit) = number("stroke-miterlimit") {
+            style.miter_limit = limit.max(1.0);
+        }
+        match element.property("fill-rule").as_deref() {
+            Some("evenodd") => style.fill_rule = FillRule::EvenOdd,
+            Some("nonzero") => style.fill_rule = FillRule::NonZero,
+            _ => {}
+        }
+        match element.property("stroke-linejoin").as_deref() {
+            Some("miter" | "miter-clip" | "arcs") => style.join = LineJoin::Miter,
+            Some("round") => style.join = LineJoin::Round,
+            Some("bevel") => style.join = LineJoin::Bevel,
+            _ => {}
+        }
+        match element.property("stroke-linecap").as_deref() {
+            Some("butt") => style.cap = LineCap::Butt,
+            Some("round") => style.cap = LineCap::Round,
+            Some("square") => style.cap = LineCap::Square,
+            _ => {}
+        }
+
+        let mut local = IDENTITY;
+        if local_name(&element.name) == "svg" && !std::ptr::eq(element, self.root) {
+            // Nested <svg> elements are placed at their x, y
+            let x = element.attribute("x").and_then(|value| parse_length(value, self.viewport.0)).unwrap_or(0.0);
+            let y = element.attribute("y").and_then(|value| parse_length(value, self.viewport.1)).unwrap_or(0.0);
+            local = [1.0, 0.0, 0.0, 1.0, x, y];
+        }
+        if let Some(transform) = element.attribute("transform") {
+            local = multiply(local, parse_transform(transform));
+        }
+        style.transform = multiply(inherited.transform, local);
+
+        Ok(style)
+    }
+
+    // The outline of a shape element in its own coordinates, None for other elements
+    fn geometry(&self, element: &Element, name: &str) -> Result<Option<Path2D>, JsValue> {
+        let (vw, vh) = self.viewport;
+        let diagonal = ((vw * vw + vh * vh) * 0.5).sqrt();
+        let length = |attribute: &str, reference: f32| {
+            element.attribute(attribute).and_then(|value| parse_length(value, reference)).unwrap_or(0.0)
+        };
+
+        let mut path = Path2D::new();
+        match name {
+            "rect" => {
+                let (x, y, width, height) = (length("x", vw), length("y", vh), length("width", vw), length("height", vh));
+                if width <= 0.0 || height <= 0.0 {
+                    return Ok(None);
+                }
+                // A missing radius takes the other one, both are limited to half the sides
+                let rx = element.attribute("rx").and_then(|value| parse_length(value, vw));
+                let ry = element.attribute("ry").and_then(|value| parse_length(value, vh));
+                let rx = rx.or(ry).unwrap_or(0.0).clamp(0.0, width * 0.5);
+                let ry = ry.or(Some(rx)).unwrap_or(0.0).clamp(0.0, height * 0.5);
+                if rx > 0.0 && ry > 0.0 {
+                    let data = format!(
+                        "M{} {} H{} A{rx} {ry} 0 0 1 {} {} V{} A{rx} {ry} 0 0 1 {} {} H{} A{rx} {ry} 0 0 1 {} {} V{} A{rx} {ry} 0 0 1 {} {} Z",
+                        x + rx,
+                        y,
+                        x + width - rx,
+                        x + width,
+                        y + ry,
+                        y + height - ry,
+                        x + width - rx,
+                        y + height,
+                        x + rx,
+                        x,
+                        y + height - ry,
+                        y + ry,
+                        x + rx,
+                        y,
+                    );
+                    return Ok(Some(Path2D::from_svg(&data)?));
+                }
+                path.rect(x, y, width, height);
+            }
+            "circle" | "ellipse" => {
+                let (cx, cy) = (length("cx", vw), length("cy", vh));
+                let (rx, ry) = if name == "circle" {
+                    let r = length("r", diagonal);
+                    (r, r)
+                } else {
+                    (length("rx", vw), length("ry", vh))
+                };
+                if rx <= 0.0 || ry <= 0.0 {
+                    return Ok(None);
+                }
+                let data = format!(
+                    "M{} {cy} A{rx} {ry} 0 0 1 {cx} {} A{rx} {ry} 0 0 1 {} {cy} A{rx} {ry} 0 0 1 {cx} {} A{rx} {ry} 0 0 1 {} {cy} Z",
+                    cx + rx,
+                    cy + ry,
+                    cx - rx,
+                    cy - ry,
+                    cx + rx,
+                );
+                return Ok(Some(Path2D::from_svg(&data)?));
+            }
+            "line" => {
+                path.move_to(length("x1", vw), length("y1", vh));
+                path.line_to(length("x2", vw), length("y2", vh));
+            }
+            "polyline" | "polygon" => {
+                let numbers = parse_numbers(element.attribute("points").unwrap_or(""));
+                let mut pairs = numbers.chunks_exact(2);
+                let Some(first) = pairs.next() else {
+                    return Ok(None);
+                };
+                path.move_to(first[0], first[1]);
+                for pair in pairs {
+                    path.line_to(pair[0], pair[1]);
+                }
+                if name == "polygon" {
+                    path.close_path();
+                }
+            }
+            "path" => {
+                // Like browsers, render the path up to the first error
+                let data = element.attribute("d").unwrap_or("");
+                let segments = match parse_path_data(data) {
+                    Ok(segments) => segments,
+                    Err(_) => parse_valid_prefix(data),
+                };
+                path.add_svg_segments(&segments);
+            }
+            _ => return Ok(None),
+        }
+
+        Ok(Some(path))
+    }
+
+    // The fill and stroke of a shape, in document coordinates
+    fn item(&self, local: Path2D, style: &Style) -> Result<Option<SvgItem>, JsValue> {
+        // Bounding box of the shape in its own coordinates for objectBoundingBox gradients
+        let bbox = path::bounds(&local.flatten(0.5)).map(|[x0, y0, x1, y1]| [x0, y0, x1 - x0, y1 - y0]);
+        let paint = |value: &Option<String>| -> Result<Option<SvgPaint>, JsValue> {
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            self.paint(value, style, bbox)
+        };
+
+        let fill = paint(&style.fill)?.map(|paint| (paint, style.fill_opacity * style.opacity));
+        let stroke = paint(&style.stroke)?
+            .filter(|_| style.stroke_width > 0.0)
+            .map(|paint| Stroke {
+                paint,
+                opacity: style.stroke_opacity * style.opacity,
+                width: style.stroke_width * transform_scale(style.transform),
+                join: style.join,
+                cap: style.cap,
+                miter_limit: style.miter_limit,
+            });
+        if fill.is_none() && stroke.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(SvgItem {
+            path: local.transformed(style.transform),
+            fill,
+            fill_rule: style.fill_rule,
+            stroke,
+        }))
+    }
+
+    // A color, currentColor or url(#gradient) with an optional fallback color
+    fn paint(&self, value: &str, style: &Style, bbox: Option<[f32; 4]>) -> Result<Option<SvgPaint>, JsValue> {
+        let value = value.trim();
+        if let Some(rest) = value.strip_prefix("url(") {
+            let (reference, fallback) = rest.split_once(')').unwrap_or((rest, ""));
+            let id = reference.trim().trim_matches(|c| c == '"' || c == '\'').trim_start_matches('#');
+            if let Some(gradient) = self.gradient(id, style.transform, bbox)? {
+                return Ok(Some(gradient));
+            }
+            let fallback = fallback.trim();
+            return if fallback.is_empty() || fallback == "none" {
+                Ok(None)
+            } else {
+                self.paint(fallback, style, bbox)
+            };
+        }
+
+        let color = if value.eq_ignore_ascii_case("currentColor") { style.color } else { parse_color(value)? };
+        Ok(Some(SvgPaint::Color(color)))
+    }
+
+    // A gradient element resolved through its href chain and mapped to document space
+    fn gradient(&self, id: &str, transform: Transform, bbox: Option<[f32; 4]>) -> Result<Option<SvgPaint>, JsValue> {
+        let mut chain: Vec<&Element> = Vec::new();
+        let mut next = Some(id.to_string());
+        while let Some(id) = next.take() {
+            let Some(element) = self.gradients.get(&id) else {
+                break;
+            };
+            chain.push(element);
+            if chain.len() >= MAX_HREF_DEPTH {
+                break;
+            }
+            next = element
+                .attribute("href")
+                .or_else(|| element.attribute("xlink:href"))
+                .map(|href| href.trim().trim_start_matches('#').to_string());
+        }
+        let Some(element) = chain.first() else {
+            return Ok(None);
+        };
+        // Attributes and stops missing on a gradient come from the one it references
+        let attribute = |name: &str| chain.iter().find_map(|element| element.attribute(name));
+
+        let mut stops: Vec<ColorStop> = Vec::new();
+        if let Some(owner) = chain.iter().find(|element| element.children.iter().any(|child| local_name(&child.name) == "stop")) {
+            for stop in owner.children.iter().filter(|child| local_name(&child.name) == "stop") {
+                let offset = stop.attribute("offset").and_then(|value| parse_length(value, 1.0)).unwrap_or(0.0);
+                let previous = stops.last().map_or(0.0, |stop| stop.offset);
+                let mut color = match stop.property("stop-color") {
+                    Some(color) if color.eq_ignore_ascii_case("currentColor") => [0.0, 0.0, 0.0, 1.0],
+                    Some(color) => parse_color(&color)?,
+                    None => [0.0, 0.0, 0.0, 1.0],
+                };
+                color[3] *= stop.property("stop-opacity").and_then(|value| parse_opacity(&value)).unwrap_or(1.0);
+                stops.push(ColorStop { offset: offset.clamp(0.0, 1.0).max(previous), color });
+            }
+        }
+        // No stops paint nothing, one stop paints its color
+        match stops.len() {
+            0 => return Ok(None),
+            1 => return Ok(Some(SvgPaint::Color(stops[0].color))),
+            _ => {}
+        }
+
+        let bounding_box = attribute("gradientUnits") != Some("userSpaceOnUse");
+        let (reference_x, reference_y, reference_r) = if bounding_box {
+            (1.0, 1.0, 1.0)
+        } else {
+            let (vw, vh) = self.viewport;
+            (vw, vh, ((vw * vw + vh * vh) * 0.5).sqrt())
+        };
+        let length = |name: &str, reference: f32, default: f32| {
+            attribute(name).and_then(|value| parse_length(value, reference)).unwrap_or(default)
+        };
+
+        let kind = if local_name(&element.name) == "linearGradient" {
+            GradientKind::Linear {
+                x0: length("x1", reference_x, 0.0),
+                y0: length("y1", reference_y, 0.0),
+                x1: length("x2", reference_x, reference_x),
+                y1: length("y2", reference_y, 0.0),
+            }
+        } else {
+            let cx = length("cx", reference_x, reference_x * 0.5);
+            let cy = length("cy", reference_y, reference_y * 0.5);
+            GradientKind::Radial {
+                x0: length("fx", reference_x, cx),
+                y0: length("fy", reference_y, cy),
+                r0: length("fr", reference_r, 0.0),
+                x1: cx,
+                y1: cy,
+                r1: length("r", reference_r, reference_r * 0.5),
+            }
+        };
+
+        let spread = match attribute("spreadMethod") {
+            Some("reflect") => SpreadMode::Reflect,
+            Some("repeat") => SpreadMode::Repeat,
+            _ => SpreadMode::Pad,
+        };
+
+        let mut gradient_to_document = transform;
+        if bounding_box {
+            // Degenerate boxes cannot map a bounding box gradient
+            let Some([x, y, width, height]) = bbox.filter(|[_, _, width, height]| *width > 0.0 && *height > 0.0) else {
+                return Ok(None);
+            };
+            gradient_to_document = multiply(gradient_to_document, [width, 0.0, 0.0, height, x, y]);
+        }
+        if let Some(value) = attribute("gradientTransform") {
+            gradient_to_document = multiply(gradient_to_document, parse_transform(value));
+        }
+        let Some(inverse) = invert(gradient_to_document) else {
+            return Ok(None);
+        };
+
+        Ok(Some(SvgPaint::Gradient(Rc::new(SvgGradient { kind, stops, spread, inverse }))))
+    }
+}
+
+// Gradients anywhere in the tree by id
+fn collect_gradients<'a>(element: &'a Element, gradients: &mut HashMap<String, &'a Element>) {
+    if matches!(local_name(&element.name), "linearGradient" | "radialGradient") {
+        if let Some(id) = element.attribute("id") {
+            gradients.insert(id.to_string(), element);
+        }
+    }
+    for child in &element.children {
+        collect_gradients(child, gradients);
+    }
+}
+
+// The segments of path data before its first error
+fn parse_valid_prefix(data: &str) -> Vec<crate::svg_path::SvgSegment> {
+    let mut end = data.len();
+    while end > 0 {
+        end -= 1;
+        while end > 0 && !data.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Ok(segments) = parse_path_data(&data[..end]) {
+            return segments;
+        }
+    }
+    Vec::new()
+}
+
+// Map the viewBox to the viewport following preserveAspectRatio
+fn view_box_transform(view_box: [f32; 4], width: f32, height: f32, aspect: &str) -> Transform {
+    let [x, y, w, h] = view_box;
+    let (mut sx, mut sy) = (width / w, height / h);
+    let mut parts = aspect.split_whitespace();
+    let align = parts.next().unwrap_or("xMidYMid");
+    if align == "none" {
+        return [sx, 0.0, 0.0, sy, -x * sx, -y * sy];
+    }
+
+    let scale = if parts.next() == Some("slice") { sx.max(sy) } else { sx.min(sy) };
+    (sx, sy) = (scale, scale);
+    let position = |start: f32, extra: f32, mode: &str| match mode {
+        "Min" => start,
+        "Max" => start + extra,
+        _ => start + extra * 0.5,
+    };
+    let x_mode = align.get(1..4).unwrap_or("Mid");
+    let y_mode = align.get(5..8).unwrap_or("Mid");
+    let tx = position(0.0, width - w * sx, x_mode) - x * sx;
+    let ty = position(0.0, height - h * sy, y_mode) - y * sy;
+    [sx, 0.0, 0.0, sy, tx, ty]
+}
+
+// A transform list: matrix, translate, scale, rotate, skewX and skewY. Parsing stops at the
+// first malformed function
+fn parse_transform(value: &str) -> Transform {
+    let mut result = IDENTITY;
+    let mut rest = value;
+    while let Some(open) = rest.find('(') {
+        let name = rest[..open].trim().trim_start_matches(',').trim();
+        let Some(close) = rest[open..].find(')') else {
+            break;
+        };
+        let arguments = parse_numbers(&rest[open + 1..open + close]);
+        rest = &rest[open + close + 1..];
+
+        let transform = match (name, arguments.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => [a, b, c, d, e, f],
+            ("translate", &[tx]) => [1.0, 0.0, 0.0, 1.0, tx, 0.0],
+            ("translate", &[tx, ty]) => [1.0, 0.0, 0.0, 1.0, tx, ty],
+            ("scale", &[s]) => [s, 0.0, 0.0, s, 0.0, 0.0],
+            ("scale", &[sx, sy]) => [sx, 0.0, 0.0, sy, 0.0, 0.0],
+            ("rotate", &[angle]) => rotation(angle),
+            ("rotate", &[angle, cx, cy]) => multiply(
+                multiply([1.0, 0.0, 0.0, 1.0, cx, cy], rotation(angle)),
+                [1.0, 0.0, 0.0, 1.0, -cx, -cy],
+            ),
+            ("skewX", &[angle]) => [1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0],
+            ("skewY", &[angle]) => [1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0],
+            _ => break,
+        };
+        result = multiply(result, transform);
+    }
+    result
+}
+
+fn rotation(degrees: f32) -> Transform {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [cos, sin, -sin, cos, 0.0, 0.0]
+}
+
+// The transform applying `second` first, then `first`
+pub(crate) fn multiply(first: Transform, second: Transform) -> Transform {
+    let [a, b, c, d, e, f] = first;
+    let [a2, b2, c2, d2, e2, f2] = second;
+    [
+        a * a2 + c * b2,
+        b * a2 + d * b2,
+        a * c2 + c * d2,
+        b * c2 + d * d2,
+        a * e2 + c * f2 + e,
+        b * e2 + d * f2 + f,
+    ]
+}
+
+pub(crate) fn invert(m: Transform) -> Option<Transform> {
+    let [a, b, c, d, e, f] = m;
+    let determinant = a * d - b * c;
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    Some([
+        d * inverse,
+        -b * inverse,
+        -c * inverse,
+        a * inverse,
+        (c * f - d * e) * inverse,
+        (b * e - a * f) * inverse,
+    ])
+}
+
+pub(crate) fn apply(m: Transform, (x, y): (f32, f32)) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+// How much a transform scales lengths on average, for stroke widths
+fn transform_scale(m: Transform) -> f32 {
+    (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+}
+
+fn bounds(points: &[(f32, f32)]) -> Option<[f32; 4]> {
+    let first = points.first()?;
+    Some(points.iter().fold([first.0, first.1, first.0, first.1], |b, p| {
+        [b[0].min(p.0), b[1].min(p.1), b[2].max(p.0), b[3].max(p.1)]
+    }))
+}
+
+// Unit normal to the left of the segment a -> b
+fn normal(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    let dx = b.0 - a.0;
+    let dy = b.1 - a.1;
+    let length = (dx * dx + dy * dy).sqrt().max(1e-12);
+    (-dy / length, dx / length)
+}
+
+// A length or coordinate: a number with an optional px unit, or a percentage of `reference`
+fn parse_length(value: &str, reference: f32) -> Option<f32> {
+    let value = value.trim();
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent.trim().parse::<f32>().ok().map(|percent| percent * 0.01 * reference);
+    }
+    value.trim_end_matches("px").trim().parse::<f32>().ok().filter(|value| value.is_finite())
+}
+
+// An opacity as a number or a percentage, clamped to [0, 1]
+fn parse_opacity(value: &str) -> Option<f32> {
+    parse_length(value, 1.0).map(|opacity| opacity.clamp(0.0, 1.0))
+}
+
+// Numbers separated by whitespace and commas, as in points and viewBox
+fn parse_numbers(value: &str) -> Vec<f32> {
+    let mut numbers = Vec::new();
+    let bytes = value.as_bytes();
+    let mut position = 0;
+    while position < bytes.len() {
+        if bytes[position].is_ascii_whitespace() || bytes[position] == b',' {
+            position += 1;
+            continue;
+        }
+        // Numbers are ASCII, anything else ends the list before it could be sliced mid-character
+        if !bytes[position].is_ascii() {
+            break;
+        }
+        let start = position;
+        position += 1;
+        while position < bytes.len() {
+            let byte = bytes[position];
+            let exponent_sign = matches!(byte, b'+' | b'-') && matches!(bytes[position - 1], b'e' | b'E');
+            let second_point = byte == b'.' && value[start..position].contains('.') && !value[start..position].contains(['e', 'E']);
+            if (byte.is_ascii_digit() || byte == b'.' || byte == b'e' || byte == b'E' || exponent_sign) && !second_point {
+                position += 1;
+            } else {
+                break;
+            }
+        }
+        match value[start..position].parse::<f32>() {
+            Ok(number) => numbers.push(number),
+            Err(_) => break,
+        }
+    }
+    numbers
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+// A small XML parser for SVG files: elements, attributes, entities, comments, CDATA,
+// processing instructions and the doctype. Returns the root element
+fn parse_xml(text: &str) -> Result<Element, String> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+    let mut rest = text;
+
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").ok_or("Unterminated XML comment")?;
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or("Unterminated CDATA section")?;
+            rest = &after[end + 3..];
+        } else if rest.starts_with("<?") {
+            let end = rest.find("?>").ok_or("Unterminated XML declaration")?;
+            rest = &rest[end + 2..];
+        } else if rest.starts_with("<!") {
+            // The doctype may hold an internal subset in brackets
+            let mut depth = 0;
+            let mut end = None;
+            for (index, byte) in rest.bytes().enumerate() {
+                match byte {
+                    b'[' => depth += 1,
+                    b']' => depth -= 1,
+                    b'>' if depth <= 0 => {
+                        end = Some(index);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            rest = &rest[end.ok_or("Unterminated doctype")? + 1..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').ok_or("Unterminated closing tag")?;
+            let name = after[..end].trim();
+            let element = stack.pop().ok_or_else(|| format!("Unexpected closing tag </{}>", name))?;
+            if element.name != name {
+                return Err(format!("Expected </{}> but found </{}>", element.name, name));
+            }
+            rest = &after[end + 1..];
+            close_element(element, &mut stack, &mut root);
+        } else {
+            let (element, self_closing, remaining) = parse_tag(&rest[1..])?;
+            rest = remaining;
+            if self_closing {
+                close_element(element, &mut stack, &mut root);
+            } else {
+                stack.push(element);
+            }
+        }
+
+        if root.is_some() {
+            break;
+        }
+    }
+
+    if let Some(open) = stack.last() {
+        return Err(format!("Unclosed element <{}>", open.name));
+    }
+    root.ok_or_else(|| "No root element".to_string())
+}
+
+fn close_element(element: Element, stack: &mut [Element], root: &mut Option<Element>) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None => *root = Some(element),
+    }
+}
+
+// Parse the inside of a start tag after '<'. Returns the element, whether it closes itself
+// and the text after the tag
+fn parse_tag(text: &str) -> Result<(Element, bool, &str), String> {
+    let name_end = text
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .ok_or("Unterminated start tag")?;
+    let mut element = Element {
+        name: text[..name_end].to_string(),
+        ..Element::default()
+    };
+    if element.name.is_empty() {
+        return Err("Missing element name".to_string());
+    }
+
+    let mut rest = &text[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Ok((element, true, after));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return Ok((element, false, after));
+        }
+
+        let equals = rest.find('=').ok_or_else(|| format!("Malformed attribute in <{}>", element.name))?;
+        let name = rest[..equals].trim().to_string();
+        let after = rest[equals + 1..].trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let quote = quote.ok_or_else(|| format!("Unquoted attribute \"{}\" in <{}>", name, element.name))?;
+        let end = after[1..].find(quote).ok_or_else(|| format!("Unterminated attribute \"{}\"", name))?;
+        element.attributes.push((name, decode_entities(&after[1..1 + end])));
+        rest = &after[end + 2..];
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semicolon) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semicolon];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[semicolon + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_lists_stop_at_non_ascii_characters() {
+        assert_eq!(parse_numbers("1,2 3.5e1"), vec![1.0, 2.0, 35.0]);
+        assert_eq!(parse_numbers("1 2 \u{00e9}3 4"), vec![1.0, 2.0]);
+        assert_eq!(parse_numbers("5\u{2009}6"), vec![5.0]);
+    }
+
+    #[test]
+    fn percentage_root_sizes_fall_back_to_the_view_box() {
+        let document = SvgDocument::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100%" height="50%" viewBox="0 0 64 32"><rect width="64" height="32"/></svg>"#,
+        )
+        .unwrap();
+        assert_eq!((document.width(), document.height()), (64.0, 32.0));
+        assert_eq!(document.shape_count(), 1);
+    }
+
+    #[test]
+    fn xml_markup_around_the_root_is_skipped() {
+        let root = parse_xml(
+            r#"<?xml version="1.0"?>
+            <!DOCTYPE svg [ <!ENTITY x "y"> ]>
+            <!-- <svg> in a comment -->
+            <svg:svg a='1 &lt; 2' b="&#x41;&#66;&amp;&unknown;">
+                <![CDATA[ <rect/> ]]>
+                <g><rect width="1"/></g>
+                <circle/>
+            </svg:svg>"#,
+        )
+        .unwrap();
+        assert_eq!(local_name(&root.name), "svg");
+        assert_eq!(root.attribute("a"), Some("1 < 2"));
+        assert_eq!(root.attribute("b"), Some("AB&&unknown;"));
+        let children: Vec<&str> = root.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(children, ["g", "circle"]);
+        assert_eq!(root.children[0].children[0].attribute("width"), Some("1"));
+
+        assert_eq!(parse_xml("<svg><g></svg>").unwrap_err(), "Expected </g> but found </svg>");
+        assert_eq!(parse_xml("<svg><g/>").unwrap_err(), "Unclosed element <svg>");
+        assert_eq!(parse_xml("<svg width=1/>").unwrap_err(), "Unquoted attribute \"width\" in <svg>");
+    }
+
+    #[test]
+    fn transform_lists_apply_right_to_left() {
+        let transform = parse_transform("translate(10 20) scale(2)");
+        assert_eq!(apply(transform, (1.0, 1.0)), (12.0, 22.0));
+
+        let (x, y) = apply(parse_transform("rotate(90, 5 5)"), (10.0, 5.0));
+        assert!((x - 5.0).abs() < 1e-5 && (y - 10.0).abs() < 1e-5);
+
+        // Parsing stops at the first malformed function
+        assert_eq!(parse_transform("translate(3) scale(1 2 3) translate(5)"), [1.0, 0.0, 0.0, 1.0, 3.0, 0.0]);
+        assert_eq!(parse_transform("matrix(1,2,3,4,5,6)"), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn view_boxes_fit_the_viewport() {
+        let view_box = [10.0, 0.0, 100.0, 50.0];
+        // Meet scales by the smaller factor and centers the other axis
+        assert_eq!(view_box_transform(view_box, 200.0, 200.0, "xMidYMid meet"), [2.0, 0.0, 0.0, 2.0, -20.0, 50.0]);
+        assert_eq!(view_box_transform(view_box, 200.0, 200.0, "xMinYMax"), [2.0, 0.0, 0.0, 2.0, -20.0, 100.0]);
+        // Slice scales by the larger one
+        assert_eq!(view_box_transform(view_box, 200.0, 200.0, "xMinYMin slice"), [4.0, 0.0, 0.0, 4.0, -40.0, 0.0]);
+        assert_eq!(view_box_transform(view_box, 200.0, 200.0, "none"), [2.0, 0.0, 0.0, 4.0, -20.0, 0.0]);
+    }
+
+    #[test]
+    fn documents_tessellate_with_inherited_styles() {
+        let document = SvgDocument::parse(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 10 10">
+                <g transform="translate(1 1)" fill="#00f">
+                    <rect width="4" height="4" style="fill: #ff0000"/>
+                    <rect x="5" width="2" height="2"/>
+                    <text>ignored</text>
+                </g>
+            </svg>"##,
+        )
+        .unwrap();
+        assert_eq!(document.shape_count(), 2);
+
+        let mesh = document.tessellate(IDENTITY, 0.1);
+        assert_eq!(mesh.positions.len(), mesh.colors.len());
+        let mut areas = [0.0; 2];
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let area = ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() * 0.5;
+            match mesh.colors[triangle[0] as usize] {
+                [1.0, 0.0, 0.0, 1.0] => areas[0] += area,
+                [0.0, 0.0, 1.0, 1.0] => areas[1] += area,
+                color => panic!("unexpected color {:?}", color),
+            }
+        }
+        // Scaled by two from the viewBox
+        assert_eq!(areas, [64.0, 16.0]);
+        let bounds = bounds(&mesh.positions).unwrap();
+        assert_eq!(bounds, [2.0, 2.0, 16.0, 10.0]);
+    }
+}
@@ -0,0 +1,406 @@
+use std::f32::consts::PI;
+
+// A command of SVG path data in absolute coordinates, with the shorthand lines and smooth
+// curves resolved
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum SvgSegment {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    QuadTo(f32, f32, f32, f32),
+    CubicTo(f32, f32, f32, f32, f32, f32),
+    // Elliptical arc from the current point: radii, x-axis rotation in degrees, flags, end point
+    ArcTo {
+        rx: f32,
+        ry: f32,
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        x: f32,
+        y: f32,
+    },
+    Close,
+}
+
+// Center parameterization of an elliptical arc
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct CenterArc {
+    pub(crate) center: (f32, f32),
+    pub(crate) radii: (f32, f32),
+    // Rotation of the x-axis in radians
+    pub(crate) rotation: f32,
+    pub(crate) start_angle: f32,
+    pub(crate) sweep: f32,
+}
+
+// Parse the `d` attribute of an SVG path: absolute and relative commands, implicit repeats,
+// H/V lines, S/T smooth curves and A elliptical arcs. Errors name the offending offset
+pub(crate) fn parse_path_data(data: &str) -> Result<Vec<SvgSegment>, String> {
+    let mut lexer = Lexer { bytes: data.as_bytes(), position: 0 };
+    let mut segments = Vec::new();
+
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // Control point of the previous curve, reflected by S and T
+    let mut last_cubic: Option<(f32, f32)> = None;
+    let mut last_quad: Option<(f32, f32)> = None;
+    let mut command: Option<u8> = None;
+
+    loop {
+        lexer.skip_separators();
+        let Some(next) = lexer.peek() else {
+            break;
+        };
+
+        let letter = if next.is_ascii_alphabetic() {
+            lexer.position += 1;
+            next
+        } else {
+            // Parameters without a command repeat the previous one, a moveto continues as lineto
+            match command {
+                Some(b'M') => b'L',
+                Some(b'm') => b'l',
+                Some(b'Z' | b'z') | None => {
+                    return Err(format!("Expected a path command at offset {}", lexer.position));
+                }
+                Some(previous) => previous,
+            }
+        };
+        if command.is_none() && !matches!(letter, b'M' | b'm') {
+            return Err("Path data must begin with a moveto command".to_string());
+        }
+        command = Some(letter);
+
+        let relative = letter.is_ascii_lowercase();
+        let offset = |point: (f32, f32)| {
+            if relative {
+                (current.0 + point.0, current.1 + point.1)
+            } else {
+                point
+            }
+        };
+
+        let (cubic, quad) = match letter.to_ascii_uppercase() {
+            b'M' => {
+                let point = offset(lexer.pair()?);
+                segments.push(SvgSegment::MoveTo(point.0, point.1));
+                current = point;
+                start = point;
+                (None, None)
+            }
+            b'L' => {
+                let point = offset(lexer.pair()?);
+                segments.push(SvgSegment::LineTo(point.0, point.1));
+                current = point;
+                (None, None)
+            }
+            b'H' => {
+                let x = lexer.number()?;
+                current = (if relative { current.0 + x } else { x }, current.1);
+                segments.push(SvgSegment::LineTo(current.0, current.1));
+                (None, None)
+            }
+            b'V' => {
+                let y = lexer.number()?;
+                current = (current.0, if relative { current.1 + y } else { y });
+                segments.push(SvgSegment::LineTo(current.0, current.1));
+                (None, None)
+            }
+            b'C' | b'S' => {
+                let control1 = if letter.eq_ignore_ascii_case(&b'C') {
+                    offset(lexer.pair()?)
+                } else {
+                    reflect(last_cubic, current)
+                };
+                let control2 = offset(lexer.pair()?);
+                let point = offset(lexer.pair()?);
+                segments.push(SvgSegment::CubicTo(control1.0, control1.1, control2.0, control2.1, point.0, point.1));
+                current = point;
+                (Some(control2), None)
+            }
+            b'Q' | b'T' => {
+                let control = if letter.eq_ignore_ascii_case(&b'Q') {
+                    offset(lexer.pair()?)
+                } else {
+                    reflect(last_quad, current)
+                };
+                let point = offset(lexer.pair()?);
+                segments.push(SvgSegment::QuadTo(control.0, control.1, point.0, point.1));
+                current = point;
+                (None, Some(control))
+            }
+            b'A' => {
+                let rx = lexer.number()?;
+                let ry = lexer.number()?;
+                let rotation = lexer.number()?;
+                let large_arc = lexer.flag()?;
+                let sweep = lexer.flag()?;
+                let point = offset(lexer.pair()?);
+                segments.push(SvgSegment::ArcTo { rx, ry, rotation, large_arc, sweep, x: point.0, y: point.1 });
+                current = point;
+                (None, None)
+            }
+            b'Z' => {
+                segments.push(SvgSegment::Close);
+                current = start;
+                (None, None)
+            }
+            _ => {
+                return Err(format!("Unknown path command '{}' at offset {}", letter as char, lexer.position - 1));
+            }
+        };
+        last_cubic = cubic;
+        last_quad = quad;
+    }
+
+    Ok(segments)
+}
+
+// Convert an arc between two points to its center parameterization, following the SVG
+// implementation notes: radii too small to reach the end point are scaled up. None when the
+// arc is a straight line (a zero radius) or nothing at all (the same end points)
+pub(crate) fn center_arc(
+    from: (f32, f32),
+    to: (f32, f32),
+    radii: (f32, f32),
+    rotation_degrees: f32,
+    large_arc: bool,
+    sweep: bool,
+) -> Option<CenterArc> {
+    let (mut rx, mut ry) = (radii.0.abs() as f64, radii.1.abs() as f64);
+    if rx == 0.0 || ry == 0.0 || from == to {
+        return None;
+    }
+
+    let (x1, y1, x2, y2) = (from.0 as f64, from.1 as f64, to.0 as f64, to.1 as f64);
+    let rotation = (rotation_degrees as f64).to_radians();
+    let (sin, cos) = rotation.sin_cos();
+
+    // The start point in the frame of the ellipse, relative to the chord midpoint
+    let (dx, dy) = ((x1 - x2) * 0.5, (y1 - y2) * 0.5);
+    let (x1p, y1p) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+
+    let lambda = (x1p * x1p) / (rx * rx) + (y1p * y1p) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1p * y1p - ry * ry * x1p * x1p;
+    let denominator = rx * rx * y1p * y1p + ry * ry * x1p * x1p;
+    let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+    let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+    let (cxp, cyp) = (coefficient * rx * y1p / ry, -coefficient * ry * x1p / rx);
+
+    let center = (cos * cxp - sin * cyp + (x1 + x2) * 0.5, sin * cxp + cos * cyp + (y1 + y2) * 0.5);
+    let u = ((x1p - cxp) / rx, (y1p - cyp) / ry);
+    let v = ((-x1p - cxp) / rx, (-y1p - cyp) / ry);
+    let start_angle = u.1.atan2(u.0);
+    let mut delta = (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1);
+    if !sweep && delta > 0.0 {
+        delta -= 2.0 * std::f64::consts::PI;
+    } else if sweep && delta < 0.0 {
+        delta += 2.0 * std::f64::consts::PI;
+    }
+
+    Some(CenterArc {
+        center: (center.0 as f32, center.1 as f32),
+        radii: (rx as f32, ry as f32),
+        rotation: rotation as f32,
+        start_angle: start_angle as f32,
+        sweep: (delta as f32).clamp(-2.0 * PI, 2.0 * PI),
+    })
+}
+
+// A coordinate for SVG output: at most three decimals, without trailing zeros
+pub(crate) fn format_number(value: f32) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "" | "-0" => "0".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+// The previous control point mirrored through the current point, or the current point
+// itself when the previous command was not a curve of the same kind
+fn reflect(control: Option<(f32, f32)>, current: (f32, f32)) -> (f32, f32) {
+    match control {
+        Some(control) => (2.0 * current.0 - control.0, 2.0 * current.1 - control.1),
+        None => current,
+    }
+}
+
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_separators(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace() || byte == b',') {
+            self.position += 1;
+        }
+    }
+
+    fn pair(&mut self) -> Result<(f32, f32), String> {
+        Ok((self.number()?, self.number()?))
+    }
+
+    // A number: sign, digits with an optional fraction, optional exponent. "1.5.5" and
+    // "1-2" are two numbers each
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let start = self.position;
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.position += 1;
+        }
+        let integer = self.digits();
+        let mut fraction = 0;
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            fraction = self.digits();
+        }
+        if integer == 0 && fraction == 0 {
+            self.position = start;
+            return Err(format!("Expected a number at offset {}", start));
+        }
+
+        // Only consume the exponent when digits follow, "2e" leaves the e alone
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            let mark = self.position;
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if self.digits() == 0 {
+                self.position = mark;
+            }
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|text| text.parse::<f32>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| format!("Invalid number at offset {}", start))
+    }
+
+    // Arc flags are a single 0 or 1 and need no separator: "a1 1 0 01 5 5"
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        let flag = match self.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(format!("Expected an arc flag (0 or 1) at offset {}", self.position)),
+        };
+        self.position += 1;
+        Ok(flag)
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.position;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.position - start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use SvgSegment::*;
+
+    fn parse(data: &str) -> Vec<SvgSegment> {
+        parse_path_data(data).unwrap_or_else(|error| panic!("{}: {}", data, error))
+    }
+
+    #[test]
+    fn implicit_repeats_continue_the_previous_command() {
+        // A moveto continues as lineto, in the same absolute or relative mode
+        assert_eq!(parse("M1 1 2 2 3 3"), [MoveTo(1.0, 1.0), LineTo(2.0, 2.0), LineTo(3.0, 3.0)]);
+        assert_eq!(parse("m1 1 2 2 3 3"), [MoveTo(1.0, 1.0), LineTo(3.0, 3.0), LineTo(6.0, 6.0)]);
+        assert_eq!(
+            parse("M0 0 h10 5 v-2 V8 z l1 1"),
+            [
+                MoveTo(0.0, 0.0),
+                LineTo(10.0, 0.0),
+                LineTo(15.0, 0.0),
+                LineTo(15.0, -2.0),
+                LineTo(15.0, 8.0),
+                Close,
+                LineTo(1.0, 1.0),
+            ]
+        );
+        assert_eq!(
+            parse("M0 0 q1 1 2 0 3 -1 4 0"),
+            [MoveTo(0.0, 0.0), QuadTo(1.0, 1.0, 2.0, 0.0), QuadTo(5.0, -1.0, 6.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn smooth_curves_reflect_only_matching_controls() {
+        assert_eq!(
+            parse("M0 0 C1 2 3 2 4 0 S7 -2 8 0"),
+            [MoveTo(0.0, 0.0), CubicTo(1.0, 2.0, 3.0, 2.0, 4.0, 0.0), CubicTo(5.0, -2.0, 7.0, -2.0, 8.0, 0.0)]
+        );
+        // After a quadratic the cubic control collapses onto the current point
+        assert_eq!(parse("M0 0 Q1 1 2 0 S3 1 4 0")[2], CubicTo(2.0, 0.0, 3.0, 1.0, 4.0, 0.0));
+        assert_eq!(parse("M0 0 Q1 1 2 0 t2 0")[2], QuadTo(3.0, -1.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn numbers_need_no_separators() {
+        assert_eq!(parse("M1.5.5"), [MoveTo(1.5, 0.5)]);
+        assert_eq!(parse("M1-2-.5+3"), [MoveTo(1.0, -2.0), LineTo(-0.5, 3.0)]);
+        assert_eq!(parse("M1e2,2.5E-1 1E+1-1e0"), [MoveTo(100.0, 0.25), LineTo(10.0, -1.0)]);
+        assert_eq!(
+            parse("M0 0a1 1 0 01 5 5"),
+            [
+                MoveTo(0.0, 0.0),
+                ArcTo { rx: 1.0, ry: 1.0, rotation: 0.0, large_arc: false, sweep: true, x: 5.0, y: 5.0 },
+            ]
+        );
+        assert_eq!(
+            parse("M0 0A2 3 45 1,0 5,5"),
+            [
+                MoveTo(0.0, 0.0),
+                ArcTo { rx: 2.0, ry: 3.0, rotation: 45.0, large_arc: true, sweep: false, x: 5.0, y: 5.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_data_names_the_offset() {
+        assert_eq!(parse_path_data("L1 1"), Err("Path data must begin with a moveto command".to_string()));
+        assert_eq!(parse_path_data("M1 1 L2"), Err("Expected a number at offset 7".to_string()));
+        // An exponent without digits is not part of the number
+        assert_eq!(parse_path_data("M1 2e"), Err("Unknown path command 'e' at offset 4".to_string()));
+        assert_eq!(parse_path_data("M0 0 A1 1 0 2 0 5 5"), Err("Expected an arc flag (0 or 1) at offset 12".to_string()));
+        assert_eq!(parse_path_data("M0 0 Z 1 1"), Err("Expected a path command at offset 7".to_string()));
+        assert_eq!(parse_path_data(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn arcs_too_small_for_their_chord_are_scaled_up() {
+        // Radius 1 cannot span 10 units: it becomes a half circle of radius 5
+        let arc = center_arc((0.0, 0.0), (10.0, 0.0), (1.0, 1.0), 0.0, false, true).unwrap();
+        assert!((arc.center.0 - 5.0).abs() < 1e-4 && arc.center.1.abs() < 1e-4);
+        assert!((arc.radii.0 - 5.0).abs() < 1e-4 && (arc.radii.1 - 5.0).abs() < 1e-4);
+        assert!((arc.sweep - PI).abs() < 1e-4);
+
+        // Large enough radii keep their size, the flags pick one of four arcs
+        let arc = center_arc((0.0, 0.0), (10.0, 0.0), (10.0, 10.0), 0.0, true, true).unwrap();
+        assert_eq!(arc.radii, (10.0, 10.0));
+        assert!(arc.sweep > PI && arc.center.1 < 0.0);
+        let arc = center_arc((0.0, 0.0), (10.0, 0.0), (10.0, 10.0), 0.0, false, false).unwrap();
+        assert!(arc.sweep < 0.0 && arc.sweep > -PI && arc.center.1 < 0.0);
+
+        assert_eq!(center_arc((0.0, 0.0), (10.0, 0.0), (0.0, 5.0), 0.0, false, true), None);
+        assert_eq!(center_arc((1.0, 1.0), (1.0, 1.0), (5.0, 5.0), 0.0, false, true), None);
+    }
+}
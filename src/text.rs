@@ -0,0 +1,706 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGl2RenderingContext, WebGlTexture};
+
+use crate::atlas::{Atlas, MAX_ATLAS_SIZE};
+use crate::batch::{Paint, ShapeBatch};
+use crate::font::{Font, Outline, Segment};
+use crate::path::Path2D;
+
+// Glyphs are rasterized into the atlas at this many pixels per em
+const SDF_EM_SIZE: f32 = 48.0;
+
+// Distance in atlas pixels encoded on each side of an outline, also bounds the stroke width
+const SDF_SPREAD: f32 = 6.0;
+
+// Maximum distance in atlas pixels between a curve and its flattened segments
+const SDF_TOLERANCE: f32 = 0.1;
+
+// Default font size, like the "10px sans-serif" default of CanvasRenderingContext2D
+const DEFAULT_FONT_SIZE: f32 = 10.0;
+
+// Horizontal alignment of text relative to its anchor point (left-to-right text)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum TextAlign {
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+}
+
+impl TextAlign {
+    pub(crate) fn parse(value: &str) -> Result<TextAlign, JsValue> {
+        match value {
+            "start" => Ok(TextAlign::Start),
+            "end" => Ok(TextAlign::End),
+            "left" => Ok(TextAlign::Left),
+            "right" => Ok(TextAlign::Right),
+            "center" => Ok(TextAlign::Center),
+            _ => Err(JsValue::from_str(&format!("Invalid text align \"{}\"", value))),
+        }
+    }
+
+    // Fraction of the line width left of the anchor
+    fn offset(self) -> f32 {
+        match self {
+            TextAlign::Start | TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::End | TextAlign::Right => 1.0,
+        }
+    }
+}
+
+// Vertical position of the anchor point relative to the text
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum TextBaseline {
+    Top,
+    Hanging,
+    Middle,
+    Alphabetic,
+    Ideographic,
+    Bottom,
+}
+
+impl TextBaseline {
+    pub(crate) fn parse(value: &str) -> Result<TextBaseline, JsValue> {
+        match value {
+            "top" => Ok(TextBaseline::Top),
+            "hanging" => Ok(TextBaseline::Hanging),
+            "middle" => Ok(TextBaseline::Middle),
+            "alphabetic" => Ok(TextBaseline::Alphabetic),
+            "ideographic" => Ok(TextBaseline::Ideographic),
+            "bottom" => Ok(TextBaseline::Bottom),
+            _ => Err(JsValue::from_str(&format!("Invalid text baseline \"{}\"", value))),
+        }
+    }
+}
+
+// Font and layout settings of the drawing state
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct TextStyle {
+    // Index of a font loaded into the TextRenderer
+    pub(crate) font: Option<usize>,
+    // Em size in CSS pixels
+    pub(crate) size: f32,
+    pub(crate) align: TextAlign,
+    pub(crate) baseline: TextBaseline,
+    // Distance between baselines in CSS pixels, None uses the font line spacing
+    pub(crate) line_height: Option<f32>,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            font: None,
+            size: DEFAULT_FONT_SIZE,
+            align: TextAlign::Start,
+            baseline: TextBaseline::Alphabetic,
+            line_height: None,
+        }
+    }
+}
+
+// Result of Canvas2D::measure_text, distances in CSS pixels like the DOM TextMetrics.
+// For multi-line text the width is the widest line and the boxes cover all lines
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextMetrics {
+    pub width: f32,
+    pub actual_bounding_box_left: f32,
+    pub actual_bounding_box_right: f32,
+    pub actual_bounding_box_ascent: f32,
+    pub actual_bounding_box_descent: f32,
+    pub font_bounding_box_ascent: f32,
+    pub font_bounding_box_descent: f32,
+    pub line_count: u32,
+}
+
+// A glyph positioned on a line, x in ems from the line start
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct PlacedGlyph {
+    pub(crate) glyph: u16,
+    pub(crate) x: f32,
+}
+
+// A laid out line of text, in ems
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TextLine {
+    pub(crate) glyphs: Vec<PlacedGlyph>,
+    pub(crate) width: f32,
+}
+
+// Where a glyph lives in the atlas
+#[derive(Copy, Clone, Debug)]
+struct GlyphEntry {
+    // Texel rect in the atlas, None for glyphs without an outline (e.g. spaces)
+    texels: Option<[u32; 4]>,
+    // Top-left of the texel rect relative to the glyph origin, in ems (y up)
+    left: f32,
+    top: f32,
+}
+
+// Loaded fonts and the signed distance field atlas their glyphs are rendered from
+pub(crate) struct TextRenderer {
+    fonts: Vec<(String, Font)>,
+    atlas: Option<Atlas>,
+    glyphs: HashMap<(usize, u16), GlyphEntry>,
+    max_atlas_size: u32,
+    // Frame counter, glyphs queued during the current frame pin the atlas content
+    frame: u64,
+    last_draw_frame: Option<u64>,
+}
+
+impl TextRenderer {
+    pub(crate) fn new(gl: &WebGl2RenderingContext) -> TextRenderer {
+        let max_texture_size = gl
+            .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|value| value.as_f64())
+            .map_or(MAX_ATLAS_SIZE, |size| size as u32);
+
+        TextRenderer {
+            fonts: Vec::new(),
+            atlas: None,
+            glyphs: HashMap::new(),
+            max_atlas_size: max_texture_size.min(MAX_ATLAS_SIZE),
+            frame: 0,
+            last_draw_frame: None,
+        }
+    }
+
+    // Parse and register a TrueType/OpenType font, replacing a font with the same name.
+    // Returns the font index
+    pub(crate) fn load_font(&mut self, name: &str, bytes: &[u8]) -> Result<usize, JsValue> {
+        let font = Font::parse(bytes.to_vec()).map_err(|err| JsValue::from_str(&err))?;
+
+        if let Some(index) = self.find_font(name) {
+            self.fonts[index].1 = font;
+            self.glyphs.retain(|(font_index, _), _| *font_index != index);
+            return Ok(index);
+        }
+
+        self.fonts.push((name.to_string(), font));
+        Ok(self.fonts.len() - 1)
+    }
+
+    pub(crate) fn find_font(&self, name: &str) -> Option<usize> {
+        self.fonts.iter().position(|(font_name, _)| font_name == name)
+    }
+
+    pub(crate) fn font(&self, index: usize) -> Option<&Font> {
+        self.fonts.get(index).map(|(_, font)| font)
+    }
+
+    // Atlas texture, its size and the distance spread, for ShapeBatch::flush
+    pub(crate) fn atlas(&self) -> Option<(&WebGlTexture, f32, f32)> {
+        self.atlas
+            .as_ref()
+            .map(|atlas| (atlas.texture(), atlas.size() as f32, SDF_SPREAD))
+    }
+
+    // Called once per rendered frame
+    pub(crate) fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Shape a line of text with kerning, in ems
+    pub(crate) fn layout_line(&self, font: &Font, text: &str) -> TextLine {
+        let mut line = TextLine::default();
+        let mut previous: Option<u16> = None;
+
+        for c in text.chars() {
+            // Tabs advance like a space
+            let c = if c == '\t' { ' ' } else { c };
+            let glyph = font.glyph_index(c);
+            if let Some(previous) = previous {
+                line.width += font.kerning(previous, glyph);
+            }
+            line.glyphs.push(PlacedGlyph { glyph, x: line.width });
+            line.width += font.advance(glyph);
+            previous = Some(glyph);
+        }
+
+        line
+    }
+
+    // Split on newlines and lay out every line
+    pub(crate) fn layout(&self, font: &Font, text: &str) -> Vec<TextLine> {
+        text.split('\n')
+            .map(|line| self.layout_line(font, line.strip_suffix('\r').unwrap_or(line)))
+            .collect()
+    }
+
+    // Queue text into the shape batch, stroked with the given line width or filled
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shapes: &mut ShapeBatch,
+        style: &TextStyle,
+        text: &str,
+        x: f32,
+        y: f32,
+        max_width: Option<f32>,
+        paint: &Paint,
+        stroke_width: Option<f32>,
+    ) -> Result<(), JsValue> {
+        let font_index = style.font.ok_or("No font set, call load_font first")?;
+        let font = self.font(font_index).ok_or("Invalid font")?;
+        let lines = self.layout(font, text);
+        self.draw_lines(gl, shapes, style, &lines, x, y, max_width, paint, stroke_width)
+    }
+
+    // Queue laid out lines, the first baseline is placed according to the style
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw_lines(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shapes: &mut ShapeBatch,
+        style: &TextStyle,
+        lines: &[TextLine],
+        x: f32,
+        y: f32,
+        max_width: Option<f32>,
+        paint: &Paint,
+        stroke_width: Option<f32>,
+    ) -> Result<(), JsValue> {
+        let font_index = style.font.ok_or("No font set, call load_font first")?;
+        for (glyph, origin, squeeze) in self.place_lines(font_index, style, lines, x, y, max_width) {
+            self.draw_glyph(gl, shapes, font_index, glyph, origin, style.size, squeeze, paint, stroke_width)?;
+        }
+
+        Ok(())
+    }
+
+    // Outlines of text placed like draw, as a path for vector export
+    pub(crate) fn text_path(&self, style: &TextStyle, text: &str, x: f32, y: f32, max_width: Option<f32>) -> Result<Path2D, JsValue> {
+        let font_index = style.font.ok_or("No font set, call load_font first")?;
+        let font = self.font(font_index).ok_or("Invalid font")?;
+        let lines = self.layout(font, text);
+
+        let mut path = Path2D::new();
+        for (glyph, origin, squeeze) in self.place_lines(font_index, style, &lines, x, y, max_width) {
+            self.glyph_path(font_index, glyph, origin, style.size, squeeze, &mut path)?;
+        }
+        Ok(path)
+    }
+
+    // Append the outline of a glyph placed like draw_glyph to a path
+    pub(crate) fn glyph_path(
+        &self,
+        font_index: usize,
+        glyph: u16,
+        origin: [f32; 2],
+        size: f32,
+        x_scale: f32,
+        path: &mut Path2D,
+    ) -> Result<(), JsValue> {
+        let font = self.font(font_index).ok_or("Invalid font")?;
+        let outline = font.outline(glyph).map_err(|err| JsValue::from_str(&err))?;
+        let scale = size / font.units_per_em();
+        let map = |p: [f32; 2]| (origin[0] + p[0] * scale * x_scale, origin[1] - p[1] * scale);
+
+        // Segments of a contour follow each other, a gap starts the next contour
+        let mut start = None;
+        let mut current = None;
+        for segment in &outline.segments {
+            let (from, to) = match *segment {
+                Segment::Line(a, b) | Segment::Quad(a, _, b) | Segment::Cubic(a, _, _, b) => (map(a), map(b)),
+            };
+            if current != Some(from) {
+                if current.is_some() {
+                    path.close_path();
+                }
+                path.move_to(from.0, from.1);
+                start = Some(from);
+            }
+            match *segment {
+                Segment::Line(..) => path.line_to(to.0, to.1),
+                Segment::Quad(_, control, _) => {
+                    let control = map(control);
+                    path.quadratic_curve_to(control.0, control.1, to.0, to.1);
+                }
+                Segment::Cubic(_, c1, c2, _) => {
+                    let (c1, c2) = (map(c1), map(c2));
+                    path.bezier_curve_to(c1.0, c1.1, c2.0, c2.1, to.0, to.1);
+                }
+            }
+            current = Some(to);
+            if current == start {
+                path.close_path();
+                current = None;
+            }
+        }
+        if current.is_some() {
+            path.close_path();
+        }
+
+        Ok(())
+    }
+
+    // Glyphs of laid out lines with their baseline origin and horizontal squeeze, the first
+    // baseline placed according to the style
+    fn place_lines(
+        &self,
+        font_index: usize,
+        style: &TextStyle,
+        lines: &[TextLine],
+        x: f32,
+        y: f32,
+        max_width: Option<f32>,
+    ) -> Vec<(u16, [f32; 2], f32)> {
+        // Like CanvasRenderingContext2D, a non-positive max width draws nothing
+        if max_width.is_some_and(|max_width| max_width <= 0.0 || max_width.is_nan()) {
+            return Vec::new();
+        }
+
+        let size = style.size;
+        let line_height = self.line_height(font_index, style);
+        let first_baseline = y + self.first_baseline_offset(font_index, style, lines.len());
+
+        let mut placed_glyphs = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let width = line.width * size;
+            let squeeze = max_width.map_or(1.0, |max_width| (max_width / width).min(1.0));
+            let start_x = x - style.align.offset() * width * squeeze;
+            let baseline = first_baseline + i as f32 * line_height;
+
+            for placed in &line.glyphs {
+                placed_glyphs.push((placed.glyph, [start_x + placed.x * size * squeeze, baseline], squeeze));
+            }
+        }
+        placed_glyphs
+    }
+
+    // Queue one glyph with its origin on the baseline at `origin`, squeezed horizontally by x_scale
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw_glyph(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        shapes: &mut ShapeBatch,
+        font_index: usize,
+        glyph: u16,
+        origin: [f32; 2],
+        size: f32,
+        x_scale: f32,
+        paint: &Paint,
+        stroke_width: Option<f32>,
+    ) -> Result<(), JsValue> {
+        let entry = self.glyph(gl, font_index, glyph)?;
+        self.last_draw_frame = Some(self.frame);
+        let Some([tx, ty, tw, th]) = entry.texels else {
+            return Ok(());
+        };
+
+        // Stroke half width converted to atlas pixels, limited by the encoded distance range
+        let mode = match stroke_width {
+            Some(width) => (width * 0.5 * SDF_EM_SIZE / size).clamp(0.01, SDF_SPREAD - 0.5),
+            None => 0.0,
+        };
+
+        let rect = [
+            origin[0] + entry.left * size * x_scale,
+            origin[1] - entry.top * size,
+            tw as f32 / SDF_EM_SIZE * size * x_scale,
+            th as f32 / SDF_EM_SIZE * size,
+        ];
+        shapes.glyph(rect, [tx as f32, ty as f32, tw as f32, th as f32], mode, paint);
+
+        Ok(())
+    }
+
+    // Measure text without drawing it
+    pub(crate) fn measure(&self, style: &TextStyle, text: &str) -> Result<TextMetrics, JsValue> {
+        let font_index = style.font.ok_or("No font set, call load_font first")?;
+        let font = self.font(font_index).ok_or("Invalid font")?;
+        let lines = self.layout(font, text);
+        let size = style.size;
+        let line_height = self.line_height(font_index, style);
+
+        // Distances are measured from the anchor point, positive left and up
+        let baseline_offset = self.first_baseline_offset(font_index, style, lines.len());
+        let mut metrics = TextMetrics {
+            font_bounding_box_ascent: font.ascender() * size - baseline_offset,
+            font_bounding_box_descent: -font.descender() * size + baseline_offset
+                + (lines.len() - 1) as f32 * line_height,
+            line_count: lines.len() as u32,
+            ..TextMetrics::default()
+        };
+
+        let mut ink: Option<[f32; 4]> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let width = line.width * size;
+            metrics.width = metrics.width.max(width);
+            let start_x = -style.align.offset() * width;
+            let baseline = baseline_offset + i as f32 * line_height;
+
+            for placed in &line.glyphs {
+                let outline = font.outline(placed.glyph).map_err(|err| JsValue::from_str(&err))?;
+                let Some([x_min, y_min, x_max, y_max]) = outline.bounds() else {
+                    continue;
+                };
+                let scale = size / font.units_per_em();
+                let left = start_x + placed.x * size + x_min * scale;
+                let right = start_x + placed.x * size + x_max * scale;
+                let top = baseline - y_max * scale;
+                let bottom = baseline - y_min * scale;
+
+                let bounds = ink.get_or_insert([left, top, right, bottom]);
+                bounds[0] = bounds[0].min(left);
+                bounds[1] = bounds[1].min(top);
+                bounds[2] = bounds[2].max(right);
+                bounds[3] = bounds[3].max(bottom);
+            }
+        }
+
+        if let Some([left, top, right, bottom]) = ink {
+            metrics.actual_bounding_box_left = -left;
+            metrics.actual_bounding_box_right = right;
+            metrics.actual_bounding_box_ascent = -top;
+            metrics.actual_bounding_box_descent = bottom;
+        }
+
+        Ok(metrics)
+    }
+
+    pub(crate) fn line_height(&self, font_index: usize, style: &TextStyle) -> f32 {
+        style.line_height.unwrap_or_else(|| {
+            self.font(font_index).map_or(1.2, |font| font.line_spacing()) * style.size
+        })
+    }
+
+    // Offset from the anchor y to the first baseline, for a block of `line_count` lines
+    fn first_baseline_offset(&self, font_index: usize, style: &TextStyle, line_count: usize) -> f32 {
+        let Some(font) = self.font(font_index) else {
+            return 0.0;
+        };
+        let size = style.size;
+        let block = line_count.saturating_sub(1) as f32 * self.line_height(font_index, style);
+
+        match style.baseline {
+            TextBaseline::Top => font.ascender() * size,
+            TextBaseline::Hanging => font.ascender() * 0.8 * size,
+            TextBaseline::Middle => (font.ascender() + font.descender()) * 0.5 * size - block * 0.5,
+            TextBaseline::Alphabetic => 0.0,
+            TextBaseline::Ideographic | TextBaseline::Bottom => font.descender() * size - block,
+        }
+    }
+
+    pub(crate) fn delete(&mut self, gl: &WebGl2RenderingContext) {
+        if let Some(atlas) = self.atlas.take() {
+            atlas.delete(gl);
+        }
+        self.glyphs.clear();
+        self.fonts.clear();
+    }
+
+    // Find a glyph in the atlas, rasterizing it on first use
+    fn glyph(&mut self, gl: &WebGl2RenderingContext, font_index: usize, glyph: u16) -> Result<GlyphEntry, JsValue> {
+        if let Some(entry) = self.glyphs.get(&(font_index, glyph)) {
+            return Ok(*entry);
+        }
+
+        let font = self.font(font_index).ok_or("Invalid font")?;
+        let outline = font.outline(glyph).map_err(|err| JsValue::from_str(&err))?;
+        let scale = SDF_EM_SIZE / font.units_per_em();
+
+        let field = DistanceField::rasterize(&outline, scale, self.max_atlas_size).map_err(|err| JsValue::from_str(&err))?;
+        let Some(field) = field else {
+            let entry = GlyphEntry {
+                texels: None,
+                left: 0.0,
+                top: 0.0,
+            };
+            self.glyphs.insert((font_index, glyph), entry);
+            return Ok(entry);
+        };
+
+        let (x, y) = self.allocate(gl, field.width, field.height)?;
+        let atlas = self.atlas.as_ref().ok_or("Glyph atlas is missing")?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(atlas.texture()));
+        gl.pixel_storei(WebGl2RenderingContext::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.pixel_storei(WebGl2RenderingContext::UNPACK_PREMULTIPLY_ALPHA_WEBGL, 0);
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            x as i32,
+            y as i32,
+            field.width as i32,
+            field.height as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(&field.rgba()),
+        )?;
+
+        let entry = GlyphEntry {
+            texels: Some([x, y, field.width, field.height]),
+            left: field.left / SDF_EM_SIZE,
+            top: field.top / SDF_EM_SIZE,
+        };
+        self.glyphs.insert((font_index, glyph), entry);
+
+        Ok(entry)
+    }
+
+    // Reserve atlas space, starting over with an empty atlas when it is full
+    fn allocate(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32) -> Result<(u32, u32), JsValue> {
+        if self.atlas.is_none() {
+            self.atlas = Some(Atlas::new(gl)?);
+        }
+        let atlas = self.atlas.as_mut().ok_or("Glyph atlas is missing")?;
+
+        if let Some(position) = atlas.allocate(gl, width, height, self.max_atlas_size)? {
+            return Ok(position);
+        }
+
+        // Glyphs already queued this frame reference the current atlas content
+        if self.last_draw_frame == Some(self.frame) {
+            return Err(JsValue::from_str("Glyph atlas is full, too many distinct glyphs drawn in one frame"));
+        }
+
+        atlas.reset(gl)?;
+        self.glyphs.clear();
+        atlas
+            .allocate(gl, width, height, self.max_atlas_size)?
+            .ok_or_else(|| JsValue::from_str("Glyph does not fit in an empty atlas"))
+    }
+}
+
+// Signed distance field of a glyph, 0.5 on the outline and increasing inside
+struct DistanceField {
+    width: u32,
+    height: u32,
+    // Top-left corner in atlas pixels relative to the glyph origin, y up
+    left: f32,
+    top: f32,
+    values: Vec<u8>,
+}
+
+impl DistanceField {
+    // Rasterize an outline at `scale` atlas pixels per font unit, None for empty outlines.
+    // Fails before allocating anything when the field is larger than an atlas page
+    fn rasterize(outline: &Outline, scale: f32, max_size: u32) -> Result<Option<DistanceField>, String> {
+        let Some([x_min, y_min, x_max, y_max]) = outline.bounds() else {
+            return Ok(None);
+        };
+
+        let left = (x_min * scale).floor() - SDF_SPREAD;
+        let bottom = (y_min * scale).floor() - SDF_SPREAD;
+        let right = (x_max * scale).ceil() + SDF_SPREAD;
+        let top = (y_max * scale).ceil() + SDF_SPREAD;
+        // Also false for NaN bounds
+        if !(right - left <= max_size as f32 && top - bottom <= max_size as f32) {
+            return Err(format!(
+                "Glyph of {} by {} pixels does not fit in an atlas page of {}",
+                right - left,
+                top - bottom,
+                max_size
+            ));
+        }
+        let width = (right - left) as u32;
+        let height = (top - bottom) as u32;
+        let texels = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or_else(|| "Glyph is too large to rasterize".to_string())?;
+
+        let lines = outline.lines(scale, SDF_TOLERANCE);
+        let mut values = Vec::with_capacity(texels);
+        for row in 0..height {
+            // Rows go downward from the top of the glyph
+            let py = top - row as f32 - 0.5;
+            for column in 0..width {
+                let px = left + column as f32 + 0.5;
+
+                let mut distance = f32::MAX;
+                let mut winding = 0;
+                for &(a, b) in &lines {
+                    distance = distance.min(segment_distance([px, py], a, b));
+
+                    // Nonzero winding from a ray cast towards +x
+                    if (a[1] <= py) != (b[1] <= py) {
+                        let t = (py - a[1]) / (b[1] - a[1]);
+                        if a[0] + t * (b[0] - a[0]) > px {
+                            winding += if b[1] > a[1] { 1 } else { -1 };
+                        }
+                    }
+                }
+
+                let signed = if winding != 0 { distance } else { -distance };
+                let value = (0.5 + signed / (2.0 * SDF_SPREAD)).clamp(0.0, 1.0);
+                values.push((value * 255.0).round() as u8);
+            }
+        }
+
+        Ok(Some(DistanceField {
+            width,
+            height,
+            left,
+            top,
+            values,
+        }))
+    }
+
+    // The atlas is RGBA, the distance goes into every channel
+    fn rgba(&self) -> Vec<u8> {
+        self.values.iter().flat_map(|value| [*value; 4]).collect()
+    }
+}
+
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let ap = [p[0] - a[0], p[1] - a[1]];
+    let len2 = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if len2 > 0.0 { ((ap[0] * ab[0] + ap[1] * ab[1]) / len2).clamp(0.0, 1.0) } else { 0.0 };
+    let dx = ap[0] - t * ab[0];
+    let dy = ap[1] - t * ab[1];
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::OutlineBuilder;
+
+    fn square(size: f32) -> Outline {
+        let mut builder = OutlineBuilder::default();
+        builder.move_to([0.0, 0.0]);
+        builder.line_to([size, 0.0]);
+        builder.line_to([size, size]);
+        builder.line_to([0.0, size]);
+        builder.close();
+        builder.finish()
+    }
+
+    #[test]
+    fn distance_field_is_half_on_the_outline() {
+        let field = DistanceField::rasterize(&square(100.0), 0.2, MAX_ATLAS_SIZE).unwrap().unwrap();
+        // 20 pixels of glyph plus the spread on both sides
+        let side = 20 + 2 * SDF_SPREAD as u32;
+        assert_eq!((field.width, field.height), (side, side));
+        assert_eq!((field.left, field.top), (-SDF_SPREAD, 20.0 + SDF_SPREAD));
+
+        let at = |column: u32, row: u32| field.values[(row * field.width + column) as usize];
+        let center = side / 2;
+        assert_eq!(at(center, center), 255);
+        assert_eq!(at(0, 0), 0);
+        // Pixel centers half a pixel inside and outside the left edge
+        let edge = SDF_SPREAD as u32;
+        assert!(at(edge, center) > 128 && at(edge, center) < 160);
+        assert!(at(edge - 1, center) < 128 && at(edge - 1, center) > 96);
+
+        assert_eq!(field.rgba().len(), field.values.len() * 4);
+        assert!(DistanceField::rasterize(&Outline::default(), 0.2, MAX_ATLAS_SIZE).unwrap().is_none());
+    }
+
+    #[test]
+    fn glyphs_larger_than_an_atlas_page_are_rejected() {
+        // 20 pixels plus the spread fit a page of 32 but not of 31
+        assert!(DistanceField::rasterize(&square(100.0), 0.2, 32).unwrap().is_some());
+        assert!(DistanceField::rasterize(&square(100.0), 0.2, 31).is_err());
+        // Sizes that would overflow the texel count are caught by the same check
+        assert!(DistanceField::rasterize(&square(1e30), 1.0, MAX_ATLAS_SIZE).is_err());
+        assert!(DistanceField::rasterize(&square(100.0), f32::NAN, MAX_ATLAS_SIZE).is_err());
+    }
+}
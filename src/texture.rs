@@ -0,0 +1,373 @@
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+use js_sys::Object;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlImageElement, ImageData, WebGl2RenderingContext, WebGlTexture};
+
+use crate::png::png_data_url;
+
+// ImageData sources are cached by object identity, which callers cannot release once they
+// drop the object, so only the most recently drawn ones are kept
+const MAX_OBJECT_TEXTURES: usize = 32;
+
+// An uploaded RGBA image. The GL texture is deleted when the last reference is dropped,
+// so patterns and queued draws keep it alive after it leaves the cache
+#[derive(Debug)]
+pub(crate) struct Texture {
+    id: u32,
+    gl: WebGl2RenderingContext,
+    texture: WebGlTexture,
+    width: u32,
+    height: u32,
+    // Uploaded with UNPACK_PREMULTIPLY_ALPHA_WEBGL
+    premultiplied: bool,
+    // Set for images uploaded or drawn while recording, raw pixels are not kept otherwise
+    source: OnceCell<ImageSource>,
+    // URL of the image in SVG exports, encoded on first use
+    href: OnceCell<Rc<str>>,
+}
+
+// What the pixels of a texture were uploaded from, kept for SVG exports
+#[derive(Debug)]
+enum ImageSource {
+    Url(String),
+    ImageData(ImageData),
+    Rgba(Vec<u8>),
+}
+
+impl Texture {
+    pub(crate) fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn premultiplied(&self) -> bool {
+        self.premultiplied
+    }
+
+    // The image URL, or a PNG data URL of uploaded pixels. None for raw pixels uploaded
+    // before recording started
+    pub(crate) fn href(&self) -> Option<Rc<str>> {
+        if let Some(href) = self.href.get() {
+            return Some(href.clone());
+        }
+        let href: Rc<str> = match self.source.get()? {
+            ImageSource::Url(url) => url.as_str().into(),
+            ImageSource::ImageData(data) => png_data_url(self.width, self.height, &data.data()).into(),
+            ImageSource::Rgba(pixels) => png_data_url(self.width, self.height, pixels).into(),
+        };
+        Some(self.href.get_or_init(|| href).clone())
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        self.gl.delete_texture(Some(&self.texture));
+    }
+}
+
+// Textures are compared by identity so draw calls can be merged cheaply
+impl PartialEq for Texture {
+    fn eq(&self, other: &Texture) -> bool {
+        self.id == other.id
+    }
+}
+
+// What an image was uploaded from
+enum TextureKey {
+    // Raw bytes uploaded under a user-chosen name
+    Named(String),
+    // An <img> element, identified by its URL
+    Url(String),
+    // Any other JS object (e.g. ImageData), identified by object identity
+    Object(JsValue),
+}
+
+// Cache of uploaded images keyed by their source, so drawing the same image
+// every frame only uploads it once
+pub(crate) struct TextureCache {
+    entries: Vec<(TextureKey, Rc<Texture>)>,
+    next_id: u32,
+    // Premultiply alpha of images uploaded from now on
+    premultiply: bool,
+    // Keep the sources of images uploaded or drawn from now on, for SVG exports
+    keep_sources: bool,
+}
+
+impl TextureCache {
+    pub(crate) fn new() -> TextureCache {
+        TextureCache {
+            entries: Vec::new(),
+            next_id: 1,
+            premultiply: false,
+            keep_sources: false,
+        }
+    }
+
+    // Textures already uploaded keep their alpha mode, the shaders read it per texture
+    pub(crate) fn set_premultiply(&mut self, premultiply: bool) {
+        self.premultiply = premultiply;
+    }
+
+    // Set while recording, so pixel copies are only made when an export may need them
+    pub(crate) fn set_keep_sources(&mut self, keep_sources: bool) {
+        self.keep_sources = keep_sources;
+    }
+
+    // Find or upload the texture for an image source: an HtmlImageElement, an
+    // ImageData, or the name of raw RGBA data uploaded with `upload_rgba`
+    pub(crate) fn resolve(&mut self, gl: &WebGl2RenderingContext, source: &JsValue) -> Result<Rc<Texture>, JsValue> {
+        if let Some(name) = source.as_string() {
+            return self
+                .find(|key| matches!(key, TextureKey::Named(n) if *n == name))
+                .ok_or_else(|| JsValue::from_str(&format!("No image data uploaded under \"{}\"", name)));
+        }
+
+        if let Some(image) = source.dyn_ref::<HtmlImageElement>() {
+            let url = image.src();
+            if let Some(texture) = self.find(|key| matches!(key, TextureKey::Url(u) if *u == url)) {
+                return Ok(texture);
+            }
+
+            if !image.complete() || image.natural_width() == 0 {
+                return Err(JsValue::from_str("Image has not finished loading"));
+            }
+
+            let source = Some(ImageSource::Url(url.clone()));
+            let texture = self.create(gl, image.natural_width(), image.natural_height(), source)?;
+            gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                WebGl2RenderingContext::RGBA as i32,
+                WebGl2RenderingContext::RGBA,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                image,
+            )?;
+            return Ok(self.insert(TextureKey::Url(url), texture));
+        }
+
+        if let Some(data) = source.dyn_ref::<ImageData>() {
+            let cached = self
+                .entries
+                .iter()
+                .position(|(key, _)| matches!(key, TextureKey::Object(object) if Object::is(object, source)));
+            if let Some(index) = cached {
+                // Entries are kept from least to most recently used
+                let entry = self.entries.remove(index);
+                let texture = entry.1.clone();
+                self.entries.push(entry);
+                if self.keep_sources {
+                    texture.source.get_or_init(|| ImageSource::ImageData(data.clone()));
+                }
+                return Ok(texture);
+            }
+            self.evict_objects(MAX_OBJECT_TEXTURES - 1);
+
+            let kept = self.keep_sources.then(|| ImageSource::ImageData(data.clone()));
+            let texture = self.create(gl, data.width(), data.height(), kept)?;
+            gl.tex_image_2d_with_u32_and_u32_and_image_data(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                WebGl2RenderingContext::RGBA as i32,
+                WebGl2RenderingContext::RGBA,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                data,
+            )?;
+            return Ok(self.insert(TextureKey::Object(source.clone()), texture));
+        }
+
+        Err(JsValue::from_str("Unsupported image source, expected an HtmlImageElement, ImageData or uploaded name"))
+    }
+
+    // Upload raw RGBA bytes under a name, replacing any previous upload with that name
+    pub(crate) fn upload_rgba(
+        &mut self,
+        gl: &WebGl2RenderingContext,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), JsValue> {
+        let texels = (width as usize).checked_mul(height as usize).and_then(|count| count.checked_mul(4));
+        if width == 0 || height == 0 || texels != Some(pixels.len()) {
+            return Err(JsValue::from_str("Pixel data must contain width * height RGBA texels"));
+        }
+
+        self.release_where(|key| matches!(key, TextureKey::Named(n) if n == name));
+
+        let source = self.keep_sources.then(|| ImageSource::Rgba(pixels.to_vec()));
+        let texture = self.create(gl, width, height, source)?;
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            WebGl2RenderingContext::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(pixels),
+        )?;
+        self.insert(TextureKey::Named(name.to_string()), texture);
+
+        Ok(())
+    }
+
+    // Drop the cached texture of a source, e.g. after the image content changed
+    pub(crate) fn release(&mut self, source: &JsValue) {
+        if let Some(name) = source.as_string() {
+            self.release_where(|key| matches!(key, TextureKey::Named(n) if *n == name));
+        } else if let Some(image) = source.dyn_ref::<HtmlImageElement>() {
+            let url = image.src();
+            self.release_where(|key| matches!(key, TextureKey::Url(u) if *u == url));
+        } else {
+            self.release_where(|key| matches!(key, TextureKey::Object(object) if Object::is(object, source)));
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn find(&self, matches: impl Fn(&TextureKey) -> bool) -> Option<Rc<Texture>> {
+        self.entries
+            .iter()
+            .find(|(key, _)| matches(key))
+            .map(|(_, texture)| texture.clone())
+    }
+
+    fn insert(&mut self, key: TextureKey, texture: Texture) -> Rc<Texture> {
+        let texture = Rc::new(texture);
+        self.entries.push((key, texture.clone()));
+        texture
+    }
+
+    fn release_where(&mut self, matches: impl Fn(&TextureKey) -> bool) {
+        self.entries.retain(|(key, _)| !matches(key));
+    }
+
+    // Drop the least recently used object entries until at most `limit` are left
+    fn evict_objects(&mut self, limit: usize) {
+        let count = self.entries.iter().filter(|(key, _)| matches!(key, TextureKey::Object(_))).count();
+        let mut excess = count.saturating_sub(limit);
+        self.entries.retain(|(key, _)| {
+            if excess > 0 && matches!(key, TextureKey::Object(_)) {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // Create and bind a texture with linear filtering, ready for upload with the current alpha mode
+    fn create(&mut self, gl: &WebGl2RenderingContext, width: u32, height: u32, source: Option<ImageSource>) -> Result<Texture, JsValue> {
+        let texture = gl.create_texture().ok_or("Failed to create image texture")?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        gl.pixel_storei(WebGl2RenderingContext::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.pixel_storei(WebGl2RenderingContext::UNPACK_PREMULTIPLY_ALPHA_WEBGL, self.premultiply as i32);
+        gl.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+            WebGl2RenderingContext::LINEAR as i32,
+        );
+        gl.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+            WebGl2RenderingContext::LINEAR as i32,
+        );
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        Ok(Texture {
+            id,
+            gl: gl.clone(),
+            texture,
+            width,
+            height,
+            premultiplied: self.premultiply,
+            source: source.map(OnceCell::from).unwrap_or_default(),
+            href: OnceCell::new(),
+        })
+    }
+}
+
+// How a pattern tiles
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Repetition {
+    Repeat,
+    RepeatX,
+    RepeatY,
+    NoRepeat,
+}
+
+impl Repetition {
+    // Parse a CanvasRenderingContext2D repetition string
+    pub(crate) fn parse(value: &str) -> Result<Repetition, JsValue> {
+        match value {
+            "" | "repeat" => Ok(Repetition::Repeat),
+            "repeat-x" => Ok(Repetition::RepeatX),
+            "repeat-y" => Ok(Repetition::RepeatY),
+            "no-repeat" => Ok(Repetition::NoRepeat),
+            _ => Err(JsValue::from_str(&format!("Invalid pattern repetition \"{}\"", value))),
+        }
+    }
+
+    pub(crate) fn repeats_x(self) -> bool {
+        matches!(self, Repetition::Repeat | Repetition::RepeatX)
+    }
+
+    pub(crate) fn repeats_y(self) -> bool {
+        matches!(self, Repetition::Repeat | Repetition::RepeatY)
+    }
+}
+
+// Image pattern fill/stroke style, created with Canvas2D::create_pattern.
+// The image is tiled from the origin of the coordinate space at its natural size
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pattern {
+    texture: Rc<Texture>,
+    repetition: Repetition,
+}
+
+impl Pattern {
+    pub(crate) fn new(texture: Rc<Texture>, repetition: Repetition) -> Pattern {
+        Pattern { texture, repetition }
+    }
+
+    pub(crate) fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub(crate) fn repetition(&self) -> Repetition {
+        self.repetition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetitions_follow_canvas_names() {
+        let parsed: Vec<(bool, bool)> = ["", "repeat", "repeat-x", "repeat-y", "no-repeat"]
+            .iter()
+            .map(|value| {
+                let repetition = Repetition::parse(value).unwrap();
+                (repetition.repeats_x(), repetition.repeats_y())
+            })
+            .collect();
+        assert_eq!(parsed, vec![(true, true), (true, true), (true, false), (false, true), (false, false)]);
+    }
+}